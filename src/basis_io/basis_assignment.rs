@@ -0,0 +1,313 @@
+//! This mod assigns the (auxiliary) basis sets per element or per atom label, which are mixed with the global
+//! `basis_path` (`auxbas_path`) by [`Molecule::collect_basis`](crate::molecule_io::Molecule::collect_basis) and
+//! [`Molecule::collect_auxbas`](crate::molecule_io::Molecule::collect_auxbas).
+//!
+//! The assignments are given by the `basis` (`auxbas`) keyword in the `[geom]` block, like
+//! `basis = "C1 def2-TZVP, H cc-pVDZ"`, or by the `[basis]` (`[auxbas]`) block, where each entry is one of
+//!  - the basis set name or path, like `C1 = "def2-TZVP"`, which is resolved as `basis_path`;
+//!  - the inline basis set in the Gaussian format, like `O = """S 3 1.00 ... ****"""`, where the header of
+//!    the element is optional;
+//!  - the inline basis set in the BSE json format, like `N = {electron_shells = [...]}`.
+//!
+//! An atom label (e.g. `C1`) takes the precedence over its element (e.g. `C`), and the later assignment
+//! overrides the earlier one. The atoms without any assignment use the global basis set.
+
+use std::path::Path;
+use rest_libcint::CintType;
+use serde::{Deserialize,Serialize};
+use serde_json::Value;
+use crate::geom_io::{GeomCell, element_from_label, formated_element_name};
+use super::{Basis4Elem, Basis4ElemRaw};
+use super::basis_formats::{parse_gaussian_basis, shell_angular_momentum};
+use super::basis_library::resolve_basis_path;
+
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub enum BasisSource {
+    /// the folder of `{element}.json` or the basis set file
+    Path(String),
+    /// the basis set defined inline in the input file
+    Inline(Basis4ElemRaw),
+}
+
+impl BasisSource {
+    /// Import the basis set of the given element
+    pub fn to_basis4elem(&self, elem: &String, cint_type: &CintType) -> anyhow::Result<Basis4Elem> {
+        match self {
+            BasisSource::Path(path) => Basis4Elem::parse_from_basis_path(path, elem, cint_type),
+            BasisSource::Inline(raw) => Ok(Basis4Elem::from_raw(raw.clone(), cint_type)),
+        }
+    }
+    pub fn description(&self) -> String {
+        match self {
+            BasisSource::Path(path) => path.clone(),
+            BasisSource::Inline(_) => String::from("the inline definition"),
+        }
+    }
+}
+
+/// Split the assignment string, like `C1 def2-TZVP, H cc-pVDZ`, into the pairs of (label, basis set).
+/// The items are separated by `,`, `;` or new lines, except for those in the parentheses like `6-311G(d,p)`
+pub fn split_assignment_string(assignment: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let mut items: Vec<String> = vec![String::new()];
+    let mut depth = 0;
+    for c in assignment.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {},
+        }
+        if depth == 0 && (c == ',' || c == ';' || c == '\n') {
+            items.push(String::new());
+        } else {
+            items.last_mut().unwrap().push(c);
+        }
+    }
+    items.iter().filter(|item| item.trim().len() > 0).map(|item| {
+        let tokens: Vec<&str> = item.split_whitespace().collect();
+        if tokens.len() != 2 {
+            return Err(anyhow::anyhow!("Unknown basis set assignment: '{}', which should be like 'C1 def2-TZVP'", item.trim()))
+        }
+        Ok((tokens[0].to_string(), tokens[1].to_string()))
+    }).collect()
+}
+
+/// Convert the numbers of the inline BSE json into strings, which are required by [`Basis4ElemRaw`]
+fn numbers_to_strings(value: &mut Value) {
+    match value {
+        Value::Number(x) => {*value = Value::String(x.to_string())},
+        Value::Array(items) => {items.iter_mut().for_each(numbers_to_strings)},
+        _ => {},
+    }
+}
+
+/// The inline basis set in the BSE json format, with or without the `elements` wrapper from `bse convert-basis`
+pub fn basis_raw_from_value(value: &Value) -> anyhow::Result<Basis4ElemRaw> {
+    let mut tmp_value = if !value["electron_shells"].is_null() {
+        value.clone()
+    } else if let Some(elements) = value["elements"].as_object() {
+        elements.values().next().ok_or(anyhow::anyhow!("No element in the inline basis set: {}", value))?.clone()
+    } else {
+        return Err(anyhow::anyhow!("The inline basis set is not in the BSE json format: {}", value))
+    };
+    for (cells, keys) in [("electron_shells", ["exponents", "coefficients"]), ("ecp_potentials", ["gaussian_exponents", "coefficients"])] {
+        if let Some(cells) = tmp_value.get_mut(cells).and_then(|x| x.as_array_mut()) {
+            cells.iter_mut().for_each(|cell| {
+                keys.iter().for_each(|key| {if let Some(x) = cell.get_mut(*key) {numbers_to_strings(x)}});
+            });
+        }
+    }
+    Ok(serde_json::from_value(tmp_value)?)
+}
+
+/// The number of the tokens of the optional header like `C 0` in the inline Gaussian basis set,
+/// which ends with the first number of `0`
+fn gaussian_header_len(tokens: &[&str]) -> usize {
+    match tokens.iter().position(|x| x.parse::<f64>().is_ok()) {
+        Some(pos) if pos > 0 && tokens[pos].eq("0") => pos + 1,
+        _ => 0,
+    }
+}
+
+/// Whether the assigned string is an inline basis set in the Gaussian format, which starts with the shell
+/// like `S 3 1.00` after the optional header, rather than a basis set name or path
+pub fn is_inline_gaussian(contents: &str) -> bool {
+    let tokens: Vec<&str> = contents.split_whitespace().collect();
+    let start = gaussian_header_len(&tokens);
+    tokens.len() >= start + 3 && shell_angular_momentum(tokens[start]).is_some() && tokens[start+1].parse::<usize>().is_ok()
+}
+
+/// Break the inline Gaussian basis set written in one line, like `S 1 1.00 0.5 1.0 ****`, into the lines
+/// of the Gaussian format
+fn gaussian_lines(contents: &str) -> anyhow::Result<String> {
+    let tokens: Vec<&str> = contents.split_whitespace().collect();
+    let header_len = gaussian_header_len(&tokens);
+    let mut lines: Vec<String> = vec![];
+    if header_len > 0 {lines.push(tokens[..header_len].join(" "))};
+    let mut i = header_len;
+    while i < tokens.len() {
+        if tokens[i].starts_with("****") {lines.push(String::from("****")); i += 1; continue}
+        let shell = tokens.get(i..i+3).ok_or(anyhow::anyhow!("Incomplete shell in the inline Gaussian basis set: {}", contents))?;
+        let angular_momentum = shell_angular_momentum(shell[0])
+            .ok_or(anyhow::anyhow!("Unknown shell of {} in the inline Gaussian basis set: {}", shell[0], contents))?;
+        let num_primitive: usize = shell[1].parse()
+            .map_err(|_| anyhow::anyhow!("Unknown number of primitives of {} in the inline Gaussian basis set: {}", shell[1], contents))?;
+        lines.push(shell.join(" "));
+        i += 3;
+        let prim_len = angular_momentum.len() + 1;
+        for _ in 0..num_primitive {
+            let prim = tokens.get(i..i+prim_len).ok_or(anyhow::anyhow!("Missing primitives in the inline Gaussian basis set: {}", contents))?;
+            lines.push(prim.join(" "));
+            i += prim_len;
+        }
+    }
+    Ok(lines.join("\n"))
+}
+
+/// The inline basis set in the Gaussian format for the element of `label`, where the header like `C 0` is optional
+/// and the definition can be written in one line
+pub fn basis_raw_from_gaussian(label: &str, contents: &str) -> anyhow::Result<Basis4ElemRaw> {
+    let elem = element_from_label(label);
+    let contents = if contents.trim().contains('\n') {contents.to_string()} else {gaussian_lines(contents)?};
+    let contents = contents.as_str();
+    let first_line = contents.lines().map(|line| line.trim()).find(|line| line.len() > 0).unwrap_or("");
+    let tokens: Vec<&str> = first_line.split_whitespace().collect();
+    let with_header = tokens.len() >= 2 && tokens.last().unwrap().eq(&"0")
+        && tokens[0].chars().all(|c| c.is_ascii_alphabetic() || c == '-');
+    let contents = if with_header {contents.to_string()} else {format!("{} 0\n{}", &elem, contents)};
+    let mut tmp_basis = parse_gaussian_basis(&contents)?;
+    if let Some(raw) = tmp_basis.remove(&elem) {
+        Ok(raw)
+    } else if tmp_basis.len() == 1 {
+        Ok(tmp_basis.into_values().next().unwrap())
+    } else {
+        Err(anyhow::anyhow!("The inline basis set of {} is not found in the given Gaussian-format definition", label))
+    }
+}
+
+/// Parse the basis set assigned to `label`, where the basis set name is searched in `basis_dir`
+/// (the parent directory of `basis_path`) and then in the basis library
+pub fn parse_basis_source(label: &str, value: &Value, basis_dir: &String, library: &Vec<String>) -> anyhow::Result<BasisSource> {
+    match value {
+        Value::String(tmp_str) => {
+            let tmp_str = tmp_str.trim();
+            if tmp_str.starts_with('{') {
+                let tmp_value: Value = serde_json::from_str(tmp_str)?;
+                Ok(BasisSource::Inline(basis_raw_from_value(&tmp_value)?))
+            } else if tmp_str.contains('\n') || (is_inline_gaussian(tmp_str) && !Path::new(tmp_str).exists()) {
+                Ok(BasisSource::Inline(basis_raw_from_gaussian(label, tmp_str)?))
+            } else {
+                let path = if tmp_str.contains('/') || Path::new(tmp_str).exists() {
+                    tmp_str.to_string()
+                } else {
+                    format!("{}/{}", basis_dir.trim_end_matches('/'), tmp_str)
+                };
+                Ok(BasisSource::Path(resolve_basis_path(&path, library)?))
+            }
+        },
+        Value::Object(_) => Ok(BasisSource::Inline(basis_raw_from_value(value)?)),
+        other => Err(anyhow::anyhow!("Unknown basis set assigned to {}: {}", label, other)),
+    }
+}
+
+/// Parse the basis set assignments given by the assignment string (or a list of them) or the table of
+/// `label = basis set`
+pub fn parse_basis_assignment(value: &Value, basis_path: &String, library: &Vec<String>) -> anyhow::Result<Vec<(String, BasisSource)>> {
+    let basis_dir = match Path::new(basis_path.trim_end_matches('/')).parent().and_then(|x| x.to_str()) {
+        Some(parent) if parent.len() > 0 => parent.to_string(),
+        _ => String::from("."),
+    };
+    let mut assignment = vec![];
+    match value {
+        Value::String(tmp_str) => {
+            for (label, basis) in split_assignment_string(tmp_str)? {
+                let source = parse_basis_source(&label, &Value::String(basis), &basis_dir, library)?;
+                assignment.push((label, source));
+            }
+        },
+        Value::Array(tmp_vec) => {
+            for tmp_value in tmp_vec {
+                assignment.extend(parse_basis_assignment(tmp_value, basis_path, library)?);
+            }
+        },
+        Value::Object(tmp_map) => {
+            for (label, tmp_value) in tmp_map {
+                assignment.push((label.clone(), parse_basis_source(label, tmp_value, &basis_dir, library)?));
+            }
+        },
+        other => {return Err(anyhow::anyhow!("Unknown basis set assignment: {}", other))},
+    }
+    Ok(assignment)
+}
+
+/// The basis set assigned to the atom with the given label and element.
+/// The atom label is matched first (case-sensitively, so that `CA` and `Ca` are different labels), and then the element
+pub fn find_basis_source<'a>(assignment: &'a Vec<(String, BasisSource)>, label: &String, elem: &String) -> Option<&'a (String, BasisSource)> {
+    assignment.iter().rev().find(|(key, _)| key.eq(label))
+        .or_else(|| assignment.iter().rev().find(|(key, _)| formated_element_name(key).eq(&formated_element_name(elem))))
+}
+
+/// The basis sets assigned to the atoms and then the ghost atoms with basis sets.
+/// `None` for the atoms using the global basis set
+pub fn atom_basis_sources<'a>(assignment: &'a Vec<(String, BasisSource)>, geom: &GeomCell) -> Vec<Option<&'a BasisSource>> {
+    let mut sources: Vec<Option<&BasisSource>> = geom.elem.iter().enumerate()
+        .map(|(i_atm, elem)| find_basis_source(assignment, &geom.get_atom_label(i_atm), elem).map(|(_, source)| source))
+        .collect();
+    sources.extend(geom.ghost_bs_elem.iter().map(|elem| find_basis_source(assignment, elem, elem).map(|(_, source)| source)));
+    sources
+}
+
+/// The elements (without duplicates) whose basis sets are taken from the given folder, or from the global
+/// basis set if `path` is `None`
+pub fn elements_of_path(elem_tot: &Vec<String>, sources: &Vec<Option<&BasisSource>>, path: Option<&String>) -> Vec<String> {
+    let mut elem_set: Vec<String> = vec![];
+    elem_tot.iter().zip(sources.iter()).for_each(|(elem, source)| {
+        let matched = match (source, path) {
+            (None, None) => true,
+            (Some(BasisSource::Path(source_path)), Some(path)) => source_path.eq(path),
+            _ => false,
+        };
+        if matched && !elem_set.contains(elem) {elem_set.push(elem.clone())}
+    });
+    elem_set
+}
+
+/// The folders (or files) of the assigned basis sets without duplicates
+pub fn assigned_paths(sources: &Vec<Option<&BasisSource>>) -> Vec<String> {
+    let mut paths: Vec<String> = vec![];
+    sources.iter().for_each(|source| {
+        if let Some(BasisSource::Path(path)) = source {
+            if !paths.contains(path) {paths.push(path.clone())}
+        }
+    });
+    paths
+}
+
+#[test]
+fn test_parse_basis_assignment() {
+    let pairs = split_assignment_string("C1 def2-TZVP, H 6-311G(d,p); O cc-pVDZ").unwrap();
+    assert_eq!(pairs, vec![
+        (String::from("C1"), String::from("def2-TZVP")),
+        (String::from("H"), String::from("6-311G(d,p)")),
+        (String::from("O"), String::from("cc-pVDZ")),
+    ]);
+    assert!(split_assignment_string("C1").is_err());
+    assert_eq!(element_from_label("C1"), String::from("C"));
+    assert_eq!(element_from_label("cl2"), String::from("Cl"));
+    assert_eq!(element_from_label("HA"), String::from("H"));
+    // the uppercase labels read as one letter with a suffix unless the one-letter element does not exist
+    [("CA", "C"), ("HG", "H"), ("NA", "N"), ("CO", "C"), ("ZN1", "Zn"), ("Ca", "Ca"), ("Hg2", "Hg"), ("na", "Na")].iter()
+        .for_each(|(label, elem)| assert_eq!(element_from_label(label), elem.to_string(), "{}", label));
+
+    let value: Value = serde_json::json!({
+        "C1": "S 1 1.00\n 0.5 1.0\n****",
+        "H": {"electron_shells": [{"angular_momentum": [0], "exponents": [1.2], "coefficients": [[1.0]]}]},
+        "O": "cc-pVDZ",
+    });
+    let assignment = parse_basis_assignment(&value, &String::from("/basis/def2-SVP"), &vec![]).unwrap();
+    let c1 = find_basis_source(&assignment, &String::from("C1"), &String::from("C")).unwrap();
+    if let BasisSource::Inline(raw) = &c1.1 {
+        assert_eq!(raw.electron_shells[0].exponents, vec![String::from("0.5")]);
+    } else {panic!("C1 should be given by the inline basis set")};
+    // other carbon atoms use the global basis set
+    assert!(find_basis_source(&assignment, &String::from("C2"), &String::from("C")).is_none());
+    let h = find_basis_source(&assignment, &String::from("H3"), &String::from("H")).unwrap();
+    if let BasisSource::Inline(raw) = &h.1 {
+        assert_eq!(raw.electron_shells[0].coefficients, vec![vec![String::from("1.0")]]);
+    } else {panic!("H should be given by the inline basis set")};
+    let o = find_basis_source(&assignment, &String::from("O"), &String::from("O")).unwrap();
+    assert_eq!(o.1.description(), String::from("/basis/cc-pVDZ"));
+    // the labels are matched case-sensitively
+    assert!(find_basis_source(&assignment, &String::from("c1"), &String::from("C")).is_none());
+
+    // the inline Gaussian basis sets written in one line, with and without the header
+    assert!(is_inline_gaussian("S 1 1.00 0.5 1.0 ****"));
+    assert!(is_inline_gaussian("N 0 S 1 1.00 0.5 1.0 ****"));
+    assert!(!is_inline_gaussian("def2-SVP"));
+    ["S 1 1.00 0.5 1.0 ****", "N 0 S 1 1.00 0.5 1.0 P 1 1.00 0.8 1.0 ****"].iter().for_each(|contents| {
+        let value: Value = serde_json::json!({"N": contents});
+        let assignment = parse_basis_assignment(&value, &String::from("/basis/def2-SVP"), &vec![]).unwrap();
+        if let BasisSource::Inline(raw) = &assignment[0].1 {
+            assert_eq!(raw.electron_shells[0].exponents, vec![String::from("0.5")]);
+        } else {panic!("N should be given by the inline basis set: {}", contents)};
+    });
+}
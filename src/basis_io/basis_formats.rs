@@ -0,0 +1,599 @@
+//! This mod imports the basis sets and the effective core potentials (ECPs) in the formats other than the
+//! BSE json, i.e.
+//!  - Gaussian (`.gbs` and the `gen`/`genecp` blocks): the basis blocks separated by `****`, followed by the ECP blocks;
+//!  - NWChem (`.nw`): the `BASIS ... END` and `ECP ... END` blocks;
+//!  - CP2K (`BASIS_*` or `.cp2k`): the all-electron basis sets only, where the basis set name can be selected by
+//!    `{file}#{name}`, otherwise the first basis set of each element in the file is taken. The GTH/MOLOPT basis sets
+//!    are rejected, since the GTH pseudopotentials they are built for are not supported;
+//!  - BSE json (`.json`): a single file of all elements, like the output of `bse get-basis`, or of one element.
+//!
+//! A basis set file is parsed only once, and the parsed elements are cached until the file is modified.
+//!
+//! All elements in a file are parsed into [`Basis4ElemRaw`], so that they are normalized in the same way as the
+//! BSE json by [`Basis4Elem::from_raw`](super::Basis4Elem::from_raw). The ECPs are given in the semi-local form of
+//! `r^(n-2) exp(-a r^2)` as in BSE, where the local channel (`ul`) takes the angular momentum of `lmax`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use lazy_static::lazy_static;
+use serde_json::Value;
+use crate::constants::SPECIES_INFO;
+use super::{BasCellRaw, Basis4ElemRaw, ECPCellRaw};
+
+lazy_static!{
+    /// The parsed basis set files with their modification time
+    static ref BASIS_FILE_CACHE: Mutex<HashMap<String, (Option<SystemTime>, HashMap<String, Basis4ElemRaw>)>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BasisFormat {
+    Json,
+    Gaussian,
+    NWChem,
+    CP2K,
+}
+
+impl BasisFormat {
+    /// The format of the basis set file by the extension or the CP2K convention of `BASIS_*`
+    pub fn from_path(path: &str) -> Option<BasisFormat> {
+        let file_path = Path::new(path);
+        let extension = file_path.extension().map_or(String::new(), |ext| ext.to_string_lossy().to_lowercase());
+        let file_name = file_path.file_name().map_or(String::new(), |name| name.to_string_lossy().to_uppercase());
+        match extension.as_str() {
+            "json" => Some(BasisFormat::Json),
+            "gbs" | "gen" | "gau" => Some(BasisFormat::Gaussian),
+            "nw" | "nwchem" => Some(BasisFormat::NWChem),
+            "cp2k" => Some(BasisFormat::CP2K),
+            _ => if file_name.starts_with("BASIS_") || file_name.eq("BASIS_SET") {Some(BasisFormat::CP2K)} else {None},
+        }
+    }
+}
+
+/// Split `{file}#{name}` into the file name and the optional basis set name
+pub fn split_basis_file_name(path: &str) -> (String, Option<String>) {
+    match path.rsplit_once('#') {
+        Some((file_name, basis_name)) if basis_name.len() > 0 => (file_name.to_string(), Some(basis_name.to_string())),
+        Some((file_name, _)) => (file_name.to_string(), None),
+        None => (path.to_string(), None),
+    }
+}
+
+/// Check if the given path refers to a basis set file (possibly with `#{name}`) in the supported formats
+pub fn is_basis_file(path: &str) -> bool {
+    let (file_name, _) = split_basis_file_name(path);
+    Path::new(&file_name).is_file() && BasisFormat::from_path(&file_name).is_some()
+}
+
+/// Parse all elements in the basis set file (Gaussian, NWChem, CP2K or BSE json)
+pub fn parse_basis_file(path: &str) -> anyhow::Result<HashMap<String, Basis4ElemRaw>> {
+    let (file_name, basis_name) = split_basis_file_name(path);
+    let contents = fs::read_to_string(&file_name)
+        .map_err(|e| anyhow::anyhow!("Fail to read the basis set file {}: {}", &file_name, e))?;
+    match BasisFormat::from_path(&file_name) {
+        Some(BasisFormat::Gaussian) => parse_gaussian_basis(&contents),
+        Some(BasisFormat::NWChem) => parse_nwchem_basis(&contents),
+        Some(BasisFormat::CP2K) => parse_cp2k_basis(&contents, basis_name.as_deref()),
+        Some(BasisFormat::Json) => parse_json_basis(&contents, &file_name),
+        None => Err(anyhow::anyhow!("Unknown format of the basis set file: {}", &file_name)),
+    }
+}
+
+/// The basis set of the given element in the basis set file, where the file is parsed only once by
+/// [`parse_basis_file`] and then taken from the cache until it is modified
+pub fn basis_in_basis_file(path: &str, elem: &str) -> anyhow::Result<Basis4ElemRaw> {
+    let (file_name, _) = split_basis_file_name(path);
+    let modified = fs::metadata(&file_name).and_then(|x| x.modified()).ok();
+    let mut cache = BASIS_FILE_CACHE.lock().unwrap();
+    let is_cached = matches!(cache.get(path), Some((time, _)) if modified.is_some() && time.eq(&modified));
+    if ! is_cached {
+        cache.insert(path.to_string(), (modified, parse_basis_file(path)?));
+    }
+    cache[path].1.get(elem).cloned()
+        .ok_or(anyhow::anyhow!("The basis set of {} is missing in {}", elem, path))
+}
+
+/// The elements available in the basis set file
+pub fn elements_in_basis_file(path: &str) -> anyhow::Result<Vec<String>> {
+    Ok(parse_basis_file(path)?.into_keys().collect())
+}
+
+/// The element symbol in the standard capitalization, e.g. `AU` -> `Au`
+fn element_symbol(token: &str) -> Option<String> {
+    let token = token.trim_start_matches('-');
+    if token.len() == 0 || token.len() > 3 || !token.chars().all(|c| c.is_ascii_alphabetic()) {return None}
+    let mut symbol = token[..1].to_uppercase();
+    symbol.push_str(&token[1..].to_lowercase());
+    Some(symbol)
+}
+
+/// The angular momentums of the shell label, where `SP` and `L` are the Pople shells
+pub fn shell_angular_momentum(label: &str) -> Option<Vec<i32>> {
+    match label.to_uppercase().as_str() {
+        "SP" | "L" => Some(vec![0,1]),
+        "S" => Some(vec![0]),
+        "P" => Some(vec![1]),
+        "D" => Some(vec![2]),
+        "F" => Some(vec![3]),
+        "G" => Some(vec![4]),
+        "H" => Some(vec![5]),
+        "I" => Some(vec![6]),
+        "K" => Some(vec![7]),
+        _ => None,
+    }
+}
+
+/// Check and convert the Fortran-styled number, e.g. `0.1D+01` -> `0.1E+01`
+fn number_string(token: &str, line: &str) -> anyhow::Result<String> {
+    let tmp_str = token.replace(['D','d'], "E");
+    tmp_str.parse::<f64>().map_err(|_| anyhow::anyhow!("Unknown number of {} in the basis set line: {}", token, line))?;
+    Ok(tmp_str)
+}
+
+fn parse_number<T: std::str::FromStr>(token: &str, line: &str) -> anyhow::Result<T> {
+    token.parse::<T>().map_err(|_| anyhow::anyhow!("Unknown number of {} in the basis set line: {}", token, line))
+}
+
+/// Collect the semi-local ECP channels into [`ECPCellRaw`], where the local channel takes `lmax`
+fn ecp_cells(local: Option<(Vec<i32>, Vec<String>, Vec<String>)>, semi_local: Vec<(i32, Vec<i32>, Vec<String>, Vec<String>)>) -> Vec<ECPCellRaw> {
+    let lmax = semi_local.iter().map(|(l, _, _, _)| *l + 1).max().unwrap_or(0);
+    let to_cell = |l: i32, r_exponents: Vec<i32>, gaussian_exponents: Vec<String>, coefficients: Vec<String>| ECPCellRaw {
+        angular_momentum: vec![l],
+        coefficients: vec![coefficients],
+        ecp_type: Some(String::from("scalar_ecp")),
+        r_exponents,
+        gaussian_exponents,
+    };
+    let mut cells = vec![];
+    if let Some((r_exponents, gaussian_exponents, coefficients)) = local {
+        cells.push(to_cell(lmax, r_exponents, gaussian_exponents, coefficients));
+    }
+    semi_local.into_iter().for_each(|(l, r_exponents, gaussian_exponents, coefficients)| {
+        cells.push(to_cell(l, r_exponents, gaussian_exponents, coefficients));
+    });
+    cells
+}
+
+fn empty_basis4elem() -> Basis4ElemRaw {
+    Basis4ElemRaw {electron_shells: vec![], references: None, ecp_potentials: None, ecp_electrons: None}
+}
+
+/// Parse the basis sets and ECPs in the Gaussian format (`.gbs` or the `gen`/`genecp` input blocks)
+pub fn parse_gaussian_basis(contents: &str) -> anyhow::Result<HashMap<String, Basis4ElemRaw>> {
+    let lines: Vec<&str> = contents.lines()
+        .map(|line| line.split('!').next().unwrap().trim())
+        .filter(|line| line.len() > 0)
+        .collect();
+    let mut basis: HashMap<String, Basis4ElemRaw> = HashMap::new();
+    let mut i_line = 0;
+    while i_line < lines.len() {
+        let line = lines[i_line];
+        if line.starts_with("****") {i_line += 1; continue}
+        // the header of elements, like "C H O 0"
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() < 2 || !tokens.last().unwrap().eq(&"0") {
+            return Err(anyhow::anyhow!("Unknown header of the Gaussian basis set block: {}", line))
+        }
+        let elems = tokens[..tokens.len()-1].iter()
+            .map(|x| element_symbol(x).ok_or(anyhow::anyhow!("Unknown element of {} in the Gaussian basis set block: {}", x, line)))
+            .collect::<anyhow::Result<Vec<String>>>()?;
+        i_line += 1;
+        let next_line = lines.get(i_line).ok_or(anyhow::anyhow!("Missing the Gaussian basis set block for {}", line))?;
+        let next_tokens: Vec<&str> = next_line.split_whitespace().collect();
+        let is_shell = next_tokens.len() >= 3 && shell_angular_momentum(next_tokens[0]).is_some() && next_tokens[2].contains('.');
+        if is_shell || next_line.starts_with("****") {
+            // the basis set block terminated by "****"
+            let mut shells = vec![];
+            while i_line < lines.len() && !lines[i_line].starts_with("****") {
+                let shell_line = lines[i_line];
+                let shell_tokens: Vec<&str> = shell_line.split_whitespace().collect();
+                if shell_tokens.len() < 3 {
+                    return Err(anyhow::anyhow!("Unknown shell in the Gaussian basis set: {}", shell_line))
+                }
+                let angular_momentum = shell_angular_momentum(shell_tokens[0])
+                    .ok_or(anyhow::anyhow!("Unknown shell in the Gaussian basis set: {}", shell_line))?;
+                let num_primitive: usize = parse_number(shell_tokens[1], shell_line)?;
+                let scale: f64 = parse_number(&shell_tokens[2].replace(['D','d'], "E"), shell_line)?;
+                let mut exponents = vec![];
+                let mut coefficients = vec![vec![]; angular_momentum.len()];
+                for i_prim in 0..num_primitive {
+                    let prim_line = lines.get(i_line+1+i_prim)
+                        .ok_or(anyhow::anyhow!("Missing primitives for the Gaussian shell: {}", shell_line))?;
+                    let prim_tokens: Vec<&str> = prim_line.split_whitespace().collect();
+                    if prim_tokens.len() != angular_momentum.len() + 1 {
+                        return Err(anyhow::anyhow!("Unknown primitive in the Gaussian basis set: {}", prim_line))
+                    }
+                    let exponent = number_string(prim_tokens[0], prim_line)?;
+                    exponents.push(if scale == 1.0 {exponent} else {
+                        format!("{:.12E}", exponent.parse::<f64>().unwrap()*scale*scale)
+                    });
+                    for (coeff, token) in coefficients.iter_mut().zip(prim_tokens[1..].iter()) {
+                        coeff.push(number_string(token, prim_line)?);
+                    }
+                }
+                shells.push(BasCellRaw {
+                    function_type: Some(String::from("gto")),
+                    region: None,
+                    angular_momentum,
+                    exponents,
+                    coefficients,
+                });
+                i_line += 1 + num_primitive;
+            }
+            elems.iter().for_each(|elem| {
+                basis.entry(elem.clone()).or_insert(empty_basis4elem()).electron_shells.extend(shells.clone());
+            });
+        } else {
+            // the ECP block: "NAME lmax ncore", followed by lmax+1 channels of "comment, nterm, nterm lines of (n, a, c)"
+            if next_tokens.len() < 3 {
+                return Err(anyhow::anyhow!("Unknown ECP block in the Gaussian format: {}", next_line))
+            }
+            let lmax: i32 = parse_number(next_tokens[1], next_line)?;
+            let ncore: usize = parse_number(next_tokens[2], next_line)?;
+            i_line += 1;
+            let mut local = None;
+            let mut semi_local = vec![];
+            for i_channel in 0..lmax+1 {
+                // skip the comment line of the channel, like "f potential" or "s-f potential"
+                i_line += 1;
+                let num_line = lines.get(i_line).ok_or(anyhow::anyhow!("Missing ECP channels for {}", line))?;
+                let num_term: usize = parse_number(num_line.split_whitespace().next().unwrap_or(""), num_line)?;
+                let (mut r_exponents, mut gaussian_exponents, mut coefficients) = (vec![], vec![], vec![]);
+                for i_term in 0..num_term {
+                    let term_line = lines.get(i_line+1+i_term).ok_or(anyhow::anyhow!("Missing ECP terms for {}", line))?;
+                    let term_tokens: Vec<&str> = term_line.split_whitespace().collect();
+                    if term_tokens.len() != 3 {
+                        return Err(anyhow::anyhow!("Unknown ECP term in the Gaussian format: {}", term_line))
+                    }
+                    r_exponents.push(parse_number(term_tokens[0], term_line)?);
+                    gaussian_exponents.push(number_string(term_tokens[1], term_line)?);
+                    coefficients.push(number_string(term_tokens[2], term_line)?);
+                }
+                i_line += 1 + num_term;
+                if i_channel == 0 {
+                    local = Some((r_exponents, gaussian_exponents, coefficients));
+                } else {
+                    semi_local.push((i_channel-1, r_exponents, gaussian_exponents, coefficients));
+                }
+            }
+            let cells = ecp_cells(local, semi_local);
+            elems.iter().for_each(|elem| {
+                let tmp_basis = basis.entry(elem.clone()).or_insert(empty_basis4elem());
+                tmp_basis.ecp_electrons = Some(ncore);
+                tmp_basis.ecp_potentials = Some(cells.clone());
+            });
+        }
+    }
+    Ok(basis)
+}
+
+/// Parse the basis sets and ECPs in the NWChem format
+pub fn parse_nwchem_basis(contents: &str) -> anyhow::Result<HashMap<String, Basis4ElemRaw>> {
+    let mut basis: HashMap<String, Basis4ElemRaw> = HashMap::new();
+    // the ECPs of each element: (local, semi-local channels)
+    let mut ecps: HashMap<String, (Option<(Vec<i32>, Vec<String>, Vec<String>)>, Vec<(i32, Vec<i32>, Vec<String>, Vec<String>)>)> = HashMap::new();
+    let mut block = String::new();
+    // the current shell or ECP channel: (element, label)
+    let mut current: Option<(String, String)> = None;
+    for raw_line in contents.lines() {
+        let line = raw_line.split('#').next().unwrap().trim();
+        if line.len() == 0 {continue}
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let keyword = tokens[0].to_lowercase();
+        if block.len() == 0 {
+            if keyword.eq("basis") || keyword.eq("ecp") {block = keyword}
+            continue
+        }
+        if keyword.eq("end") {
+            block = String::new();
+            current = None;
+            continue
+        }
+        let is_number = tokens[0].replace(['D','d'], "E").parse::<f64>().is_ok();
+        if !is_number {
+            let elem = element_symbol(tokens[0])
+                .ok_or(anyhow::anyhow!("Unknown element of {} in the NWChem basis set: {}", tokens[0], line))?;
+            if tokens.len() < 2 {
+                return Err(anyhow::anyhow!("Unknown line in the NWChem basis set: {}", line))
+            }
+            let label = tokens[1].to_lowercase();
+            if block.eq("ecp") && label.eq("nelec") {
+                let ncore: usize = parse_number(tokens.get(2).unwrap_or(&""), line)?;
+                basis.entry(elem.clone()).or_insert(empty_basis4elem()).ecp_electrons = Some(ncore);
+                current = None;
+            } else if block.eq("basis") {
+                let angular_momentum = shell_angular_momentum(&label)
+                    .ok_or(anyhow::anyhow!("Unknown shell in the NWChem basis set: {}", line))?;
+                basis.entry(elem.clone()).or_insert(empty_basis4elem()).electron_shells.push(BasCellRaw {
+                    function_type: Some(String::from("gto")),
+                    region: None,
+                    angular_momentum,
+                    exponents: vec![],
+                    coefficients: vec![],
+                });
+                current = Some((elem, label));
+            } else {
+                let tmp_ecp = ecps.entry(elem.clone()).or_insert((None, vec![]));
+                if label.eq("ul") {
+                    tmp_ecp.0 = Some((vec![], vec![], vec![]));
+                } else {
+                    let l = shell_angular_momentum(&label)
+                        .filter(|l| l.len() == 1)
+                        .ok_or(anyhow::anyhow!("Unknown ECP channel in the NWChem format: {}", line))?[0];
+                    tmp_ecp.1.push((l, vec![], vec![], vec![]));
+                }
+                current = Some((elem, label));
+            }
+            continue
+        }
+        // the numbers of the current shell or ECP channel
+        let (elem, label) = current.as_ref().ok_or(anyhow::anyhow!("Numbers without the shell in the NWChem basis set: {}", line))?;
+        if block.eq("basis") {
+            let shell = basis.get_mut(elem).unwrap().electron_shells.last_mut().unwrap();
+            if shell.coefficients.len() == 0 {
+                shell.coefficients = vec![vec![]; tokens.len()-1];
+            }
+            if tokens.len() < 2 || shell.coefficients.len() != tokens.len()-1 {
+                return Err(anyhow::anyhow!("Inconsistent contractions in the NWChem basis set: {}", line))
+            }
+            shell.exponents.push(number_string(tokens[0], line)?);
+            for (coeff, token) in shell.coefficients.iter_mut().zip(tokens[1..].iter()) {
+                coeff.push(number_string(token, line)?);
+            }
+        } else {
+            if tokens.len() != 3 {
+                return Err(anyhow::anyhow!("Unknown ECP term in the NWChem format: {}", line))
+            }
+            let tmp_ecp = ecps.get_mut(elem).unwrap();
+            let (r_exponents, gaussian_exponents, coefficients) = if label.eq("ul") {
+                let tmp_local = tmp_ecp.0.as_mut().unwrap();
+                (&mut tmp_local.0, &mut tmp_local.1, &mut tmp_local.2)
+            } else {
+                let tmp_channel = tmp_ecp.1.last_mut().unwrap();
+                (&mut tmp_channel.1, &mut tmp_channel.2, &mut tmp_channel.3)
+            };
+            r_exponents.push(parse_number(tokens[0], line)?);
+            gaussian_exponents.push(number_string(tokens[1], line)?);
+            coefficients.push(number_string(tokens[2], line)?);
+        }
+    }
+    for (elem, (local, semi_local)) in ecps.into_iter() {
+        let tmp_basis = basis.entry(elem.clone()).or_insert(empty_basis4elem());
+        if tmp_basis.ecp_electrons.is_none() {
+            return Err(anyhow::anyhow!("The number of ECP electrons (nelec) is missing for {}", &elem))
+        }
+        tmp_basis.ecp_potentials = Some(ecp_cells(local, semi_local));
+    }
+    Ok(basis)
+}
+
+/// Parse the basis sets in the CP2K format. If `basis_name` is not given, the first basis set of each element is taken
+pub fn parse_cp2k_basis(contents: &str, basis_name: Option<&str>) -> anyhow::Result<HashMap<String, Basis4ElemRaw>> {
+    let lines: Vec<&str> = contents.lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| line.len() > 0)
+        .collect();
+    let target = basis_name.map(|name| name.to_lowercase());
+    let mut basis: HashMap<String, Basis4ElemRaw> = HashMap::new();
+    let mut i_line = 0;
+    while i_line < lines.len() {
+        let header = lines[i_line];
+        let header_tokens: Vec<&str> = header.split_whitespace().collect();
+        if header_tokens.len() < 2 {
+            return Err(anyhow::anyhow!("Unknown header of the CP2K basis set: {}", header))
+        }
+        let elem = element_symbol(header_tokens[0])
+            .ok_or(anyhow::anyhow!("Unknown element of {} in the CP2K basis set: {}", header_tokens[0], header))?;
+        let is_selected = match &target {
+            Some(name) => header_tokens[1..].iter().any(|x| x.to_lowercase().eq(name)),
+            None => true,
+        } && !basis.contains_key(&elem);
+        if is_selected && header_tokens[1..].iter().any(|x| {let x = x.to_uppercase(); x.contains("GTH") || x.contains("MOLOPT")}) {
+            return Err(anyhow::anyhow!("The GTH/MOLOPT basis set of {} is built for the GTH pseudopotentials, which are not supported: {}", &elem, header))
+        }
+        let num_line = lines.get(i_line+1).ok_or(anyhow::anyhow!("Missing the number of sets for the CP2K basis set: {}", header))?;
+        let num_set: usize = parse_number(num_line, num_line)?;
+        i_line += 2;
+        let mut shells = vec![];
+        for _ in 0..num_set {
+            let set_line = lines.get(i_line).ok_or(anyhow::anyhow!("Missing sets for the CP2K basis set: {}", header))?;
+            let set_tokens = set_line.split_whitespace().map(|x| parse_number::<i32>(x, set_line)).collect::<anyhow::Result<Vec<i32>>>()?;
+            if set_tokens.len() < 5 || set_tokens.len() as i32 != 4 + set_tokens[2] - set_tokens[1] + 1 {
+                return Err(anyhow::anyhow!("Unknown set in the CP2K basis set: {}", set_line))
+            }
+            let (lmin, lmax, num_exp) = (set_tokens[1], set_tokens[2], set_tokens[3] as usize);
+            let num_shells = &set_tokens[4..];
+            let num_column: i32 = num_shells.iter().sum();
+            let mut exponents = vec![];
+            let mut columns = vec![vec![]; num_column as usize];
+            for i_exp in 0..num_exp {
+                let prim_line = lines.get(i_line+1+i_exp).ok_or(anyhow::anyhow!("Missing primitives for the CP2K basis set: {}", header))?;
+                let prim_tokens: Vec<&str> = prim_line.split_whitespace().collect();
+                if prim_tokens.len() != num_column as usize + 1 {
+                    return Err(anyhow::anyhow!("Unknown primitive in the CP2K basis set: {}", prim_line))
+                }
+                exponents.push(number_string(prim_tokens[0], prim_line)?);
+                for (column, token) in columns.iter_mut().zip(prim_tokens[1..].iter()) {
+                    column.push(number_string(token, prim_line)?);
+                }
+            }
+            let mut i_column = 0;
+            for (l, num_shell) in (lmin..lmax+1).zip(num_shells.iter()) {
+                let num_shell = *num_shell as usize;
+                if num_shell > 0 {
+                    shells.push(BasCellRaw {
+                        function_type: Some(String::from("gto")),
+                        region: None,
+                        angular_momentum: vec![l],
+                        exponents: exponents.clone(),
+                        coefficients: columns[i_column..i_column+num_shell].to_vec(),
+                    });
+                }
+                i_column += num_shell;
+            }
+            i_line += 1 + num_exp;
+        }
+        if is_selected {
+            let mut tmp_basis = empty_basis4elem();
+            tmp_basis.electron_shells = shells;
+            basis.insert(elem, tmp_basis);
+        }
+    }
+    if let (Some(name), true) = (basis_name, basis.len() == 0) {
+        return Err(anyhow::anyhow!("The basis set {} is not found in the CP2K basis set file", name))
+    }
+    Ok(basis)
+}
+
+/// The element of the atomic number in the BSE json
+fn element_of_atomic_number(number: &str) -> Option<String> {
+    let charge = number.parse::<f64>().ok()?;
+    SPECIES_INFO.iter().find(|(_, (_, c))| *c == charge).map(|(name, _)| name.to_string())
+}
+
+/// Parse the basis sets in the BSE json, which is either the file of all elements like
+/// `{"elements": {"1": {"electron_shells": [...]}}}`, or the file of one element named by `{element}.json`
+pub fn parse_json_basis(contents: &str, file_name: &str) -> anyhow::Result<HashMap<String, Basis4ElemRaw>> {
+    let raw: Value = serde_json::from_str(contents)
+        .map_err(|e| anyhow::anyhow!("Fail to parse the json basis set file {}: {}", file_name, e))?;
+    let mut basis: HashMap<String, Basis4ElemRaw> = HashMap::new();
+    if let Some(elements) = raw["elements"].as_object() {
+        for (number, value) in elements.iter() {
+            let elem = element_of_atomic_number(number)
+                .ok_or(anyhow::anyhow!("Unknown element of {} in the json basis set file {}", number, file_name))?;
+            basis.insert(elem, serde_json::from_value(value.clone())?);
+        }
+    } else if ! raw["electron_shells"].is_null() {
+        let stem = Path::new(file_name).file_stem().map_or(String::new(), |x| x.to_string_lossy().to_string());
+        let elem = element_symbol(&stem).filter(|x| SPECIES_INFO.contains_key(x.as_str()))
+            .ok_or(anyhow::anyhow!("The element of the json basis set file {} should be given by the file name, like H.json", file_name))?;
+        basis.insert(elem, serde_json::from_value(raw)?);
+    } else {
+        return Err(anyhow::anyhow!("The basis set file is not in the right format: {}", file_name))
+    }
+    Ok(basis)
+}
+
+#[test]
+fn test_parse_basis_formats() {
+    let gaussian = "\
+! STO-3G and def2 ECP for Au
+****
+H     0
+S    3   1.00
+      0.3425250914D+01       0.1543289673D+00
+      0.6239137298D+00       0.5353281423D+00
+      0.1688554040D+00       0.4446345422D+00
+****
+C     0
+S    1   1.00
+      71.6168370              1.0000000
+SP   1   1.00
+      2.9412494              -0.09996723             0.15591627
+****
+Au     0
+S    1   1.00
+      0.5              1.0
+****
+
+AU     0
+AU-ECP     2     60
+d potential
+  1
+2      4.78982000             30.49008890
+s-d potential
+  2
+2     13.20510000            426.84667920
+2      4.78982000            -30.49008890
+p-d potential
+  1
+2     10.45202000            261.19958038
+";
+    let nwchem = "\
+BASIS \"ao basis\" SPHERICAL PRINT
+#BASIS SET: (3s) -> [1s]
+H    S
+      0.3425250914E+01       0.1543289673E+00
+      0.6239137298E+00       0.5353281423E+00
+      0.1688554040E+00       0.4446345422E+00
+C    S
+      71.6168370              1.0000000
+C    SP
+      2.9412494              -0.09996723             0.15591627
+Au    S
+      0.5              1.0
+END
+ECP
+Au nelec 60
+Au ul
+2      4.78982000             30.49008890
+Au S
+2     13.20510000            426.84667920
+2      4.78982000            -30.49008890
+Au P
+2     10.45202000            261.19958038
+END
+";
+    let from_gaussian = parse_gaussian_basis(gaussian).unwrap();
+    let from_nwchem = parse_nwchem_basis(nwchem).unwrap();
+    for elem in ["H", "C", "Au"] {
+        let (g, n) = (&from_gaussian[elem], &from_nwchem[elem]);
+        assert_eq!(g.electron_shells.len(), n.electron_shells.len());
+        g.electron_shells.iter().zip(n.electron_shells.iter()).for_each(|(gs, ns)| {
+            assert_eq!(gs.angular_momentum, ns.angular_momentum);
+            gs.exponents.iter().zip(ns.exponents.iter()).for_each(|(ge, ne)| {
+                assert!((ge.parse::<f64>().unwrap() - ne.parse::<f64>().unwrap()).abs() < 1.0e-10);
+            });
+            assert_eq!(gs.coefficients.len(), ns.coefficients.len());
+        });
+        assert_eq!(g.ecp_electrons, n.ecp_electrons);
+    }
+    assert_eq!(from_gaussian["C"].electron_shells[1].angular_momentum, vec![0,1]);
+    let (g_ecp, n_ecp) = (from_gaussian["Au"].ecp_potentials.as_ref().unwrap(), from_nwchem["Au"].ecp_potentials.as_ref().unwrap());
+    assert_eq!(g_ecp.len(), 3);
+    g_ecp.iter().zip(n_ecp.iter()).for_each(|(g, n)| {
+        assert_eq!(g.angular_momentum, n.angular_momentum);
+        assert_eq!(g.r_exponents, n.r_exponents);
+        assert_eq!(g.gaussian_exponents, n.gaussian_exponents);
+        assert_eq!(g.coefficients, n.coefficients);
+    });
+    // the local channel takes lmax
+    assert_eq!(g_ecp[0].angular_momentum, vec![2]);
+    assert_eq!(g_ecp[1].coefficients[0].len(), 2);
+
+    let cp2k = "\
+# two basis sets of H
+H  SZV-ALL
+ 1
+ 2 0 0 2 1
+  7.0 0.1
+  1.0 0.9
+H  DZVP-ALL DZVP-ALL-H
+ 1
+ 2 0 1 3 2 1
+  11.478 0.0249 -0.0125 0.0247
+  2.0 0.5 0.3 0.6
+  0.5 0.7 1.0 0.4
+";
+    let first = parse_cp2k_basis(cp2k, None).unwrap();
+    assert_eq!(first["H"].electron_shells.len(), 1);
+    let dzvp = parse_cp2k_basis(cp2k, Some("DZVP-ALL")).unwrap();
+    let shells = &dzvp["H"].electron_shells;
+    assert_eq!(shells.len(), 2);
+    assert_eq!(shells[0].coefficients.len(), 2);
+    assert_eq!(shells[1].angular_momentum, vec![1]);
+    assert_eq!(shells[1].coefficients[0], vec!["0.0247", "0.6", "0.4"]);
+    assert!(parse_cp2k_basis(cp2k, Some("TZV2P-ALL")).is_err());
+    // the GTH/MOLOPT basis sets are rejected
+    let molopt = cp2k.replace("DZVP-ALL DZVP-ALL-H", "DZVP-MOLOPT-GTH DZVP-MOLOPT-GTH-q1");
+    assert!(parse_cp2k_basis(&molopt, Some("DZVP-MOLOPT-GTH")).is_err());
+    assert!(parse_cp2k_basis(&molopt, Some("SZV-ALL")).is_ok());
+
+    let json = "{\"elements\": {\"1\": {\"electron_shells\": [{\"function_type\": \"gto\", \"region\": \"valence\",
+        \"angular_momentum\": [0], \"exponents\": [\"0.5\"], \"coefficients\": [[\"1.0\"]]}]}}}";
+    let from_json = parse_json_basis(json, "sto.json").unwrap();
+    assert_eq!(from_json["H"].electron_shells[0].exponents, vec!["0.5"]);
+    assert!(parse_json_basis("{}", "sto.json").is_err());
+}
@@ -0,0 +1,156 @@
+//! This mod resolves the folders of the (auxiliary) basis sets from a local basis library, i.e. a list of
+//! directories like `basis-set-pool`, each of which contains one folder of `{element}.json` files per basis set.
+//!
+//! The folder names are compared by the normalized names, which are case-insensitive and ignore the hyphens,
+//! while the parentheses, commas and underscores are kept as one separator, so that `6-311++G(3df,3pd)` is found
+//! in the folder of `6-311++G_3df_3pd`, but `def2-SV(P)` is never confused with `def2-SVP`. The lookup fails
+//! if several folders share the normalized name without an exact match. In the offline mode, the missing
+//! elements are reported before the run without touching the network, while a similar legal name is suggested
+//! by [`basis_fuzzy_matcher`].
+
+use std::fs::read_dir;
+use std::path::Path;
+use regex::Regex;
+use super::basis_formats::{elements_in_basis_file, is_basis_file};
+use super::basis_list::basis_fuzzy_matcher;
+
+/// The normalized basis set name for the comparison: lowercase letters and digits, together with `+` and `*`,
+/// where the groups of `(`, `)`, `,` and `_` are replaced by a single `_`
+pub fn normalized_basis_name(name: &str) -> String {
+    let mut normalized = String::new();
+    name.chars().for_each(|c| {
+        if c.is_ascii_alphanumeric() || c == '+' || c == '*' {
+            normalized.push(c.to_ascii_lowercase());
+        } else if "(),_".contains(c) && ! normalized.ends_with('_') {
+            normalized.push('_');
+        }
+    });
+    normalized.trim_matches('_').to_string()
+}
+
+/// The basis set name given by the last component of the path
+pub fn basis_name_from_path(path: &String) -> String {
+    let re = Regex::new(r"/?(?P<basis>[^/]*)/?$").unwrap();
+    let cap = re.captures(path).unwrap();
+    cap.name("basis").unwrap().as_str().to_string()
+}
+
+/// Search the folder of the given basis set in the library directories.
+/// The directories are searched in order, and the first matched folder is returned.
+/// An error is returned if several folders in one directory match the normalized name, but none of them exactly
+pub fn search_basis_library(basis_name: &String, library: &Vec<String>) -> anyhow::Result<Option<String>> {
+    let target = normalized_basis_name(basis_name);
+    if target.len() == 0 {return Ok(None)}
+    for lib_dir in library.iter() {
+        let entries = if let Ok(entries) = read_dir(lib_dir) {entries} else {continue};
+        let mut matched: Vec<String> = entries.filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().to_str().map(|x| x.to_string()))
+            .filter(|name| normalized_basis_name(name).eq(&target))
+            .collect();
+        // prefer the exact match, if there are several folders with the same normalized name
+        matched.sort_by_key(|name| !name.eq(basis_name));
+        if matched.len() > 1 && ! matched[0].eq(basis_name) {
+            return Err(anyhow::anyhow!("The basis set {} is ambiguous in the library directory {}, which matches the folders: {:?}",
+                basis_name, lib_dir, matched))
+        }
+        if let Some(name) = matched.first() {
+            return Ok(Some(format!("{}/{}", lib_dir.trim_end_matches('/'), name)))
+        }
+    }
+    Ok(None)
+}
+
+/// Resolve the folder of the basis set given by `path`:
+///  1) `path` itself, if it is an existing folder or a basis set file;
+///  2) the folder in the parent directory of `path`, and then in the library directories, with the same normalized name.
+/// Otherwise, `path` is returned untouched
+pub fn resolve_basis_path(path: &String, library: &Vec<String>) -> anyhow::Result<String> {
+    if Path::new(path).is_dir() || is_basis_file(path) {return Ok(path.clone())}
+    let basis_name = basis_name_from_path(path);
+    let mut search_dirs = vec![];
+    if let Some(parent) = Path::new(path.trim_end_matches('/')).parent() {
+        let parent = parent.to_str().unwrap_or("");
+        search_dirs.push(if parent.len() == 0 {String::from(".")} else {parent.to_string()});
+    }
+    search_dirs.extend(library.iter().cloned());
+    Ok(search_basis_library(&basis_name, &search_dirs)?.unwrap_or(path.clone()))
+}
+
+/// The default auxiliary basis set from the library, if `auxbas_path` is not given: the folder of
+/// `{basis}-JKFIT` or `{basis}-RIFIT` for the basis set given by `basis_path`
+pub fn default_auxbas_path(basis_path: &String, library: &Vec<String>) -> anyhow::Result<Option<String>> {
+    let basis_name = basis_name_from_path(basis_path);
+    for suffix in ["JKFIT", "RIFIT"] {
+        if let Some(path) = search_basis_library(&format!("{}-{}", basis_name, suffix), library)? {
+            return Ok(Some(path))
+        }
+    }
+    Ok(None)
+}
+
+/// The elements with the basis sets (`{element}.json`) available in the given folder or basis set file.
+/// Unlike [`local_element_checker`](super::bse_downloader::local_element_checker), the folder is never created
+pub fn available_elements(path: &String) -> anyhow::Result<Vec<String>> {
+    if is_basis_file(path) {return elements_in_basis_file(path)}
+    let entries = if let Ok(entries) = read_dir(path) {entries} else {return Ok(vec![])};
+    Ok(entries.filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str().map(|x| x.to_string()))
+        .filter_map(|name| name.strip_suffix(".json").map(|x| x.to_string()))
+        .collect())
+}
+
+/// The error message for the elements missing in the folder of the (auxiliary) basis set
+pub fn missing_elements_message(basis_kind: &str, path: &String, missing_elem: &Vec<String>, library: &Vec<String>) -> String {
+    let basis_name = basis_name_from_path(path);
+    let expected_files: Vec<String> = missing_elem.iter().map(|elem| format!("{}/{}.json", path, elem)).collect();
+    let mut message = if is_basis_file(path) {
+        format!("The {} set file {} ({}) does not cover the elements: {:?}", basis_kind, &basis_name, path, missing_elem)
+    } else if Path::new(path).is_dir() {
+        format!("The {} set {} in ({}) does not cover the elements: {:?}. The missing files are expected at: {:?}",
+            basis_kind, &basis_name, path, missing_elem, expected_files)
+    } else {
+        format!("The folder of the {} set {} is not found: ({}), which is required by the elements: {:?}. The missing files are expected at: {:?}",
+            basis_kind, &basis_name, path, missing_elem, expected_files)
+    };
+    if library.len() > 0 {
+        message = format!("{}\nThe basis library directories searched: {:?}", message, library);
+    }
+    if let Some(matched) = basis_fuzzy_matcher(&basis_name) {
+        if ! normalized_basis_name(&matched).eq(&normalized_basis_name(&basis_name)) {
+            message = format!("{}\n{} may not be a valid basis set name, similar name is {}", message, &basis_name, &matched);
+        }
+    }
+    format!("{}\nThe basis sets are not downloaded from BasisSetExchange in the offline mode (basis_offline = true)", message)
+}
+
+#[test]
+fn test_resolve_basis_path() {
+    assert_eq!(normalized_basis_name("6-311++G(3df,3pd)"), normalized_basis_name("6-311++G_3df_3pd"));
+    assert_ne!(normalized_basis_name("6-31G*"), normalized_basis_name("6-31G"));
+    assert_ne!(normalized_basis_name("def2-SV(P)"), normalized_basis_name("def2-SVP"));
+    assert_ne!(normalized_basis_name("def2-SV(P)-JKFIT"), normalized_basis_name("def2-SVP-JKFIT"));
+
+    let lib_dir = std::env::temp_dir().join(format!("rest_basis_library_{}", std::process::id()));
+    std::fs::create_dir_all(lib_dir.join("def2-TZVP")).unwrap();
+    std::fs::write(lib_dir.join("def2-TZVP").join("H.json"), "{}").unwrap();
+    let library = vec![lib_dir.to_str().unwrap().to_string()];
+
+    let resolved = resolve_basis_path(&String::from("/not/existing/def2-tzvp"), &library).unwrap();
+    assert_eq!(resolved, format!("{}/def2-TZVP", &library[0]));
+    assert_eq!(available_elements(&resolved).unwrap(), vec![String::from("H")]);
+    // the missing folder is returned untouched and never created
+    let missing = String::from("/not/existing/def2-qzvpx");
+    assert_eq!(resolve_basis_path(&missing, &library).unwrap(), missing);
+    assert_eq!(available_elements(&missing).unwrap().len(), 0);
+    assert!(! Path::new(&missing).exists());
+    // the default auxiliary basis set
+    std::fs::create_dir_all(lib_dir.join("def2-TZVP-RIFIT")).unwrap();
+    assert_eq!(default_auxbas_path(&resolved, &library).unwrap(), Some(format!("{}/def2-TZVP-RIFIT", &library[0])));
+    // the ambiguous folders without the exact match
+    std::fs::create_dir_all(lib_dir.join("def2-sv_p")).unwrap();
+    std::fs::create_dir_all(lib_dir.join("DEF2-SV(P)")).unwrap();
+    assert!(resolve_basis_path(&String::from("/not/existing/def2-SV(P)"), &library).is_err());
+
+    std::fs::remove_dir_all(&lib_dir).unwrap();
+}
@@ -0,0 +1,262 @@
+//! This mod modifies the (auxiliary) basis sets after loading, so that the basis-set files in `basis-set-pool`
+//! are never edited by hand:
+//!  - `decontract`: decontract all shells, or the shells of the given angular momentums, into the primitive GTOs
+//!    with the unique exponents;
+//!  - `diffuse` and `tight`: add the even-tempered diffuse (tight) functions per angular momentum, which are
+//!    generated by [`etb_gen_primitive`] from the most diffuse (tight) exponent of the shells;
+//!  - `max_l`: remove the shells above the given angular momentum.
+//!
+//! The modifiers are specified by the keywords with the prefix of `basis_` or `auxbas_` in the `[ctrl]` block,
+//! for example, `basis_decontract = "sp"`, `basis_diffuse = {s = 1, p = 1}`, `basis_etb_beta = 2.5` and
+//! `auxbas_max_l = "f"`. The shells are removed first, then decontracted, and finally augmented.
+
+use rest_libcint::CintType;
+use serde::{Deserialize,Serialize};
+use serde_json::Value;
+use super::{BasCell, Basis4Elem};
+use super::etb::etb_gen_primitive;
+
+/// The ratio between the adjacent exponents of the added functions, if the shells of the given angular
+/// momentum have only one exponent
+const DEFAULT_ETB_BETA: f64 = 2.5;
+const SHELL_LABELS: [char; 8] = ['s','p','d','f','g','h','i','k'];
+
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct BasisModifier {
+    pub decontract: bool,
+    /// the angular momentums of the shells to be decontracted. Empty for all shells
+    pub decontract_shells: Vec<i32>,
+    /// the numbers of the diffuse functions added to the given angular momentum, or to all angular momentums for `None`
+    pub diffuse: Vec<(Option<i32>, usize)>,
+    /// the numbers of the tight functions added to the given angular momentum, or to all angular momentums for `None`
+    pub tight: Vec<(Option<i32>, usize)>,
+    /// the ratio between the adjacent exponents of the added functions.
+    /// `None` to extrapolate the two most diffuse (tight) exponents of the shells
+    pub etb_beta: Option<f64>,
+    /// remove the shells with the angular momentum larger than `max_l`
+    pub max_l: Option<i32>,
+}
+
+/// The angular momentum given by the shell label (`s`, `p`, `d`, ...) or the number
+pub fn angular_momentum_from_str(token: &str) -> anyhow::Result<i32> {
+    let token = token.trim().to_lowercase();
+    if let Ok(l) = token.parse::<i32>() {
+        if l >= 0 {return Ok(l)}
+    }
+    let mut chars = token.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => SHELL_LABELS.iter().position(|x| *x == c).map(|l| l as i32)
+            .ok_or(anyhow::anyhow!("Unknown angular momentum: {}", token)),
+        _ => Err(anyhow::anyhow!("Unknown angular momentum: {}", token)),
+    }
+}
+
+/// The angular momentums given by a string like `spd` or `s,p,d`, a number, or a list of them
+fn parse_angular_momentums(value: &Value) -> anyhow::Result<Vec<i32>> {
+    match value {
+        Value::Number(tmp_num) => Ok(vec![angular_momentum_from_str(&tmp_num.to_string())?]),
+        Value::String(tmp_str) => {
+            if tmp_str.chars().all(|c| c.is_ascii_alphabetic()) {
+                tmp_str.chars().map(|c| angular_momentum_from_str(&c.to_string())).collect()
+            } else {
+                tmp_str.split(|c: char| c.is_whitespace() || c == ',').filter(|x| x.len() > 0)
+                    .map(|x| angular_momentum_from_str(x)).collect()
+            }
+        },
+        Value::Array(tmp_vec) => {
+            let mut tmp_ls = vec![];
+            for tmp_value in tmp_vec {tmp_ls.extend(parse_angular_momentums(tmp_value)?)};
+            Ok(tmp_ls)
+        },
+        other => Err(anyhow::anyhow!("Unknown angular momentums: {}", other)),
+    }
+}
+
+/// The numbers of the added functions: a number for all angular momentums, or a table like `{s = 1, p = 2}`
+fn parse_added_functions(value: &Value) -> anyhow::Result<Vec<(Option<i32>, usize)>> {
+    let to_usize = |x: &Value| x.as_u64().map(|n| n as usize).or(x.as_str().and_then(|n| n.parse().ok()))
+        .ok_or(anyhow::anyhow!("Unknown number of the added functions: {}", x));
+    match value {
+        Value::Object(tmp_map) => {
+            tmp_map.iter().map(|(l, n)| Ok((Some(angular_momentum_from_str(l)?), to_usize(n)?))).collect()
+        },
+        Value::Null => Ok(vec![]),
+        other => Ok(vec![(None, to_usize(other)?)]),
+    }
+}
+
+/// The unique exponents in the descending order
+fn unique_exponents(exponents: impl Iterator<Item = f64>) -> Vec<f64> {
+    let mut tmp_exps: Vec<f64> = exponents.collect();
+    tmp_exps.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    tmp_exps.dedup_by(|a, b| (*a - *b).abs() <= 1.0e-8*b.abs());
+    tmp_exps
+}
+
+impl BasisModifier {
+    pub fn new() -> BasisModifier {
+        BasisModifier {
+            decontract: false,
+            decontract_shells: vec![],
+            diffuse: vec![],
+            tight: vec![],
+            etb_beta: None,
+            max_l: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.decontract || self.diffuse.len() > 0 || self.tight.len() > 0 || self.max_l.is_some()
+    }
+
+    /// Parse the modifiers from the keywords of `{prefix}_decontract`, `{prefix}_diffuse`, `{prefix}_tight`,
+    /// `{prefix}_etb_beta` and `{prefix}_max_l` in the `[ctrl]` block
+    pub fn parse_from_ctrl(tmp_ctrl: &serde_json::Map<String, Value>, prefix: &str) -> anyhow::Result<BasisModifier> {
+        let mut modifier = BasisModifier::new();
+        let get_value = |key: &str| tmp_ctrl.get(&format!("{}_{}", prefix, key)).unwrap_or(&Value::Null);
+        match get_value("decontract") {
+            Value::Bool(tmp_bool) => {modifier.decontract = *tmp_bool},
+            Value::Null => {},
+            Value::String(tmp_str) if tmp_str.to_lowercase().eq("all") || tmp_str.to_lowercase().eq("true") => {modifier.decontract = true},
+            Value::String(tmp_str) if tmp_str.to_lowercase().eq("false") => {},
+            other => {
+                modifier.decontract = true;
+                modifier.decontract_shells = parse_angular_momentums(other)?;
+            },
+        };
+        modifier.diffuse = parse_added_functions(get_value("diffuse"))?;
+        modifier.tight = parse_added_functions(get_value("tight"))?;
+        modifier.etb_beta = match get_value("etb_beta") {
+            Value::String(tmp_str) => Some(tmp_str.trim().parse::<f64>()
+                .map_err(|_| anyhow::anyhow!("Unknown {}_etb_beta: {}", prefix, tmp_str))?),
+            Value::Number(tmp_num) => tmp_num.as_f64(),
+            Value::Null => None,
+            other => anyhow::bail!("Unknown {}_etb_beta: {}", prefix, other),
+        };
+        if let Some(beta) = modifier.etb_beta {
+            if beta <= 1.0 {anyhow::bail!("{}_etb_beta should be larger than 1.0, but {} is given", prefix, beta)}
+        }
+        modifier.max_l = match get_value("max_l") {
+            Value::Null => None,
+            other => parse_angular_momentums(other)?.into_iter().max(),
+        };
+        Ok(modifier)
+    }
+
+    /// Modify the shells of a given element, returning the kept shells and the new primitive shells,
+    /// where the latter are not yet normalized. The warnings are printed for `print_level > 0`
+    pub fn modify_shells(&self, shells: Vec<BasCell>, print_level: usize) -> (Vec<BasCell>, Vec<BasCell>) {
+        let mut kept_shells: Vec<BasCell> = shells.into_iter()
+            .filter(|shell| self.max_l.map_or(true, |max_l| shell.angular_momentum[0] <= max_l))
+            .collect();
+        let mut new_shells: Vec<BasCell> = vec![];
+
+        let mut ang_list: Vec<i32> = kept_shells.iter().map(|shell| shell.angular_momentum[0]).collect();
+        ang_list.sort();
+        ang_list.dedup();
+
+        if self.decontract {
+            for l in ang_list.iter().filter(|l| self.decontract_shells.len() == 0 || self.decontract_shells.contains(l)) {
+                let exponents = unique_exponents(kept_shells.iter().filter(|shell| shell.angular_momentum[0] == *l)
+                    .flat_map(|shell| shell.exponents.clone()));
+                kept_shells.retain(|shell| shell.angular_momentum[0] != *l);
+                exponents.iter().for_each(|alpha| new_shells.push(etb_gen_primitive(&(*l as usize), &0, alpha, &1.0)));
+            }
+        }
+
+        for (added, is_diffuse) in [(&self.diffuse, true), (&self.tight, false)] {
+            for (l_opt, num) in added.iter() {
+                let ls = if let Some(l) = l_opt {vec![*l]} else {ang_list.clone()};
+                for l in ls {
+                    let exponents = unique_exponents(kept_shells.iter().chain(new_shells.iter())
+                        .filter(|shell| shell.angular_momentum[0] == l)
+                        .flat_map(|shell| shell.exponents.clone()));
+                    if exponents.len() == 0 {
+                        if print_level > 0 {println!("Warning: no shell of l = {} is found, and the {} functions are not added", l, if is_diffuse {"diffuse"} else {"tight"})};
+                        continue;
+                    }
+                    let n_exp = exponents.len();
+                    // the most diffuse (tight) exponent and the ratio for the even-tempered extrapolation
+                    let (alpha, ratio) = if is_diffuse {
+                        (exponents[n_exp-1], if n_exp > 1 {exponents[n_exp-2]/exponents[n_exp-1]} else {DEFAULT_ETB_BETA})
+                    } else {
+                        (exponents[0], if n_exp > 1 {exponents[0]/exponents[1]} else {DEFAULT_ETB_BETA})
+                    };
+                    let beta = self.etb_beta.unwrap_or(ratio);
+                    let beta = if is_diffuse {1.0/beta} else {beta};
+                    (1..num+1).for_each(|i| new_shells.push(etb_gen_primitive(&(l as usize), &i, &alpha, &beta)));
+                }
+            }
+        }
+
+        (kept_shells, new_shells)
+    }
+
+    /// Apply the modifiers to the basis set of a given element
+    pub fn apply(&self, basis: &mut Basis4Elem, cint_type: &CintType, print_level: usize) {
+        if !self.is_active() {return}
+        let shells = std::mem::take(&mut basis.electron_shells);
+        let (mut kept_shells, mut new_shells) = self.modify_shells(shells, print_level);
+        new_shells.iter_mut().for_each(|shell| shell.basis_normalization(cint_type));
+        kept_shells.extend(new_shells);
+        kept_shells.sort_by(|a,b| a.angular_momentum[0].cmp(&b.angular_momentum[0]));
+        basis.electron_shells = kept_shells;
+    }
+
+    pub fn description(&self) -> String {
+        let label = |l: &i32| SHELL_LABELS.get(*l as usize).map_or(l.to_string(), |c| c.to_string());
+        let added = |funcs: &Vec<(Option<i32>, usize)>| funcs.iter()
+            .map(|(l, n)| format!("{}{}", n, l.as_ref().map_or(String::from("(all)"), |l| label(l))))
+            .collect::<Vec<String>>().join(" ");
+        let mut items = vec![];
+        if let Some(max_l) = &self.max_l {items.push(format!("remove the shells above l = {}", label(max_l)))};
+        if self.decontract {
+            if self.decontract_shells.len() == 0 {
+                items.push(String::from("decontract all shells"));
+            } else {
+                items.push(format!("decontract the {} shells", self.decontract_shells.iter().map(label).collect::<String>()));
+            }
+        };
+        if self.diffuse.len() > 0 {items.push(format!("add the diffuse functions {}", added(&self.diffuse)))};
+        if self.tight.len() > 0 {items.push(format!("add the tight functions {}", added(&self.tight)))};
+        if let Some(beta) = &self.etb_beta {items.push(format!("with the ratio of {}", beta))};
+        items.join("; ")
+    }
+}
+
+#[test]
+fn test_basis_modifier() {
+    let shell = |l: i32, exponents: Vec<f64>, coefficients: Vec<Vec<f64>>| BasCell {
+        function_type: None, region: None, angular_momentum: vec![l],
+        exponents, native_coefficients: coefficients.clone(), coefficients,
+    };
+    let shells = vec![
+        shell(0, vec![10.0, 2.0, 0.5], vec![vec![0.2, 0.5, 0.4]]),
+        shell(0, vec![0.5], vec![vec![1.0]]),
+        shell(1, vec![1.0, 0.25], vec![vec![0.6, 0.5]]),
+        shell(2, vec![0.8], vec![vec![1.0]]),
+    ];
+    let tmp_ctrl: Value = serde_json::json!({
+        "basis_decontract": "s", "basis_diffuse": {"p": 1}, "basis_tight": 1, "basis_max_l": "p",
+    });
+    let modifier = BasisModifier::parse_from_ctrl(tmp_ctrl.as_object().unwrap(), "basis").unwrap();
+    assert_eq!(modifier.decontract_shells, vec![0]);
+    assert_eq!(modifier.max_l, Some(1));
+    assert!(! BasisModifier::parse_from_ctrl(tmp_ctrl.as_object().unwrap(), "auxbas").unwrap().is_active());
+    for etb_beta in [serde_json::json!(0.8), serde_json::json!("2.5x"), serde_json::json!([2.5])] {
+        let tmp_ctrl: Value = serde_json::json!({"basis_etb_beta": etb_beta});
+        assert!(BasisModifier::parse_from_ctrl(tmp_ctrl.as_object().unwrap(), "basis").is_err());
+    }
+
+    let (kept_shells, new_shells) = modifier.modify_shells(shells, 0);
+    // the contracted p shell is kept, while the d shell is removed
+    assert_eq!(kept_shells.len(), 1);
+    assert_eq!(kept_shells[0].angular_momentum, vec![1]);
+    let exponents = |l: i32| new_shells.iter().filter(|x| x.angular_momentum[0] == l)
+        .flat_map(|x| x.exponents.clone()).collect::<Vec<f64>>();
+    // three unique s primitives and one tight s function extrapolated with the ratio of 10.0/2.0
+    assert_eq!(exponents(0), vec![10.0, 2.0, 0.5, 50.0]);
+    // one diffuse and one tight p functions with the ratio of 4.0
+    let p_exponents = exponents(1);
+    assert!((p_exponents[0]-0.0625).abs() < 1.0e-12 && (p_exponents[1]-4.0).abs() < 1.0e-12);
+}
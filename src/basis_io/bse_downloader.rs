@@ -19,6 +19,7 @@ use crate::geom_io::{GeomCell, get_mass_charge};
 use array_tool::vec::{self, Intersect};
 
 use super::Basis4ElemRaw;
+use super::basis_formats::{elements_in_basis_file, is_basis_file};
 
 //use super::Basis4Elem;
 
@@ -106,7 +107,7 @@ pub fn bse_basis_getter(basis_set: &String, cell: &GeomCell, path: &String) {
     //create_dir_all(path);
     //element checker
     let ctrl_elem = ctrl_element_checker(cell);
-    let local_elem = local_element_checker(path);
+    let local_elem = local_element_checker(path).unwrap();
     //println!("local elements are {:?}", local_elem);
     //println!("ctrl elements are {:?}", ctrl_elem);
 
@@ -177,7 +178,7 @@ pub fn bse_basis_getter_v2(basis_set: &String, cell: &GeomCell, path: &String, r
 /*     
     //element checker
     let ctrl_elem = ctrl_element_checker(cell);
-    let local_elem = local_element_checker(path);
+    let local_elem = local_element_checker(path).unwrap();
     //println!("local elements are {:?}", local_elem);
     //println!("ctrl elements are {:?}", ctrl_elem);
 
@@ -247,7 +248,7 @@ pub fn bse_auxbas_getter(basis_set: &String, cell: &GeomCell, path: &String) {
     //create_dir_all(path);
     //element checker
     let ctrl_elem = ctrl_element_checker(cell);
-    let local_elem = local_element_checker(path);
+    let local_elem = local_element_checker(path).unwrap();
     //println!("local elements are {:?}", local_elem);
     //println!("ctrl elements are {:?}", ctrl_elem);
 
@@ -318,7 +319,7 @@ pub fn bse_auxbas_getter_v2(basis_set: &String, cell: &GeomCell, path: &String,
 /*     
     //element checker
     let ctrl_elem = ctrl_element_checker(cell);
-    let local_elem = local_element_checker(path);
+    let local_elem = local_element_checker(path).unwrap();
     //println!("local elements are {:?}", local_elem);
     //println!("ctrl elements are {:?}", ctrl_elem);
 
@@ -444,7 +445,11 @@ pub fn basis_modifier(basis_path: &String) {
 }
 
 /// Checks elements information (.json) of a certain basis set in a given path. Returning a vector of element names.
-pub fn local_element_checker(path: &String) -> Vec<String> {
+/// The basis set file, if given, is parsed, and the parse errors are propagated.
+pub fn local_element_checker(path: &String) -> anyhow::Result<Vec<String>> {
+    if is_basis_file(path) {
+        return elements_in_basis_file(path)
+    }
     create_dir_all(&path);
     let elem_pattern = format!("{}/*.json", path);
     let mut elem_set = vec![];
@@ -461,7 +466,7 @@ pub fn local_element_checker(path: &String) -> Vec<String> {
     }
     //println!("{:?}, {}", elem_set, elem_set.len());
 
-    elem_set
+    Ok(elem_set)
 }
 
 /// Checks elements in the given molecule. Returning a vector of element names.
@@ -486,7 +491,7 @@ pub fn ctrl_element_checker(cell: &GeomCell) -> Vec<String> {
 #[test]
 fn local_test() {
     let path = String::from("/share/home/tygao/REST2.0/basis-set-pool/cc-pVTZ");
-    local_element_checker(&path);
+    local_element_checker(&path).unwrap();
 }    
 //passed
 
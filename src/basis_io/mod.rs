@@ -18,6 +18,10 @@ use crate::constants::{C2S_L0, C2S_L1, C2S_L2, C2S_L3, C2S_L4, c2s_matrix_const,
 use crate::utilities;
 pub mod bse_downloader;
 pub mod basis_list;
+pub mod basis_library;
+pub mod basis_formats;
+pub mod basis_assignment;
+pub mod basis_modifier;
 pub mod etb;
 pub mod ecp;
 use self::basic_math::{double_factorial, specific_double_factorial};
@@ -186,6 +190,20 @@ impl Basis4Elem {
         } else {
             panic!("The basis set file is not in the right format: {:?}", &file_name);
         };
+        Ok(Basis4Elem::from_raw(tmp_basis, cint_type))
+    }
+    /// Import the basis set of the given element from `basis_path`, which is either a folder of `{element}.json`
+    /// or a basis set file in the Gaussian, NWChem, CP2K or BSE json formats (see [`basis_formats`](self::basis_formats))
+    pub fn parse_from_basis_path(basis_path: &String, elem: &String, cint_type: &CintType)-> anyhow::Result<Basis4Elem> {
+        if basis_formats::is_basis_file(basis_path) {
+            let tmp_basis = basis_formats::basis_in_basis_file(basis_path, elem)?;
+            Ok(Basis4Elem::from_raw(tmp_basis, cint_type))
+        } else {
+            Basis4Elem::parse_json_from_file(format!("{}/{}.json", basis_path, elem), cint_type)
+        }
+    }
+    /// Normalize the raw basis set, which is shared by all formats of the basis set files
+    pub fn from_raw(tmp_basis: Basis4ElemRaw, cint_type: &CintType) -> Basis4Elem {
         let mut tmp_vec:Vec<BasCell> = vec![];
         &tmp_basis.electron_shells.iter().for_each(|x: &BasCellRaw| {
             let tmp_bas_cell = x.parse();
@@ -214,13 +232,13 @@ impl Basis4Elem {
         //};
         // Re-ordering the basis function shells according to the angular momentum
         tmp_vec.sort_by(|a,b| a.angular_momentum[0].cmp(&b.angular_momentum[0]));
-        Ok(Basis4Elem{
+        Basis4Elem{
             electron_shells: tmp_vec,
             references: tmp_basis.references,
             ecp_electrons,
             ecp_potentials,
             global_index: (0,0)
-        })
+        }
     }
     pub fn to_numgrid_io(&self) -> (HashMap<usize,f64>,f64) {
         let mut alpha_max = -std::f64::MAX;
@@ -1063,6 +1081,61 @@ pub fn cartesian_gto_1st_batch_v03(a: f64, l: usize, c:&[f64;3],r:&[[f64;3]]) ->
     vec![aox,aoy,aoz]
 }
 
+/// The analytic second derivatives of the cartesian GTOs, d^2 gau/dx_i dx_j, with the shape of 3*3*[num_bas, num_grids].
+/// For each direction, f(x) = x^l exp(-a x^2) gives
+///   f'  = l x^{l-1} - 2a x^{l+1}
+///   f'' = l(l-1) x^{l-2} - 2a(2l+1) x^l + 4a^2 x^{l+2}
+pub fn cartesian_gto_2nd_batch_v03(a: f64, l: usize, c:&[f64;3],r:&[[f64;3]]) -> Vec<Vec<MatrixFull<f64>>> {
+    let num_grids = r.len();
+    let num_bas = (l+1)*(l+2)/2;
+    let norm0 = (2.0*a/PI).powf(0.75)*(4.0*a).powf((l as f64)/2.0);
+
+    let binding = cartesian_gto_const(l);
+    let basinfo = &binding.to_matrixfullslice();
+
+    let mut aopp = vec![vec![MatrixFull::new([num_bas,num_grids], 0.0);3];3];
+    r.iter().enumerate().for_each(|(i_grid, r)| {
+        let mut rr = [0.0;3];
+        let rdot = izip!(rr.iter_mut(),r.iter(),c.iter()).fold(0.0, |rdot, (rr,r,c)| {
+            *rr= r-c;
+            rdot + rr.powf(2.0_f64)}
+        );
+        let exp_part = norm0*libm::exp(-a*rdot);
+        basinfo.iter_columns_full().enumerate().for_each(|(i_bas, bas_i)| {
+            // [f, f', f''] for each direction
+            let mut fxyz = [[0.0;3];3];
+            izip!(fxyz.iter_mut(),bas_i[0..3].iter(),rr.iter()).for_each(|(f,lx,rrx)| {
+                let pow = |n: f64| if n < 0.0 {0.0} else {rrx.powf(n)};
+                f[0] = pow(*lx);
+                f[1] = lx*pow(lx-1.0) - 2.0*a*pow(lx+1.0);
+                f[2] = lx*(lx-1.0)*pow(lx-2.0) - 2.0*a*(2.0*lx+1.0)*pow(*lx) + 4.0*a*a*pow(lx+2.0);
+            });
+            for x in 0..3 {
+                for y in x..3 {
+                    let value = (0..3).fold(bas_i[3]*exp_part, |acc, z| {
+                        let order = (z==x) as usize + (z==y) as usize;
+                        acc*fxyz[z][order]
+                    });
+                    aopp[x][y][[i_bas,i_grid]] = value;
+                    aopp[y][x][[i_bas,i_grid]] = value;
+                }
+            }
+        });
+    });
+
+    aopp
+}
+
+pub fn cartesian_gto_2nd_cint_batch_serial(a: f64, l: usize, c:&[f64;3],r:&[[f64;3]]) -> Vec<Vec<MatrixFull<f64>>> {
+    // the same libcint normalization of the radial part as cartesian_gto_1st_cint_batch_serial_v03
+    let mut std_gto_2nd = cartesian_gto_2nd_batch_v03(a, l, c, r);
+    let fac = cint_norm_factor(l as i32, a);
+    std_gto_2nd.iter_mut().for_each(|std_gto_x| {
+        std_gto_x.iter_mut().for_each(|std_gto| std_gto.self_multiple(fac))
+    });
+    std_gto_2nd
+}
+
 pub fn cartesian_gto_1st_std(a: f64, l: usize, c:&[f64;3],r:&[f64;3]) -> [MatrixFull<f64>;3] {
     let mut gto_dx: Vec<f64> = vec![];
     let mut gto_dy: Vec<f64> = vec![];
@@ -1559,6 +1632,41 @@ pub fn spheric_gto_1st_value_serial(r:&[[f64;3]], gto_center:&[f64;3], bas:&Basi
     paox
 }
 
+/// The analytic second derivatives of the spheric GTOs on the grids, with the shape of 3*3*[num_bas, num_grids]
+pub fn spheric_gto_2nd_value_serial(r:&[[f64;3]], gto_center:&[f64;3], bas:&Basis4Elem) -> Vec<Vec<MatrixFull<f64>>> {
+    let num_grids:usize = r.len();
+    let num_bas_s = bas.electron_shells.iter().fold(0, |acc, ibas| {
+        acc + (ibas.angular_momentum[0] as usize*2+1)*ibas.coefficients.len()
+    });
+    let mut paoxy = vec![vec![MatrixFull::new([num_bas_s,num_grids],0.0);3];3];
+    let mut ibas_start = 0;
+    for ibas in &bas.electron_shells {
+        let iang = ibas.angular_momentum[0] as usize;
+        let c2s_mat = &c2s_matrix_const(iang);
+        let s_len = 2*iang + 1;
+        let c_len = (iang+1)*(iang+2)/2;
+        for coeff in ibas.coefficients.iter() {
+            let mut tmp_cart = vec![vec![MatrixFull::new([c_len, num_grids],0.0);3];3];
+            coeff.iter().zip(ibas.exponents.iter()).for_each(|(icoeff,iexp)| {
+                let tmp_cart_0 = cartesian_gto_2nd_cint_batch_serial(*iexp, iang, gto_center, r);
+                tmp_cart.iter_mut().flatten().zip(tmp_cart_0.iter().flatten()).for_each(|(to,from)| {
+                    to.self_scaled_add(from, *icoeff);
+                });
+            });
+            paoxy.iter_mut().flatten().zip(tmp_cart.iter().flatten()).for_each(|(paoxy_xy,tmp_cart_xy)| {
+                let mut tmp_spheric = MatrixFull::new([s_len,num_grids],0.0);
+                tmp_spheric.to_matrixfullslicemut().lapack_dgemm(
+                    &c2s_mat.to_matrixfullslice(),
+                    &tmp_cart_xy.to_matrixfullslice(),
+                    'T','N',1.0,0.0);
+                paoxy_xy.copy_from_matr(ibas_start..ibas_start+s_len,0..num_grids,  &tmp_spheric, 0..s_len,0..num_grids);
+            });
+            ibas_start += s_len;
+        }
+    }
+    paoxy
+}
+
 pub fn gto_1st_value(r:&[f64;3], gto_center:&[f64;3], bas:&Basis4Elem, basis_type: &String) -> Vec<Vec<f64>> {
     let mut value:Vec<Vec<f64>> = vec![vec![];3];
     //let mut value:[Vec<f64>;3] = [Vec::new();3];
@@ -1766,6 +1874,27 @@ fn debug_par() {
 }
 
 
+#[test]
+fn test_cartesian_gto_2nd() {
+    // the analytic second derivatives against the central differences of the analytic first derivatives
+    let (a, c, h) = (0.8, [0.1, -0.2, 0.3], 1.0e-5);
+    let r = vec![[0.5, 0.4, -0.7], [-1.1, 0.2, 0.9]];
+    for l in 0..4 {
+        let aopp = cartesian_gto_2nd_batch_v03(a, l, &c, &r);
+        for x in 0..3 {
+            let (mut r_p, mut r_m) = (r.clone(), r.clone());
+            r_p.iter_mut().for_each(|ri| ri[x] += h);
+            r_m.iter_mut().for_each(|ri| ri[x] -= h);
+            let (aop_p, aop_m) = (cartesian_gto_1st_batch_v03(a, l, &c, &r_p), cartesian_gto_1st_batch_v03(a, l, &c, &r_m));
+            for y in 0..3 {
+                izip!(aopp[x][y].data.iter(), aop_p[y].data.iter(), aop_m[y].data.iter()).for_each(|(ana, p, m)| {
+                    assert!((ana - (p-m)/(2.0*h)).abs() < 1.0e-6);
+                });
+            }
+        }
+    }
+}
+
 #[test]
 fn norm_factor_gto_radial() {
     let l = 2_i32;
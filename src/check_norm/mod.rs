@@ -9,9 +9,13 @@ pub enum OCCType {
     INTEGER,
     FRAC,
     ATMSAD,
+    // finite-temperature smearing with the width of ctrl.smearing_width
+    FERMI,
+    GAUSSIAN,
 }
 
 pub mod force_state_occupation;
+pub mod smearing;
 
 pub fn generate_occupation_frac_occ(mol: &Molecule, scftype: &SCFType, eigenvalues: &[Vec<f64>;2], tolerant: f64) -> ([Vec<f64>;2],[usize;2],[usize;2]) {
     let num_state = mol.num_state;
@@ -0,0 +1,137 @@
+//! Fractional occupations by the finite-temperature smearing of the orbital energies
+//!
+//! * Fermi-Dirac:  f(e) = 1/(1+exp[(e-mu)/sigma]), 
+//!                 with the entropy of s(f) = -[f*ln(f) + (1-f)*ln(1-f)]
+//! * Gaussian:     f(e) = erfc[(e-mu)/sigma]/2, 
+//!                 with the generalized entropy of s(e) = exp[-((e-mu)/sigma)^2]/(2*sqrt(pi))
+//!
+//! The chemical potential (mu) is searched for each spin channel to conserve the number of electrons.
+//! The electronic free energy is E - sigma*S, and the zero-width energy is extrapolated as E - sigma*S/2.
+use libm::erfc;
+use crate::molecule_io::Molecule;
+use crate::scf_io::SCFType;
+use super::{OCCType, generate_occupation_integer};
+
+/// Orbitals with occupation numbers below this threshold are not included in the density matrix
+pub const SMEARING_OCC_THRESHOLD: f64 = 1.0e-10;
+
+/// The occupation (between 0 and 1) of the orbital with the energy of `e`
+pub fn smearing_occupation(e: f64, mu: f64, width: f64, occ_type: &OCCType) -> f64 {
+    let x = (e-mu)/width;
+    match occ_type {
+        OCCType::GAUSSIAN => 0.5*erfc(x),
+        _ => {
+            if x > 0.0 {
+                let ex = (-x).exp();
+                ex/(1.0+ex)
+            } else {
+                1.0/(1.0+x.exp())
+            }
+        }
+    }
+}
+
+/// The (generalized) entropy of the orbital with the energy of `e`, in the unit of the Boltzmann constant
+pub fn smearing_entropy(e: f64, mu: f64, width: f64, occ_type: &OCCType) -> f64 {
+    match occ_type {
+        OCCType::GAUSSIAN => {
+            let x = (e-mu)/width;
+            (-x*x).exp()/(2.0*std::f64::consts::PI.sqrt())
+        },
+        _ => {
+            let f = smearing_occupation(e, mu, width, occ_type);
+            let mut s = 0.0;
+            if f > 0.0 {s -= f*f.ln()};
+            if f < 1.0 {s -= (1.0-f)*(1.0-f).ln()};
+            s
+        }
+    }
+}
+
+/// Search the chemical potential by bisection, so that `sum_{i} f(e_i) = num_elec`
+pub fn smearing_fermi_level(eigenvalues: &[f64], num_elec: f64, width: f64, occ_type: &OCCType) -> f64 {
+    let e_min = eigenvalues.iter().fold(f64::MAX, |acc, e| acc.min(*e));
+    let e_max = eigenvalues.iter().fold(f64::MIN, |acc, e| acc.max(*e));
+    let mut mu_low = e_min - 50.0*width - 1.0;
+    let mut mu_high = e_max + 50.0*width + 1.0;
+    let mut mu = 0.5*(mu_low + mu_high);
+    for _ in 0..200 {
+        mu = 0.5*(mu_low + mu_high);
+        let count = eigenvalues.iter().fold(0.0, |acc, e| acc + smearing_occupation(*e, mu, width, occ_type));
+        if (count - num_elec).abs() < 1.0e-13 {break}
+        if count > num_elec {mu_high = mu} else {mu_low = mu}
+    }
+    mu
+}
+
+/// Generate the smeared occupation numbers.
+/// Return (occupation, homo, lumo, fermi_level, entropy_term), where 
+/// * `homo` is the highest orbital with the occupation larger than [`SMEARING_OCC_THRESHOLD`], and `lumo = homo+1`,
+///   which should be within `num_state`,
+/// * `entropy_term` is -sigma*S in Hartree.
+///
+/// For ROHF, or if the orbital energies are not yet available, the integer occupation is used.
+pub fn generate_occupation_smearing(mol: &Molecule, scftype: &SCFType, eigenvalues: &[Vec<f64>;2]) 
+    -> ([Vec<f64>;2],[usize;2],[usize;2],[f64;2],f64) {
+    let num_state = mol.num_state;
+    let width = mol.ctrl.smearing_width;
+    let occ_type = &mol.ctrl.occupation_type;
+    let (occ_num, spin_list) = match scftype {
+        SCFType::RHF => (2.0, vec![0]),
+        SCFType::UHF => (1.0, vec![0,1]),
+        SCFType::ROHF => {
+            let (occupation, homo, lumo) = generate_occupation_integer(mol, scftype);
+            return (occupation, homo, lumo, [0.0;2], 0.0)
+        }
+    };
+    if spin_list.iter().any(|i_spin| eigenvalues[*i_spin].len() < num_state) {
+        let (occupation, homo, lumo) = generate_occupation_integer(mol, scftype);
+        return (occupation, homo, lumo, [0.0;2], 0.0)
+    }
+
+    let mut occupation:[Vec<f64>;2] = [vec![],vec![]];
+    let mut homo:[usize;2] = [0,0];
+    let mut lumo:[usize;2] = [0,0];
+    let mut fermi_level = [0.0;2];
+    let mut entropy = 0.0;
+    spin_list.iter().for_each(|i_spin| {
+        let i_spin = *i_spin;
+        let mo_energy = &eigenvalues[i_spin][..num_state];
+        // num_elec = [total, alpha, beta]
+        let num_elec_spin = mol.num_elec[i_spin+1];
+        occupation[i_spin] = vec![0.0;num_state];
+        if num_elec_spin > 0.0 {
+            let mu = smearing_fermi_level(mo_energy, num_elec_spin, width, occ_type);
+            occupation[i_spin].iter_mut().zip(mo_energy.iter()).for_each(|(occ, e)| {
+                *occ = occ_num*smearing_occupation(*e, mu, width, occ_type);
+                entropy += occ_num*smearing_entropy(*e, mu, width, occ_type);
+            });
+            fermi_level[i_spin] = mu;
+        } else {
+            fermi_level[i_spin] = mo_energy[0];
+        }
+        homo[i_spin] = occupation[i_spin].iter().enumerate()
+            .fold(0, |acc, (i, occ)| if *occ > SMEARING_OCC_THRESHOLD {i} else {acc});
+        // make sure there is at least one LUMO
+        if homo[i_spin] + 1 >= num_state {
+            panic!("Error:: all the {} orbitals in the {}-spin channel are occupied by the smearing with the width of {} Ha. Please use a smaller smearing_width or a larger basis set",
+                   num_state, i_spin, width);
+        }
+        lumo[i_spin] = homo[i_spin] + 1;
+    });
+    if let SCFType::RHF = scftype {fermi_level[1] = fermi_level[0]};
+
+    (occupation, homo, lumo, fermi_level, -width*entropy)
+}
+
+#[test]
+fn test_smearing_fermi_level() {
+    let eigenvalues = vec![-1.0, -0.5, -0.2, -0.19, 0.3];
+    for occ_type in [OCCType::FERMI, OCCType::GAUSSIAN] {
+        let mu = smearing_fermi_level(&eigenvalues, 3.0, 0.01, &occ_type);
+        let count = eigenvalues.iter().fold(0.0, |acc, e| acc + smearing_occupation(*e, mu, 0.01, &occ_type));
+        assert!((count-3.0).abs() < 1.0e-10);
+        // the chemical potential lies in the middle of the near-degenerate pair
+        assert!((mu+0.195).abs() < 1.0e-6);
+    }
+}
@@ -248,6 +248,10 @@ pub const PTR_EXPCUTOFF: i32 = 0;
 pub const PTR_COMMON_ORG: i32 = 1;
 // for Gauge origin
 pub const PTR_RINV_ORIG: i32 = 4;
+// for the Gaussian charge of the 1/r operator, i.e. erf(sqrt(zeta)*r)/r
+pub const PTR_RINV_ZETA: usize = 7;
+// for the range-separated Coulomb operator erf(omega*r)/r
+pub const PTR_RANGE_OMEGA: usize = 8;
 
 pub const NUC_MOD_OF: i32 = 2;
 
@@ -294,6 +298,12 @@ pub const PLANCK: f64 = 6.626070040e-34;        // J*s http://physics.nist.gov/c
 pub const E_CHARGE: f64 = 1.6021766208e-19;
 pub const DEBYE:f64 = 3.335641e-30;            // C*m = 1e-18/LIGHT_SPEED_SI https://cccbdb.nist.gov/debye.asp
 pub const AU2DEBYE:f64 = E_CHARGE * BOHR*1e-10 / DEBYE; // 2.541746
+pub const LIGHT_SPEED_SI: f64 = 2.99792458e8;     // m/s https://physics.nist.gov/cgi-bin/cuu/Value?c
+pub const BOLTZMANN: f64 = 1.380649e-23;          // J/K https://physics.nist.gov/cgi-bin/cuu/Value?k
+pub const AMU: f64 = 1.66053906660e-27;           // kg https://physics.nist.gov/cgi-bin/cuu/Value?ukg
+pub const HARTREE_SI: f64 = 4.3597447222071e-18;  // J https://physics.nist.gov/cgi-bin/cuu/Value?hrj
+pub const CALORIE: f64 = 4.184;                   // J
+pub const KCAL_MOL: f64 = 627.5095;               // kcal/mol per Hartree
 
 
 pub const MPI_CHUNK:usize = 134217728; // around 1 GB
\ No newline at end of file
@@ -0,0 +1,158 @@
+//! The coupled-perturbed Hartree-Fock/Kohn-Sham (CPHF/CPKS) equations for the static perturbations.
+//!
+//! For a real one-electron perturbation `h1`, the first-order orbital rotations `U` are given by
+//! ```text
+//!   (A+B) U = -Cv^T h1 Co,   D1 = f (Cv U Co^T + Co U^T Cv^T)
+//! ```
+//! where `f = 2` for the restricted references and 1 for each spin channel of the unrestricted ones.
+//! The action of (A+B) is evaluated by [`SCF::response_fn_ks`] in the occupied-virtual space of
+//! [`ResponseSpace`], and the equations are solved by the preconditioned conjugate gradient (PCG) method.
+//! The solver is the basis of the static polarizability, and could be used by the analytic hessians
+//! and the relaxed densities.
+use tensors::{MathMatrix, MatrixFull};
+
+use crate::dft::fxc::XcKernel;
+use crate::mpi_io::MPIOperator;
+use crate::response::{ResponseSpace, ao_dipole_integrals, build_xc_kernel, check_response_availability};
+use crate::scf_io::SCF;
+use crate::utilities::linear_algebra::dot;
+
+/// The relative threshold of the curvature p^T H p / p^T p in the PCG iterations
+pub const PCG_CURVATURE_THRESHOLD: f64 = 1.0e-10;
+
+/// Apply the (singlet) orbital hessian (A+B) to the amplitudes `x`
+pub fn apply_apb(scf_data: &SCF, space: &ResponseSpace, kernel: Option<&XcKernel>, x: &[f64]) -> Vec<f64> {
+    let dm = space.amplitude_to_ao(x);
+    let (v_jxc, v_k) = scf_data.response_fn_ks(&dm, kernel, true);
+    let v_apb: Vec<MatrixFull<f64>> = v_jxc.iter().zip(v_k.iter()).map(|(vj, vk)| {
+        let mut v = vj.clone();
+        v.self_scaled_add(vk, -1.0);
+        v.self_scaled_add(&vk.transpose(), -1.0);
+        v
+    }).collect();
+    let mut apb = space.ao_to_amplitude(&v_apb);
+    apb.iter_mut().zip(space.de.iter().zip(x.iter())).for_each(|(apb, (de, x))| *apb += de*x);
+    apb
+}
+
+/// Solve `H U = rhs` for a set of right-hand sides by the preconditioned conjugate gradient method, 
+/// where `H` is symmetric and positive definite and only accessed by `h_op`, and `hdiag` is used as the preconditioner.
+/// An error is returned for the non-positive curvature `p^T H p <= 0`, i.e. `H` is not positive definite
+pub fn solve_pcg<F>(h_op: &mut F, hdiag: &[f64], rhs: &[Vec<f64>], tol: f64, max_cycle: usize, print_level: usize) -> anyhow::Result<(Vec<Vec<f64>>, bool)>
+    where F: FnMut(&[f64]) -> Vec<f64> {
+    let precond = |r: &[f64]| -> Vec<f64> {
+        r.iter().zip(hdiag.iter()).map(|(r, h)| if h.abs() < 1.0e-8 {r/1.0e-8_f64.copysign(*h)} else {r/h}).collect()
+    };
+    let num_rhs = rhs.len();
+    let mut u: Vec<Vec<f64>> = rhs.iter().map(|b| precond(b)).collect();
+    let mut r: Vec<Vec<f64>> = rhs.iter().zip(u.iter()).map(|(b, u)| {
+        let hu = h_op(u);
+        b.iter().zip(hu.iter()).map(|(b, hu)| b - hu).collect()
+    }).collect();
+    let mut z: Vec<Vec<f64>> = r.iter().map(|r| precond(r)).collect();
+    let mut p = z.clone();
+    let mut rz: Vec<f64> = r.iter().zip(z.iter()).map(|(r, z)| dot(r, z)).collect();
+    let mut active = vec![true; num_rhs];
+    let mut converged = false;
+
+    for i_cycle in 0..max_cycle {
+        let mut max_r_norm: f64 = 0.0;
+        for k in 0..num_rhs {
+            if ! active[k] {continue}
+            let r_norm = dot(&r[k], &r[k]).sqrt();
+            max_r_norm = max_r_norm.max(r_norm);
+            if r_norm < tol {active[k] = false; continue}
+
+            let hp = h_op(&p[k]);
+            let curvature = dot(&p[k], &hp);
+            if curvature <= PCG_CURVATURE_THRESHOLD*dot(&p[k], &p[k]) {
+                return Err(anyhow::anyhow!("The non-positive curvature p^T H p = {:16.8e} is found in the PCG iterations, \
+                    so that the orbital hessian is not positive definite and the reference is unstable", curvature))
+            }
+            let alpha = rz[k]/curvature;
+            u[k].iter_mut().zip(p[k].iter()).for_each(|(u, p)| *u += alpha*p);
+            r[k].iter_mut().zip(hp.iter()).for_each(|(r, hp)| *r -= alpha*hp);
+            z[k] = precond(&r[k]);
+            let rz_new = dot(&r[k], &z[k]);
+            let beta = rz_new/rz[k];
+            rz[k] = rz_new;
+            p[k].iter_mut().zip(z[k].iter()).for_each(|(p, z)| *p = z + beta*(*p));
+        }
+        if print_level > 1 {
+            println!("CPHF PCG cycle {:3}: max |r| = {:10.5e}", i_cycle, max_r_norm);
+        }
+        if active.iter().all(|a| ! a) {
+            converged = true;
+            break
+        }
+    }
+
+    Ok((u, converged))
+}
+
+/// Solve the CPHF/CPKS equations for the real one-electron perturbations `h1` in the atomic-orbital basis,
+/// which are the same for both spin channels.
+/// Return the first-order density matrices D1[perturbation][spin]
+pub fn solve_one_electron_perturbations(scf_data: &SCF, h1: &[MatrixFull<f64>], mpi_operator: &Option<MPIOperator>) -> anyhow::Result<Vec<Vec<MatrixFull<f64>>>> {
+    check_response_availability(scf_data, mpi_operator, "CPHF/CPKS");
+    let tol = scf_data.mol.ctrl.cphf_conv_tol;
+    let max_cycle = scf_data.mol.ctrl.cphf_max_cycle;
+    let print_level = scf_data.mol.ctrl.print_level;
+
+    let space = ResponseSpace::build(scf_data);
+    let kernel = build_xc_kernel(scf_data)?;
+    let rhs: Vec<Vec<f64>> = h1.iter().map(|h1| {
+        let h1_spin = vec![h1.clone(); space.spin_channel];
+        space.ao_to_amplitude(&h1_spin).iter().map(|h| -h).collect()
+    }).collect();
+
+    let mut apb_op = |x: &[f64]| apply_apb(scf_data, &space, kernel.as_ref(), x);
+    let (u, converged) = solve_pcg(&mut apb_op, &space.de, &rhs, tol, max_cycle, print_level)?;
+    if ! converged {
+        println!("WARNING: the CPHF/CPKS equations are not converged in {} cycles", max_cycle);
+    }
+
+    let factor = if space.spin_channel == 1 {2.0} else {1.0};
+    Ok(u.iter().map(|u| {
+        space.amplitude_to_ao(u).into_iter().map(|dm| {
+            let mut dm1 = dm.transpose();
+            dm1.self_add(&dm);
+            dm1.data.iter_mut().for_each(|d| *d *= factor);
+            dm1
+        }).collect()
+    }).collect())
+}
+
+/// The static dipole polarizability tensor: alpha_{xy} = -tr(r_x D1_y) in atomic units
+pub fn static_polarizability(scf_data: &SCF, mpi_operator: &Option<MPIOperator>) -> anyhow::Result<[[f64;3];3]> {
+    let ao_dip = ao_dipole_integrals(scf_data);
+    let dm1 = solve_one_electron_perturbations(scf_data, &ao_dip, mpi_operator)?;
+    let mut polar = [[0.0;3];3];
+    for x in 0..3 {
+        for y in 0..3 {
+            polar[x][y] = -dm1[y].iter().fold(0.0, |acc, dm1_s| acc + dot(&ao_dip[x].data, &dm1_s.data));
+        }
+    }
+    Ok(polar)
+}
+
+#[test]
+fn test_solve_pcg() {
+    // a symmetric and positive definite matrix with the dominant diagonal terms
+    let hmat = [[4.0, 1.0, 0.5], [1.0, 3.0, 0.2], [0.5, 0.2, 2.0]];
+    let hdiag = vec![4.0, 3.0, 2.0];
+    let mut h_op = |x: &[f64]| -> Vec<f64> {
+        (0..3).map(|i| (0..3).fold(0.0, |acc, j| acc + hmat[i][j]*x[j])).collect()
+    };
+    let rhs = vec![vec![1.0, 2.0, 3.0]];
+    let (u, converged) = solve_pcg(&mut h_op, &hdiag, &rhs, 1.0e-10, 20, 0).unwrap();
+    assert!(converged);
+    let hu = h_op(&u[0]);
+    hu.iter().zip(rhs[0].iter()).for_each(|(hu, b)| assert!((hu - b).abs() < 1.0e-9));
+    // an indefinite matrix is rejected
+    let hmat = [[4.0, 1.0, 0.5], [1.0, -3.0, 0.2], [0.5, 0.2, 2.0]];
+    let mut h_op = |x: &[f64]| -> Vec<f64> {
+        (0..3).map(|i| (0..3).fold(0.0, |acc, j| acc + hmat[i][j]*x[j])).collect()
+    };
+    assert!(solve_pcg(&mut h_op, &[4.0, -3.0, 2.0], &rhs, 1.0e-10, 20, 0).is_err());
+}
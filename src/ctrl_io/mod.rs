@@ -7,6 +7,11 @@ use std::{fs, sync::Arc};
 use crate::{check_norm::force_state_occupation::ForceStateOccupation, dft::{DFAFamily, DFA4REST}, geom_io::{GeomCell, GeomUnit, MOrC}, utilities};
 use rayon::ThreadPoolBuilder;
 use crate::check_norm::OCCType;
+use crate::basis_io::basis_formats::is_basis_file;
+use crate::basis_io::basis_library::{default_auxbas_path, resolve_basis_path};
+use crate::basis_io::basis_assignment::{BasisSource, parse_basis_assignment};
+use crate::basis_io::basis_modifier::BasisModifier;
+use crate::solvation::solvent_epsilon;
 
 use serde_json;
 use toml;
@@ -25,6 +30,56 @@ mod pyrest_ctrl_io;
 pub enum JobType {
     SinglePoint,
     GeomOpt,
+    Freq,
+    TS,
+    IRC,
+    Scan,
+}
+
+/// An internal coordinate scanned from `start` to `end` (in Angstrom or degree) with `num_points` points
+#[derive(Clone,Debug, Deserialize, Serialize)]
+pub struct ScanCoordinate {
+    /// 2 atoms for a bond, 3 for an angle and 4 for a dihedral, starting from 0
+    pub atoms: Vec<usize>,
+    pub start: f64,
+    pub end: f64,
+    pub num_points: usize,
+}
+
+impl ScanCoordinate {
+    /// Parse the format of `[bond|angle|dihedral] i j (k (l)) start end num_points` with the atom indices starting from 1
+    pub fn parse_from_string(line: &str) -> ScanCoordinate {
+        let line_low = line.trim().to_lowercase();
+        let mut items: Vec<&str> = line_low.split(|c: char| c.is_whitespace() || c == ',').filter(|x| x.len() > 0).collect();
+        let expected_len = match items.get(0) {
+            Some(&"bond") | Some(&"b") | Some(&"r") => {items.remove(0); Some(2)},
+            Some(&"angle") | Some(&"a") => {items.remove(0); Some(3)},
+            Some(&"dihedral") | Some(&"d") => {items.remove(0); Some(4)},
+            other => None,
+        };
+        if items.len() < 5 || items.len() > 7 || expected_len.map_or(false, |n| n + 3 != items.len()) {
+            panic!("Error: unknown format of scan_coordinates: {}", line);
+        }
+        let num_atoms = items.len() - 3;
+        let atoms: Vec<usize> = items[..num_atoms].iter().map(|x| {
+            let index: usize = x.parse().unwrap_or_else(|_| panic!("Error: unknown format of scan_coordinates: {}", line));
+            if index < 1 {panic!("Error: the atom indices in scan_coordinates start from 1: {}", line)}
+            index - 1
+        }).collect();
+        let start: f64 = items[num_atoms].parse().unwrap_or_else(|_| panic!("Error: unknown format of scan_coordinates: {}", line));
+        let end: f64 = items[num_atoms+1].parse().unwrap_or_else(|_| panic!("Error: unknown format of scan_coordinates: {}", line));
+        let num_points: usize = items[num_atoms+2].parse().unwrap_or_else(|_| panic!("Error: unknown format of scan_coordinates: {}", line));
+        ScanCoordinate {atoms, start, end, num_points: num_points.max(1)}
+    }
+
+    /// The target value of the i-th point in Angstrom or degree
+    pub fn value_at(&self, i: usize) -> f64 {
+        if self.num_points <= 1 {
+            self.start
+        } else {
+            self.start + (self.end - self.start)*(i as f64)/((self.num_points - 1) as f64)
+        }
+    }
 }
 
 /// **InputKeywords** for a specific calculation
@@ -33,12 +88,29 @@ pub enum JobType {
 /// 
 ///  ### Basis set keywords
 ///  - `basis_path`:   `String`. The path where you can find the basis-set file in json format. If the basis-set file is missing, REST will try to download it from BasisSetExchange
+///                   It can also be a basis-set file in the Gaussian (`.gbs`), NWChem (`.nw`) or CP2K (`BASIS_*#name`) format
 ///  - `basis_type`:   `String`. It can be `spheric` or `cartesian`
 ///  - `auxbas_path`:  `String`. The path where you can find the auxiliary basis-set file in json format. If the basis-set file is missing, REST will try to download it from BasisSetExchange
 ///  - `auxbas_type`:  `String`. It can be `spheric` or `cartesian`
+///  - `basis_library`: `String` or `[String]`. The directories of the local basis library, like `basis-set-pool`, where the missing folders of `basis_path` and `auxbas_path` are searched by the basis set names
+///  - `basis_offline`: `Bool`. True: never download the missing basis sets from BasisSetExchange, and report the missing elements instead
+///  - `[basis]` and `[auxbas]` blocks, or `basis` and `auxbas` in the `[geom]` block: the (auxiliary) basis sets assigned per element or per atom label,
+///                   like `basis = "C1 def2-TZVP, H cc-pVDZ"`. The inline basis sets in the Gaussian or BSE json format are also supported (see [`basis_assignment`](crate::basis_io::basis_assignment))
+///  - `geom_file` in the `[geom]` block: the geometry file in the XYZ, extended XYZ, PDB or Z-matrix format, used instead of `position`.
+///                   With `geom_file_fix_by_occupancy = true`, the atoms with zero occupancy in the PDB file are fixed.
+///                   The charge, multiplicity and lattice given in the file are used unless they are set in the input (see [`geom_file`](crate::geom_io::geom_file))
+///  - `lattice` in the `[geom]` block: the lattice vectors of the Gamma-point periodic calculations, which are limited to
+///                   the LDA and GGA functionals with RI-J. HF and the hybrid functionals are not supported (see [`pbc`](crate::pbc))
+///  - `basis_decontract`, `basis_diffuse`, `basis_tight`, `basis_etb_beta` and `basis_max_l`: the modifiers of the basis sets after loading, i.e. decontracting the shells,
+///                   adding the even-tempered diffuse or tight functions, and removing the shells above the given angular momentum.
+///                   The same modifiers with the prefix of `auxbas_` are for the auxiliary basis sets (see [`basis_modifier`](crate::basis_io::basis_modifier))
 ///  - `even_tempered-basis`: `Bool`. True: turn on ETB to generate the auxiliary basis set
 ///  - `etb_start_atom_number`: `Usize`. Use ETB, for the element with atomic index larger than this value  
 ///  - `etb_beta`: `f64`. Relevant to the ETB basis set size. Smaller value indicates larger ETB basis set. NOTE: etb_beta should be larger than 1.0
+///
+///  ### Gradient keywords
+///  - `auxbasis_response`: `Bool`. Default (true). Include the response of the auxiliary basis set in the RI-V analytic gradients,
+///                   which are then the exact derivatives of the RI-V energy. False: the gradients are obtained by finite differences instead
 #[derive(Debug,Clone,Serialize, Deserialize)]
 #[pyclass]
 pub struct InputKeywords {
@@ -53,6 +125,14 @@ pub struct InputKeywords {
     #[pyo3(get, set)]
     pub auxbas_path: String,
     #[pyo3(get, set)]
+    pub basis_library: Vec<String>,
+    #[pyo3(get, set)]
+    pub basis_offline: bool,
+    pub basis_assignment: Vec<(String, BasisSource)>,
+    pub auxbas_assignment: Vec<(String, BasisSource)>,
+    pub basis_modifier: BasisModifier,
+    pub auxbas_modifier: BasisModifier,
+    #[pyo3(get, set)]
     pub auxbas_type: String,
     #[pyo3(get, set)]
     pub use_auxbas: bool,
@@ -121,6 +201,9 @@ pub struct InputKeywords {
     pub rad_grid_method: String,
     #[pyo3(get, set)]
     pub external_grids: String,
+    // Keywords for the VV10 non-local correlation
+    pub vv10_coeff: Option<[f64;2]>,
+    pub vv10_grid_level: Option<usize>,
     // Keywords for the scf procedures
     #[pyo3(get, set)]
     pub mixer: String,
@@ -133,6 +216,10 @@ pub struct InputKeywords {
     #[pyo3(get, set)]
     pub start_check_oscillation: usize,
     #[pyo3(get, set)]
+    pub start_soscf_error: f64,
+    #[pyo3(get, set)]
+    pub soscf_trust_radius: f64,
+    #[pyo3(get, set)]
     pub level_shift: Option<f64>,
     #[pyo3(get, set)]
     pub max_scf_cycle: usize,
@@ -165,6 +252,90 @@ pub struct InputKeywords {
     pub noiter: bool,
     #[pyo3(get, set)]
     pub check_stab: bool,
+    // Keywords for the linear-response TDHF/TDDFT excitations
+    #[pyo3(get, set)]
+    pub tddft_nstates: usize,
+    #[pyo3(get, set)]
+    pub tddft_tda: bool,
+    #[pyo3(get, set)]
+    pub tddft_triplet: bool,
+    #[pyo3(get, set)]
+    pub tddft_conv_tol: f64,
+    #[pyo3(get, set)]
+    pub tddft_max_cycle: usize,
+    // Keywords for the CPHF/CPKS solver
+    #[pyo3(get, set)]
+    pub cphf_conv_tol: f64,
+    #[pyo3(get, set)]
+    pub cphf_max_cycle: usize,
+    // Keywords for the geometry optimization
+    #[pyo3(get, set)]
+    pub geom_opt_optimizer: String,
+    #[pyo3(get, set)]
+    pub geom_opt_max_cycle: usize,
+    #[pyo3(get, set)]
+    pub geom_opt_max_force: f64,
+    #[pyo3(get, set)]
+    pub geom_opt_rms_force: f64,
+    #[pyo3(get, set)]
+    pub geom_opt_max_displacement: f64,
+    #[pyo3(get, set)]
+    pub geom_opt_rms_displacement: f64,
+    #[pyo3(get, set)]
+    pub geom_opt_trust_radius: f64,
+    #[pyo3(get, set)]
+    pub geom_opt_trajectory: String,
+    // Keywords for the transition-state search
+    #[pyo3(get, set)]
+    pub ts_mode: usize,
+    #[pyo3(get, set)]
+    pub ts_initial_hessian: String,
+    // Keywords for the intrinsic reaction coordinate
+    #[pyo3(get, set)]
+    pub irc_step_size: f64,
+    #[pyo3(get, set)]
+    pub irc_max_points: usize,
+    #[pyo3(get, set)]
+    pub irc_trajectory: String,
+    // Keywords for the potential-energy-surface scan
+    pub scan_coordinates: Vec<ScanCoordinate>,
+    #[pyo3(get, set)]
+    pub scan_type: String,
+    #[pyo3(get, set)]
+    pub scan_trajectory: String,
+    // Keywords for the harmonic frequencies and the thermochemistry
+    #[pyo3(get, set)]
+    pub freq_displacement: f64,
+    #[pyo3(get, set)]
+    pub freq_temperature: f64,
+    #[pyo3(get, set)]
+    pub freq_pressure: f64,
+    #[pyo3(get, set)]
+    pub freq_symmetry_number: usize,
+    // Keywords for the periodic boundary conditions
+    #[pyo3(get, set)]
+    pub pbc_precision: f64,
+    #[pyo3(get, set)]
+    pub pbc_ewald_omega: f64,
+    #[pyo3(get, set)]
+    pub pbc_grid_cutoff: f64,
+    // Keywords for the implicit solvation
+    #[pyo3(get, set)]
+    pub solvent: String,
+    #[pyo3(get, set)]
+    pub solvent_model: String,
+    #[pyo3(get, set)]
+    pub solvent_eps: Option<f64>,
+    #[pyo3(get, set)]
+    pub solvent_grid_points: usize,
+    // Keywords for the QM/MM embedding
+    #[pyo3(get, set)]
+    pub point_charges_gradient_file: Option<String>,
+    // Keywords for the finite external electric field
+    #[pyo3(get, set)]
+    pub electric_field: Option<[f64;3]>,
+    #[pyo3(get, set)]
+    pub finite_field_step: f64,
     #[pyo3(get, set)]
     pub use_dm_only: bool,
     pub use_ri_vj: bool,
@@ -185,6 +356,7 @@ pub struct InputKeywords {
     pub empirical_dispersion: Option<String>,
     pub occupation_type: OCCType,
     pub frac_tolerant: f64,
+    pub smearing_width: f64,
     // Keywords for DeepPot
     #[pyo3(get, set)]
     pub deep_pot: bool,
@@ -216,9 +388,15 @@ impl InputKeywords {
             basis_path: String::from("./STO-3G"),
             basis_type: String::from("spheric"),
             auxbas_path: String::from("./def2-SV(P)-JKFIT"),
+            basis_library: vec![],
+            basis_offline: false,
+            basis_assignment: vec![],
+            auxbas_assignment: vec![],
+            basis_modifier: BasisModifier::new(),
+            auxbas_modifier: BasisModifier::new(),
             auxbas_type: String::from("spheric"),
             use_auxbas: true,
-            auxbasis_response: false,
+            auxbasis_response: true,
             use_isdf: false,
             ri_k_only: false,
             isdf_k_only: false,
@@ -253,6 +431,8 @@ impl InputKeywords {
             pruning: String::from("nwchem"),
             rad_grid_method: String::from("treutler"),
             external_grids: "none".to_string(),
+            vv10_coeff: None,
+            vv10_grid_level: None,
             // ETB for autogen the auxbasis
             even_tempered_basis: false,
             etb_start_atom_number: 37,
@@ -267,6 +447,8 @@ impl InputKeywords {
             num_max_diis: 8,
             start_diis_cycle: 1,
             start_check_oscillation: 20,
+            start_soscf_error: 1.0e-1,
+            soscf_trust_radius: 0.5,
             level_shift : None, 
             max_scf_cycle: 100,
             scf_acc_rho: 1.0e-6,
@@ -277,6 +459,43 @@ impl InputKeywords {
             initial_guess: String::from("sad"),
             noiter: false,
             check_stab: false,
+            tddft_nstates: 0,
+            tddft_tda: false,
+            tddft_triplet: false,
+            tddft_conv_tol: 1.0e-5,
+            tddft_max_cycle: 50,
+            cphf_conv_tol: 1.0e-6,
+            cphf_max_cycle: 50,
+            geom_opt_optimizer: String::from("rfo"),
+            geom_opt_max_cycle: 100,
+            geom_opt_max_force: 4.5e-4,
+            geom_opt_rms_force: 3.0e-4,
+            geom_opt_max_displacement: 1.8e-3,
+            geom_opt_rms_displacement: 1.2e-3,
+            geom_opt_trust_radius: 0.3,
+            geom_opt_trajectory: String::from("geom_opt_traj.xyz"),
+            ts_mode: 1,
+            ts_initial_hessian: String::from("numerical"),
+            irc_step_size: 0.1,
+            irc_max_points: 30,
+            irc_trajectory: String::from("irc_traj.xyz"),
+            scan_coordinates: vec![],
+            scan_type: String::from("relaxed"),
+            scan_trajectory: String::from("scan_traj.xyz"),
+            freq_displacement: 0.005,
+            freq_temperature: 298.15,
+            freq_pressure: 101325.0,
+            freq_symmetry_number: 1,
+            pbc_precision: 1.0e-8,
+            pbc_ewald_omega: 0.5,
+            pbc_grid_cutoff: 12.0,
+            solvent: String::from("none"),
+            solvent_model: String::from("cpcm"),
+            solvent_eps: None,
+            solvent_grid_points: 194,
+            point_charges_gradient_file: None,
+            electric_field: None,
+            finite_field_step: 0.002,
             // Kyewords for the manner to evaluate the Vk (and also Vxc) potentials
             // True:  using only density matrix in the evaluation
             // False: use coefficients as well with higher efficiency
@@ -301,6 +520,7 @@ impl InputKeywords {
             bench_eps: false,
             occupation_type: OCCType::INTEGER,
             frac_tolerant: 1.0e-3,
+            smearing_width: 1.0e-2,
             auxiliary_reference_states: Vec::new(),
             force_state_occupation: Vec::new(),
             rpa_de_excitation_parameters: None 
@@ -357,13 +577,28 @@ impl InputKeywords {
                 // ====================================
                 //  Keywords for the (aux) basis sets
                 // ====================================
+                tmp_input.basis_library = match tmp_ctrl.get("basis_library").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {vec![tmp_str.clone()]},
+                    serde_json::Value::Array(tmp_op) => {
+                        tmp_op.iter().map(|x| x.as_str().unwrap_or_else(|| panic!("basis_library should be given as a list of directories, but {:?} is found", x)).to_string()).collect()
+                    },
+                    other => {vec![]},
+                };
+                tmp_input.basis_offline = match tmp_ctrl.get("basis_offline").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => tmp_str.to_lowercase().parse().unwrap_or(false),
+                    serde_json::Value::Bool(tmp_bool) => tmp_bool.clone(),
+                    other => false,
+                };
                 tmp_input.basis_path = match tmp_ctrl.get("basis_path").unwrap_or(&serde_json::Value::Null) {
                    serde_json::Value::String(tmp_bas) => {
-                        if ! std::path::Path::new(tmp_bas).is_dir() {
+                        let tmp_bas = resolve_basis_path(tmp_bas, &tmp_input.basis_library)?;
+                        if ! std::path::Path::new(&tmp_bas).is_dir() && ! is_basis_file(&tmp_bas) {
                             println!("The specified folder for the basis sets is missing: ({})", tmp_bas);
-                            println!("REST trys to fetch the basis sets from the basis-set exchange pool (https://www.basissetexchange.org/)");
+                            if ! tmp_input.basis_offline {
+                                println!("REST trys to fetch the basis sets from the basis-set exchange pool (https://www.basissetexchange.org/)");
+                            }
                         };
-                        tmp_bas.clone()
+                        tmp_bas
                    },
                    other => {
                         if ! std::path::Path::new(&String::from("./")).is_dir() {
@@ -377,6 +612,8 @@ impl InputKeywords {
                     other => {String::from("spheric")}
                 };
                 //if tmp_input.print_level> 0 {println!("The {}-GTO basis set is taken from {}", tmp_input.basis_type,tmp_input.basis_path)};
+                tmp_input.basis_modifier = BasisModifier::parse_from_ctrl(tmp_ctrl, "basis")?;
+                tmp_input.auxbas_modifier = BasisModifier::parse_from_ctrl(tmp_ctrl, "auxbas")?;
 
                 tmp_input.pruning = match tmp_ctrl.get("pruning").unwrap_or(&serde_json::Value::Null){
                     serde_json::Value::String(tmp_type) => {tmp_type.to_lowercase()},
@@ -458,25 +695,29 @@ impl InputKeywords {
                 };
                 tmp_input.auxbas_path = match tmp_ctrl.get("auxbas_path").unwrap_or(&serde_json::Value::Null) {
                    serde_json::Value::String(tmp_bas) => {
-                        if ! std::path::Path::new(tmp_bas).is_dir() {
+                        let tmp_bas = resolve_basis_path(tmp_bas, &tmp_input.basis_library)?;
+                        if ! std::path::Path::new(&tmp_bas).is_dir() && ! is_basis_file(&tmp_bas) {
                             println!("The specified folder for the auxiliar basis sets is missing: ({})", tmp_bas);
                             //tmp_input.use_auxbas = false;
                         }
                         //tmp_input.use_auxbas = true;
-                        tmp_bas.clone()
+                        tmp_bas
                    },
                    other => {
                         //if ! std::path::Path::new(&String::from("./")).is_dir() {
                         //    println!("The specified folder for the auxiliar basis sets is missing: (./)");
                         //};
-                        println!("No auxiliary basis set is specified. REST will try to find the auxiliary basis fromt the current folder: (./)");
-                        let default_bas = String::from("./");
-                        if ! std::path::Path::new(&default_bas).is_dir() {
-                            //tmp_input.use_auxbas = false;
-                        } else {
-                            //tmp_input.use_auxbas = true;
+                        // the auxiliary basis set matching basis_path in the basis library, or the current folder
+                        match default_auxbas_path(&tmp_input.basis_path, &tmp_input.basis_library)? {
+                            Some(default_bas) => {
+                                println!("No auxiliary basis set is specified. REST takes the auxiliary basis from the basis library: ({})", default_bas);
+                                default_bas
+                            },
+                            None => {
+                                println!("No auxiliary basis set is specified. REST will try to find the auxiliary basis fromt the current folder: (./)");
+                                String::from("./")
+                            }
                         }
-                        default_bas
                    }
                 };
                 //if tmp_input.use_auxbas && tmp_input.print_level>0 {
@@ -487,7 +728,7 @@ impl InputKeywords {
                 // ==============================================
                 tmp_input.auxbasis_response = match tmp_ctrl.get("auxbasis_response").unwrap_or(&serde_json::Value::Null) {
                     serde_json::Value::Bool(tmp_str) => {*tmp_str},
-                    other => {false},
+                    other => {true},
                 };
                 // ==============================================
                 //  JobType
@@ -499,6 +740,16 @@ impl InputKeywords {
                            tmp_xc_low.eq("geometry relaxation") || tmp_xc_low.eq("geom_opt") ||
                            tmp_xc_low.eq("geom_relax") || tmp_xc_low.eq("relax") {
                             JobType::GeomOpt
+                        } else if tmp_xc_low.eq("ts") || tmp_xc_low.eq("transition state") ||
+                          tmp_xc_low.eq("transition_state") || tmp_xc_low.eq("saddle") {
+                            JobType::TS
+                        } else if tmp_xc_low.eq("irc") || tmp_xc_low.eq("intrinsic reaction coordinate") {
+                            JobType::IRC
+                        } else if tmp_xc_low.eq("scan") || tmp_xc_low.eq("pes scan") || tmp_xc_low.eq("pes_scan") {
+                            JobType::Scan
+                        } else if tmp_xc_low.eq("freq") || tmp_xc_low.eq("frequency") ||
+                          tmp_xc_low.eq("frequencies") {
+                            JobType::Freq
                         } else if tmp_xc_low.eq("energy") || tmp_xc_low.eq("single point") ||
                           tmp_xc_low.eq("single_point") {
                             JobType::SinglePoint
@@ -681,6 +932,28 @@ impl InputKeywords {
                     serde_json::Value::Number(tmp_num) => {tmp_num.as_i64().unwrap_or(3) as usize},
                     other => {3_usize},
                 };
+                // the VV10 parameters [b, C], which override those provided by the functional
+                tmp_input.vv10_coeff = match tmp_ctrl.get("vv10_coeff").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::Array(tmp_op) => {
+                        if tmp_op.len() == 2 {
+                            let mut tmp_array = [0.0;2];
+                            tmp_array.iter_mut().zip(tmp_op.iter()).for_each(|(to, from)| {
+                                *to = from.as_f64().unwrap()
+                            });
+                            Some(tmp_array)
+                        } else {
+                            panic!("vv10_coeff should be given as [b, C], but {:?} is found", tmp_op)
+                        }
+                    },
+                    other => None,
+                };
+                // the generation level of the (coarser) grids for the VV10 kernel.
+                // None: use the same grids as the scf procedure
+                tmp_input.vv10_grid_level = match tmp_ctrl.get("vv10_grid_level").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().ok()},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_i64().map(|x| x as usize)},
+                    other => None,
+                };
 
                 tmp_input.even_tempered_basis = match tmp_ctrl.get("even_tempered_basis").unwrap_or(&serde_json::Value::Null) {
                     serde_json::Value::Bool(tmp_str) => {*tmp_str},
@@ -766,6 +1039,16 @@ impl InputKeywords {
                     serde_json::Value::Number(tmp_num) => {tmp_num.as_i64().unwrap_or(20) as usize},
                     other => {20_usize}
                 };
+                tmp_input.start_soscf_error = match tmp_ctrl.get("start_soscf_error").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(1.0e-1)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(1.0e-1)},
+                    other => {1.0e-1}
+                };
+                tmp_input.soscf_trust_radius = match tmp_ctrl.get("soscf_trust_radius").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(0.5)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(0.5)},
+                    other => {0.5}
+                };
 
                 // Initial guess relevant keywords
                 tmp_input.guessfile = match tmp_ctrl.get("guessfile").unwrap_or(&serde_json::Value::Null) {
@@ -807,6 +1090,242 @@ impl InputKeywords {
                     serde_json::Value:: Bool(tmp_bool) => tmp_bool.clone(),
                     other => false,
                 };
+                // the number of the excited states by TDHF/TDDFT. 0: no excited-state calculation
+                tmp_input.tddft_nstates = match tmp_ctrl.get("tddft_nstates").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(0_usize)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_i64().unwrap_or(0) as usize},
+                    other => {0_usize},
+                };
+                // true: the Tamm-Dancoff approximation; false: the full Casida equation
+                tmp_input.tddft_tda = match tmp_ctrl.get("tddft_tda").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value:: String(tmp_str) => tmp_str.to_lowercase().parse().unwrap_or(false),
+                    serde_json::Value:: Bool(tmp_bool) => tmp_bool.clone(),
+                    other => false,
+                };
+                // true: the triplet excitations of the restricted references; false: the singlet ones
+                tmp_input.tddft_triplet = match tmp_ctrl.get("tddft_triplet").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value:: String(tmp_str) => tmp_str.to_lowercase().parse().unwrap_or(false),
+                    serde_json::Value:: Bool(tmp_bool) => tmp_bool.clone(),
+                    other => false,
+                };
+                tmp_input.tddft_conv_tol = match tmp_ctrl.get("tddft_conv_tol").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(1.0e-5)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(1.0e-5)},
+                    other => {1.0e-5},
+                };
+                tmp_input.tddft_max_cycle = match tmp_ctrl.get("tddft_max_cycle").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(50_usize)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_i64().unwrap_or(50) as usize},
+                    other => {50_usize},
+                };
+                tmp_input.cphf_conv_tol = match tmp_ctrl.get("cphf_conv_tol").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(1.0e-6)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(1.0e-6)},
+                    other => {1.0e-6},
+                };
+                tmp_input.cphf_max_cycle = match tmp_ctrl.get("cphf_max_cycle").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(50_usize)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_i64().unwrap_or(50) as usize},
+                    other => {50_usize},
+                };
+                // "rfo": the quasi-Newton optimizer in the redundant internal coordinates; "lbfgs": L-BFGS in the Cartesian coordinates
+                tmp_input.geom_opt_optimizer = match tmp_ctrl.get("geom_opt_optimizer").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase()},
+                    other => {String::from("rfo")},
+                };
+                tmp_input.geom_opt_max_cycle = match tmp_ctrl.get("geom_opt_max_cycle").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(100_usize)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_i64().unwrap_or(100) as usize},
+                    other => {100_usize},
+                };
+                // the convergence criteria on the forces (in a.u.) in the internal coordinates
+                tmp_input.geom_opt_max_force = match tmp_ctrl.get("geom_opt_max_force").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(4.5e-4)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(4.5e-4)},
+                    other => {4.5e-4},
+                };
+                tmp_input.geom_opt_rms_force = match tmp_ctrl.get("geom_opt_rms_force").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(3.0e-4)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(3.0e-4)},
+                    other => {3.0e-4},
+                };
+                // the convergence criteria on the displacements (in a.u.) in the internal coordinates
+                tmp_input.geom_opt_max_displacement = match tmp_ctrl.get("geom_opt_max_displacement").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(1.8e-3)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(1.8e-3)},
+                    other => {1.8e-3},
+                };
+                tmp_input.geom_opt_rms_displacement = match tmp_ctrl.get("geom_opt_rms_displacement").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(1.2e-3)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(1.2e-3)},
+                    other => {1.2e-3},
+                };
+                // the initial trust radius of the steps in the internal coordinates
+                tmp_input.geom_opt_trust_radius = match tmp_ctrl.get("geom_opt_trust_radius").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(0.3)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(0.3)},
+                    other => {0.3},
+                };
+                // the multi-frame xyz file to record the geometry of each step
+                tmp_input.geom_opt_trajectory = match tmp_ctrl.get("geom_opt_trajectory").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.clone()},
+                    other => {String::from("geom_opt_traj.xyz")},
+                };
+                // the Hessian eigenvector (in the ascending order, starting from 1) followed uphill in the TS search
+                tmp_input.ts_mode = match tmp_ctrl.get("ts_mode").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(1_usize)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_i64().unwrap_or(1) as usize},
+                    other => {1_usize},
+                };
+                // "numerical": the initial Hessian of the TS search by finite differences; "model": the model Hessian
+                tmp_input.ts_initial_hessian = match tmp_ctrl.get("ts_initial_hessian").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase()},
+                    other => {String::from("numerical")},
+                };
+                // the arc length (in amu^1/2 Bohr) of each IRC step
+                tmp_input.irc_step_size = match tmp_ctrl.get("irc_step_size").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(0.1)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(0.1)},
+                    other => {0.1},
+                };
+                // the maximum number of IRC points in each direction
+                tmp_input.irc_max_points = match tmp_ctrl.get("irc_max_points").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(30_usize)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_i64().unwrap_or(30) as usize},
+                    other => {30_usize},
+                };
+                // the multi-frame xyz file of the IRC path with the energies
+                tmp_input.irc_trajectory = match tmp_ctrl.get("irc_trajectory").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.clone()},
+                    other => {String::from("irc_traj.xyz")},
+                };
+                // the scanned coordinates, e.g. "dihedral 1 2 3 4 0.0 180.0 13". More than one coordinate makes a grid
+                tmp_input.scan_coordinates = match tmp_ctrl.get("scan_coordinates").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {
+                        tmp_str.lines().filter(|x| x.trim().len() > 0).map(|x| ScanCoordinate::parse_from_string(x)).collect()
+                    },
+                    serde_json::Value::Array(tmp_vec) => {
+                        tmp_vec.iter().map(|x| ScanCoordinate::parse_from_string(x.as_str().unwrap_or(""))).collect()
+                    },
+                    other => {vec![]},
+                };
+                // "relaxed": optimize the other coordinates at each point; "rigid": single points
+                tmp_input.scan_type = match tmp_ctrl.get("scan_type").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase()},
+                    other => {String::from("relaxed")},
+                };
+                tmp_input.scan_trajectory = match tmp_ctrl.get("scan_trajectory").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.clone()},
+                    other => {String::from("scan_traj.xyz")},
+                };
+                // the step size (in Bohr) of the finite differences of gradients for the Hessian
+                tmp_input.freq_displacement = match tmp_ctrl.get("freq_displacement").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(0.005)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(0.005)},
+                    other => {0.005},
+                };
+                // the temperature (in K) for the thermochemistry
+                tmp_input.freq_temperature = match tmp_ctrl.get("freq_temperature").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(298.15)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(298.15)},
+                    other => {298.15},
+                };
+                // the pressure (in Pa) for the thermochemistry
+                tmp_input.freq_pressure = match tmp_ctrl.get("freq_pressure").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(101325.0)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(101325.0)},
+                    other => {101325.0},
+                };
+                // the rotational symmetry number for the rotational entropy
+                tmp_input.freq_symmetry_number = match tmp_ctrl.get("freq_symmetry_number").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(1_usize)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_i64().unwrap_or(1) as usize},
+                    other => {1_usize},
+                };
+                // ================================================
+                //  Keywords associated with the periodic boundary conditions
+                // ================================================
+                // the precision that determines the cutoffs of the lattice sums
+                tmp_input.pbc_precision = match tmp_ctrl.get("pbc_precision").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(1.0e-8)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(1.0e-8)},
+                    other => {1.0e-8},
+                };
+                // the Ewald splitting parameter (in Bohr^-1)
+                tmp_input.pbc_ewald_omega = match tmp_ctrl.get("pbc_ewald_omega").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(0.5)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(0.5)},
+                    other => {0.5},
+                };
+                // the image atoms within this distance (in Bohr) are included in the Becke partition of the periodic grids
+                tmp_input.pbc_grid_cutoff = match tmp_ctrl.get("pbc_grid_cutoff").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(12.0)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(12.0)},
+                    other => {12.0},
+                };
+                // ================================================
+                //  Keywords associated with the implicit solvation
+                // ================================================
+                // the solvent name in the dielectric table of solvation::dielectric_constant, or "none" for the gas phase
+                tmp_input.solvent = match tmp_ctrl.get("solvent").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase()},
+                    other => {String::from("none")},
+                };
+                // "cpcm" or "cosmo"
+                tmp_input.solvent_model = match tmp_ctrl.get("solvent_model").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {
+                        let tmp_model = tmp_str.to_lowercase();
+                        if tmp_model.eq("cpcm") || tmp_model.eq("c-pcm") {
+                            String::from("cpcm")
+                        } else if tmp_model.eq("cosmo") {
+                            String::from("cosmo")
+                        } else {
+                            anyhow::bail!("Unknown solvent_model ({}). Please use 'cpcm' or 'cosmo'", tmp_str)
+                        }
+                    },
+                    other => {String::from("cpcm")},
+                };
+                // the dielectric constant of the solvent, which takes precedence over the one in the table
+                tmp_input.solvent_eps = match tmp_ctrl.get("solvent_eps").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().ok()},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64()},
+                    other => None,
+                };
+                if !tmp_input.solvent.eq("none") || tmp_input.solvent_eps.is_some() {
+                    solvent_epsilon(&tmp_input.solvent, tmp_input.solvent_eps)?;
+                }
+                // the number of the Lebedev points on each atomic sphere of the cavity
+                tmp_input.solvent_grid_points = match tmp_ctrl.get("solvent_grid_points").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(194_usize)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_i64().unwrap_or(194) as usize},
+                    other => {194_usize},
+                };
+                // the file to which the gradients on the MM point charges are written for the external MM driver
+                tmp_input.point_charges_gradient_file = match tmp_ctrl.get("point_charges_gradient_file").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {Some(tmp_str.clone())},
+                    other => None,
+                };
+                // the uniform external electric field [Fx, Fy, Fz] in a.u., which couples to the dipole operator
+                tmp_input.electric_field = match tmp_ctrl.get("electric_field").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::Array(tmp_op) => {
+                        if tmp_op.len() == 3 {
+                            let mut tmp_array = [0.0;3];
+                            tmp_array.iter_mut().zip(tmp_op.iter()).for_each(|(to, from)| {
+                                *to = from.as_f64().unwrap()
+                            });
+                            Some(tmp_array)
+                        } else {
+                            panic!("electric_field should be given as [Fx, Fy, Fz], but {:?} is found", tmp_op)
+                        }
+                    },
+                    other => None,
+                };
+                // the field strength (a.u.) of the finite-field differentiation for the (hyper)polarizabilities
+                tmp_input.finite_field_step = match tmp_ctrl.get("finite_field_step").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(0.002)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(0.002)},
+                    other => {0.002},
+                };
                 tmp_input.use_dm_only = match tmp_ctrl.get("use_dm_only").unwrap_or(&serde_json::Value::Null) {
                     serde_json::Value:: String(tmp_str) => tmp_str.to_lowercase().parse().unwrap_or(false),
                     serde_json::Value:: Bool(tmp_bool) => tmp_bool.clone(),
@@ -830,6 +1349,10 @@ impl InputKeywords {
                             OCCType::ATMSAD
                         } else if tmp_occupation_type.eq("frac") {
                             OCCType::FRAC
+                        } else if tmp_occupation_type.eq("fermi") || tmp_occupation_type.eq("fermi-dirac") {
+                            OCCType::FERMI
+                        } else if tmp_occupation_type.eq("gaussian") || tmp_occupation_type.eq("gauss") {
+                            OCCType::GAUSSIAN
                         } else {
                             OCCType::INTEGER
                         }
@@ -841,6 +1364,11 @@ impl InputKeywords {
                     serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(1.0e-3)},
                     other => {1.0e-3}
                 };
+                tmp_input.smearing_width = match tmp_ctrl.get("smearing_width").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.to_lowercase().parse().unwrap_or(1.0e-2)},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64().unwrap_or(1.0e-2)},
+                    other => {1.0e-2}
+                };
                 tmp_input.force_state_occupation= match tmp_ctrl.get("force_state_occupation").unwrap_or(&serde_json::Value::Null) {
                     serde_json::Value::String(tmp_op) => {vec![]},
                     serde_json::Value::Array(tmp_op) => {
@@ -1086,8 +1614,33 @@ impl InputKeywords {
                     println!("Warning:: unknown geometry unit is specified: {}. Angstrom will be used", tmp_unit);
                     tmp_geomcell.unit=GeomUnit::Angstrom;
                 };
+                // the geometry from an external file (*.xyz, *.pdb, or *.zmat), which takes the place of "position"
+                let tmp_geom_file = match tmp_geom.get("geom_file").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {
+                        if tmp_geom.get("position").is_some() {
+                            return Err(anyhow::anyhow!("Both 'geom_file' and 'position' are given in the [geom] block. Please use only one of them"))
+                        }
+                        let tmp_unit = tmp_geomcell.unit.clone();
+                        // the atoms with zero occupancy in the PDB file are fixed only if required
+                        let fix_by_occupancy = match tmp_geom.get("geom_file_fix_by_occupancy").unwrap_or(&serde_json::Value::Null) {
+                            serde_json::Value::Bool(tmp_bool) => *tmp_bool,
+                            serde_json::Value::String(tmp_str) => tmp_str.to_lowercase().parse().unwrap_or(false),
+                            other => false,
+                        };
+                        Some(GeomCell::parse_geom_file(tmp_str, &tmp_unit, fix_by_occupancy)?)
+                    },
+                    other => None,
+                };
                 //(tmp_geomcell.elem, tmp_geomcell.fix, tmp_geomcell.position, tmp_geomcell.nfree, )
                 match tmp_geom.get("position").unwrap_or(&serde_json::Value::Null) {
+                    _ if tmp_geom_file.is_some() => {
+                        let (file_geom, _, _) = tmp_geom_file.as_ref().unwrap();
+                        tmp_geomcell.elem = file_geom.elem.clone();
+                        tmp_geomcell.fix = file_geom.fix.clone();
+                        tmp_geomcell.position = file_geom.position.clone();
+                        tmp_geomcell.nfree = file_geom.nfree;
+                        tmp_geomcell.atom_label = file_geom.atom_label.clone();
+                    },
                     serde_json::Value::Array(tmp_vec) => {
                         let tmp_unit = tmp_geomcell.unit.clone();
                         
@@ -1097,6 +1650,7 @@ impl InputKeywords {
                         tmp_geomcell.fix = tmp2;
                         tmp_geomcell.position = tmp3;
                         tmp_geomcell.nfree = tmp4;
+                        tmp_geomcell.split_atom_labels();
                     },
                     serde_json::Value::String(tmp_str) => {
                         let tmp_unit = tmp_geomcell.unit.clone();
@@ -1107,18 +1661,29 @@ impl InputKeywords {
                         tmp_geomcell.fix = tmp2;
                         tmp_geomcell.position = tmp3;
                         tmp_geomcell.nfree = tmp4;
+                        tmp_geomcell.split_atom_labels();
 
                     }
                     other => {
                         panic!("Error in reading the geometry position")
                     }
                 };
+                let num_atoms = tmp_geomcell.elem.len();
+                tmp_geomcell.constraints = match tmp_geom.get("constraints").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {
+                        GeomCell::parse_constraints_from_string(tmp_str, num_atoms)?
+                    },
+                    serde_json::Value::Array(tmp_vec) => {
+                        let tmp_str = tmp_vec.iter().map(|x| x.as_str().unwrap_or("").to_string()).collect::<Vec<String>>().join("\n");
+                        GeomCell::parse_constraints_from_string(&tmp_str, num_atoms)?
+                    },
+                    other => {vec![]},
+                };
                 match tmp_geom.get("lattice").unwrap_or(&serde_json::Value::Null) {
                     serde_json::Value::Array(tmp_vec) => {
                         let tmp_unit = tmp_geomcell.unit.clone();
                         tmp_geomcell.lattice = GeomCell::parse_lattice(tmp_vec, &tmp_unit)?;
                         tmp_geomcell.pbc = MOrC::Crystal;
-                        panic!("Find lattice vectors. PBC calculations should be turn on, which, however, is not yet implemented");
                     },
                     other => {
                         //if tmp_input.print_level>0 {
@@ -1163,11 +1728,76 @@ impl InputKeywords {
                         tmp_geomcell.ghost_ep_pos = MatrixFull::empty();
                     }
                 }
+                // the ghost atoms and the lattice given in the geometry file
+                if let Some((file_geom, file_charge, file_multiplicity)) = &tmp_geom_file {
+                    if file_geom.ghost_bs_elem.len() > 0 {
+                        tmp_geomcell.ghost_bs_elem.extend(file_geom.ghost_bs_elem.iter().cloned());
+                        let mut tmp_pos = tmp_geomcell.ghost_bs_pos.data.clone();
+                        tmp_pos.extend(file_geom.ghost_bs_pos.data.iter());
+                        tmp_geomcell.ghost_bs_pos = MatrixFull::from_vec([3, tmp_pos.len()/3], tmp_pos).unwrap();
+                    }
+                    if let (MOrC::Crystal, None) = (&file_geom.pbc, tmp_geom.get("lattice")) {
+                        tmp_geomcell.lattice = file_geom.lattice.clone();
+                        tmp_geomcell.pbc = MOrC::Crystal;
+                    }
+                    // the charge and the spin multiplicity in the file are used only if not given in the "ctrl" block
+                    let tmp_ctrl = tmp_keys.get("ctrl");
+                    if let (Some(charge), None) = (file_charge, tmp_ctrl.and_then(|x| x.get("charge"))) {
+                        tmp_input.charge = *charge;
+                    }
+                    if let (Some(multiplicity), None) = (file_multiplicity, tmp_ctrl.and_then(|x| x.get("spin"))) {
+                        tmp_input.spin = *multiplicity;
+                    }
+                }
+                // the MM point charges for the electrostatic embedding from an external file (*.pqr, *.pdb, or
+                // the columns of `charge x y z`), which are appended to those given by "ghost"
+                match tmp_geom.get("point_charges_file").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {
+                        let tmp_unit = tmp_geomcell.unit.clone();
+                        let (pc_chrg, pc_pos) = GeomCell::parse_point_charges_from_file(tmp_str, &tmp_unit)?;
+                        tmp_geomcell.ghost_pc_chrg.extend(pc_chrg);
+                        let mut tmp_pos = tmp_geomcell.ghost_pc_pos.data.clone();
+                        tmp_pos.extend(pc_pos.data);
+                        tmp_geomcell.ghost_pc_pos = MatrixFull::from_vec([3, tmp_pos.len()/3], tmp_pos).unwrap();
+                    },
+                    other => {},
+                }
+                // the width of the Gaussian-smeared point charges in the unit of the geometry
+                let tmp_unit = tmp_geomcell.unit.clone();
+                tmp_geomcell.ghost_pc_width = match tmp_geom.get("point_charges_width").unwrap_or(&serde_json::Value::Null) {
+                    serde_json::Value::String(tmp_str) => {tmp_str.parse::<f64>().ok()},
+                    serde_json::Value::Number(tmp_num) => {tmp_num.as_f64()},
+                    other => None,
+                }.map(|width| if let GeomUnit::Angstrom = tmp_unit {width/crate::constants::ANG} else {width});
             },
             other => {
                 panic!("Error:: no 'geom' keyword or some inproper settings of 'geom' keyword in the input file");
             },
         }
+        //==================================================================
+        //
+        //  parse the (auxiliary) basis sets assigned per element or per atom label
+        //  from the "geom" block and then the "basis" ("auxbas") block
+        //
+        //==================================================================
+        for (tmp_key, basis_path) in [("basis", tmp_input.basis_path.clone()), ("auxbas", tmp_input.auxbas_path.clone())] {
+            let mut tmp_assignment = vec![];
+            let tmp_values = [tmp_keys.get("geom").and_then(|x| x.get(tmp_key)), tmp_keys.get(tmp_key)];
+            for tmp_value in tmp_values.iter().flatten() {
+                tmp_assignment.extend(parse_basis_assignment(tmp_value, &basis_path, &tmp_input.basis_library)?);
+            }
+            if tmp_key.eq("basis") {
+                tmp_input.basis_assignment = tmp_assignment;
+            } else {
+                tmp_input.auxbas_assignment = tmp_assignment;
+            }
+        }
+        // the uniform electric field breaks the translational symmetry of the periodic cell
+        if let MOrC::Crystal = tmp_geomcell.pbc {
+            if tmp_input.electric_field.is_some() || tmp_input.outputs.iter().any(|x| x.eq("finite_field")) {
+                anyhow::bail!("The electric field and the finite-field properties are not available for the periodic systems")
+            }
+        }
         Ok((tmp_input,tmp_geomcell))
         
     }
@@ -1210,6 +1840,10 @@ pub fn overall_report_on_ctrl_geom(ctrl: &InputKeywords, geom: &GeomCell) {
     match ctrl.job_type {
         JobType::SinglePoint => {println!("Calculation type: Single-point energy")},
         JobType::GeomOpt => {println!("Calculation type: Geometry optimization")},
+        JobType::Freq => {println!("Calculation type: Harmonic frequencies")},
+        JobType::TS => {println!("Calculation type: Transition-state search")},
+        JobType::IRC => {println!("Calculation type: Intrinsic reaction coordinate")},
+        JobType::Scan => {println!("Calculation type: Potential-energy-surface scan ({})", ctrl.scan_type)},
     }
     println!("The exchange-correlation method: {}", ctrl.xc);
 
@@ -1234,6 +1868,26 @@ pub fn overall_report_on_ctrl_geom(ctrl: &InputKeywords, geom: &GeomCell) {
     if ctrl.use_auxbas {
         println!("The {}-GTO auxiliary basis set is taken from {}", ctrl.auxbas_type,ctrl.auxbas_path)
     };
+    if ctrl.basis_library.len() > 0 {
+        println!("The local basis library: {:?}", ctrl.basis_library);
+    };
+    ctrl.basis_assignment.iter().for_each(|(label, source)| {
+        println!("The basis set of {} is taken from {}", label, source.description());
+    });
+    if ctrl.use_auxbas {
+        ctrl.auxbas_assignment.iter().for_each(|(label, source)| {
+            println!("The auxiliary basis set of {} is taken from {}", label, source.description());
+        });
+    };
+    if ctrl.basis_modifier.is_active() {
+        println!("The basis sets are modified: {}", ctrl.basis_modifier.description());
+    };
+    if ctrl.use_auxbas && ctrl.auxbas_modifier.is_active() {
+        println!("The auxiliary basis sets are modified: {}", ctrl.auxbas_modifier.description());
+    };
+    if ctrl.basis_offline {
+        println!("Offline mode for the basis sets: the missing ones are not downloaded from BasisSetExchange");
+    };
 
     if ctrl.spin_channel == 1 {
         println!("Spin polarization: Off")
@@ -1246,6 +1900,12 @@ pub fn overall_report_on_ctrl_geom(ctrl: &InputKeywords, geom: &GeomCell) {
     println!("max_num_angular_points: {}", ctrl.max_num_angular_points);
     println!("hardness: {}", ctrl.hardness);
     println!("Grid generation level: {}", ctrl.grid_gen_level);
+    if let Some(vv10_coeff) = ctrl.vv10_coeff {
+        println!("VV10 parameters (b, C): ({}, {})", vv10_coeff[0], vv10_coeff[1]);
+    }
+    if let Some(level) = ctrl.vv10_grid_level {
+        println!("Grid generation level for the VV10 kernel: {}", level);
+    }
     println!("Even tempered basis generation: {}", ctrl.even_tempered_basis);
     println!("SCF convergency thresholds: {:e} for density matrix", ctrl.scf_acc_rho);
     println!("                            {:e} Ha. for sum of eigenvalues", ctrl.scf_acc_eev);
@@ -1263,10 +1923,32 @@ pub fn overall_report_on_ctrl_geom(ctrl: &InputKeywords, geom: &GeomCell) {
                   &tmp_mixer, &ctrl.mix_param, &ctrl.num_max_diis);
         println!("Turn on the {} mixing after {} step(s) of SCF iteractions with the linear mixing", 
                   &tmp_mixer, &ctrl.start_diis_cycle);
+    } else if tmp_mixer.eq(&"ediis") 
+           || tmp_mixer.eq(&"adiis")
+           || tmp_mixer.eq(&"ediis+diis")
+           || tmp_mixer.eq(&"adiis+diis") {
+        println!("The {} mixing with max_vec_len = {} is employed for the SCF procedure", 
+                  &tmp_mixer, &ctrl.num_max_diis);
+        println!("Turn on the {} mixing after {} step(s) of SCF iteractions with the linear mixing", 
+                  &tmp_mixer, &ctrl.start_diis_cycle);
+    } else if tmp_mixer.eq(&"newton") 
+           || tmp_mixer.eq(&"soscf") {
+        println!("The second-order SCF ({}) with the trust-region augmented hessian is employed for the SCF procedure", &tmp_mixer);
+        println!("Turn on the diis mixing with (param, max_vec_len) = ({}, {}) after {} step(s) of SCF iteractions with the linear mixing", 
+                  &ctrl.mix_param, &ctrl.num_max_diis, &ctrl.start_diis_cycle);
+        println!("Switch to the orbital rotations once the DIIS error is below {:e}, with the initial trust radius of {}", 
+                  &ctrl.start_soscf_error, &ctrl.soscf_trust_radius);
     } else {
         //ctrl.mixer = String::from("direct");
         panic!("Unknown charge density mixer ({})! No charge density mixing will be invoked.", ctrl.mixer);
     };
+    match ctrl.occupation_type {
+        OCCType::FERMI | OCCType::GAUSSIAN => {
+            println!("The {:?} smearing of the occupation is employed with the width of {} Ha", 
+                      &ctrl.occupation_type, &ctrl.smearing_width);
+        },
+        _ => {}
+    }
     // if guessfile is specified, reading the external initial guess file is prior to reading the restart file
     if ctrl.restart && ! std::path::Path::new(&ctrl.chkfile).exists() {
         println!("The specified checkfile is missing, which will be created after the SCF procedure \n({})",&ctrl.chkfile)
@@ -1288,10 +1970,31 @@ pub fn overall_report_on_ctrl_geom(ctrl: &InputKeywords, geom: &GeomCell) {
         println!("Even tempered basis beta is: {}", ctrl.etb_beta);
     }
 
+    if let Some(field) = ctrl.electric_field {
+        println!("External electric field [a.u.]: ({:12.8}, {:12.8}, {:12.8})", field[0], field[1], field[2]);
+    }
+    if ctrl.outputs.iter().any(|x| x.eq("finite_field")) {
+        println!("Finite-field (hyper)polarizabilities with the field step of {} a.u.", ctrl.finite_field_step);
+    }
+
+    if ! ctrl.solvent.eq("none") || ctrl.solvent_eps.is_some() {
+        if let Some(eps) = ctrl.solvent_eps {
+            println!("Implicit solvation by {} with the dielectric constant of {}", ctrl.solvent_model.to_uppercase(), eps);
+        } else {
+            println!("Implicit solvation by {} in {}", ctrl.solvent_model.to_uppercase(), ctrl.solvent);
+        }
+        println!("The cavity is discretized by {} Lebedev points on each atomic sphere", ctrl.solvent_grid_points);
+    }
+
 
     match geom.pbc {
         MOrC::Molecule => println!("It is a finite cluster calculation"),
-        MOrC::Crystal => println!("It is a periodic calculation")
+        MOrC::Crystal => {
+            println!("It is a periodic calculation at the Gamma point");
+            println!("Lattice sums with the precision of {:8.2e} and the Ewald parameter of {:6.3} Bohr^-1",
+                ctrl.pbc_precision, ctrl.pbc_ewald_omega);
+            println!("Image atoms within at least {:6.2} Bohr are included in the periodic grids", ctrl.pbc_grid_cutoff);
+        }
     }
 
 
@@ -70,9 +70,12 @@ impl InputKeywords {
     }
     pub fn py_set_mixer(&mut self, mixer: String) {
         self.mixer = mixer.to_lowercase();
-        let flag = self.mixer.eq("direct") || self.mixer.eq("linear") || self.mixer.eq("diis");
+        let flag = self.mixer.eq("direct") || self.mixer.eq("linear") || self.mixer.eq("diis")
+            || self.mixer.eq("newton") || self.mixer.eq("soscf")
+            || self.mixer.eq("ediis") || self.mixer.eq("adiis")
+            || self.mixer.eq("ediis+diis") || self.mixer.eq("adiis+diis");
         if ! flag {
-            println!("Warning: please use either 'direct', 'linear', or 'diis'")
+            println!("Warning: please use either 'direct', 'linear', 'diis', 'ediis', 'adiis', 'ediis+diis', 'adiis+diis', or 'newton'")
         }
     }
     pub fn py_set_mix_param(&mut self, mix_param:f64) {
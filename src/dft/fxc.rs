@@ -0,0 +1,192 @@
+//! The exchange-correlation kernel for the linear response of the density, which is used by
+//! TDDFT and the coupled-perturbed Kohn-Sham equations.
+//!
+//! The kernel is always evaluated in the spin-polarized form, such that the singlet and triplet
+//! responses of the restricted references are given by the perturbations with
+//! `(dD_alpha, dD_beta) = (dD, dD)` and `(dD, -dD)`, respectively.
+//! ```text
+//!   dV_s(r) = \sum_t f_{st}(r) drho_t(r)                          (LDA)
+//!           + the corresponding terms of sigma and nabla rho      (GGA)
+//! ```
+//! The kernel is not yet available for the meta-GGA functionals, and the VV10 non-local correlation
+//! is not included.
+use tensors::MatrixFull;
+use tensors::matrix_blas_lapack::{_dgemm_full, contract_vxc_0_serial};
+use crate::dft::{DFA4REST, Grids};
+use crate::dft::libxc::LibXCFamily;
+
+/// The density threshold below which the kernel is set to zero
+pub const FXC_RHO_THRESHOLD: f64 = 1.0e-10;
+
+pub struct XcKernel {
+    pub use_density_gradient: bool,
+    /// the ground-state density gradients [spin][x][grid]
+    pub rhop: [[Vec<f64>;3];2],
+    /// vsigma in the order of (uu, ud, dd) for each grid
+    pub vsigma: Vec<[f64;3]>,
+    /// v2rho2 in the order of (u_u, u_d, d_d) for each grid
+    pub v2rho2: Vec<[f64;3]>,
+    /// v2rhosigma in the order of (u_uu, u_ud, u_dd, d_uu, d_ud, d_dd) for each grid
+    pub v2rhosigma: Vec<[f64;6]>,
+    /// v2sigma2 in the order of (uu_uu, uu_ud, uu_dd, ud_ud, ud_dd, dd_dd) for each grid
+    pub v2sigma2: Vec<[f64;6]>,
+}
+
+impl XcKernel {
+    /// Tabulate the kernel on the grids for the spin-resolved ground-state density matrices `dm`.
+    /// For the restricted references, `dm` should be [D/2, D/2].
+    pub fn build(xc_data: &DFA4REST, grids: &Grids, dm: &[MatrixFull<f64>;2]) -> anyhow::Result<XcKernel> {
+        let num_grids = grids.coordinates.len();
+        let use_density_gradient = xc_data.use_density_gradient();
+        if xc_data.use_kinetic_density() {
+            return Err(anyhow::anyhow!("The exchange-correlation kernel is not yet implemented for meta-GGA functionals"))
+        }
+        // check the families before the tabulation of the kernel
+        for xc_code in xc_data.dfa_compnt_scf.iter() {
+            let xc_func = super::libxc::XcFuncType::xc_func_init(*xc_code, 2);
+            match xc_func.xc_func_family {
+                LibXCFamily::LDA | LibXCFamily::GGA | LibXCFamily::HybridGGA => {},
+                _ => return Err(anyhow::anyhow!("The exchange-correlation kernel is not yet implemented for {}", xc_func.get_family_name()))
+            }
+        }
+
+        let (rho, rhop) = grids.prepare_tabulated_density_slots_dm_only(&dm.to_vec(), 2, 0..num_grids);
+        let rhop: [[Vec<f64>;3];2] = if use_density_gradient {
+            [0usize,1usize].map(|i_spin| {
+                let rhop_s = rhop.get_reducing_matrix(i_spin).unwrap();
+                [0usize,1usize,2usize].map(|x| rhop_s.get_slice_x(x).to_vec())
+            })
+        } else {
+            Default::default()
+        };
+        // the spin-polarized layout of libxc: [rho_u, rho_d] for each grid
+        let rho_ud: Vec<f64> = (0..num_grids).flat_map(|g| [rho[[g,0]], rho[[g,1]]]).collect();
+        let sigma_ud: Vec<f64> = if use_density_gradient {
+            (0..num_grids).flat_map(|g| {
+                let dot = |s: usize, t: usize| (0..3).fold(0.0, |acc, x| acc + rhop[s][x][g]*rhop[t][x][g]);
+                [dot(0,0), dot(0,1), dot(1,1)]
+            }).collect()
+        } else {
+            vec![]
+        };
+
+        let mut vsigma = vec![[0.0;3]; if use_density_gradient {num_grids} else {0}];
+        let mut v2rho2 = vec![[0.0;3]; num_grids];
+        let mut v2rhosigma = vec![[0.0;6]; if use_density_gradient {num_grids} else {0}];
+        let mut v2sigma2 = vec![[0.0;6]; if use_density_gradient {num_grids} else {0}];
+
+        xc_data.dfa_compnt_scf.iter().zip(xc_data.dfa_paramr_scf.iter()).for_each(|(xc_code, xc_para)| {
+            let xc_func = super::libxc::XcFuncType::xc_func_init(*xc_code, 2);
+            match xc_func.xc_func_family {
+                LibXCFamily::LDA => {
+                    let tmp_v2rho2 = xc_func.lda_fxc(&rho_ud);
+                    v2rho2.iter_mut().zip(tmp_v2rho2.chunks_exact(3)).for_each(|(to, from)| {
+                        to.iter_mut().zip(from.iter()).for_each(|(to, from)| *to += xc_para*from)
+                    });
+                },
+                LibXCFamily::GGA | LibXCFamily::HybridGGA => {
+                    let (_, tmp_vsigma, tmp_v2rho2, tmp_v2rhosigma, tmp_v2sigma2) = xc_func.gga_vxc_fxc(&rho_ud, &sigma_ud);
+                    vsigma.iter_mut().zip(tmp_vsigma.chunks_exact(3)).for_each(|(to, from)| {
+                        to.iter_mut().zip(from.iter()).for_each(|(to, from)| *to += xc_para*from)
+                    });
+                    v2rho2.iter_mut().zip(tmp_v2rho2.chunks_exact(3)).for_each(|(to, from)| {
+                        to.iter_mut().zip(from.iter()).for_each(|(to, from)| *to += xc_para*from)
+                    });
+                    v2rhosigma.iter_mut().zip(tmp_v2rhosigma.chunks_exact(6)).for_each(|(to, from)| {
+                        to.iter_mut().zip(from.iter()).for_each(|(to, from)| *to += xc_para*from)
+                    });
+                    v2sigma2.iter_mut().zip(tmp_v2sigma2.chunks_exact(6)).for_each(|(to, from)| {
+                        to.iter_mut().zip(from.iter()).for_each(|(to, from)| *to += xc_para*from)
+                    });
+                },
+                _ => {}
+            }
+        });
+
+        // neglect the kernel in the low-density region, where the derivatives are numerically unstable
+        (0..num_grids).filter(|g| rho[[*g,0]] + rho[[*g,1]] < FXC_RHO_THRESHOLD).for_each(|g| {
+            v2rho2[g] = [0.0;3];
+            if use_density_gradient {
+                vsigma[g] = [0.0;3];
+                v2rhosigma[g] = [0.0;6];
+                v2sigma2[g] = [0.0;6];
+            }
+        });
+
+        Ok(XcKernel {use_density_gradient, rhop, vsigma, v2rho2, v2rhosigma, v2sigma2})
+    }
+
+    /// The response of the exchange-correlation potential to the symmetric perturbations of
+    /// the spin-resolved density matrices `ddm`: [dV_alpha, dV_beta] in the atomic-orbital basis
+    pub fn apply(&self, grids: &Grids, ddm: &[MatrixFull<f64>;2]) -> [MatrixFull<f64>;2] {
+        let num_grids = grids.coordinates.len();
+        let num_basis = ddm[0].size[0];
+        let (drho, drhop) = grids.prepare_tabulated_density_slots_dm_only(&ddm.to_vec(), 2, 0..num_grids);
+        let drhop: [[Vec<f64>;3];2] = if self.use_density_gradient {
+            [0usize,1usize].map(|i_spin| {
+                let drhop_s = drhop.get_reducing_matrix(i_spin).unwrap();
+                [0usize,1usize,2usize].map(|x| drhop_s.get_slice_x(x).to_vec())
+            })
+        } else {
+            Default::default()
+        };
+
+        // dv_rho[spin][grid] and dw[spin][x][grid]
+        let mut dv_rho = [vec![0.0;num_grids], vec![0.0;num_grids]];
+        let mut dw: [[Vec<f64>;3];2] = [0,1].map(|_| [0,1,2].map(|_| vec![0.0; num_grids]));
+        for g in 0..num_grids {
+            let dr = [drho[[g,0]], drho[[g,1]]];
+            let f = &self.v2rho2[g];
+            dv_rho[0][g] = f[0]*dr[0] + f[1]*dr[1];
+            dv_rho[1][g] = f[1]*dr[0] + f[2]*dr[1];
+            if ! self.use_density_gradient {continue}
+
+            let rp = |s: usize, x: usize| self.rhop[s][x][g];
+            let drp = |s: usize, x: usize| drhop[s][x][g];
+            let ds = [
+                2.0*(0..3).fold(0.0, |acc, x| acc + rp(0,x)*drp(0,x)),
+                (0..3).fold(0.0, |acc, x| acc + rp(0,x)*drp(1,x) + rp(1,x)*drp(0,x)),
+                2.0*(0..3).fold(0.0, |acc, x| acc + rp(1,x)*drp(1,x)),
+            ];
+            let frs = &self.v2rhosigma[g];
+            let fss = &self.v2sigma2[g];
+            // the symmetric 3x3 v2sigma2 from the packed form
+            let fss_full = [[fss[0], fss[1], fss[2]], [fss[1], fss[3], fss[4]], [fss[2], fss[4], fss[5]]];
+            for s in 0..3 {
+                dv_rho[0][g] += frs[s]*ds[s];
+                dv_rho[1][g] += frs[3+s]*ds[s];
+            }
+            let dv_sigma: [f64;3] = [0usize,1usize,2usize].map(|s| {
+                frs[s]*dr[0] + frs[3+s]*dr[1] + (0..3).fold(0.0, |acc, t| acc + fss_full[s][t]*ds[t])
+            });
+            let vs = &self.vsigma[g];
+            for x in 0..3 {
+                dw[0][x][g] = 2.0*dv_sigma[0]*rp(0,x) + dv_sigma[1]*rp(1,x) + 2.0*vs[0]*drp(0,x) + vs[1]*drp(1,x);
+                dw[1][x][g] = 2.0*dv_sigma[2]*rp(1,x) + dv_sigma[1]*rp(0,x) + 2.0*vs[2]*drp(1,x) + vs[1]*drp(0,x);
+            }
+        }
+
+        // dV_{uv} = \int [dv_rho u v + dw . nabla (u v)] dr = W U^T + U W^T,
+        // with W_{u,g} = w_g [1/2 dv_rho_g u_g + dw_g . nabla u_g]
+        let ao = grids.ao.as_ref().unwrap();
+        let ao_ref = ao.to_matrixfullslice();
+        [0usize,1usize].map(|i_spin| {
+            let mut wao = MatrixFull::new([num_basis, num_grids], 0.0);
+            let wv_rho: Vec<f64> = grids.weights.iter().zip(dv_rho[i_spin].iter()).map(|(w, v)| 0.5*w*v).collect();
+            contract_vxc_0_serial(&mut wao, &ao_ref, &wv_rho, None);
+            if self.use_density_gradient {
+                let aop = grids.aop.as_ref().unwrap();
+                for x in 0usize..3usize {
+                    let aop_x = aop.get_reducing_matrix(x).unwrap();
+                    let wdw_x: Vec<f64> = grids.weights.iter().zip(dw[i_spin][x].iter()).map(|(w, v)| w*v).collect();
+                    contract_vxc_0_serial(&mut wao, &aop_x, &wdw_x, None);
+                }
+            }
+            let mut dv = MatrixFull::new([num_basis, num_basis], 0.0);
+            _dgemm_full(&wao, 'N', ao, 'T', &mut dv, 1.0, 0.0);
+            let dv_t = dv.transpose();
+            dv.self_add(&dv_t);
+            dv
+        })
+    }
+}
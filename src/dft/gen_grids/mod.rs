@@ -8,10 +8,10 @@
 
 mod atom;
 mod becke_partitioning;
-mod bragg;
+pub mod bragg;
 mod bse;
 mod comparison;
-mod lebedev;
+pub mod lebedev;
 mod parameters;
 mod python;
 mod radial;
@@ -192,6 +192,12 @@ impl XcFuncType {
         }
     }
 
+    /// Whether the meta-GGA functional depends on the laplacian of the density
+    pub fn use_density_laplacian(&self) -> bool {
+        let flags = unsafe{ffi_xc::xc_func_info_get_flags(self.xc_func_info_type)} as u32;
+        flags & ffi_xc::XC_FLAGS_NEEDS_LAPLACIAN != 0
+    }
+
     pub fn use_exact_exchange(&self) -> bool {
         match self.xc_func_family {
             LibXCFamily::HybridGGA => {true},
@@ -239,6 +245,24 @@ impl XcFuncType {
         unsafe{ffi_xc::xc_hyb_exx_coef(self.xc_func_type)}
     }
 
+    /// The range-separation parameters [omega, alpha, beta] of the hybrid functional, where
+    /// the exact exchange is given by alpha/r + beta*erfc(omega*r)/r
+    pub fn xc_hyb_cam_coeff(&self) -> [f64;3] {
+        let mut omega = 0.0;
+        let mut alpha = 0.0;
+        let mut beta = 0.0;
+        unsafe{ffi_xc::xc_hyb_cam_coef(self.xc_func_type, &mut omega, &mut alpha, &mut beta)};
+        [omega, alpha, beta]
+    }
+
+    /// The parameters [b, C] of the VV10 non-local correlation, which are zero for the functionals without VV10
+    pub fn xc_nlc_coeff(&self) -> [f64;2] {
+        let mut nlc_b = 0.0;
+        let mut nlc_c = 0.0;
+        unsafe{ffi_xc::xc_nlc_coef(self.xc_func_type, &mut nlc_b, &mut nlc_c)};
+        [nlc_b, nlc_c]
+    }
+
     pub fn lda_exc(&self, rho: &[f64]) -> Vec<f64> {
         let length = rho.len()/&self.spin_channel;
         //println!("debug rho length: {}",length);
@@ -306,6 +330,103 @@ impl XcFuncType {
         (exc,vrho,vsigma)
     }
 
+    pub fn mgga_exc(&self, rho: &[f64], sigma: &[f64], tau: &[f64]) -> Vec<f64> {
+        let length = rho.len()/&self.spin_channel;
+        let mut exc = vec![0.0; length];
+        // the laplacian of the density is not tabulated, and set to zero
+        let lapl = vec![0.0; rho.len()];
+        unsafe{
+            ffi_xc::xc_mgga_exc(
+                self.xc_func_type,
+                length as u64,
+                rho.as_ptr(),
+                sigma.as_ptr(),
+                lapl.as_ptr(),
+                tau.as_ptr(),
+                exc.as_mut_ptr());
+        }
+        exc
+    }
+
+    pub fn mgga_exc_vxc(&self, rho: &[f64], sigma: &[f64], tau: &[f64]) -> (Vec<f64>, Vec<f64>, Vec<f64>, Vec<f64>) {
+        let length = rho.len()/&self.spin_channel;
+        let mut exc = vec![0.0; length];
+        let mut vrho = vec![0.0; length*&self.spin_channel];
+        let mut vsigma = if self.spin_channel == 1 {
+            vec![0.0; length]
+        } else {
+            vec![0.0; length*3]
+        };
+        let mut vtau = vec![0.0; length*&self.spin_channel];
+        // the laplacian of the density is not tabulated, and set to zero
+        let lapl = vec![0.0; rho.len()];
+        let mut vlapl = vec![0.0; length*&self.spin_channel];
+        unsafe{
+            ffi_xc::xc_mgga_exc_vxc(
+                self.xc_func_type,
+                length as u64,
+                rho.as_ptr(),
+                sigma.as_ptr(),
+                lapl.as_ptr(),
+                tau.as_ptr(),
+                exc.as_mut_ptr(),
+                vrho.as_mut_ptr(),
+                vsigma.as_mut_ptr(),
+                vlapl.as_mut_ptr(),
+                vtau.as_mut_ptr()
+            );
+        }
+        (exc,vrho,vsigma,vtau)
+    }
+
+    /// The second derivative of the LDA functional: v2rho2 with 1 (unpolarized) or 3 (uu, ud, dd) components per point
+    pub fn lda_fxc(&self, rho: &[f64]) -> Vec<f64> {
+        let length = rho.len()/&self.spin_channel;
+        let mut v2rho2 = if self.spin_channel == 1 {
+            vec![0.0; length]
+        } else {
+            vec![0.0; length*3]
+        };
+        unsafe{
+            ffi_xc::xc_lda_fxc(
+                self.xc_func_type,
+                length as u64,
+                rho.as_ptr(),
+                v2rho2.as_mut_ptr());
+        }
+        v2rho2
+    }
+
+    /// The first and second derivatives of the GGA functional: (vrho, vsigma, v2rho2, v2rhosigma, v2sigma2).
+    /// For the spin-polarized case, the components per point are 2, 3, 3, 6 and 6, respectively, in the libxc order
+    pub fn gga_vxc_fxc(&self, rho: &[f64], sigma: &[f64]) -> (Vec<f64>, Vec<f64>, Vec<f64>, Vec<f64>, Vec<f64>) {
+        let length = rho.len()/&self.spin_channel;
+        let [n_vrho, n_vsigma, n_v2rho2, n_v2rhosigma, n_v2sigma2] = if self.spin_channel == 1 {
+            [1,1,1,1,1]
+        } else {
+            [2,3,3,6,6]
+        };
+        let mut vrho = vec![0.0; length*n_vrho];
+        let mut vsigma = vec![0.0; length*n_vsigma];
+        let mut v2rho2 = vec![0.0; length*n_v2rho2];
+        let mut v2rhosigma = vec![0.0; length*n_v2rhosigma];
+        let mut v2sigma2 = vec![0.0; length*n_v2sigma2];
+        unsafe{
+            ffi_xc::xc_gga_vxc_fxc(
+                self.xc_func_type,
+                length as u64,
+                rho.as_ptr(),
+                sigma.as_ptr(),
+                vrho.as_mut_ptr(),
+                vsigma.as_mut_ptr(),
+                v2rho2.as_mut_ptr(),
+                v2rhosigma.as_mut_ptr(),
+                v2sigma2.as_mut_ptr()
+            );
+        }
+        (vrho, vsigma, v2rho2, v2rhosigma, v2sigma2)
+    }
+
     // xc_func_info relevant functions:
     pub fn get_family_name_std(family_id: u32) -> String {
         if family_id == ffi_xc::XC_FAMILY_LDA {
@@ -1,5 +1,7 @@
 mod libxc;
 pub mod gen_grids;
+pub mod vv10;
+pub mod fxc;
 
 use mpi::collective::SystemOperation;
 use mpi::ffi::MPI_T_SCOPE_GROUP_EQ;
@@ -16,7 +18,8 @@ use rayon::iter::{IntoParallelRefIterator, IndexedParallelIterator, ParallelIter
 use regex::Regex;
 use crate::basis_io::{Basis4Elem, cartesian_gto_cint, cartesian_gto_std, gto_value, BasCell, gto_value_debug, cint_norm_factor, gto_1st_value, spheric_gto_value_matrixfull, spheric_gto_1st_value_batch, spheric_gto_value_matrixfull_serial, spheric_gto_1st_value_batch_serial, spheric_gto_value_serial, spheric_gto_1st_value_serial};
 use crate::molecule_io::Molecule;
-use crate::geom_io::get_mass_charge;
+use crate::geom_io::{get_mass_charge, MOrC};
+use crate::pbc;
 use crate::mpi_io::{mpi_broadcast, mpi_broadcast_vector, mpi_reduce, MPIData, MPIOperator};
 use crate::scf_io::SCF;
 use crate::utilities::{self, balancing};
@@ -35,6 +38,8 @@ use serde::{Deserialize, Serialize};
 use libxc::{XcFuncType, LibXCFamily};
 //use std::intrinsics::expf64;
 
+const MGGA_WITHOUT_AOP: &str = "The meta-GGA functionals require the AO gradients on the grids for the kinetic energy density, which are not available";
+
 #[derive(Clone,Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DFAFamily {
     LDA,
@@ -194,6 +199,26 @@ impl DFA4REST {
             [411,0,0]
         } else if lower_name.eq(&"pbe0".to_string()) {
             [406,0,0]
+        } else if lower_name.eq(&"tpss".to_string()) {
+            [0,202,231]
+        } else if lower_name.eq(&"m06-l".to_string()) || lower_name.eq(&"m06l".to_string()) {
+            [0,203,233]
+        } else if lower_name.eq(&"scan".to_string()) {
+            [0,263,267]
+        } else if lower_name.eq(&"r2scan".to_string()) {
+            [0,497,498]
+        } else if lower_name.eq(&"b97m-v".to_string()) {
+            [254,0,0]
+        } else if lower_name.eq(&"wb97x-v".to_string()) || lower_name.eq(&"ωb97x-v".to_string()) {
+            [466,0,0]
+        } else if lower_name.eq(&"wb97m-v".to_string()) || lower_name.eq(&"ωb97m-v".to_string()) {
+            [531,0,0]
+        } else if lower_name.eq(&"cam-b3lyp".to_string()) || lower_name.eq(&"camb3lyp".to_string()) {
+            [433,0,0]
+        } else if lower_name.eq(&"wb97x".to_string()) || lower_name.eq(&"ωb97x".to_string()) || lower_name.eq(&"omegab97x".to_string()) {
+            [464,0,0]
+        } else if lower_name.eq(&"lc-wpbe".to_string()) || lower_name.eq(&"lcwpbe".to_string()) || lower_name.eq(&"lc-ωpbe".to_string()) {
+            [478,0,0]
         // for a list of exchange functionals
         } else if lower_name.eq(&"lda_x_slater".to_string()) {
             [0,1,0]
@@ -203,6 +228,12 @@ impl DFA4REST {
             [0,101,0]
         } else if lower_name.eq(&"gga_x_xpbe".to_string()) {
             [0,123,0]
+        } else if lower_name.eq(&"mgga_x_tpss".to_string()) {
+            [0,202,0]
+        } else if lower_name.eq(&"mgga_x_scan".to_string()) {
+            [0,263,0]
+        } else if lower_name.eq(&"mgga_x_r2scan".to_string()) {
+            [0,497,0]
         // for a list of correlation functionals
         } else if lower_name.eq(&"lda_c_vwn".to_string()) {
             [0,0,7]
@@ -214,6 +245,12 @@ impl DFA4REST {
             [0,0,130]
         } else if lower_name.eq(&"gga_c_xpbe".to_string()) {
             [0,0,136]
+        } else if lower_name.eq(&"mgga_c_tpss".to_string()) {
+            [0,0,231]
+        } else if lower_name.eq(&"mgga_c_scan".to_string()) {
+            [0,0,267]
+        } else if lower_name.eq(&"mgga_c_r2scan".to_string()) {
+            [0,0,498]
         } else {
             println!("Unknown XC method is specified: {}. The standard Hartree-Fock approximation is involked", &name);
             [0,0,0]
@@ -584,6 +621,67 @@ impl DFA4REST {
         self.dfa_hybrid_scf.abs() >= 1.0e-6
     }
 
+    /// The fraction of the exact exchange used in the scf procedure, which is 1.0 for HF
+    pub fn exx_fraction_scf(&self) -> f64 {
+        if self.is_dfa_scf() {self.dfa_hybrid_scf} else {1.0}
+    }
+
+    /// The range-separation parameters [omega, alpha, beta] of the scf functional taken from libxc,
+    /// such that K = alpha*K(1/r) + beta*K(erfc(omega*r)/r).
+    /// None for the global hybrids and the functionals without exact exchange
+    pub fn dfa_hybrid_cam_scf(&self) -> Option<[f64;3]> {
+        let cam_list = self.dfa_compnt_scf.iter()
+            .map(|xc_func| self.init_libxc(xc_func))
+            .filter(|xc_func| xc_func.use_exact_exchange())
+            .map(|xc_func| xc_func.xc_hyb_cam_coeff())
+            .filter(|cam| cam[0].abs() >= 1.0e-6 && cam[2].abs() >= 1.0e-6)
+            .collect_vec();
+        if cam_list.len() == 1 {
+            Some(cam_list[0])
+        } else {
+            None
+        }
+    }
+
+    /// The parameters [b, C] of the VV10 non-local correlation of the scf functional taken from libxc,
+    /// e.g. for wB97M-V, wB97X-V and B97M-V
+    pub fn vv10_coeff_scf(&self) -> Option<[f64;2]> {
+        self.dfa_compnt_scf.iter()
+            .map(|xc_func| self.init_libxc(xc_func).xc_nlc_coeff())
+            .find(|nlc| nlc[0].abs() >= 1.0e-6)
+    }
+
+    pub fn is_range_separated(&self) -> bool {
+        self.dfa_hybrid_cam_scf().is_some()
+    }
+
+    /// The coefficients [c_full, c_lr] of the exact exchange used in the scf procedure:
+    ///   K = c_full*K(1/r) + c_lr*K(erf(omega*r)/r)
+    /// which is [alpha+beta, -beta] for range-separated hybrids
+    pub fn exx_range_coeff_scf(&self) -> (f64, f64) {
+        if let Some([_, alpha, beta]) = self.dfa_hybrid_cam_scf() {
+            (alpha+beta, -beta)
+        } else {
+            (self.dfa_hybrid_scf, 0.0)
+        }
+    }
+
+    /// The fifth-rung (PT2 and RPA) energies mix the full-range HF exchange of the scf orbitals by
+    /// `dfa_hybrid_scf` and `dfa_hybrid_pos`, so the range-separated components are not yet supported
+    pub fn check_fifth_dfa(&self) -> anyhow::Result<()> {
+        if !self.is_fifth_dfa() {return Ok(())}
+        let is_range_separated_pos = self.dfa_compnt_pos.as_ref().map_or(false, |dfa_compnt_pos| {
+            dfa_compnt_pos.iter().map(|xc_func| self.init_libxc(xc_func))
+                .filter(|xc_func| xc_func.use_exact_exchange())
+                .map(|xc_func| xc_func.xc_hyb_cam_coeff())
+                .any(|cam| cam[0].abs() >= 1.0e-6 && cam[2].abs() >= 1.0e-6)
+        });
+        if self.is_range_separated() || is_range_separated_pos {
+            anyhow::bail!("The range-separated exchange is not yet available in the fifth-rung (PT2 and RPA) functionals, whose post-scf energies use the full-range HF exchange");
+        }
+        Ok(())
+    }
+
     pub fn is_fifth_dfa(&self) -> bool {
         match self.dfa_family_pos {
             None => false,
@@ -592,7 +690,7 @@ impl DFA4REST {
     }
 
     pub fn use_eri(&self) -> bool {
-        self.is_hybrid() || self.is_fifth_dfa()
+        self.is_hybrid() || self.is_range_separated() || self.is_fifth_dfa()
     }
 
 
@@ -620,7 +718,16 @@ impl DFA4REST {
         is_flag
     }
 
-    pub fn xc_exc_vxc(&self, grids: &Grids, spin_channel: usize, dm: &Vec<MatrixFull<f64>>, mo: &[MatrixFull<f64>;2], occ: &[Vec<f64>;2], print_level:usize) -> (Vec<f64>, Vec<MatrixFull<f64>>) {
+    /// The meta-GGA functionals depending on the laplacian of the density are not yet supported
+    pub fn check_meta_gga(&self) -> anyhow::Result<()> {
+        let dfa_compnt = self.dfa_compnt_scf.iter().chain(self.dfa_compnt_pos.iter().flatten());
+        if let Some(xc_code) = dfa_compnt.find(|xc_code| self.init_libxc(xc_code).use_density_laplacian()) {
+            anyhow::bail!("The meta-GGA functionals depending on the laplacian of the density are not yet supported: libxc functional {}", xc_code)
+        }
+        Ok(())
+    }
+
+    pub fn xc_exc_vxc(&self, grids: &Grids, spin_channel: usize, dm: &Vec<MatrixFull<f64>>, mo: &[MatrixFull<f64>;2], occ: &[Vec<f64>;2], print_level:usize) -> (Vec<f64>, Vec<MatrixFull<f64>>, Vec<MatrixFull<f64>>) {
         let num_grids = grids.coordinates.len();
         let num_basis = dm[0].size[0];
         let mut exc = MatrixFull::new([num_grids,1],0.0);
@@ -646,6 +753,17 @@ impl DFA4REST {
         };
         let dt3 = utilities::timing(&dt2, Some("evaluate sigma"));
 
+        let tau = if self.use_kinetic_density() {
+            grids.prepare_tabulated_tau_slots(mo, occ, spin_channel, 0..num_grids)
+        } else {
+            MatrixFull::empty()
+        };
+        let mut vtau = if self.use_kinetic_density() {
+            MatrixFull::new([num_grids,spin_channel],0.0)
+        } else {
+            MatrixFull::empty()
+        };
+
         self.dfa_compnt_scf.iter().zip(self.dfa_paramr_scf.iter()).for_each(|(xc_func,xc_para)| {
             let xc_func = self.init_libxc(xc_func);
             match xc_func.xc_func_family {
@@ -685,6 +803,29 @@ impl DFA4REST {
                         vsigma.par_self_scaled_add(&tmp_vsigma.transpose_and_drop(), *xc_para);
                     }
                 },
+                libxc::LibXCFamily::MGGA | libxc::LibXCFamily::HybridMGGA => {
+                    if spin_channel==1 {
+                        let (tmp_exc,tmp_vrho,tmp_vsigma,tmp_vtau) = xc_func.mgga_exc_vxc(rho.data_ref().unwrap(),sigma.data_ref().unwrap(),tau.data_ref().unwrap());
+                        let tmp_exc = MatrixFull::from_vec([num_grids,1],tmp_exc).unwrap();
+                        let tmp_vrho = MatrixFull::from_vec([num_grids,1],tmp_vrho).unwrap();
+                        let tmp_vsigma= MatrixFull::from_vec([num_grids,1],tmp_vsigma).unwrap();
+                        let tmp_vtau = MatrixFull::from_vec([num_grids,1],tmp_vtau).unwrap();
+                        exc.par_self_scaled_add(&tmp_exc,*xc_para);
+                        vrho.par_self_scaled_add(&tmp_vrho,*xc_para);
+                        vsigma.par_self_scaled_add(&tmp_vsigma, *xc_para);
+                        vtau.par_self_scaled_add(&tmp_vtau, *xc_para);
+                    } else {
+                        let (tmp_exc,tmp_vrho,tmp_vsigma,tmp_vtau) = xc_func.mgga_exc_vxc(rho.transpose().data_ref().unwrap(),sigma.transpose().data_ref().unwrap(),tau.transpose().data_ref().unwrap());
+                        let tmp_exc = MatrixFull::from_vec([num_grids,1],tmp_exc).unwrap();
+                        let tmp_vrho = MatrixFull::from_vec([2,num_grids],tmp_vrho).unwrap();
+                        let tmp_vsigma= MatrixFull::from_vec([3,num_grids],tmp_vsigma).unwrap();
+                        let tmp_vtau = MatrixFull::from_vec([2,num_grids],tmp_vtau).unwrap();
+                        exc.par_self_scaled_add(&tmp_exc,*xc_para);
+                        vrho.par_self_scaled_add(&tmp_vrho.transpose_and_drop(),*xc_para);
+                        vsigma.par_self_scaled_add(&tmp_vsigma.transpose_and_drop(), *xc_para);
+                        vtau.par_self_scaled_add(&tmp_vtau.transpose_and_drop(), *xc_para);
+                    }
+                },
                 _ => {println!("{} is not yet implemented", xc_func.get_family_name())}
             }
         });
@@ -834,7 +975,21 @@ impl DFA4REST {
 
         let dt7 = utilities::timing(&dt6, Some("weight vxc_ao"));
 
-        (exc_total,vxc_ao)
+        // for vtau, which is contracted with the ao gradients directly into the matrix form
+        let vtau_mf = if self.use_kinetic_density() {
+            if let Some(aop) = &grids.aop {
+                (0..spin_channel).map(|i_spin| {
+                    let wvtau = vtau.iter_column(i_spin).zip(grids.weights.iter()).map(|(v,w)| v*w).collect::<Vec<f64>>();
+                    contract_vtau_slots(aop, 0..num_grids, &wvtau)
+                }).collect::<Vec<MatrixFull<f64>>>()
+            } else {
+                panic!("{}", MGGA_WITHOUT_AOP)
+            }
+        } else {
+            vec![]
+        };
+
+        (exc_total,vxc_ao,vtau_mf)
     }
     pub fn xc_exc_vxc_slots_dm_only(&self, 
         range_grids: Range<usize>, 
@@ -842,7 +997,7 @@ impl DFA4REST {
         spin_channel: usize, 
         dm: &Vec<MatrixFull<f64>>, 
         mo: &[MatrixFull<f64>;2], 
-        occ: &[Vec<f64>;2]) -> (Vec<f64>, Vec<MatrixFull<f64>>,[f64;2]) 
+        occ: &[Vec<f64>;2]) -> (Vec<f64>, Vec<MatrixFull<f64>>,[f64;2],Vec<MatrixFull<f64>>) 
         {
         //let num_grids = grids.coordinates.len();
         let num_grids = range_grids.len();
@@ -879,6 +1034,17 @@ impl DFA4REST {
             MatrixFull::empty()
         };
 
+        let loc_tau = if self.use_kinetic_density() {
+            grids.prepare_tabulated_tau_slots_dm_only(dm, spin_channel, range_grids.clone())
+        } else {
+            MatrixFull::empty()
+        };
+        let mut loc_vtau = if self.use_kinetic_density() {
+            MatrixFull::new([num_grids,spin_channel],0.0)
+        } else {
+            MatrixFull::empty()
+        };
+
         self.dfa_compnt_scf.iter().zip(self.dfa_paramr_scf.iter()).for_each(|(xc_func,xc_para)| {
             let xc_func = self.init_libxc(xc_func);
             match xc_func.xc_func_family {
@@ -918,6 +1084,29 @@ impl DFA4REST {
                         loc_vsigma.self_scaled_add(&tmp_vsigma.transpose_and_drop(), *xc_para);
                     }
                 },
+                libxc::LibXCFamily::MGGA | libxc::LibXCFamily::HybridMGGA => {
+                    if spin_channel==1 {
+                        let (tmp_exc,tmp_vrho,tmp_vsigma,tmp_vtau) = xc_func.mgga_exc_vxc(loc_rho.data_ref().unwrap(),loc_sigma.data_ref().unwrap(),loc_tau.data_ref().unwrap());
+                        let tmp_exc = MatrixFull::from_vec([num_grids,1],tmp_exc).unwrap();
+                        let tmp_vrho = MatrixFull::from_vec([num_grids,1],tmp_vrho).unwrap();
+                        let tmp_vsigma= MatrixFull::from_vec([num_grids,1],tmp_vsigma).unwrap();
+                        let tmp_vtau = MatrixFull::from_vec([num_grids,1],tmp_vtau).unwrap();
+                        loc_exc.self_scaled_add(&tmp_exc,*xc_para);
+                        loc_vrho.self_scaled_add(&tmp_vrho,*xc_para);
+                        loc_vsigma.self_scaled_add(&tmp_vsigma, *xc_para);
+                        loc_vtau.self_scaled_add(&tmp_vtau, *xc_para);
+                    } else {
+                        let (tmp_exc,tmp_vrho,tmp_vsigma,tmp_vtau) = xc_func.mgga_exc_vxc(loc_rho.transpose().data_ref().unwrap(),loc_sigma.transpose().data_ref().unwrap(),loc_tau.transpose().data_ref().unwrap());
+                        let tmp_exc = MatrixFull::from_vec([num_grids,1],tmp_exc).unwrap();
+                        let tmp_vrho = MatrixFull::from_vec([2,num_grids],tmp_vrho).unwrap();
+                        let tmp_vsigma= MatrixFull::from_vec([3,num_grids],tmp_vsigma).unwrap();
+                        let tmp_vtau = MatrixFull::from_vec([2,num_grids],tmp_vtau).unwrap();
+                        loc_exc.self_scaled_add(&tmp_exc,*xc_para);
+                        loc_vrho.self_scaled_add(&tmp_vrho.transpose_and_drop(),*xc_para);
+                        loc_vsigma.self_scaled_add(&tmp_vsigma.transpose_and_drop(), *xc_para);
+                        loc_vtau.self_scaled_add(&tmp_vtau.transpose_and_drop(), *xc_para);
+                    }
+                },
                 _ => {println!("{} is not yet implemented", xc_func.get_family_name())}
             }
         });
@@ -1054,7 +1243,88 @@ impl DFA4REST {
             });
         }
 
-        (loc_exc_total,loc_vxc_ao,loc_total_elec)
+        // for vtau, which is contracted with the ao gradients directly into the matrix form
+        let loc_vtau_mf = if self.use_kinetic_density() {
+            if let Some(aop) = &grids.aop {
+                (0..spin_channel).map(|i_spin| {
+                    let loc_wvtau = loc_vtau.iter_column(i_spin).zip(loc_weights.iter()).map(|(v,w)| v*w).collect::<Vec<f64>>();
+                    contract_vtau_slots(aop, range_grids.clone(), &loc_wvtau)
+                }).collect::<Vec<MatrixFull<f64>>>()
+            } else {
+                panic!("{}", MGGA_WITHOUT_AOP)
+            }
+        } else {
+            vec![]
+        };
+
+        (loc_exc_total,loc_vxc_ao,loc_total_elec,loc_vtau_mf)
+    }
+
+    /// Tabulate the density, density gradient and the first functional derivatives (vrho, vsigma)
+    /// on the given slot of grids. It is used by the analytic nuclear gradients,
+    /// where the potential is contracted with the derivatives of atomic orbitals directly.
+    ///   rho:    [num_grids, spin_channel]
+    ///   rhop:   [num_grids, 3, spin_channel]
+    ///   vrho:   [num_grids, spin_channel]
+    ///   vsigma: [num_grids, 1] for spin_channel=1, and [num_grids, 3] for spin_channel=2
+    pub fn xc_vrho_vsigma_slots_dm_only(&self,
+        range_grids: Range<usize>,
+        grids: &Grids,
+        spin_channel: usize,
+        dm: &Vec<MatrixFull<f64>>) -> (MatrixFull<f64>, RIFull<f64>, MatrixFull<f64>, MatrixFull<f64>)
+        {
+        let num_grids = range_grids.len();
+
+        let (loc_rho,loc_rhop) = grids.prepare_tabulated_density_slots_dm_only(dm, spin_channel,range_grids.clone());
+        let loc_sigma = if self.use_density_gradient() {
+            prepare_tabulated_sigma(&loc_rhop, spin_channel)
+        } else {
+            MatrixFull::empty()
+        };
+
+        let mut loc_vrho = MatrixFull::new([num_grids,spin_channel],0.0);
+        let mut loc_vsigma=if self.use_density_gradient() && spin_channel==1 {
+            MatrixFull::new([num_grids,1],0.0)
+        } else if self.use_density_gradient() && spin_channel==2 {
+            MatrixFull::new([num_grids,3],0.0)
+        } else {
+            MatrixFull::empty()
+        };
+
+        self.dfa_compnt_scf.iter().zip(self.dfa_paramr_scf.iter()).for_each(|(xc_func,xc_para)| {
+            let xc_func = self.init_libxc(xc_func);
+            match xc_func.xc_func_family {
+                libxc::LibXCFamily::LDA => {
+                    if spin_channel==1 {
+                        let (_,tmp_vrho) = xc_func.lda_exc_vxc(loc_rho.data_ref().unwrap());
+                        let tmp_vrho = MatrixFull::from_vec([num_grids,1],tmp_vrho).unwrap();
+                        loc_vrho.self_scaled_add(&tmp_vrho,*xc_para);
+                    } else {
+                        let (_,tmp_vrho) = xc_func.lda_exc_vxc(loc_rho.transpose().data_ref().unwrap());
+                        let tmp_vrho = MatrixFull::from_vec([2,num_grids],tmp_vrho).unwrap();
+                        loc_vrho.self_scaled_add(&tmp_vrho.transpose_and_drop(),*xc_para);
+                    }
+                },
+                libxc::LibXCFamily::GGA | libxc::LibXCFamily::HybridGGA => {
+                    if spin_channel==1 {
+                        let (_,tmp_vrho, tmp_vsigma) = xc_func.gga_exc_vxc(loc_rho.data_ref().unwrap(),loc_sigma.data_ref().unwrap());
+                        let tmp_vrho = MatrixFull::from_vec([num_grids,1],tmp_vrho).unwrap();
+                        let tmp_vsigma= MatrixFull::from_vec([num_grids,1],tmp_vsigma).unwrap();
+                        loc_vrho.self_scaled_add(&tmp_vrho,*xc_para);
+                        loc_vsigma.self_scaled_add(&tmp_vsigma, *xc_para);
+                    } else {
+                        let (_,tmp_vrho, tmp_vsigma) = xc_func.gga_exc_vxc(loc_rho.transpose().data_ref().unwrap(),loc_sigma.transpose().data_ref().unwrap());
+                        let tmp_vrho = MatrixFull::from_vec([2,num_grids],tmp_vrho).unwrap();
+                        let tmp_vsigma= MatrixFull::from_vec([3,num_grids],tmp_vsigma).unwrap();
+                        loc_vrho.self_scaled_add(&tmp_vrho.transpose_and_drop(),*xc_para);
+                        loc_vsigma.self_scaled_add(&tmp_vsigma.transpose_and_drop(), *xc_para);
+                    }
+                },
+                _ => {println!("{} is not yet implemented", xc_func.get_family_name())}
+            }
+        });
+
+        (loc_rho, loc_rhop, loc_vrho, loc_vsigma)
     }
 
     pub fn xc_exc_vxc_slots(&self, 
@@ -1063,7 +1333,7 @@ impl DFA4REST {
         spin_channel: usize, 
         dm: &Vec<MatrixFull<f64>>, 
         mo: &[MatrixFull<f64>;2], 
-        occ: &[Vec<f64>;2]) -> (Vec<f64>, Vec<MatrixFull<f64>>,[f64;2]) 
+        occ: &[Vec<f64>;2]) -> (Vec<f64>, Vec<MatrixFull<f64>>,[f64;2],Vec<MatrixFull<f64>>) 
         {
         //let num_grids = grids.coordinates.len();
         let num_grids = range_grids.len();
@@ -1104,6 +1374,17 @@ impl DFA4REST {
         //    self.dfa_paramr_scf
         //}
 
+        let loc_tau = if self.use_kinetic_density() {
+            grids.prepare_tabulated_tau_slots(mo, occ, spin_channel, range_grids.clone())
+        } else {
+            MatrixFull::empty()
+        };
+        let mut loc_vtau = if self.use_kinetic_density() {
+            MatrixFull::new([num_grids,spin_channel],0.0)
+        } else {
+            MatrixFull::empty()
+        };
+
         self.dfa_compnt_scf.iter().zip(self.dfa_paramr_scf.iter()).for_each(|(xc_func,xc_para)| {
             let xc_func = self.init_libxc(xc_func);
             match xc_func.xc_func_family {
@@ -1143,6 +1424,29 @@ impl DFA4REST {
                         loc_vsigma.self_scaled_add(&tmp_vsigma.transpose_and_drop(), *xc_para);
                     }
                 },
+                libxc::LibXCFamily::MGGA | libxc::LibXCFamily::HybridMGGA => {
+                    if spin_channel==1 {
+                        let (tmp_exc,tmp_vrho,tmp_vsigma,tmp_vtau) = xc_func.mgga_exc_vxc(loc_rho.data_ref().unwrap(),loc_sigma.data_ref().unwrap(),loc_tau.data_ref().unwrap());
+                        let tmp_exc = MatrixFull::from_vec([num_grids,1],tmp_exc).unwrap();
+                        let tmp_vrho = MatrixFull::from_vec([num_grids,1],tmp_vrho).unwrap();
+                        let tmp_vsigma= MatrixFull::from_vec([num_grids,1],tmp_vsigma).unwrap();
+                        let tmp_vtau = MatrixFull::from_vec([num_grids,1],tmp_vtau).unwrap();
+                        loc_exc.self_scaled_add(&tmp_exc,*xc_para);
+                        loc_vrho.self_scaled_add(&tmp_vrho,*xc_para);
+                        loc_vsigma.self_scaled_add(&tmp_vsigma, *xc_para);
+                        loc_vtau.self_scaled_add(&tmp_vtau, *xc_para);
+                    } else {
+                        let (tmp_exc,tmp_vrho,tmp_vsigma,tmp_vtau) = xc_func.mgga_exc_vxc(loc_rho.transpose().data_ref().unwrap(),loc_sigma.transpose().data_ref().unwrap(),loc_tau.transpose().data_ref().unwrap());
+                        let tmp_exc = MatrixFull::from_vec([num_grids,1],tmp_exc).unwrap();
+                        let tmp_vrho = MatrixFull::from_vec([2,num_grids],tmp_vrho).unwrap();
+                        let tmp_vsigma= MatrixFull::from_vec([3,num_grids],tmp_vsigma).unwrap();
+                        let tmp_vtau = MatrixFull::from_vec([2,num_grids],tmp_vtau).unwrap();
+                        loc_exc.self_scaled_add(&tmp_exc,*xc_para);
+                        loc_vrho.self_scaled_add(&tmp_vrho.transpose_and_drop(),*xc_para);
+                        loc_vsigma.self_scaled_add(&tmp_vsigma.transpose_and_drop(), *xc_para);
+                        loc_vtau.self_scaled_add(&tmp_vtau.transpose_and_drop(), *xc_para);
+                    }
+                },
                 _ => {println!("{} is not yet implemented", xc_func.get_family_name())}
             }
         });
@@ -1279,7 +1583,21 @@ impl DFA4REST {
             });
         }
 
-        (loc_exc_total,loc_vxc_ao,loc_total_elec)
+        // for vtau, which is contracted with the ao gradients directly into the matrix form
+        let loc_vtau_mf = if self.use_kinetic_density() {
+            if let Some(aop) = &grids.aop {
+                (0..spin_channel).map(|i_spin| {
+                    let loc_wvtau = loc_vtau.iter_column(i_spin).zip(loc_weights.iter()).map(|(v,w)| v*w).collect::<Vec<f64>>();
+                    contract_vtau_slots(aop, range_grids.clone(), &loc_wvtau)
+                }).collect::<Vec<MatrixFull<f64>>>()
+            } else {
+                panic!("{}", MGGA_WITHOUT_AOP)
+            }
+        } else {
+            vec![]
+        };
+
+        (loc_exc_total,loc_vxc_ao,loc_total_elec,loc_vtau_mf)
     }
 
     pub fn post_xc_exc(&self, post_xc: &Vec<String>, grids: &crate::dft::Grids, dm: &Vec<MatrixFull<f64>>, mo: &[MatrixFull<f64>;2], occ: &[Vec<f64>;2]) 
@@ -1294,7 +1612,15 @@ impl DFA4REST {
             let code = DFA4REST::xc_func_init_fdqc(x,spin_channel);
             let x_flag = code.iter().fold(false, |flag, xc_code| {
                 let xc_func = self.init_libxc(xc_code);
-                flag || xc_func.is_gga()|| xc_func.is_hybrid_gga()
+                flag || xc_func.is_gga()|| xc_func.is_hybrid_gga() || xc_func.is_mgga() || xc_func.is_hybrid_mgga()
+            });
+            flag || x_flag
+        });
+        let use_kinetic_density = post_xc.iter().fold(false,|flag, x| {
+            let code = DFA4REST::xc_func_init_fdqc(x,spin_channel);
+            let x_flag = code.iter().fold(false, |flag, xc_code| {
+                let xc_func = self.init_libxc(xc_code);
+                flag || xc_func.use_kinetic_density()
             });
             flag || x_flag
         });
@@ -1303,13 +1629,18 @@ impl DFA4REST {
         } else {
             MatrixFull::empty()
         };
+        let tau = if use_kinetic_density {
+            grids.prepare_tabulated_tau_slots(mo, occ, spin_channel, 0..num_grids)
+        } else {
+            MatrixFull::empty()
+        };
         post_xc.iter().for_each(|x| {
             let mut exc = MatrixFull::new([num_grids,1],0.0);
             let mut exc_total =[0.0,0.0];
             let code = DFA4REST::xc_func_init_fdqc(x,spin_channel);
             //println!("debug xc_code: {:?}", &code);
             code.iter().for_each(|xc_code| {
-                exc.par_self_scaled_add(&self.xc_exc_code(xc_code, &rho, &sigma, spin_channel),1.0);
+                exc.par_self_scaled_add(&self.xc_exc_code(xc_code, &rho, &sigma, &tau, spin_channel),1.0);
             });
 
             for i_spin in 0..spin_channel {
@@ -1340,6 +1671,11 @@ impl DFA4REST {
         } else {
             MatrixFull::empty()
         };
+        let tau = if self.use_kinetic_density() {
+            grids.prepare_tabulated_tau_slots(mo, occ, spin_channel, 0..num_grids)
+        } else {
+            MatrixFull::empty()
+        };
 
         if iop==0 {  // for the SCF energy
             //let rho_trans = if spin_channel== 1 {
@@ -1370,6 +1706,16 @@ impl DFA4REST {
                         ).unwrap();
                         exc.par_self_scaled_add(&tmp_exc,*xc_para);
                     },
+                    libxc::LibXCFamily::MGGA | libxc::LibXCFamily::HybridMGGA => {
+                        let tmp_exc = MatrixFull::from_vec([num_grids,1],
+                            if spin_channel==1 {
+                                xc_func.mgga_exc(rho.data_ref().unwrap(),sigma.data_ref().unwrap(),tau.data_ref().unwrap())
+                            } else {
+                                xc_func.mgga_exc(rho.transpose().data_ref().unwrap(),sigma.transpose().data_ref().unwrap(),tau.transpose().data_ref().unwrap())
+                            }
+                        ).unwrap();
+                        exc.par_self_scaled_add(&tmp_exc,*xc_para);
+                    },
                     _ => {println!("{} is not yet implemented", xc_func.get_family_name())}
                 }
             });
@@ -1398,6 +1744,16 @@ impl DFA4REST {
                             ).unwrap();
                             exc.par_self_scaled_add(&tmp_exc,*xc_para);
                         },
+                        libxc::LibXCFamily::MGGA | libxc::LibXCFamily::HybridMGGA => {
+                            let tmp_exc = MatrixFull::from_vec([num_grids,1],
+                                if spin_channel==1 {
+                                    xc_func.mgga_exc(rho.data_ref().unwrap(),sigma.data_ref().unwrap(),tau.data_ref().unwrap())
+                                } else {
+                                    xc_func.mgga_exc(rho.transpose().data_ref().unwrap(),sigma.transpose().data_ref().unwrap(),tau.transpose().data_ref().unwrap())
+                                }
+                            ).unwrap();
+                            exc.par_self_scaled_add(&tmp_exc,*xc_para);
+                        },
                         _ => {println!("{} is not yet implemented", xc_func.get_family_name())}
                     }
                 });
@@ -1433,7 +1789,7 @@ impl DFA4REST {
         global_exc_total
 
     }
-    pub fn xc_exc_code(&self, xc_code: &usize, rho: &MatrixFull<f64>, sigma:&MatrixFull<f64>, spin_channel: usize) -> MatrixFull<f64> {
+    pub fn xc_exc_code(&self, xc_code: &usize, rho: &MatrixFull<f64>, sigma:&MatrixFull<f64>, tau: &MatrixFull<f64>, spin_channel: usize) -> MatrixFull<f64> {
         let xc_func = self.init_libxc(xc_code);
         let num_grids = rho.size()[0];
         let tmp_exc = match xc_func.xc_func_family {
@@ -1458,12 +1814,41 @@ impl DFA4REST {
                 ).unwrap()
                 //exc.par_self_scaled_add(&tmp_exc,*xc_para);
             },
+            libxc::LibXCFamily::MGGA | libxc::LibXCFamily::HybridMGGA => {
+                MatrixFull::from_vec([num_grids,1],
+                    if spin_channel==1 {
+                        xc_func.mgga_exc(rho.data_ref().unwrap(),sigma.data_ref().unwrap(),tau.data_ref().unwrap())
+                    } else {
+                        xc_func.mgga_exc(rho.transpose().data_ref().unwrap(),sigma.transpose().data_ref().unwrap(),tau.transpose().data_ref().unwrap())
+                    }
+                ).unwrap()
+            },
             _ => {panic!("{} is not yet implemented", xc_func.get_family_name())}
         };
         tmp_exc
     }
 }
 
+/// Contract the weighted potential of the kinetic energy density with the AO gradients on the given grids:
+///   vtau_mf[mu,nu] = 1/2 \sum_g \sum_x d_x phi_mu(g) wvtau(g) d_x phi_nu(g)
+/// with the shape of [num_basis, num_basis]
+pub fn contract_vtau_slots(aop: &RIFull<f64>, range_grids: Range<usize>, wvtau: &[f64]) -> MatrixFull<f64> {
+    let num_basis = aop.size[0];
+    let num_grids = range_grids.len();
+    let mut vtau_mf = MatrixFull::new([num_basis,num_basis],0.0);
+    for x in 0usize..3usize {
+        let loc_aop_x = aop.get_reducing_matrix_columns(range_grids.clone(),x).unwrap();
+        let mut loc_wao = MatrixFull::new([num_basis,num_grids],0.0);
+        contract_vxc_0_serial(&mut loc_wao, &loc_aop_x, wvtau, Some(0.5));
+        let aop_x = aop.get_reducing_matrix(x).unwrap();
+        _dgemm(
+            &aop_x, (0..num_basis, range_grids.clone()), 'N',
+            &loc_wao, (0..num_basis, 0..num_grids), 'T',
+            &mut vtau_mf, (0..num_basis, 0..num_basis), 1.0, 1.0);
+    }
+    vtau_mf
+}
+
 pub fn contract_vxc_0(mat_a: &mut MatrixFull<f64>, mat_b: &MatrixFullSlice<f64>, slice_c: &[f64], scaling_factor: Option<f64>) {
     match scaling_factor {
         None =>  {
@@ -1679,9 +2064,17 @@ impl Grids {
         let rad_grid_method: String = mol.ctrl.rad_grid_method.clone();
 
         // obtain system-dependent parameters
-        let mass_charge = get_mass_charge(&mol.geom.elem);
-        let proton_charges: Vec<i32> = mass_charge.iter().map(|value| value.1 as i32).collect();
-        let center_coordinates_bohr = mol.geom.to_numgrid_io();
+        // for crystals, the image atoms nearby are included in the Becke partition of the grids in the cell,
+        // while the grid points beyond pbc::grid_radius from their centers, where the density vanishes, are dropped
+        let (center_coordinates_bohr, proton_charges, grid_radius) = if let MOrC::Crystal = mol.geom.pbc {
+            let grid_radius = pbc::grid_radius(mol);
+            let (centers, charges) = pbc::grid_centers(&mol.geom, 2.0*grid_radius);
+            (centers, charges, grid_radius)
+        } else {
+            let mass_charge = get_mass_charge(&mol.geom.elem);
+            let proton_charges: Vec<i32> = mass_charge.iter().map(|value| value.1 as i32).collect();
+            (mol.geom.to_numgrid_io(), proton_charges, f64::MAX)
+        };
         //mol.fdqc_bas[0].
         let mut alpha_max: Vec<f64> = vec![];
         let mut alpha_min: Vec<HashMap<usize,f64>> = vec![];
@@ -1713,9 +2106,14 @@ impl Grids {
             );
             //println!("alpha_min: {:?}, alpha_max: {:6.3}",&value.0, &value.1);
             //println!("rs_atom: {:?}, ws_atom: {:?}",&rs_atom, &ws_atom);
-            num_points += rs_atom.len();
-            coordinates.extend(rs_atom.iter().map(|value| [value.0,value.1,value.2]));
-            weights.extend(ws_atom);
+            let center = center_coordinates_bohr[center_index];
+            rs_atom.iter().zip(ws_atom.iter()).filter(|(r, _)| {
+                ((r.0-center.0).powi(2) + (r.1-center.1).powi(2) + (r.2-center.2).powi(2)).sqrt() <= grid_radius
+            }).for_each(|(r, w)| {
+                num_points += 1;
+                coordinates.push([r.0,r.1,r.2]);
+                weights.push(*w);
+            });
         });
 
         utilities::timing(&dt0, Some("Generating the grids"));
@@ -1817,6 +2215,9 @@ impl Grids {
             None
         };
 
+        // for crystals, the basis functions are summed over the lattice images
+        let ao_images = if let MOrC::Crystal = mol.geom.pbc {Some(pbc::ao_image_translations(mol))} else {None};
+
         let par_tasks = utilities::balancing(num_grids, rayon::current_num_threads());
         let (sender, receiver) = channel();
         par_tasks.par_iter().for_each_with(sender, |s, range_grids| {
@@ -1829,20 +2230,28 @@ impl Grids {
             } else {
                 RIFull::empty()
             };
-            mol.basis4elem.iter().zip(mol.geom.position.iter_columns_full()).for_each(|(elem, geom)| {
+            mol.basis4elem.iter().zip(mol.geom.position.iter_columns_full()).enumerate().for_each(|(i_atom, (elem, geom))| {
                 let ind_glb_bas = elem.global_index.0;
                 let loc_num_bas = elem.global_index.1;
                 let start = ind_glb_bas;
                 let end = start + loc_num_bas;
                 let mut tmp_geom = [0.0;3];
                 tmp_geom.iter_mut().zip(geom.iter()).for_each(|value| {*value.0 = *value.1});
-                let tab_den = spheric_gto_value_serial(&self.coordinates[range_grids.clone()], &tmp_geom, elem);
+                let tab_den = if let Some(ao_images) = &ao_images {
+                    pbc::image_gto_value(&self.coordinates[range_grids.clone()], &tmp_geom, &ao_images[i_atom], elem)
+                } else {
+                    spheric_gto_value_serial(&self.coordinates[range_grids.clone()], &tmp_geom, elem)
+                };
 
                 loc_ao.copy_from_matr(start..end, 0..loc_num_grids, &tab_den, 0..loc_num_bas, 0..loc_num_grids);
 
                 if mol.xc_data.use_density_gradient() {
                     //println!("debug 01");
-                    let tab_dev = spheric_gto_1st_value_serial(&self.coordinates[range_grids.clone()], &tmp_geom, elem);
+                    let tab_dev = if let Some(ao_images) = &ao_images {
+                        pbc::image_gto_1st_value(&self.coordinates[range_grids.clone()], &tmp_geom, &ao_images[i_atom], elem)
+                    } else {
+                        spheric_gto_1st_value_serial(&self.coordinates[range_grids.clone()], &tmp_geom, elem)
+                    };
                     //println!("debug 02");
                     for x in 0..3 {
                         let gto_1st_x = &tab_dev[x];
@@ -2015,7 +2424,8 @@ impl Grids {
         time_records.count("TabAO");
         time_records.report_all();
 
-        // to implement kinetic density
+        // the kinetic density for meta-GGA is evaluated on the fly from aop,
+        // see prepare_tabulated_tau_slots and prepare_tabulated_tau_slots_dm_only
     }
     pub fn prepare_tabulated_density_prev(&self, dm: &mut Vec<MatrixFull<f64>>, spin_channel: usize) -> MatrixFull<f64> {
         let mut cur_rho = MatrixFull::new([self.coordinates.len(),spin_channel],0.0);
@@ -2284,6 +2694,74 @@ impl Grids {
         (cur_rho, cur_rhop)
     }
 
+    /// Tabulate the kinetic energy density on the given grids with the shape of [num_grids, spin_channel]:
+    ///   tau_s = 1/2 \sum_i n_i |nabla psi_i|^2
+    pub fn prepare_tabulated_tau_slots(&self, mo: &[MatrixFull<f64>;2], occ: &[Vec<f64>;2], spin_channel: usize, range_grids: Range<usize>) -> MatrixFull<f64> {
+        let num_grids = range_grids.len();
+        let mut cur_tau = MatrixFull::new([num_grids,spin_channel],0.0);
+        if let Some(aop) = &self.aop {
+            for i_spin in 0..spin_channel {
+                let mo_s = mo.get(i_spin).unwrap();
+                let homo_s  = occ[i_spin].iter().enumerate()
+                    .filter(|(i,occ)| **occ >=1.0e-6)
+                    .map(|(i,occ)| i).max();
+                let occ_s = if let Some(homo_s) = homo_s {
+                    occ.get(i_spin).unwrap()[0..homo_s+1].iter().map(|occ| occ.sqrt()).collect::<Vec<f64>>()
+                } else {
+                    // In this case, no electrons in the i_spin channel, for which homo_s = None
+                    vec![]
+                };
+                let num_occ = occ_s.len();
+                if num_occ == 0 {continue};
+                let wmo = _einsum_01_serial(&mo_s.to_matrixfullslice(),&occ_s);
+                for i in (0..3) {
+                    let mut tmop = MatrixFull::new([num_occ,num_grids],0.0);
+                    let aop_i = aop.get_reducing_matrix(i).unwrap();
+                    _dgemm(&wmo, (0..wmo.size[0], 0..wmo.size[1]), 'T',
+                           &aop_i, (0..aop_i.size[0],range_grids.clone()), 'N',
+                           &mut tmop, (0..wmo.size[1],0..num_grids),
+                           1.0,0.0
+                    );
+                    let taui_s = _einsum_02_serial(&tmop.to_matrixfullslice(), &tmop.to_matrixfullslice());
+                    cur_tau.iter_column_mut(i_spin).zip(taui_s.iter()).for_each(|(to, from)| {*to += 0.5*from});
+                }
+            }
+        } else {
+            panic!("{}", MGGA_WITHOUT_AOP)
+        }
+        cur_tau
+    }
+
+    /// Tabulate the kinetic energy density from the density matrix with the shape of [num_grids, spin_channel]:
+    ///   tau_s = 1/2 \sum_x \sum_{mu,nu} d_x phi_mu dm_s[mu,nu] d_x phi_nu
+    pub fn prepare_tabulated_tau_slots_dm_only(&self, dm: &Vec<MatrixFull<f64>>, spin_channel: usize, range_grids: Range<usize>) -> MatrixFull<f64> {
+        let num_grids = range_grids.len();
+        let num_basis = dm[0].size[0];
+        let mut cur_tau = MatrixFull::new([num_grids,spin_channel],0.0);
+        if let Some(aop) = &self.aop {
+            for i_spin in 0..spin_channel {
+                let dm_s = &dm[i_spin];
+                for i in (0..3) {
+                    let aop_i = aop.get_reducing_matrix(i).unwrap();
+                    let mut wao = MatrixFull::new([num_basis,num_grids],0.0);
+                    _dgemm(
+                        dm_s, (0..num_basis,0..num_basis),'N',
+                        &aop_i, (0..num_basis, range_grids.clone()),'N',
+                        &mut wao, (0..num_basis, 0..num_grids), 1.0, 0.0);
+                    let loc_aop_i = aop.get_reducing_matrix_columns(range_grids.clone(),i).unwrap();
+                    loc_aop_i.iter_columns_full().zip(wao.iter_columns_full())
+                    .zip(cur_tau.iter_column_mut(i_spin))
+                    .for_each(|((aop_r,wao_r), cur_tau_r)| {
+                        *cur_tau_r += 0.5*wao_r.iter().zip(aop_r.iter()).fold(0.0, |acc,(wao,aop)| {acc + wao*aop})
+                    });
+                }
+            }
+        } else {
+            panic!("{}", MGGA_WITHOUT_AOP)
+        }
+        cur_tau
+    }
+
     pub fn prepare_tabulated_rhop(&self, dm: &mut Vec<MatrixFull<f64>>, spin_channel: usize) -> RIFull<f64> {
         let num_basis = dm.get(0).unwrap().size.get(0).unwrap().clone();
         let num_grids = self.coordinates.len();
@@ -2552,6 +3030,69 @@ fn debug_num_density_for_atom() {
     
 }
 
+#[test]
+fn test_meta_gga_energy_stationary() {
+    // the converged TPSS energy is stationary with respect to the rotation between the HOMO and the LUMO,
+    // which fails if vtau is missing from the Kohn-Sham potential
+    use crate::ctrl_io::InputKeywords;
+    let ctrl_str = "[ctrl]
+        print_level = 0
+        xc = \"tpss\"
+        basis_path = \"basis-set-pool/def2-SVP\"
+        auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+        scf_acc_rho = 1.0e-10
+        scf_acc_eev = 1.0e-10
+        scf_acc_etot = 1.0e-12
+        [geom]
+        name = \"H2O\"
+        unit = \"Angstrom\"
+        position = \"\"\"
+            O   0.000   0.000   0.120
+            H   0.000   0.760  -0.480
+            H   0.000  -0.760  -0.480\"\"\"";
+    let tmp_keys = toml::from_str::<serde_json::Value>(ctrl_str).unwrap();
+    let (ctrl, geom) = InputKeywords::parse_ctl_from_json(&tmp_keys).unwrap();
+    let mol = Molecule::build_native(ctrl, geom, None).unwrap();
+    let scf_data = crate::scf_io::scf(mol, &None).unwrap();
+    assert!(scf_data.mol.xc_data.use_kinetic_density());
+    let (homo, lumo) = (scf_data.homo[0], scf_data.lumo[0]);
+    let energy_at = |theta: f64| {
+        let mut new_scf = scf_data.clone();
+        let num_basis = new_scf.eigenvectors[0].size[0];
+        (0..num_basis).for_each(|mu| {
+            let (c_i, c_a) = (scf_data.eigenvectors[0][[mu,homo]], scf_data.eigenvectors[0][[mu,lumo]]);
+            new_scf.eigenvectors[0][[mu,homo]] = theta.cos()*c_i + theta.sin()*c_a;
+            new_scf.eigenvectors[0][[mu,lumo]] = -theta.sin()*c_i + theta.cos()*c_a;
+        });
+        new_scf.generate_density_matrix();
+        new_scf.generate_hf_hamiltonian(&None);
+        new_scf.scf_energy
+    };
+    let theta = 1.0e-3;
+    let (e_plus, e_minus) = (energy_at(theta), energy_at(-theta));
+    let gradient = (e_plus - e_minus)/(2.0*theta);
+    assert!(gradient.abs() < 1.0e-5, "dE/dtheta = {}", gradient);
+    assert!(e_plus > scf_data.scf_energy && e_minus > scf_data.scf_energy);
+}
+
+#[test]
+fn test_range_separated_coefficients() {
+    // CAM-B3LYP: 19% exact exchange at short range and 65% at long range with omega = 0.33
+    let dfa = DFA4REST::new("cam-b3lyp", 1, 0);
+    let [omega, alpha, beta] = dfa.dfa_hybrid_cam_scf().unwrap();
+    assert!((omega-0.33).abs() < 1.0e-8 && (alpha-0.65).abs() < 1.0e-8 && (beta+0.46).abs() < 1.0e-8);
+    let (c_full, c_lr) = dfa.exx_range_coeff_scf();
+    assert!((c_full-0.19).abs() < 1.0e-8 && (c_lr-0.46).abs() < 1.0e-8);
+    // wB97X: 15.7706% at short range and 100% at long range with omega = 0.3
+    let (c_full, c_lr) = DFA4REST::new("wb97x", 1, 0).exx_range_coeff_scf();
+    assert!((c_full-0.157706).abs() < 1.0e-6 && (c_lr-0.842294).abs() < 1.0e-6);
+    // the global hybrids and the doubly hybrids with the global exchange
+    let dfa = DFA4REST::new("b3lyp", 1, 0);
+    assert!(dfa.dfa_hybrid_cam_scf().is_none());
+    assert_eq!(dfa.exx_range_coeff_scf(), (dfa.dfa_hybrid_scf, 0.0));
+    assert!(DFA4REST::new("xyg3", 1, 0).check_fifth_dfa().is_ok());
+}
+
 #[test]
 fn test_libxc() {
     let mut rho:Vec<f64> = vec![0.1,0.2,0.3,0.4,0.5,0.6,0.8];
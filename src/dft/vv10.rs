@@ -0,0 +1,180 @@
+//! The VV10 non-local correlation functional[^1] evaluated on the numerical grids.
+//!
+//! ```text
+//!   E_nl = \int rho(r) [beta + 1/2 \int rho(r') Phi(r,r') dr'] dr
+//!   Phi  = -3/2 / [g g' (g + g')],  g = omega_0(r) R^2 + kappa(r),  R = |r - r'|
+//! ```
+//! with `omega_0 = sqrt(C |nabla rho/rho|^4 + 4 pi rho/3)` and `kappa = b (3 pi/2) [rho/(9 pi)]^(1/6)`.
+//! The outer integration uses the standard grids of the scf procedure, while the inner (kernel)
+//! integration could be carried out on a coarser grid.
+//!
+//! [^1]: O. A. Vydrov and T. Van Voorhis, J. Chem. Phys. 133, 244103 (2010).
+use rayon::prelude::*;
+use tensors::MatrixFull;
+use tensors::matrix_blas_lapack::{_dgemm_full, contract_vxc_0_serial};
+use crate::dft::Grids;
+
+/// The density threshold below which the grid points are neglected in the VV10 evaluation
+pub const VV10_RHO_THRESHOLD: f64 = 1.0e-8;
+
+/// Evaluate the VV10 energy density (per particle) and the potentials on the outer grids.
+///
+/// Input:
+///   rho, sigma: the total density and |nabla rho|^2 on the outer grids
+///   vv_*: the coordinates, weights, density and |nabla rho|^2 on the kernel grids
+///   coeff: the VV10 parameters [b, C]
+/// Output:
+///   (exc, vrho, vsigma) on the outer grids
+pub fn vv10_exc_vxc(
+    coordinates: &[[f64;3]], rho: &[f64], sigma: &[f64],
+    vv_coordinates: &[[f64;3]], vv_weights: &[f64], vv_rho: &[f64], vv_sigma: &[f64],
+    coeff: [f64;2]) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+
+    let [b_vv, c_vv] = coeff;
+    let pi = std::f64::consts::PI;
+    let pi43 = 4.0*pi/3.0;
+    let kappa_vv = b_vv*1.5*pi*(9.0*pi).powf(-1.0/6.0);
+    let beta = (3.0/(b_vv*b_vv)).powf(0.75)/32.0;
+
+    // the kernel grids: [coordinate, rho*weight, omega_0, kappa]
+    let kernel: Vec<([f64;3],f64,f64,f64)> = vv_coordinates.iter().zip(vv_weights.iter())
+        .zip(vv_rho.iter().zip(vv_sigma.iter()))
+        .filter(|(_,(rho_p,_))| **rho_p >= VV10_RHO_THRESHOLD)
+        .map(|((coord_p, w_p),(rho_p, sigma_p))| {
+            let w0_tmp = sigma_p/(rho_p*rho_p);
+            let w0_p = (c_vv*w0_tmp*w0_tmp + pi43*rho_p).sqrt();
+            let k_p = kappa_vv*rho_p.powf(1.0/6.0);
+            (*coord_p, rho_p*w_p, w0_p, k_p)
+        }).collect();
+
+    let num_grids = coordinates.len();
+    let mut exc = vec![0.0;num_grids];
+    let mut vrho = vec![0.0;num_grids];
+    let mut vsigma = vec![0.0;num_grids];
+
+    coordinates.par_iter().zip(rho.par_iter().zip(sigma.par_iter()))
+        .zip(exc.par_iter_mut().zip(vrho.par_iter_mut().zip(vsigma.par_iter_mut())))
+        .for_each(|((coord, (rho, sigma)), (exc, (vrho, vsigma)))| {
+        if *rho < VV10_RHO_THRESHOLD {return}
+        let w0_tmp = sigma/(rho*rho);
+        let w0_tmp = c_vv*w0_tmp*w0_tmp;
+        let w0 = (w0_tmp + pi43*rho).sqrt();
+        // rho*d(omega_0)/d(rho), rho*d(kappa)/d(rho) and rho*d(omega_0)/d(sigma)
+        let dw0_drho = (0.5*pi43*rho - 2.0*w0_tmp)/w0;
+        let dw0_dsigma = if *sigma > 0.0 {w0_tmp*rho/(sigma*w0)} else {0.0};
+        let k = kappa_vv*rho.powf(1.0/6.0);
+        let dk_drho = k/6.0;
+
+        let (mut f, mut u, mut w) = (0.0, 0.0, 0.0);
+        kernel.iter().for_each(|(coord_p, rho_w_p, w0_p, k_p)| {
+            let dx = coord_p[0]-coord[0];
+            let dy = coord_p[1]-coord[1];
+            let dz = coord_p[2]-coord[2];
+            let r2 = dx*dx + dy*dy + dz*dz;
+            let g_p = r2*w0_p + k_p;
+            let g = r2*w0 + k;
+            let g_t = g + g_p;
+            let t1 = rho_w_p/(g*g_p*g_t);
+            let t2 = t1*(1.0/g + 1.0/g_t);
+            f += t1;
+            u += t2;
+            w += r2*t2;
+        });
+        let f = -1.5*f;
+
+        *exc = beta + 0.5*f;
+        *vrho = beta + f + 1.5*(u*dk_drho + w*dw0_drho);
+        *vsigma = 1.5*w*dw0_dsigma;
+    });
+
+    (exc, vrho, vsigma)
+}
+
+/// Evaluate the VV10 energy and its potential matrix in the atomic-orbital basis.
+///
+/// Input:
+///   grids: the outer grids with the tabulated ao and their derivatives
+///   vv_grids: the kernel grids with the tabulated ao and their derivatives
+///   dm: the total density matrix in the shape of [num_basis, num_basis]
+///   coeff: the VV10 parameters [b, C]
+/// Output:
+///   (E_nl, V_nl), where V_nl is the same for both spin channels
+pub fn vv10_energy_potential(grids: &Grids, vv_grids: &Grids, dm: &MatrixFull<f64>, coeff: [f64;2]) -> (f64, MatrixFull<f64>) {
+    let num_basis = dm.size[0];
+    let num_grids = grids.coordinates.len();
+
+    let (rho, rhop) = tabulated_density_and_gradient(grids, dm);
+    let sigma: Vec<f64> = (0..num_grids).map(|i| {
+        rhop[0][i]*rhop[0][i] + rhop[1][i]*rhop[1][i] + rhop[2][i]*rhop[2][i]
+    }).collect();
+    let (vv_rho, vv_sigma) = if std::ptr::eq(grids, vv_grids) {
+        (rho.clone(), sigma.clone())
+    } else {
+        let (vv_rho, vv_rhop) = tabulated_density_and_gradient(vv_grids, dm);
+        let vv_sigma: Vec<f64> = (0..vv_rho.len()).map(|i| {
+            vv_rhop[0][i]*vv_rhop[0][i] + vv_rhop[1][i]*vv_rhop[1][i] + vv_rhop[2][i]*vv_rhop[2][i]
+        }).collect();
+        (vv_rho, vv_sigma)
+    };
+
+    let (exc, vrho, vsigma) = vv10_exc_vxc(
+        &grids.coordinates, &rho, &sigma,
+        &vv_grids.coordinates, &vv_grids.weights, &vv_rho, &vv_sigma, coeff);
+
+    let energy = grids.weights.iter().zip(rho.iter()).zip(exc.iter())
+        .fold(0.0, |acc, ((w, rho), exc)| acc + w*rho*exc);
+
+    // V_{uv} = \int [vrho u v + 2 vsigma nabla rho . nabla (u v)] dr
+    //        = W U^T + U W^T, with W_{u,g} = w_g [1/2 vrho_g u_g + 2 vsigma_g nabla rho_g . nabla u_g]
+    let ao = grids.ao.as_ref().unwrap();
+    let aop = grids.aop.as_ref().unwrap();
+    let mut wao = MatrixFull::new([num_basis, num_grids], 0.0);
+    let wvrho: Vec<f64> = grids.weights.iter().zip(vrho.iter()).map(|(w, v)| 0.5*w*v).collect();
+    contract_vxc_0_serial(&mut wao, &ao.to_matrixfullslice(), &wvrho, None);
+    for x in 0usize..3usize {
+        let aop_x = aop.get_reducing_matrix(x).unwrap();
+        let wvsigma_x: Vec<f64> = grids.weights.iter().zip(vsigma.iter()).zip(rhop[x].iter())
+            .map(|((w, v), d)| w*v*d).collect();
+        contract_vxc_0_serial(&mut wao, &aop_x, &wvsigma_x, Some(2.0));
+    }
+    let mut vnl = MatrixFull::new([num_basis, num_basis], 0.0);
+    _dgemm_full(&wao, 'N', ao, 'T', &mut vnl, 1.0, 0.0);
+    for j in 0..num_basis {
+        for i in 0..j {
+            let v_ij = vnl[[i,j]] + vnl[[j,i]];
+            vnl[[i,j]] = v_ij;
+            vnl[[j,i]] = v_ij;
+        }
+        vnl[[j,j]] *= 2.0;
+    }
+
+    (energy, vnl)
+}
+
+fn tabulated_density_and_gradient(grids: &Grids, dm: &MatrixFull<f64>) -> (Vec<f64>, [Vec<f64>;3]) {
+    if grids.ao.is_none() || grids.aop.is_none() {
+        panic!("VV10 requires the atomic orbitals and their derivatives tabulated on the grids")
+    }
+    let num_grids = grids.coordinates.len();
+    let (rho, rhop) = grids.prepare_tabulated_density_slots_dm_only(&vec![dm.clone()], 1, 0..num_grids);
+    let rhop_s = rhop.get_reducing_matrix(0).unwrap();
+    let rhop = [rhop_s.get_slice_x(0).to_vec(), rhop_s.get_slice_x(1).to_vec(), rhop_s.get_slice_x(2).to_vec()];
+    (rho.data, rhop)
+}
+
+#[test]
+fn test_vv10_asymptotic_pair() {
+    // for two distant points, the VV10 energy approaches the -C6/R^6 form,
+    // and thus scales as R^-6 to a good approximation
+    let coeff = [5.9, 0.0093];
+    let energy = |dist: f64| {
+        let (exc, _, _) = vv10_exc_vxc(
+            &[[0.0,0.0,0.0]], &[0.1], &[0.01],
+            &[[0.0,0.0,dist]], &[1.0], &[0.1], &[0.01], coeff);
+        let beta = (3.0/(coeff[0]*coeff[0])).powf(0.75)/32.0;
+        exc[0] - beta
+    };
+    let ratio = energy(100.0)/energy(200.0);
+    assert!(energy(100.0) < 0.0);
+    assert!((ratio/64.0-1.0).abs() < 1.0e-2);
+}
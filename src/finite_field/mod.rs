@@ -0,0 +1,320 @@
+//! Static dipole (hyper)polarizabilities by the finite-field differentiation.
+//!
+//! With the uniform electric field `F` coupled by `h_core += F*r` (see the keyword `electric_field`),
+//! the energy and the dipole moment are expanded as
+//! ```text
+//!   E(F)   = E0 - mu_i F_i - 1/2 alpha_ij F_i F_j - 1/6 beta_ijk F_i F_j F_k
+//!   mu_i(F) = mu0_i + alpha_ij F_j + 1/2 beta_ijk F_j F_k
+//! ```
+//! For the SCF-level methods, of which the dipole moment is the exact field derivative of the energy,
+//! alpha and beta are given by the first and second central differences of the dipoles on 19 field points.
+//! For the post-SCF methods, like the xDH and RPA families, the relaxed dipoles are not available,
+//! and the second and third central differences of the total energies on 33 field points are used instead.
+//! The energy differences at the field step `h` scale with `h^3` for beta, so that the SCF at each field point
+//! is converged to at least [`FF_ACC_ETOT`] and [`FF_ACC_RHO`], whatever the thresholds in the `[ctrl]` block are.
+//! The reference SCF is reused only if it is converged as tightly; otherwise it is repeated at zero field.
+use std::collections::HashMap;
+use crate::constants::AU2DEBYE;
+use crate::dft::DFAFamily;
+use crate::mpi_io::MPIOperator;
+use crate::post_scf_analysis::evaluate_dipole_moment;
+use crate::scf_io::{initialize_scf, SCF};
+use crate::{collect_total_energy, performance_essential_calculations, utilities};
+
+/// The loosest SCF convergence thresholds on the total energy and the density matrix at the field points
+pub const FF_ACC_ETOT: f64 = 1.0e-10;
+pub const FF_ACC_RHO: f64 = 1.0e-8;
+
+pub struct FiniteFieldProperties {
+    /// the total energy and the dipole moment at the reference field in a.u.
+    pub energy: f64,
+    pub dipole: [f64;3],
+    pub polarizability: [[f64;3];3],
+    pub hyperpolarizability: [[[f64;3];3];3],
+    /// true if alpha and beta are obtained from the energies, otherwise from the dipoles
+    pub from_energies: bool,
+}
+
+/// The total energy and the dipole moment (a.u.) with the field of `field` on top of the reference one
+pub fn energy_and_dipole_at(scf_data: &SCF, field: [f64;3], mpi_operator: &Option<MPIOperator>) -> (f64, [f64;3]) {
+    let mut time_mark = utilities::TimeRecords::new();
+    let mut new_scf = scf_data.clone();
+    let mut total_field = scf_data.mol.ctrl.electric_field.unwrap_or([0.0;3]);
+    total_field.iter_mut().zip(field.iter()).for_each(|(to, from)| *to += from);
+    new_scf.mol.ctrl.electric_field = Some(total_field);
+    new_scf.mol.ctrl.print_level = 0;
+    new_scf.mol.ctrl.scf_acc_etot = scf_data.mol.ctrl.scf_acc_etot.min(FF_ACC_ETOT);
+    new_scf.mol.ctrl.scf_acc_rho = scf_data.mol.ctrl.scf_acc_rho.min(FF_ACC_RHO);
+    new_scf.mol.ctrl.initial_guess = String::from("inherit");
+    initialize_scf(&mut new_scf, mpi_operator);
+    let energy = performance_essential_calculations(&mut new_scf, &mut time_mark, mpi_operator);
+    let dipole = evaluate_dipole_moment(&new_scf, None).map(|x| x/AU2DEBYE);
+    (energy, dipole)
+}
+
+/// mu, alpha and beta from the energies at the field points `step*n`, provided by `energy_at(n)`
+pub fn derivatives_from_energies<F>(mut energy_at: F, step: f64) -> ([f64;3], [[f64;3];3], [[[f64;3];3];3])
+where F: FnMut([i32;3]) -> f64
+{
+    let point = |terms: &[(usize, i32)]| -> [i32;3] {
+        let mut n = [0;3];
+        terms.iter().for_each(|(x, k)| n[*x] += k);
+        n
+    };
+    let e0 = energy_at([0;3]);
+    let mut dipole = [0.0;3];
+    let mut polar = [[0.0;3];3];
+    let mut hyper = [[[0.0;3];3];3];
+    for i in 0..3 {
+        let (ep, em) = (energy_at(point(&[(i,1)])), energy_at(point(&[(i,-1)])));
+        let (ep2, em2) = (energy_at(point(&[(i,2)])), energy_at(point(&[(i,-2)])));
+        dipole[i] = -(8.0*(ep - em) - (ep2 - em2))/(12.0*step);
+        polar[i][i] = -(ep - 2.0*e0 + em)/step.powi(2);
+        hyper[i][i][i] = -(ep2 - 2.0*ep + 2.0*em - em2)/(2.0*step.powi(3));
+    }
+    for i in 0..3 {
+        for j in 0..3 {
+            if i == j {continue}
+            let epp = energy_at(point(&[(i,1),(j,1)]));
+            let epm = energy_at(point(&[(i,1),(j,-1)]));
+            let emp = energy_at(point(&[(i,-1),(j,1)]));
+            let emm = energy_at(point(&[(i,-1),(j,-1)]));
+            polar[i][j] = -(epp - epm - emp + emm)/(4.0*step.powi(2));
+            // d^3E/dF_i^2 dF_j
+            let (ejp, ejm) = (energy_at(point(&[(j,1)])), energy_at(point(&[(j,-1)])));
+            let b_iij = -((epp - 2.0*ejp + emp) - (epm - 2.0*ejm + emm))/(2.0*step.powi(3));
+            hyper[i][i][j] = b_iij;
+            hyper[i][j][i] = b_iij;
+            hyper[j][i][i] = b_iij;
+        }
+    }
+    let mut b_xyz = 0.0;
+    for sx in [-1,1] {
+        for sy in [-1,1] {
+            for sz in [-1,1] {
+                b_xyz -= (sx*sy*sz) as f64*energy_at([sx,sy,sz]);
+            }
+        }
+    }
+    b_xyz /= 8.0*step.powi(3);
+    [[0,1,2],[0,2,1],[1,0,2],[1,2,0],[2,0,1],[2,1,0]].iter().for_each(|[i,j,k]| hyper[*i][*j][*k] = b_xyz);
+
+    (dipole, polar, hyper)
+}
+
+/// alpha and beta from the dipoles at the field points `step*n`, provided by `dipole_at(n)`
+pub fn derivatives_from_dipoles<F>(mut dipole_at: F, step: f64) -> ([[f64;3];3], [[[f64;3];3];3])
+where F: FnMut([i32;3]) -> [f64;3]
+{
+    let point = |terms: &[(usize, i32)]| -> [i32;3] {
+        let mut n = [0;3];
+        terms.iter().for_each(|(x, k)| n[*x] += k);
+        n
+    };
+    let mu0 = dipole_at([0;3]);
+    let mut polar = [[0.0;3];3];
+    let mut hyper = [[[0.0;3];3];3];
+    for j in 0..3 {
+        let (mup, mum) = (dipole_at(point(&[(j,1)])), dipole_at(point(&[(j,-1)])));
+        for i in 0..3 {
+            polar[i][j] = (mup[i] - mum[i])/(2.0*step);
+            hyper[i][j][j] = (mup[i] - 2.0*mu0[i] + mum[i])/step.powi(2);
+        }
+    }
+    for j in 0..3 {
+        for k in j+1..3 {
+            let mupp = dipole_at(point(&[(j,1),(k,1)]));
+            let mupm = dipole_at(point(&[(j,1),(k,-1)]));
+            let mump = dipole_at(point(&[(j,-1),(k,1)]));
+            let mumm = dipole_at(point(&[(j,-1),(k,-1)]));
+            for i in 0..3 {
+                let b_ijk = (mupp[i] - mupm[i] - mump[i] + mumm[i])/(4.0*step.powi(2));
+                hyper[i][j][k] = b_ijk;
+                hyper[i][k][j] = b_ijk;
+            }
+        }
+    }
+
+    (polar, hyper)
+}
+
+pub fn finite_field_calculations(scf_data: &SCF, mpi_operator: &Option<MPIOperator>) -> FiniteFieldProperties {
+    let is_master = mpi_operator.as_ref().map_or(true, |mp_op| mp_op.rank == 0);
+    let step = scf_data.mol.ctrl.finite_field_step;
+    let from_energies = match scf_data.mol.xc_data.dfa_family_pos {
+        Some(DFAFamily::PT2) | Some(DFAFamily::SBGE2) | Some(DFAFamily::RPA) | Some(DFAFamily::SCSRPA) => true,
+        _ => false,
+    };
+
+    // the results at the reference field are reused if the reference SCF is converged tightly enough
+    let mut cache: HashMap<[i32;3], (f64, [f64;3])> = HashMap::new();
+    if scf_data.mol.ctrl.scf_acc_etot <= FF_ACC_ETOT && scf_data.mol.ctrl.scf_acc_rho <= FF_ACC_RHO {
+        cache.insert([0;3], (collect_total_energy(scf_data), evaluate_dipole_moment(scf_data, None).map(|x| x/AU2DEBYE)));
+    } else if scf_data.mol.ctrl.print_level > 0 && is_master {
+        println!("The SCF at each field point is converged to {:e} Ha. for the total energy and {:e} for the density matrix",
+            scf_data.mol.ctrl.scf_acc_etot.min(FF_ACC_ETOT), scf_data.mol.ctrl.scf_acc_rho.min(FF_ACC_RHO));
+    }
+    let mut result_at = |n: [i32;3]| -> (f64, [f64;3]) {
+        *cache.entry(n).or_insert_with(|| {
+            let field = n.map(|k| k as f64*step);
+            if scf_data.mol.ctrl.print_level > 1 && is_master {
+                println!("Finite field at ({:10.6}, {:10.6}, {:10.6}) a.u.", field[0], field[1], field[2]);
+            }
+            energy_and_dipole_at(scf_data, field, mpi_operator)
+        })
+    };
+
+    let (energy, dipole_0) = result_at([0;3]);
+    let (dipole, polarizability, hyperpolarizability) = if from_energies {
+        derivatives_from_energies(|n| result_at(n).0, step)
+    } else {
+        let (polar, hyper) = derivatives_from_dipoles(|n| result_at(n).1, step);
+        (dipole_0, polar, hyper)
+    };
+
+    let properties = FiniteFieldProperties {energy, dipole, polarizability, hyperpolarizability, from_energies};
+    if is_master {
+        properties.formated_output();
+    }
+    properties
+}
+
+impl FiniteFieldProperties {
+    /// The projection of beta onto the dipole moment: 1/5 \sum_ij mu_i (beta_ijj + beta_jij + beta_jji)/|mu|
+    pub fn beta_parallel(&self) -> Option<f64> {
+        let mu_norm = self.dipole.iter().fold(0.0, |acc, x| acc + x*x).sqrt();
+        if mu_norm < 1.0e-6 {return None}
+        let b = &self.hyperpolarizability;
+        let mut b_par = 0.0;
+        for i in 0..3 {
+            for j in 0..3 {
+                b_par += self.dipole[i]*(b[i][j][j] + b[j][i][j] + b[j][j][i]);
+            }
+        }
+        Some(b_par/(5.0*mu_norm))
+    }
+
+    pub fn formated_output(&self) {
+        let labels = ["x","y","z"];
+        println!("----------------------------------------------------------------------");
+        if self.from_energies {
+            println!("Finite-field properties from the total energies");
+        } else {
+            println!("Finite-field properties from the dipole moments");
+        }
+        println!("----------------------------------------------------------------------");
+        println!("Dipole moment [a.u.]: {:16.8} {:16.8} {:16.8}", self.dipole[0], self.dipole[1], self.dipole[2]);
+        println!("Static dipole polarizability [a.u.]: ");
+        self.polarizability.iter().zip(labels.iter()).for_each(|(polar_x, x)| {
+            println!("{:>3} {:16.8} {:16.8} {:16.8}", x, polar_x[0], polar_x[1], polar_x[2]);
+        });
+        let polar = &self.polarizability;
+        println!("Isotropic polarizability [a.u.]: {:16.8}", (polar[0][0] + polar[1][1] + polar[2][2])/3.0);
+        println!("Static first hyperpolarizability [a.u.]: ");
+        for i in 0..3 {
+            for j in 0..3 {
+                println!("{:>2}{:>1}  {:16.8} {:16.8} {:16.8}", labels[i], labels[j],
+                    self.hyperpolarizability[i][j][0], self.hyperpolarizability[i][j][1], self.hyperpolarizability[i][j][2]);
+            }
+        }
+        if let Some(b_par) = self.beta_parallel() {
+            println!("Hyperpolarizability along the dipole moment (beta_||) [a.u.]: {:16.8}", b_par);
+        }
+        println!("----------------------------------------------------------------------");
+    }
+}
+
+#[test]
+fn test_finite_field_derivatives_of_cubic_model() {
+    let mu = [0.3, -0.2, 0.5];
+    let alpha = [[9.0, 0.4, -0.3], [0.4, 7.5, 0.2], [-0.3, 0.2, 11.0]];
+    // a fully symmetric beta
+    let mut beta = [[[0.0;3];3];3];
+    let b_unique = [(0,0,0,12.0), (0,0,1,-3.0), (0,0,2,1.5), (0,1,1,2.0), (0,1,2,-0.7),
+                    (0,2,2,4.0), (1,1,1,-8.0), (1,1,2,0.9), (1,2,2,-1.1), (2,2,2,20.0)];
+    b_unique.iter().for_each(|(i,j,k,b)| {
+        for [p,q,r] in [[i,j,k],[i,k,j],[j,i,k],[j,k,i],[k,i,j],[k,j,i]] {
+            beta[*p][*q][*r] = *b;
+        }
+    });
+    let step = 0.01;
+    let energy_at = |n: [i32;3]| -> f64 {
+        let f = n.map(|k| k as f64*step);
+        let mut e = -1.0;
+        for i in 0..3 {
+            e -= mu[i]*f[i];
+            for j in 0..3 {
+                e -= 0.5*alpha[i][j]*f[i]*f[j];
+                for k in 0..3 {
+                    e -= beta[i][j][k]*f[i]*f[j]*f[k]/6.0;
+                }
+            }
+        }
+        e
+    };
+    let dipole_at = |n: [i32;3]| -> [f64;3] {
+        let f = n.map(|k| k as f64*step);
+        let mut d = mu.clone();
+        for i in 0..3 {
+            for j in 0..3 {
+                d[i] += alpha[i][j]*f[j];
+                for k in 0..3 {
+                    d[i] += 0.5*beta[i][j][k]*f[j]*f[k];
+                }
+            }
+        }
+        d
+    };
+    let (mu_e, alpha_e, beta_e) = derivatives_from_energies(energy_at, step);
+    let (alpha_d, beta_d) = derivatives_from_dipoles(dipole_at, step);
+    for i in 0..3 {
+        assert!((mu_e[i] - mu[i]).abs() < 1.0e-6);
+        for j in 0..3 {
+            assert!((alpha_e[i][j] - alpha[i][j]).abs() < 1.0e-6);
+            assert!((alpha_d[i][j] - alpha[i][j]).abs() < 1.0e-6);
+            for k in 0..3 {
+                assert!((beta_e[i][j][k] - beta[i][j][k]).abs() < 1.0e-4);
+                assert!((beta_d[i][j][k] - beta[i][j][k]).abs() < 1.0e-6);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_cpks_polarizability_against_finite_field() {
+    // the SCF convergence is tightened for the dipole differences at the field step of 1.0e-3
+    for xc in ["hf", "pbe"] {
+        let ctrl_str = format!("[ctrl]
+            print_level = 0
+            xc = \"{}\"
+            basis_path = \"basis-set-pool/def2-SVP\"
+            auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+            eri_type = \"ri-v\"
+            use_ri_symm = false
+            scf_acc_rho = 1.0e-10
+            scf_acc_eev = 1.0e-10
+            scf_acc_etot = 1.0e-12
+            cphf_conv_tol = 1.0e-9
+            [geom]
+            name = \"H2O\"
+            unit = \"Angstrom\"
+            position = \"\"\"
+                O   0.000   0.000   0.120
+                H   0.000   0.780  -0.470
+                H   0.100  -0.740  -0.480\"\"\"", xc);
+        let scf_data = crate::grad::scf_for_test(&ctrl_str);
+        let polar_cpks = crate::cphf::static_polarizability(&scf_data, &None).unwrap();
+        let step = 1.0e-3;
+        for j in 0..3 {
+            let mut field = [0.0;3];
+            field[j] = step;
+            let (_, mu_p) = energy_and_dipole_at(&scf_data, field, &None);
+            field[j] = -step;
+            let (_, mu_m) = energy_and_dipole_at(&scf_data, field, &None);
+            for i in 0..3 {
+                let polar_ff = (mu_p[i] - mu_m[i])/(2.0*step);
+                assert!((polar_cpks[i][j] - polar_ff).abs() < 1.0e-3, "{}: alpha_{}{} of CPKS {} vs finite field {}", xc, i, j, polar_cpks[i][j], polar_ff);
+            }
+        }
+    }
+}
@@ -0,0 +1,420 @@
+//! Harmonic vibrational frequencies and the ideal-gas thermochemistry.
+//!
+//! The Cartesian Hessian is obtained by the central differences of the nuclear gradients:
+//! ```text
+//!   H_{i,j} = [g_j(R + h e_i) - g_j(R - h e_i)]/(2h)
+//! ```
+//! which is then mass-weighted, projected out of the translations and rotations, and
+//! diagonalized for the harmonic frequencies and normal modes. The thermochemistry follows the
+//! ideal-gas/rigid-rotor/harmonic-oscillator (RRHO) approximation, for which the imaginary
+//! modes are skipped.
+use tensors::MatrixFull;
+use crate::constants::{AMU, ANG, AVOGADRO, BOLTZMANN, CALORIE, CM, EV, FQ, HARTREE_SI, LIGHT_SPEED_SI, PLANCK};
+use crate::geom_io::{get_mass_charge, GeomCell};
+use crate::geom_opt::FORCE_DISPLACEMENT;
+use crate::grad::calc_force;
+use crate::mpi_io::MPIOperator;
+use crate::scf_io::{initialize_scf, SCF};
+use crate::{collect_total_energy, performance_essential_calculations, utilities};
+use crate::utilities::linear_algebra::{dot, eigh, matmul};
+
+/// The modes (in cm-1) below this value are excluded from the vibrational partition function
+const LOW_FREQUENCY_THRESHOLD: f64 = 1.0e-6;
+
+pub struct VibrationalAnalysis {
+    /// the Cartesian Hessian in the shape of [3*natm, 3*natm]
+    pub hessian: MatrixFull<f64>,
+    /// the number of projected translations and rotations: 5 for linear molecules, otherwise 6
+    pub num_trans_rot: usize,
+    /// the harmonic frequencies in cm-1, where the imaginary ones are given as negative numbers
+    pub frequencies: Vec<f64>,
+    /// the normalized Cartesian displacements of the normal modes in columns, [3*natm, num_modes]
+    pub normal_modes: MatrixFull<f64>,
+    /// the reduced masses of the normal modes in amu
+    pub reduced_masses: Vec<f64>,
+}
+
+pub struct Thermochemistry {
+    /// temperature in K and pressure in Pa
+    pub temperature: f64,
+    pub pressure: f64,
+    /// the zero-point energy in Hartree
+    pub zpe: f64,
+    /// the thermal corrections (including the ZPE) to the internal energy, enthalpy and Gibbs free energy in Hartree
+    pub thermal_energy: f64,
+    pub thermal_enthalpy: f64,
+    pub thermal_gibbs: f64,
+    /// the translational, rotational, vibrational and electronic entropies in Hartree/K
+    pub entropy: [f64;4],
+}
+
+/// The Cartesian Hessian from the central differences of the nuclear gradients
+pub fn numerical_hessian(scf_data: &SCF, displace: f64, mpi_operator: &Option<MPIOperator>) -> MatrixFull<f64> {
+    let num_atoms = scf_data.mol.geom.position.size[1];
+    let num_coords = 3*num_atoms;
+    let is_master = mpi_operator.as_ref().map_or(true, |mp_op| mp_op.rank == 0);
+    let print_level = scf_data.mol.ctrl.print_level;
+
+    let gradient_at = |atm_idx: usize, xyz: usize, step: f64| -> Vec<f64> {
+        let mut time_mark = utilities::TimeRecords::new();
+        let mut new_scf = scf_data.clone();
+        new_scf.mol.geom.position[[xyz, atm_idx]] += step;
+        new_scf.mol.ctrl.print_level = 0;
+        new_scf.mol.ctrl.initial_guess = String::from("inherit");
+        initialize_scf(&mut new_scf, mpi_operator);
+        performance_essential_calculations(&mut new_scf, &mut time_mark, mpi_operator);
+        let (_, gradient) = calc_force(&new_scf, FORCE_DISPLACEMENT, mpi_operator);
+        if gradient.data.len() != num_coords {
+            panic!("The numerical Hessian requires the gradients of all atoms, but got {} of {} components", gradient.data.len(), num_coords)
+        }
+        gradient.data
+    };
+
+    if print_level > 0 && is_master {
+        println!("Numerical Hessian from {} displaced gradients with the step of {:8.5} Bohr", 2*num_coords, displace);
+    }
+    let mut hessian = MatrixFull::new([num_coords, num_coords], 0.0);
+    for atm_idx in 0..num_atoms {
+        for xyz in 0..3 {
+            let i = 3*atm_idx + xyz;
+            if print_level > 1 && is_master {
+                println!("  displacing atom {:3} along {}", atm_idx, ["x","y","z"][xyz]);
+            }
+            let g_plus = gradient_at(atm_idx, xyz, displace);
+            let g_minus = gradient_at(atm_idx, xyz, -displace);
+            hessian.iter_column_mut(i).zip(g_plus.iter().zip(g_minus.iter())).for_each(|(to, (gp, gm))| {
+                *to = 0.5*(gp - gm)/displace
+            });
+        }
+    }
+    // symmetrize the finite-difference Hessian
+    for j in 0..num_coords {
+        for i in 0..j {
+            let h_ij = 0.5*(hessian[[i,j]] + hessian[[j,i]]);
+            hessian[[i,j]] = h_ij;
+            hessian[[j,i]] = h_ij;
+        }
+    }
+
+    hessian
+}
+
+/// The orthonormal translations and rotations in the mass-weighted Cartesian coordinates
+pub fn trans_rot_vectors(geom: &GeomCell) -> Vec<Vec<f64>> {
+    let masses: Vec<f64> = get_mass_charge(&geom.elem).iter().map(|(m, _)| *m).collect();
+    let num_atoms = masses.len();
+    let (com, _) = geom.evaluate_center_of_mass();
+
+    let mut candidates: Vec<Vec<f64>> = vec![];
+    for x in 0..3 {
+        let mut t = vec![0.0; 3*num_atoms];
+        masses.iter().enumerate().for_each(|(a, m)| t[3*a+x] = m.sqrt());
+        candidates.push(t);
+    }
+    for x in 0..3 {
+        let mut r = vec![0.0; 3*num_atoms];
+        masses.iter().enumerate().for_each(|(a, m)| {
+            let pos: Vec<f64> = (0..3).map(|y| geom.position[[y,a]] - com[y]).collect();
+            // e_x cross (R_a - R_com)
+            let (y, z) = ((x+1)%3, (x+2)%3);
+            r[3*a+y] = -pos[z]*m.sqrt();
+            r[3*a+z] = pos[y]*m.sqrt();
+        });
+        candidates.push(r);
+    }
+
+    // Gram-Schmidt, dropping the redundant rotations of linear molecules and atoms
+    let mut basis: Vec<Vec<f64>> = vec![];
+    candidates.into_iter().for_each(|mut v| {
+        basis.iter().for_each(|b| {
+            let proj = dot(b, &v);
+            v.iter_mut().zip(b.iter()).for_each(|(v, b)| *v -= proj*b);
+        });
+        let norm = dot(&v, &v).sqrt();
+        if norm > 1.0e-6 {
+            v.iter_mut().for_each(|v| *v /= norm);
+            basis.push(v);
+        }
+    });
+    basis
+}
+
+/// The projector onto the vibrational space in the mass-weighted coordinates, P = 1 - \sum_t |t><t|
+pub fn trans_rot_projector(geom: &GeomCell) -> MatrixFull<f64> {
+    let num_coords = 3*geom.elem.len();
+    let mut proj = MatrixFull::new([num_coords, num_coords], 0.0);
+    for i in 0..num_coords {
+        proj[[i,i]] = 1.0;
+    }
+    trans_rot_vectors(geom).iter().for_each(|t| {
+        for j in 0..num_coords {
+            for i in 0..num_coords {
+                proj[[i,j]] -= t[i]*t[j];
+            }
+        }
+    });
+    proj
+}
+
+/// The mass-weighted Hessian, H_ij/sqrt(m_i m_j), with the translations and rotations projected out
+pub fn projected_mass_weighted_hessian(hessian: &MatrixFull<f64>, geom: &GeomCell) -> MatrixFull<f64> {
+    let masses: Vec<f64> = get_mass_charge(&geom.elem).iter().map(|(m, _)| *m).collect();
+    let num_coords = 3*masses.len();
+    let sqrt_m: Vec<f64> = (0..num_coords).map(|i| masses[i/3].sqrt()).collect();
+    let proj = trans_rot_projector(geom);
+    let mut hmw = MatrixFull::new([num_coords, num_coords], 0.0);
+    for j in 0..num_coords {
+        for i in 0..num_coords {
+            hmw[[i,j]] = hessian[[i,j]]/(sqrt_m[i]*sqrt_m[j]);
+        }
+    }
+    matmul(&proj, &matmul(&hmw, &proj))
+}
+
+/// Diagonalize the mass-weighted Hessian after projecting out the translations and rotations
+pub fn harmonic_analysis(hessian: &MatrixFull<f64>, geom: &GeomCell) -> VibrationalAnalysis {
+    let masses: Vec<f64> = get_mass_charge(&geom.elem).iter().map(|(m, _)| *m).collect();
+    let num_coords = 3*masses.len();
+    let sqrt_m: Vec<f64> = (0..num_coords).map(|i| masses[i/3].sqrt()).collect();
+
+    let tr = trans_rot_vectors(geom);
+    let num_trans_rot = tr.len();
+    let num_modes = num_coords - num_trans_rot;
+    let hmw = projected_mass_weighted_hessian(hessian, geom);
+
+    // shift the translations and rotations far above the vibrations, such that the lowest
+    // num_modes eigenpairs are the vibrational ones, including the imaginary modes
+    let shift = 1.0e2*(1.0 + hmw.data.iter().fold(0.0_f64, |acc, h| acc.max(h.abs())));
+    let mut hmw_shifted = hmw.clone();
+    tr.iter().for_each(|t| {
+        for j in 0..num_coords {
+            for i in 0..num_coords {
+                hmw_shifted[[i,j]] += shift*t[i]*t[j];
+            }
+        }
+    });
+    let (evec, eval) = eigh(&hmw_shifted);
+
+    // eigenvalues in Hartree/(Bohr^2 amu) -> frequencies in cm-1
+    let to_wavenumber = |lambda: f64| {
+        let omega = (lambda.abs()/FQ).sqrt()*EV*CM;
+        if lambda < 0.0 {-omega} else {omega}
+    };
+    let frequencies: Vec<f64> = eval[..num_modes].iter().map(|lambda| to_wavenumber(*lambda)).collect();
+
+    let mut normal_modes = MatrixFull::new([num_coords, num_modes], 0.0);
+    let mut reduced_masses = vec![0.0; num_modes];
+    for k in 0..num_modes {
+        let disp: Vec<f64> = (0..num_coords).map(|i| evec[[i,k]]/sqrt_m[i]).collect();
+        let norm2 = dot(&disp, &disp);
+        reduced_masses[k] = 1.0/norm2;
+        normal_modes.iter_column_mut(k).zip(disp.iter()).for_each(|(to, from)| *to = from/norm2.sqrt());
+    }
+
+    VibrationalAnalysis {hessian: hessian.clone(), num_trans_rot, frequencies, normal_modes, reduced_masses}
+}
+
+/// The RRHO thermochemistry for the given harmonic frequencies (in cm-1)
+pub fn rrho_thermochemistry(geom: &GeomCell, frequencies: &[f64], multiplicity: f64, symmetry_number: usize,
+    temperature: f64, pressure: f64) -> Thermochemistry {
+    let kt = BOLTZMANN*temperature;
+    let (_, total_mass) = geom.evaluate_center_of_mass();
+
+    // translations
+    let mass = total_mass*AMU;
+    let q_trans = (2.0*std::f64::consts::PI*mass*kt/(PLANCK*PLANCK)).powf(1.5)*kt/pressure;
+    let e_trans = 1.5*kt;
+    let s_trans = BOLTZMANN*(q_trans.ln() + 2.5);
+
+    // rotations
+    let sigma = symmetry_number.max(1) as f64;
+    let moments: Vec<f64> = principal_moments(geom).into_iter().filter(|i| *i > 1.0e-6).collect();
+    // the rotational temperatures h^2/(8 pi^2 I k)
+    let theta_rot: Vec<f64> = moments.iter().map(|i| {
+        PLANCK*PLANCK/(8.0*std::f64::consts::PI.powi(2)*i*AMU*(ANG*1.0e-10).powi(2)*BOLTZMANN)
+    }).collect();
+    let (e_rot, s_rot) = match theta_rot.len() {
+        0 => (0.0, 0.0),
+        1 | 2 => (kt, BOLTZMANN*((temperature/(sigma*theta_rot[0])).ln() + 1.0)),
+        _ => {
+            let q_rot = std::f64::consts::PI.sqrt()/sigma
+                *(temperature.powi(3)/(theta_rot[0]*theta_rot[1]*theta_rot[2])).sqrt();
+            (1.5*kt, BOLTZMANN*(q_rot.ln() + 1.5))
+        }
+    };
+
+    // vibrations, skipping the imaginary modes
+    let (mut zpe, mut e_vib, mut s_vib) = (0.0, 0.0, 0.0);
+    frequencies.iter().filter(|nu| **nu > LOW_FREQUENCY_THRESHOLD).for_each(|nu| {
+        let hv = PLANCK*LIGHT_SPEED_SI*1.0e2*nu;
+        let x = hv/kt;
+        zpe += 0.5*hv;
+        e_vib += hv*(0.5 + 1.0/x.exp_m1());
+        s_vib += BOLTZMANN*(x/x.exp_m1() - (-(-x).exp()).ln_1p());
+    });
+
+    // electronic
+    let s_elec = BOLTZMANN*multiplicity.max(1.0).ln();
+
+    let thermal_energy = e_trans + e_rot + e_vib;
+    let thermal_enthalpy = thermal_energy + kt;
+    let entropy = [s_trans, s_rot, s_vib, s_elec];
+    let s_total: f64 = entropy.iter().sum();
+    let thermal_gibbs = thermal_enthalpy - temperature*s_total;
+
+    Thermochemistry {
+        temperature,
+        pressure,
+        zpe: zpe/HARTREE_SI,
+        thermal_energy: thermal_energy/HARTREE_SI,
+        thermal_enthalpy: thermal_enthalpy/HARTREE_SI,
+        thermal_gibbs: thermal_gibbs/HARTREE_SI,
+        entropy: entropy.map(|s| s/HARTREE_SI),
+    }
+}
+
+/// Perform the numerical Hessian, the harmonic analysis and the thermochemistry at the current geometry
+pub fn frequency_calculations(scf_data: &SCF, mpi_operator: &Option<MPIOperator>) -> (VibrationalAnalysis, Thermochemistry) {
+    let ctrl = &scf_data.mol.ctrl;
+    let is_master = mpi_operator.as_ref().map_or(true, |mp_op| mp_op.rank == 0);
+    if scf_data.mol.geom.fix.iter().any(|fix| *fix) && is_master {
+        println!("WARNING: the fixed atoms are displaced as well in the frequency calculation");
+    }
+
+    let hessian = numerical_hessian(scf_data, ctrl.freq_displacement, mpi_operator);
+    let vib = harmonic_analysis(&hessian, &scf_data.mol.geom);
+    let thermo = rrho_thermochemistry(&scf_data.mol.geom, &vib.frequencies, ctrl.spin,
+        ctrl.freq_symmetry_number, ctrl.freq_temperature, ctrl.freq_pressure);
+
+    if ctrl.print_level > 0 && is_master {
+        vib.formated_output(&scf_data.mol.geom.elem, ctrl.print_level);
+        thermo.formated_output(collect_total_energy(scf_data));
+    }
+
+    (vib, thermo)
+}
+
+impl VibrationalAnalysis {
+    pub fn num_imaginary(&self) -> usize {
+        self.frequencies.iter().filter(|nu| **nu < 0.0).count()
+    }
+
+    pub fn formated_output(&self, elem: &Vec<String>, print_level: usize) {
+        println!("----------------------------------------------------------------------");
+        println!("Harmonic vibrational frequencies ({} translations and rotations projected out)", self.num_trans_rot);
+        println!("----------------------------------------------------------------------");
+        println!("{:>6} {:>14} {:>18}", "Mode", "Freq. (cm-1)", "Red. mass (amu)");
+        self.frequencies.iter().zip(self.reduced_masses.iter()).enumerate().for_each(|(k, (nu, mu))| {
+            if *nu < 0.0 {
+                println!("{:6} {:13.2}i {:18.4}", k+1, nu.abs(), mu);
+            } else {
+                println!("{:6} {:14.2} {:18.4}", k+1, nu, mu);
+            }
+        });
+        let num_imag = self.num_imaginary();
+        if num_imag > 0 {
+            println!("Number of imaginary frequencies: {}", num_imag);
+        }
+        if print_level > 1 {
+            println!("Normal modes (normalized Cartesian displacements):");
+            for k in 0..self.frequencies.len() {
+                println!("Mode {:4}: {:12.2} cm-1", k+1, self.frequencies[k]);
+                elem.iter().enumerate().for_each(|(a, elem)| {
+                    println!("{:3}{:12.6}{:12.6}{:12.6}", elem,
+                        self.normal_modes[[3*a,k]], self.normal_modes[[3*a+1,k]], self.normal_modes[[3*a+2,k]]);
+                });
+            }
+        }
+        println!("----------------------------------------------------------------------");
+    }
+}
+
+impl Thermochemistry {
+    pub fn formated_output(&self, total_energy: f64) {
+        let s_total: f64 = self.entropy.iter().sum();
+        // Hartree/K -> cal/(mol K)
+        let to_cal = HARTREE_SI*AVOGADRO/CALORIE;
+        println!("----------------------------------------------------------------------");
+        println!("Thermochemistry (RRHO) at {:8.2} K and {:12.2} Pa", self.temperature, self.pressure);
+        println!("----------------------------------------------------------------------");
+        println!("Zero-point energy                  : {:18.10} Ha", self.zpe);
+        println!("Thermal correction to energy       : {:18.10} Ha", self.thermal_energy);
+        println!("Thermal correction to enthalpy     : {:18.10} Ha", self.thermal_enthalpy);
+        println!("Thermal correction to Gibbs energy : {:18.10} Ha", self.thermal_gibbs);
+        println!("Entropy (trans, rot, vib, elec)    : {:10.4} {:10.4} {:10.4} {:10.4} cal/(mol K)",
+            self.entropy[0]*to_cal, self.entropy[1]*to_cal, self.entropy[2]*to_cal, self.entropy[3]*to_cal);
+        println!("Total entropy                      : {:18.4} cal/(mol K)", s_total*to_cal);
+        println!("E + ZPE                            : {:18.10} Ha", total_energy + self.zpe);
+        println!("Enthalpy H                         : {:18.10} Ha", total_energy + self.thermal_enthalpy);
+        println!("Gibbs free energy G                : {:18.10} Ha", total_energy + self.thermal_gibbs);
+        println!("----------------------------------------------------------------------");
+    }
+}
+
+/// The principal moments of inertia in amu*Angstrom^2
+fn principal_moments(geom: &GeomCell) -> Vec<f64> {
+    let masses: Vec<f64> = get_mass_charge(&geom.elem).iter().map(|(m, _)| *m).collect();
+    let (com, _) = geom.evaluate_center_of_mass();
+    let mut inertia = MatrixFull::new([3,3], 0.0);
+    masses.iter().enumerate().for_each(|(a, m)| {
+        let r: Vec<f64> = (0..3).map(|x| (geom.position[[x,a]] - com[x])*ANG).collect();
+        let r2 = dot(&r, &r);
+        for j in 0..3 {
+            for i in 0..3 {
+                let delta = if i == j {r2} else {0.0};
+                inertia[[i,j]] += m*(delta - r[i]*r[j]);
+            }
+        }
+    });
+    let (_, moments) = eigh(&inertia);
+    moments
+}
+
+#[test]
+fn test_rrho_h2_rotational_entropy() {
+    // H2 at 298.15 K: S_trans ~ 28.08 cal/(mol K) and S_rot ~ 3.04 cal/(mol K) with sigma = 2
+    let mut geom = GeomCell::init_geom();
+    geom.elem = vec![String::from("H"), String::from("H")];
+    geom.fix = vec![false, false];
+    geom.position = MatrixFull::from_vec([3,2], vec![0.0,0.0,0.0, 0.0,0.0,0.7414/ANG]).unwrap();
+    let thermo = rrho_thermochemistry(&geom, &[4401.0], 1.0, 2, 298.15, 101325.0);
+    let to_cal = HARTREE_SI*AVOGADRO/CALORIE;
+    assert!((thermo.entropy[0]*to_cal - 28.08).abs() < 0.05);
+    assert!((thermo.entropy[1]*to_cal - 3.04).abs() < 0.05);
+    // ZPE = 1/2 h c nu
+    assert!((thermo.zpe - 0.5*4401.0/(EV*CM)).abs() < 1.0e-6);
+}
+
+#[test]
+fn test_hessian_with_frozen_atom() {
+    // the frozen O atom is displaced as well, so that the Hessian keeps the translational invariance
+    let ctrl_str = "[ctrl]
+        print_level = 0
+        xc = \"hf\"
+        basis_path = \"basis-set-pool/def2-SVP\"
+        auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+        eri_type = \"ri-v\"
+        scf_acc_rho = 1.0e-9
+        scf_acc_eev = 1.0e-9
+        scf_acc_etot = 1.0e-11
+        [geom]
+        name = \"H2O\"
+        unit = \"Angstrom\"
+        position = \"\"\"
+            O   0   0.000   0.000   0.120
+            H   1   0.000   0.760  -0.480
+            H   1   0.000  -0.760  -0.480\"\"\"";
+    let scf_data = crate::grad::scf_for_test(ctrl_str);
+    assert!(scf_data.mol.geom.fix[0]);
+    let hessian = numerical_hessian(&scf_data, 5.0e-3, &None);
+    for i in 0..9 {
+        for y in 0..3 {
+            let sum_rule: f64 = (0..3).map(|b| hessian[[i, 3*b+y]]).sum();
+            assert!(sum_rule.abs() < 1.0e-4, "translational sum rule of the Hessian: {}", sum_rule);
+        }
+    }
+    assert!((0..3).any(|x| hessian[[x,x]].abs() > 0.1));
+    let vib = harmonic_analysis(&hessian, &scf_data.mol.geom);
+    assert_eq!(vib.frequencies.len(), 3);
+    assert!(vib.frequencies.iter().all(|freq| *freq > 1000.0), "{:?}", vib.frequencies);
+}
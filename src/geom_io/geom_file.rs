@@ -0,0 +1,402 @@
+//! This mod reads the geometry from the external file given by `geom_file` in the `[geom]` block:
+//!  - `.xyz`: the XYZ file, of which the first frame is taken. In the REST style, the fix flag can follow the element
+//!    like `C 0 x y z`, where `0` is for the fixed atoms. The extended XYZ file is supported with the `key=value` pairs
+//!    in the comment line, i.e. `charge`, `multiplicity` and `Lattice="ax ay az bx by bz cx cy cz"`, as well as the
+//!    per-atom `fix` (or `move_mask`) and `ghost` columns declared by `Properties`. The lattice is only used for
+//!    the periodic systems with `pbc="T T T"`, while the boxed molecules (e.g. `pbc="F F F"` by ASE) stay molecules;
+//!  - `.pdb` (`.ent`): the ATOM/HETATM records of the first model and the lattice by CRYST1. The atoms with zero
+//!    occupancy are fixed only if `geom_file_fix_by_occupancy = true` is given in the `[geom]` block, as many
+//!    tools write the zero occupancy for all atoms;
+//!  - `.zmat` (`.zmt`, `.gzmat`): the Z-matrix with the reference atoms given by the indices (from 1) or the labels,
+//!    followed by the variables like `r1 = 0.96` after a blank line or a `Variables:` line. The dummy atoms (`X`, `Du`)
+//!    are removed after the conversion into the Cartesian coordinates.
+//!
+//! The XYZ and PDB files are in Angstrom, while the Z-matrix is in the unit of the `[geom]` block with the angles in
+//! degrees. The ghost atoms with basis sets are marked as `X-C`, `Gh(C)`, `@C`, `ghost-C` or `C-Bq` in all formats.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use rest_tensors::MatrixFull;
+use crate::constants::ANG;
+use crate::utilities::linear_algebra::vec3::{cross, normalize, sub};
+use super::{GeomCell, GeomUnit, MOrC, element_from_label, formated_element_name};
+
+#[derive(Debug,Clone)]
+pub struct FileAtom {
+    pub label: String,
+    pub ghost: bool,
+    pub fix: bool,
+    pub position: [f64;3],
+}
+
+/// The geometry read from the file, in the unit of the file
+#[derive(Debug,Clone)]
+pub struct GeomFile {
+    pub atoms: Vec<FileAtom>,
+    /// the lattice vectors of `a`, `b` and `c`
+    pub lattice: Option<[f64;9]>,
+    pub charge: Option<f64>,
+    pub multiplicity: Option<f64>,
+}
+
+impl GeomFile {
+    fn new(atoms: Vec<FileAtom>) -> GeomFile {
+        GeomFile {atoms, lattice: None, charge: None, multiplicity: None}
+    }
+}
+
+/// Split the ghost-atom marker from the atom token, e.g. `X-C` -> (`C`, true)
+pub fn parse_ghost_marker(token: &str) -> (String, bool) {
+    let lower = token.to_lowercase();
+    for prefix in ["ghost-", "ghost:", "x-", "@"] {
+        if lower.starts_with(prefix) && lower.len() > prefix.len() {return (token[prefix.len()..].to_string(), true)}
+    }
+    if lower.starts_with("gh(") && lower.ends_with(')') {return (token[3..token.len()-1].to_string(), true)}
+    if lower.ends_with("-bq") && lower.len() > 3 {return (token[..token.len()-3].to_string(), true)}
+    (token.to_string(), false)
+}
+
+/// The dummy atoms in the Z-matrix, like `X`, `X1` and `Du`
+fn is_dummy_atom(label: &str) -> bool {
+    let lower = label.to_lowercase();
+    lower.eq("du") || lower.eq("q")
+        || (lower.starts_with('x') && lower.trim_start_matches('x').chars().all(|c| c.is_ascii_digit()))
+}
+
+fn parse_f64(token: &str, line: &str) -> anyhow::Result<f64> {
+    token.replace(['D','d'], "E").parse::<f64>().map_err(|_| anyhow::anyhow!("Unknown number of {} in the geometry line: {}", token, line))
+}
+
+/// The `key=value` pairs in the comment line of the extended XYZ file, where the keys are in lowercase
+fn parse_comment_pairs(comment: &str) -> HashMap<String, String> {
+    let mut pairs = HashMap::new();
+    let mut tokens: Vec<String> = vec![];
+    let mut current = String::new();
+    let mut in_quote = false;
+    for c in comment.chars() {
+        if c == '"' {
+            in_quote = !in_quote;
+        } else if c.is_whitespace() && !in_quote {
+            if current.len() > 0 {tokens.push(current.clone()); current.clear()}
+        } else {
+            current.push(c);
+        }
+    }
+    if current.len() > 0 {tokens.push(current)}
+    tokens.iter().for_each(|token| {
+        if let Some((key, value)) = token.split_once('=') {
+            pairs.insert(key.trim().to_lowercase(), value.trim().to_string());
+        }
+    });
+    pairs
+}
+
+fn parse_logical(token: &str) -> bool {
+    let lower = token.to_lowercase();
+    lower.eq("t") || lower.eq("true") || lower.eq("1")
+}
+
+/// Parse the first frame of the (extended) XYZ file
+pub fn parse_xyz(contents: &str) -> anyhow::Result<GeomFile> {
+    let mut lines = contents.lines();
+    let num_atoms: usize = lines.next().unwrap_or("").trim().parse()
+        .map_err(|_| anyhow::anyhow!("The first line of the XYZ file should be the number of atoms"))?;
+    let comment = lines.next().unwrap_or("");
+    let pairs = parse_comment_pairs(comment);
+
+    // the columns of (name, number of columns) declared by `Properties`
+    let properties: Vec<(String, usize)> = match pairs.get("properties") {
+        Some(tmp_str) => {
+            let items: Vec<&str> = tmp_str.split(':').collect();
+            if items.len() % 3 != 0 {
+                return Err(anyhow::anyhow!("Unknown Properties in the extended XYZ file: {}", tmp_str))
+            }
+            items.chunks(3).map(|x| Ok((x[0].to_lowercase(), x[2].parse::<usize>()
+                .map_err(|_| anyhow::anyhow!("Unknown Properties in the extended XYZ file: {}", tmp_str))?)))
+                .collect::<anyhow::Result<Vec<(String, usize)>>>()?
+        },
+        None => vec![(String::from("species"), 1), (String::from("pos"), 3)],
+    };
+    let mut columns: HashMap<String, (usize, usize)> = HashMap::new();
+    let mut offset = 0;
+    for (name, num) in properties.iter() {
+        columns.insert(name.clone(), (offset, *num));
+        offset += num;
+    }
+    let (species_col, pos_col) = match (columns.get("species"), columns.get("pos")) {
+        (Some(species), Some(pos)) => (species.0, pos.0),
+        _ => return Err(anyhow::anyhow!("The species and pos columns are required in the extended XYZ file")),
+    };
+
+    let mut atoms = vec![];
+    for i_atom in 0..num_atoms {
+        let line = lines.next().ok_or(anyhow::anyhow!("Only {} atoms are found in the XYZ file, but {} are declared", i_atom, num_atoms))?;
+        let mut tokens: Vec<&str> = line.split_whitespace().collect();
+        // the REST-style fix flag following the element: `C 0 x y z`
+        let mut fix = false;
+        if pairs.get("properties").is_none() && tokens.len() == 5 && tokens[1].parse::<i32>().is_ok() {
+            fix = tokens[1].parse::<i32>().unwrap() == 0;
+            tokens.remove(1);
+        }
+        if tokens.len() < offset {
+            return Err(anyhow::anyhow!("Unknown atom in the XYZ file: {}", line))
+        }
+        let (label, mut ghost) = parse_ghost_marker(tokens[species_col]);
+        let mut position = [0.0;3];
+        for x in 0..3 {position[x] = parse_f64(tokens[pos_col+x], line)?};
+        if let Some((col, num)) = columns.get("fix") {
+            fix = tokens[*col..col+num].iter().any(|x| parse_logical(x));
+        } else if let Some((col, num)) = columns.get("move_mask") {
+            fix = tokens[*col..col+num].iter().all(|x| !parse_logical(x));
+        }
+        if let Some((col, _)) = columns.get("ghost") {
+            ghost = ghost || parse_logical(tokens[*col]);
+        }
+        atoms.push(FileAtom {label, ghost, fix, position});
+    }
+
+    let mut geom_file = GeomFile::new(atoms);
+    geom_file.charge = pairs.get("charge").and_then(|x| x.parse::<f64>().ok());
+    geom_file.multiplicity = pairs.get("multiplicity").or(pairs.get("mult")).and_then(|x| x.parse::<f64>().ok());
+    // the lattice is used only if the system is periodic in all three directions, where a missing `pbc`
+    // means periodic as in ASE
+    let is_periodic = pairs.get("pbc").map_or(true, |tmp_str| {
+        let flags: Vec<&str> = tmp_str.split_whitespace().collect();
+        flags.len() == 3 && flags.iter().all(|x| parse_logical(x))
+    });
+    if let (Some(tmp_str), true) = (pairs.get("lattice"), is_periodic) {
+        let values = tmp_str.split_whitespace().map(|x| parse_f64(x, comment)).collect::<anyhow::Result<Vec<f64>>>()?;
+        if values.len() != 9 {
+            return Err(anyhow::anyhow!("The lattice of the extended XYZ file should have 9 values: {}", tmp_str))
+        }
+        geom_file.lattice = Some(values.try_into().unwrap());
+    }
+    Ok(geom_file)
+}
+
+/// The lattice vectors from the cell parameters, where `a` is along x and `b` is in the xy plane
+pub fn lattice_from_cell_parameters(a: f64, b: f64, c: f64, alpha: f64, beta: f64, gamma: f64) -> [f64;9] {
+    let (cos_a, cos_b, cos_g) = (alpha.to_radians().cos(), beta.to_radians().cos(), gamma.to_radians().cos());
+    let sin_g = gamma.to_radians().sin();
+    let cy = (cos_a - cos_b*cos_g)/sin_g;
+    let cz = (1.0 - cos_b*cos_b - cy*cy).max(0.0).sqrt();
+    [a, 0.0, 0.0, b*cos_g, b*sin_g, 0.0, c*cos_b, c*cy, c*cz]
+}
+
+/// Parse the ATOM/HETATM records of the first model in the PDB file, where the atoms with zero occupancy
+/// are fixed if `fix_by_occupancy` is true
+pub fn parse_pdb(contents: &str, fix_by_occupancy: bool) -> anyhow::Result<GeomFile> {
+    let mut atoms = vec![];
+    let mut lattice = None;
+    for line in contents.lines() {
+        if line.starts_with("ENDMDL") {break}
+        if line.starts_with("CRYST1") {
+            let values = line.split_whitespace().skip(1).take(6).map(|x| parse_f64(x, line)).collect::<anyhow::Result<Vec<f64>>>()?;
+            // skip the dummy unit cell for the non-periodic systems
+            if values.len() == 6 && !values[0..3].iter().all(|x| (x-1.0).abs() < 1.0e-6) {
+                lattice = Some(lattice_from_cell_parameters(values[0], values[1], values[2], values[3], values[4], values[5]));
+            }
+        } else if line.starts_with("ATOM") || line.starts_with("HETATM") {
+            if line.len() < 54 {
+                return Err(anyhow::anyhow!("Unknown ATOM record in the PDB file: {}", line))
+            }
+            let (name, ghost) = parse_ghost_marker(line.get(12..16).unwrap_or("").trim());
+            let mut position = [0.0;3];
+            for (x, range) in [30..38, 38..46, 46..54].into_iter().enumerate() {position[x] = parse_f64(line[range].trim(), line)?};
+            let fix = fix_by_occupancy && line.get(54..60).and_then(|x| x.trim().parse::<f64>().ok()).map_or(false, |occ| occ == 0.0);
+            let label = match line.get(76..78).map(|x| x.trim()) {
+                Some(elem) if elem.len() > 0 => formated_element_name(&elem.to_string()),
+                _ => element_from_label(&name),
+            };
+            atoms.push(FileAtom {label, ghost, fix, position});
+        }
+    }
+    let mut geom_file = GeomFile::new(atoms);
+    geom_file.lattice = lattice;
+    Ok(geom_file)
+}
+
+/// Place the atom bonded to `c` with the distance `r`, the angle `theta` of (b, c, new) and the dihedral `phi` of
+/// (a, b, c, new) by the natural extension reference frame method
+fn place_atom(a: &[f64;3], b: &[f64;3], c: &[f64;3], r: f64, theta: f64, phi: f64) -> [f64;3] {
+    let bc = normalize(&sub(c, b));
+    let n = normalize(&cross(&sub(b, a), &bc));
+    let m = cross(&n, &bc);
+    let d2 = [-r*theta.cos(), r*theta.sin()*phi.cos(), r*theta.sin()*phi.sin()];
+    [0,1,2].map(|x| c[x] + d2[0]*bc[x] + d2[1]*m[x] + d2[2]*n[x])
+}
+
+/// Parse the Z-matrix with variables, which is converted into the Cartesian coordinates
+pub fn parse_zmatrix(contents: &str) -> anyhow::Result<GeomFile> {
+    let mut atom_lines: Vec<&str> = vec![];
+    let mut var_lines: Vec<&str> = vec![];
+    let mut in_variables = false;
+    for xline in contents.lines() {
+        let line = xline.trim();
+        if line.starts_with('#') || line.starts_with('!') {continue}
+        let lower = line.to_lowercase();
+        if line.len() == 0 {
+            if atom_lines.len() > 0 {in_variables = true}
+        } else if lower.starts_with("variables") || lower.starts_with("constants") {
+            in_variables = true;
+        } else if in_variables {
+            var_lines.push(line);
+        } else {
+            atom_lines.push(line);
+        }
+    }
+    let mut variables: HashMap<String, f64> = HashMap::new();
+    for line in var_lines {
+        let tokens: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == '=').filter(|x| x.len() > 0).collect();
+        if tokens.len() < 2 {
+            return Err(anyhow::anyhow!("Unknown variable in the Z-matrix: {}", line))
+        }
+        variables.insert(tokens[0].to_string(), parse_f64(tokens[1], line)?);
+    }
+    let value = |token: &str, line: &str| -> anyhow::Result<f64> {
+        if let Ok(x) = parse_f64(token, line) {return Ok(x)}
+        let (sign, name) = if let Some(name) = token.strip_prefix('-') {(-1.0, name)} else {(1.0, token.trim_start_matches('+'))};
+        variables.get(name).map(|x| sign*x).ok_or(anyhow::anyhow!("Unknown variable of {} in the Z-matrix: {}", token, line))
+    };
+
+    let mut labels: Vec<String> = vec![];
+    let mut positions: Vec<[f64;3]> = vec![];
+    for line in atom_lines {
+        let tokens: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ',').filter(|x| x.len() > 0).collect();
+        let i_atom = labels.len();
+        let num_refs = i_atom.min(3);
+        if tokens.len() < 1 + 2*num_refs {
+            return Err(anyhow::anyhow!("Unknown atom in the Z-matrix, which needs {} reference atoms: {}", num_refs, line))
+        }
+        let reference = |token: &str| -> anyhow::Result<usize> {
+            match token.parse::<usize>() {
+                Ok(index) if index >= 1 && index <= i_atom => Ok(index-1),
+                Ok(_) => Err(anyhow::anyhow!("The reference atom {} is out of range in the Z-matrix: {}", token, line)),
+                Err(_) => labels.iter().position(|x| x.eq(token))
+                    .ok_or(anyhow::anyhow!("Unknown reference atom {} in the Z-matrix: {}", token, line)),
+            }
+        };
+        let position = match num_refs {
+            0 => [0.0;3],
+            1 => {
+                let c = reference(tokens[1])?;
+                let r = value(tokens[2], line)?;
+                [positions[c][0], positions[c][1], positions[c][2]+r]
+            },
+            _ => {
+                let c = reference(tokens[1])?;
+                let r = value(tokens[2], line)?;
+                let b = reference(tokens[3])?;
+                let theta = value(tokens[4], line)?.to_radians();
+                let (a_pos, phi) = if num_refs == 3 {
+                    (positions[reference(tokens[5])?], value(tokens[6], line)?.to_radians())
+                } else {
+                    // the third atom is placed in the xz plane
+                    let bc = sub(&positions[c], &positions[b]);
+                    let e = if bc[0].abs() > 0.9*bc.iter().map(|x| x*x).sum::<f64>().sqrt() {[0.0,1.0,0.0]} else {[1.0,0.0,0.0]};
+                    ([positions[b][0]+e[0], positions[b][1]+e[1], positions[b][2]+e[2]], 0.0)
+                };
+                place_atom(&a_pos, &positions[b], &positions[c], r, theta, phi)
+            },
+        };
+        labels.push(tokens[0].to_string());
+        positions.push(position);
+    }
+
+    let atoms = labels.iter().zip(positions.iter()).filter(|(label, _)| !is_dummy_atom(label))
+        .map(|(label, position)| {
+            let (label, ghost) = parse_ghost_marker(label);
+            FileAtom {label, ghost, fix: false, position: *position}
+        }).collect();
+    Ok(GeomFile::new(atoms))
+}
+
+impl GeomCell {
+    /// Read the geometry from the file in the XYZ, extended XYZ, PDB or Z-matrix format, returning the geometry
+    /// (in Bohr), together with the charge and the spin multiplicity if they are given in the file
+    pub fn parse_geom_file(filename: &String, unit: &GeomUnit, fix_by_occupancy: bool) -> anyhow::Result<(GeomCell, Option<f64>, Option<f64>)> {
+        let contents = fs::read_to_string(filename)
+            .map_err(|e| anyhow::anyhow!("Fail to read the geometry file {}: {}", filename, e))?;
+        let extension = Path::new(filename).extension()
+            .map_or(String::new(), |ext| ext.to_string_lossy().to_lowercase());
+        let (geom_file, in_angstrom) = match extension.as_str() {
+            "xyz" | "extxyz" => (parse_xyz(&contents)?, true),
+            "pdb" | "ent" => (parse_pdb(&contents, fix_by_occupancy)?, true),
+            "zmat" | "zmt" | "gzmat" => (parse_zmatrix(&contents)?, if let GeomUnit::Angstrom = unit {true} else {false}),
+            _ => return Err(anyhow::anyhow!("Unknown format of the geometry file: {}. Please use .xyz, .pdb or .zmat", filename)),
+        };
+        if geom_file.atoms.iter().all(|atom| atom.ghost) {
+            return Err(anyhow::anyhow!("No atom is found in the geometry file: {}", filename))
+        }
+        // To store the geometry position in "Bohr" according to the convention of quantum chemistry.
+        let scale = if in_angstrom {ANG.powf(-1.0)} else {1.0};
+
+        let mut new_geom = GeomCell::init_geom();
+        new_geom.name = filename.clone();
+        new_geom.unit = unit.clone();
+        let mut tmp_pos: Vec<f64> = vec![];
+        let mut tmp_ghost_pos: Vec<f64> = vec![];
+        for atom in geom_file.atoms.iter() {
+            let elem = element_from_label(&atom.label);
+            if atom.ghost {
+                new_geom.ghost_bs_elem.push(elem);
+                tmp_ghost_pos.extend(atom.position.iter().map(|x| x*scale));
+            } else {
+                new_geom.elem.push(elem);
+                new_geom.atom_label.push(atom.label.clone());
+                new_geom.fix.push(atom.fix);
+                if !atom.fix {new_geom.nfree += 1};
+                tmp_pos.extend(atom.position.iter().map(|x| x*scale));
+            }
+        }
+        new_geom.position = MatrixFull::from_vec([3, tmp_pos.len()/3], tmp_pos).unwrap();
+        if tmp_ghost_pos.len() > 0 {
+            new_geom.ghost_bs_pos = MatrixFull::from_vec([3, tmp_ghost_pos.len()/3], tmp_ghost_pos).unwrap();
+        }
+        if let Some(lattice) = &geom_file.lattice {
+            new_geom.lattice = MatrixFull::from_vec([3,3], lattice.iter().map(|x| x*scale).collect()).unwrap();
+            new_geom.pbc = MOrC::Crystal;
+        }
+        Ok((new_geom, geom_file.charge, geom_file.multiplicity))
+    }
+}
+
+#[test]
+fn test_parse_geom_files() {
+    let xyz = "4\nLattice=\"10.0 0.0 0.0 0.0 10.0 0.0 0.0 0.0 10.0\" Properties=species:S:1:pos:R:3:fix:L:1 charge=-1 multiplicity=2\n\
+               O1 0.0 0.0 0.0 T\nH 0.0 0.757 0.587 F\nH 0.0 -0.757 0.587 F\nX-O 3.0 0.0 0.0 F\n";
+    let geom_file = parse_xyz(xyz).unwrap();
+    assert_eq!(geom_file.atoms.len(), 4);
+    assert!(geom_file.atoms[0].fix && !geom_file.atoms[1].fix);
+    assert_eq!(geom_file.atoms[0].label, String::from("O1"));
+    assert!(geom_file.atoms[3].ghost && geom_file.atoms[3].label.eq("O"));
+    assert_eq!((geom_file.charge, geom_file.multiplicity), (Some(-1.0), Some(2.0)));
+    assert_eq!(geom_file.lattice.unwrap()[4], 10.0);
+    // the boxed molecule written by ASE is not periodic
+    let geom_file = parse_xyz("1\nLattice=\"10.0 0.0 0.0 0.0 10.0 0.0 0.0 0.0 10.0\" pbc=\"F F F\"\nHe 0.0 0.0 0.0\n").unwrap();
+    assert!(geom_file.lattice.is_none());
+    // the REST-style fix flag in the plain XYZ file
+    let geom_file = parse_xyz("2\n\nC 0 0.0 0.0 0.0\nO 1 0.0 0.0 1.128\n").unwrap();
+    assert!(geom_file.atoms[0].fix && !geom_file.atoms[1].fix);
+
+    let pdb = "CRYST1   20.000   20.000   20.000  90.00  90.00  90.00 P 1           1\n\
+               HETATM    1  O   HOH A   1       0.000   0.000   0.000  0.00  0.00           O\n\
+               HETATM    2  H1  HOH A   1       0.000   0.757   0.587  1.00  0.00           H\n\
+               ENDMDL\nHETATM    3  H2  HOH A   1       0.000  -0.757   0.587  1.00  0.00           H\n";
+    let geom_file = parse_pdb(pdb, false).unwrap();
+    assert_eq!(geom_file.atoms.len(), 2);
+    assert!(!geom_file.atoms[0].fix && geom_file.atoms[1].label.eq("H"));
+    let geom_file = parse_pdb(pdb, true).unwrap();
+    assert!(geom_file.atoms[0].fix && !geom_file.atoms[1].fix);
+    assert!((geom_file.lattice.unwrap()[8]-20.0).abs() < 1.0e-10);
+
+    let zmat = "O\nH1 1 r\nH2 O r H1 a\nX 1 1.0 2 90.0 3 180.0\n\nVariables:\nr = 0.96\na = 104.5\n";
+    let geom_file = parse_zmatrix(zmat).unwrap();
+    assert_eq!(geom_file.atoms.len(), 3);
+    let (o, h1, h2) = (geom_file.atoms[0].position, geom_file.atoms[1].position, geom_file.atoms[2].position);
+    let dist = |a: &[f64;3], b: &[f64;3]| sub(a, b).iter().map(|x| x*x).sum::<f64>().sqrt();
+    assert!((dist(&o, &h2)-0.96).abs() < 1.0e-10);
+    let cos_hoh = (0..3).map(|x| (h1[x]-o[x])*(h2[x]-o[x])).sum::<f64>()/(0.96*0.96);
+    assert!((cos_hoh.acos().to_degrees()-104.5).abs() < 1.0e-8);
+}
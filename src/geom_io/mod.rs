@@ -16,6 +16,7 @@ use serde_json::Value;
 use crate::basis_io::Basis4Elem;
 use crate::constants::{SPECIES_NAME,MASS_CHARGE,ANG,SPECIES_INFO};
 mod pyrest_geom_io;
+pub mod geom_file;
 
 
 
@@ -33,6 +34,10 @@ pub struct GeomCell {
     pub elem: Vec<String>,
     #[pyo3(get,set)]
     pub fix:  Vec<bool>,
+    /// the atom labels given in the geometry, like `C1` for a carbon atom, which are used to assign the basis sets
+    /// to specific atoms. Empty if the labels are not available
+    #[pyo3(get,set)]
+    pub atom_label: Vec<String>,
     pub unit: GeomUnit,
     //pub position: MatrixXx3<f64>, 
     pub position: MatrixFull<f64>, 
@@ -44,10 +49,16 @@ pub struct GeomCell {
     pub ghost_bs_pos: MatrixFull<f64>,
     pub ghost_pc_chrg:     Vec<f64>,
     pub ghost_pc_pos: MatrixFull<f64>,
+    /// the width (in Bohr) of the Gaussian-smeared point charges, whose potentials are q*erf(r/width)/r.
+    /// None for the bare point charges
+    pub ghost_pc_width: Option<f64>,
     pub ghost_ep_path:     Vec<String>,
     pub ghost_ep_pos: MatrixFull<f64>,
     #[pyo3(get,set)]
     pub rest : Vec<(usize,String)>,
+    /// the internal coordinates kept fixed in geometry optimizations, given by the atom indices:
+    /// 2 for bonds, 3 for angles and 4 for dihedrals
+    pub constraints: Vec<Vec<usize>>,
 }
 
 //impl GeomCell {
@@ -102,6 +113,23 @@ pub fn formated_element_name(elem: &String) -> String {
 }
 
 
+/// The element of the atom label, like `C1` -> `C`, `Cl2` -> `Cl` and `HA` -> `H`.
+/// The uppercase labels, like `CA`, `HG` and `NA` in the PDB files, read as one letter with a suffix
+/// unless the one-letter element does not exist, like `ZN1` -> `Zn`.
+/// The label is returned untouched if no element is found
+pub fn element_from_label(label: &str) -> String {
+    let letters: String = label.chars().take_while(|c| c.is_ascii_alphabetic()).take(2).collect();
+    let lens = if letters.len() == 2 && letters.chars().all(|c| c.is_ascii_uppercase()) {[1,2]} else {[2,1]};
+    for len in lens {
+        if letters.len() >= len {
+            let elem = formated_element_name(&letters[..len].to_string());
+            if SPECIES_INFO.get(elem.as_str()).is_some() {return elem}
+        }
+    }
+    label.to_string()
+}
+
+
 pub fn get_mass_charge(elem_list: &Vec<String>) -> Vec<(f64,f64)> {
 
     //let name_mass_charge: HashMap<&String,&(f64,f64)> = element_name.iter().zip(atomic_mass_charge.iter()).collect();
@@ -138,6 +166,7 @@ impl GeomCell {
             nfree           : 0,
             elem            : vec![], 
             fix             : vec![],
+            atom_label      : vec![],
             unit            : GeomUnit::Angstrom,
             //position        : Tensors::new('F',vec![3,1],0.0f64),
             //lattice         : Tensors::new('F',vec![3,1],0.0f64),
@@ -149,8 +178,10 @@ impl GeomCell {
             ghost_bs_pos    : MatrixFull::empty(),
             ghost_pc_chrg   : vec![],
             ghost_pc_pos    : MatrixFull::empty(),
+            ghost_pc_width  : None,
             ghost_ep_path   : vec![],
             ghost_ep_pos    : MatrixFull::empty(),
+            constraints     : vec![],
         }
     }
     pub fn copy(&mut self, name:String) -> GeomCell {
@@ -165,10 +196,12 @@ impl GeomCell {
             MOrC::Molecule => MOrC::Molecule,
         };
         new_mol.rest = self.rest.to_owned();
+        new_mol.constraints = self.constraints.to_owned();
         for (elem,fix) in izip!(&mut self.elem, &mut self.fix) {
             new_mol.elem.push(elem.to_string());
             new_mol.fix.push(*fix);
         }
+        new_mol.atom_label = self.atom_label.to_owned();
         new_mol
     }
     pub fn get_nfree(&self) -> anyhow::Result<usize> {
@@ -177,6 +210,19 @@ impl GeomCell {
     pub fn get_elem(&self, index_a:usize) -> anyhow::Result<String> {
         Ok(self.elem[index_a].to_owned())
     }
+    /// The label of the given atom, or the element name if the labels are not available
+    pub fn get_atom_label(&self, index_a:usize) -> String {
+        if self.atom_label.len() == self.elem.len() {
+            self.atom_label[index_a].to_owned()
+        } else {
+            self.elem[index_a].to_owned()
+        }
+    }
+    /// Split the atom labels in `elem`, like `C1`, into the elements and `atom_label`
+    pub fn split_atom_labels(&mut self) {
+        self.atom_label = self.elem.clone();
+        self.elem = self.atom_label.iter().map(|label| element_from_label(label)).collect();
+    }
     pub fn get_elems_iter(&self) ->  std::slice::Iter<'_, std::string::String> {
         self.elem.iter()
     }
@@ -267,7 +313,6 @@ impl GeomCell {
     }
 
     pub fn geom_shift(&mut self, atm_idx:usize, vec_xyz:Vec<f64>) {
-        let mut gi = self.get_relax_index(atm_idx).unwrap();
         let mut given_atm = &mut self.position[(..,atm_idx)];
         given_atm.iter_mut().zip(vec_xyz.iter()).for_each(|(to, from)| {
             *to += from
@@ -279,7 +324,7 @@ impl GeomCell {
         // re0: the standard Cartesian position format with or without ',' as seperator
         //      no fix atom information
         let re0 = Regex::new(r"(?x)\s*
-                            (?P<elem>\w{1,2})\s*,?    # the element
+                            (?P<elem>[A-Za-z]\w*)\s*,?    # the element or the atom label, like C1
                             \s+
                             (?P<x>[\+-]?\d+.\d+)\s*,? # the 'x' position
                             \s+
@@ -290,7 +335,7 @@ impl GeomCell {
         // re1: the standard Cartesian position format with or without ',' as seperator
         //      info. of fix atom is specified following the element name
         let re1 = Regex::new(r"(?x)\s*
-                            (?P<elem>\w{1,2})\s*,?    # the element
+                            (?P<elem>[A-Za-z]\w*)\s*,?    # the element or the atom label, like C1
                             \s+
                             (?P<fix>\d)\s*,? # 1 for geometry relazation; 0 for fix
                             \s+
@@ -389,7 +434,7 @@ impl GeomCell {
         // re0: the standard Cartesian position format with or without ',' as seperator
         //      no fix atom information
         let re0 = Regex::new(r"(?x)\s*
-                            (?P<elem>\w{1,2})\s*,?    # the element
+                            (?P<elem>[A-Za-z]\w*)\s*,?    # the element or the atom label, like C1
                             \s+
                             (?P<x>[\+-]?\d+.\d+)\s*,? # the 'x' position
                             \s+
@@ -400,7 +445,7 @@ impl GeomCell {
         // re1: the standard Cartesian position format with or without ',' as seperator
         //      info. of fix atom is specified following the element name
         let re1 = Regex::new(r"(?x)\s*
-                            (?P<elem>\w{1,2})\s*,?    # the element
+                            (?P<elem>[A-Za-z]\w*)\s*,?    # the element or the atom label, like C1
                             \s+
                             (?P<fix>\d)\s*,? # 1 for geometry relazation; 0 for fix
                             \s+
@@ -457,7 +502,7 @@ impl GeomCell {
         // re0: the standard Cartesian position format with or without ',' as seperator
         //      no fix atom information
         let re0 = Regex::new(r"(?x)\s*
-                            (?P<elem>\w{1,2})\s*,?    # the element
+                            (?P<elem>[A-Za-z]\w*)\s*,?    # the element or the atom label, like C1
                             \s+
                             (?P<x>[\+-]?\d+.\d+)\s*,? # the 'x' position
                             \s+
@@ -468,7 +513,7 @@ impl GeomCell {
         // re1: the standard Cartesian position format with or without ',' as seperator
         //      info. of fix atom is specified following the element name
         let re1 = Regex::new(r"(?x)\s*
-                            (?P<elem>\w{1,2})\s*,?    # the element
+                            (?P<elem>[A-Za-z]\w*)\s*,?    # the element or the atom label, like C1
                             \s+
                             (?P<fix>\d)\s*,? # 1 for geometry relazation; 0 for fix
                             \s+
@@ -649,6 +694,95 @@ impl GeomCell {
 
     }
 
+    /// Read the MM point charges from an external file, returning the charges and the positions in Bohr:
+    /// - `*.pqr`: the ATOM/HETATM records ending with `x y z charge radius` in Angstrom;
+    /// - `*.pdb`: the ATOM/HETATM records in Angstrom with the partial charges in the B-factor columns (61-66);
+    /// - otherwise: one charge per line in the format of `charge x y z` in the given unit, where an optional
+    ///   first line with the number of charges and the lines starting with '#' are skipped.
+    pub fn parse_point_charges_from_file(filename: &String, unit: &GeomUnit) -> anyhow::Result<(Vec<f64>, MatrixFull<f64>)> {
+        let contents = fs::read_to_string(filename)
+            .map_err(|e| anyhow::anyhow!("Fail to read the point charges from {}: {}", filename, e))?;
+        let extension = std::path::Path::new(filename).extension()
+            .map_or(String::new(), |ext| ext.to_string_lossy().to_lowercase());
+        let mut tmp_chg: Vec<f64> = vec![];
+        let mut tmp_pos: Vec<f64> = vec![];
+        let parse_f64 = |x: &str, line: &str| -> anyhow::Result<f64> {
+            x.trim().parse::<f64>().map_err(|_| anyhow::anyhow!("Unknown format of the point charge in {}: {}", filename, line))
+        };
+
+        let in_angstrom = if extension.eq("pqr") {
+            for line in contents.lines().filter(|l| l.starts_with("ATOM") || l.starts_with("HETATM")) {
+                let items: Vec<&str> = line.split_whitespace().collect();
+                if items.len() < 7 {
+                    return Err(anyhow::anyhow!("Unknown format of the point charge in {}: {}", filename, line));
+                }
+                let n = items.len();
+                for x in &items[n-5..n-2] {tmp_pos.push(parse_f64(*x, line)?)};
+                tmp_chg.push(parse_f64(items[n-2], line)?);
+            }
+            true
+        } else if extension.eq("pdb") {
+            for line in contents.lines().filter(|l| l.starts_with("ATOM") || l.starts_with("HETATM")) {
+                if line.len() < 66 {
+                    return Err(anyhow::anyhow!("No partial charge in the B-factor columns of {}: {}", filename, line));
+                }
+                for range in [30..38, 38..46, 46..54] {tmp_pos.push(parse_f64(&line[range], line)?)};
+                tmp_chg.push(parse_f64(&line[60..66], line)?);
+            }
+            true
+        } else {
+            for (i, xline) in contents.lines().enumerate() {
+                let line = xline.trim();
+                if line.len() == 0 || line.starts_with('#') {continue}
+                let items: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ',').filter(|x| x.len() > 0).collect();
+                if i == 0 && items.len() == 1 {continue}
+                if items.len() < 4 {
+                    return Err(anyhow::anyhow!("Unknown format of the point charge in {}: {}", filename, line));
+                }
+                tmp_chg.push(parse_f64(items[0], line)?);
+                for x in &items[1..4] {tmp_pos.push(parse_f64(*x, line)?)};
+            }
+            if let GeomUnit::Angstrom = unit {true} else {false}
+        };
+
+        let mut tmp_pos_tensor = MatrixFull::from_vec([3, tmp_chg.len()], tmp_pos).unwrap();
+        if in_angstrom {
+            // To store the geometry position in "Bohr" according to the convention of quantum chemistry. 
+            tmp_pos_tensor.self_multiple(ANG.powf(-1.0));
+        }
+        Ok((tmp_chg, tmp_pos_tensor))
+    }
+
+    /// Parse the constraints for geometry optimizations, one per line, in the format of
+    /// `[bond|angle|dihedral] i j (k (l))` with the atom indices starting from 1.
+    /// The type is determined by the number of indices if it is not given.
+    pub fn parse_constraints_from_string(constraints: &String, num_atoms: usize) -> anyhow::Result<Vec<Vec<usize>>> {
+        let mut tmp_constraints: Vec<Vec<usize>> = vec![];
+        for xline in constraints.lines() {
+            let line = xline.trim().to_lowercase();
+            if line.len() == 0 || line.starts_with('#') {continue}
+            let mut items: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ',').filter(|x| x.len() > 0).collect();
+            let expected_len = match items[0] {
+                "bond" | "b" | "r" => {items.remove(0); Some(2)},
+                "angle" | "a" => {items.remove(0); Some(3)},
+                "dihedral" | "d" => {items.remove(0); Some(4)},
+                other => None,
+            };
+            let atoms: Vec<usize> = items.iter().map(|x| {
+                let index: usize = x.parse().unwrap_or_else(|_| panic!("Error: unknown constraint format: {}", xline));
+                if index < 1 || index > num_atoms {
+                    panic!("Error: the atom index {} in the constraint '{}' is out of range", index, xline);
+                }
+                index - 1
+            }).collect();
+            if atoms.len() < 2 || atoms.len() > 4 || expected_len.map_or(false, |n| n != atoms.len()) {
+                panic!("Error: unknown constraint format: {}", xline);
+            }
+            tmp_constraints.push(atoms);
+        }
+        Ok(tmp_constraints)
+    }
+
     pub fn to_xyz(&self, filename: String) {
         let ang = crate::constants::ANG;
         let mut input = fs::File::create(&filename).unwrap();
@@ -752,7 +886,8 @@ pub fn calc_nuc_energy_with_point_charges(geom: &GeomCell, basis4elem: &Vec<Basi
 
                 // MARK: do not count the interaction with the charge at the same position
                 if dd.abs() > 1.0E-8 {
-                    nuc_energy += *chg*j_charge/dd;
+                    let screening = if let Some(width) = geom.ghost_pc_width {libm::erf(dd/width)} else {1.0};
+                    nuc_energy += *chg*j_charge*screening/dd;
                 }
             });
             //// calculate the nuclear energy between the ghost atoms
@@ -767,6 +902,19 @@ pub fn calc_nuc_energy_with_point_charges(geom: &GeomCell, basis4elem: &Vec<Basi
     nuc_energy
 }
 
+/// The interaction between the nuclei and a uniform external electric field, -\sum_A Z_A F*R_A,
+/// where the ECP electrons are excluded from the nuclear charges
+pub fn calc_nuc_energy_with_electric_field(geom: &GeomCell, basis4elem: &Vec<Basis4Elem>, field: &[f64;3]) -> f64 {
+    let mass_charge = get_mass_charge(&geom.elem);
+    geom.position.iter_columns_full().enumerate().fold(0.0, |acc, (i, ri)| {
+        let mut i_charge = mass_charge[i].1;
+        if let Some(i_ecp) = basis4elem.get(i).unwrap().ecp_electrons {
+            i_charge -= i_ecp as f64;
+        };
+        acc - i_charge*ri.iter().zip(field.iter()).fold(0.0, |acc, (r, f)| acc + r*f)
+    })
+}
+
 #[test]
 fn test_string_parse_1() {
     let geom_str = "
@@ -921,3 +1069,24 @@ pub fn test_parse() {
         println!("{}, {}", cap["rmsd1"].to_string(), cap["rmsd2"].to_string());
     }
 }
+
+#[test]
+fn test_parse_point_charges_from_file() {
+    // unique file names for the concurrent test runs
+    let stamp = format!("{}_{}", std::process::id(), std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos());
+    let dir = std::env::temp_dir();
+    let pqr = dir.join(format!("rest_test_point_charges_{}.pqr", stamp));
+    fs::write(&pqr, "REMARK water\nATOM      1  OW  WAT     1       0.000   0.000   0.000 -0.8340 1.7683\nATOM      2  HW1 WAT     1       0.957   0.000   0.000  0.4170 0.0000\n").unwrap();
+    let (chrg, pos) = GeomCell::parse_point_charges_from_file(&pqr.to_string_lossy().to_string(), &GeomUnit::Bohr).unwrap();
+    assert_eq!(chrg, vec![-0.834, 0.417]);
+    assert!((pos[[0,1]] - 0.957/ANG).abs() < 1.0e-10);
+
+    let columns = dir.join(format!("rest_test_point_charges_{}.pc", stamp));
+    fs::write(&columns, "2\n-0.834 0.0 0.0 0.0\n# a comment\n0.417 1.8 0.0 0.0\n").unwrap();
+    let (chrg, pos) = GeomCell::parse_point_charges_from_file(&columns.to_string_lossy().to_string(), &GeomUnit::Bohr).unwrap();
+    assert_eq!(chrg, vec![-0.834, 0.417]);
+    assert_eq!(pos.size, [3,2]);
+    assert!((pos[[0,1]] - 1.8).abs() < 1.0e-10);
+    fs::remove_file(&pqr).unwrap();
+    fs::remove_file(&columns).unwrap();
+}
@@ -41,6 +41,7 @@ impl GeomCell {
         self.fix = fix;
         self.position = pos;
         self.nfree = n_free;
+        self.split_atom_labels();
 
     }
     pub fn py_set_position(&mut self, pos: Vec<String>) {
@@ -49,6 +50,7 @@ impl GeomCell {
         self.fix = fix;
         self.position = pos;
         self.nfree = n_free;
+        self.split_atom_labels();
     }
     pub fn py_calc_nuc_energy(&self) -> f64 {
         self.calc_nuc_energy()
@@ -0,0 +1,289 @@
+//! Redundant internal coordinates: the primitive bonds, angles and dihedrals, their Wilson
+//! B-matrix and the model Hessian.
+//!
+//! The primitives are generated from the connectivity determined by the scaled Bragg radii,
+//! where the disconnected fragments are joined by their shortest inter-fragment distances.
+//! The (nearly) linear angles are skipped, as well as the dihedrals defined on them.
+//! All the positions are in Bohr and the angles in radian.
+use crate::dft::gen_grids::bragg::get_bragg_angstrom;
+use crate::constants::ANG;
+use crate::utilities::linear_algebra::vec3::{add, cross, dot, norm, scale, sub};
+
+/// Two atoms are bonded if their distance is smaller than this factor times the sum of their radii
+pub const BOND_SCALE_FACTOR: f64 = 1.3;
+/// The angles larger than this value (in degree) are treated as linear and skipped
+pub const LINEAR_ANGLE_THRESHOLD: f64 = 175.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Primitive {
+    Bond(usize, usize),
+    /// i-j-k with j the apex
+    Angle(usize, usize, usize),
+    /// the torsion of i-j-k-l around the j-k bond
+    Dihedral(usize, usize, usize, usize),
+}
+
+impl Primitive {
+    /// Build the primitive from 2, 3 or 4 atom indices
+    pub fn from_atoms(atoms: &[usize]) -> Primitive {
+        match atoms.len() {
+            2 => Primitive::Bond(atoms[0], atoms[1]),
+            3 => Primitive::Angle(atoms[0], atoms[1], atoms[2]),
+            4 => Primitive::Dihedral(atoms[0], atoms[1], atoms[2], atoms[3]),
+            _ => panic!("An internal coordinate should be defined by 2, 3 or 4 atoms, but got {:?}", atoms)
+        }
+    }
+
+    pub fn atoms(&self) -> Vec<usize> {
+        match self {
+            Primitive::Bond(i, j) => vec![*i, *j],
+            Primitive::Angle(i, j, k) => vec![*i, *j, *k],
+            Primitive::Dihedral(i, j, k, l) => vec![*i, *j, *k, *l],
+        }
+    }
+
+    /// The same primitive regardless of the direction, e.g. Bond(1,2) and Bond(2,1)
+    pub fn is_equivalent(&self, other: &Primitive) -> bool {
+        let mut reversed = other.atoms();
+        reversed.reverse();
+        self.atoms() == other.atoms() || self.atoms() == reversed
+    }
+
+    pub fn is_dihedral(&self) -> bool {
+        matches!(self, Primitive::Dihedral(..))
+    }
+
+    pub fn value(&self, coords: &[[f64;3]]) -> f64 {
+        match self {
+            Primitive::Bond(i, j) => norm(&sub(&coords[*i], &coords[*j])),
+            Primitive::Angle(i, j, k) => {
+                let u = sub(&coords[*i], &coords[*j]);
+                let v = sub(&coords[*k], &coords[*j]);
+                (dot(&u, &v)/(norm(&u)*norm(&v))).max(-1.0).min(1.0).acos()
+            },
+            Primitive::Dihedral(i, j, k, l) => {
+                let b1 = sub(&coords[*j], &coords[*i]);
+                let b2 = sub(&coords[*k], &coords[*j]);
+                let b3 = sub(&coords[*l], &coords[*k]);
+                let m = cross(&b1, &b2);
+                let n = cross(&b2, &b3);
+                (norm(&b2)*dot(&b1, &n)).atan2(dot(&m, &n))
+            },
+        }
+    }
+
+    /// The derivatives of the primitive with respect to the positions of its atoms,
+    /// in the same order as `atoms()`
+    pub fn derivatives(&self, coords: &[[f64;3]]) -> Vec<[f64;3]> {
+        match self {
+            Primitive::Bond(i, j) => {
+                let u = sub(&coords[*i], &coords[*j]);
+                let u = scale(&u, 1.0/norm(&u));
+                vec![u, scale(&u, -1.0)]
+            },
+            Primitive::Angle(i, j, k) => {
+                let u = sub(&coords[*i], &coords[*j]);
+                let v = sub(&coords[*k], &coords[*j]);
+                let (lu, lv) = (norm(&u), norm(&v));
+                let (u, v) = (scale(&u, 1.0/lu), scale(&v, 1.0/lv));
+                let cos_t = dot(&u, &v).max(-1.0).min(1.0);
+                let sin_t = (1.0 - cos_t*cos_t).sqrt().max(1.0e-8);
+                let d_i = scale(&sub(&scale(&u, cos_t), &v), 1.0/(lu*sin_t));
+                let d_k = scale(&sub(&scale(&v, cos_t), &u), 1.0/(lv*sin_t));
+                let d_j = scale(&add(&d_i, &d_k), -1.0);
+                vec![d_i, d_j, d_k]
+            },
+            Primitive::Dihedral(i, j, k, l) => {
+                let b1 = sub(&coords[*j], &coords[*i]);
+                let b2 = sub(&coords[*k], &coords[*j]);
+                let b3 = sub(&coords[*l], &coords[*k]);
+                let m = cross(&b1, &b2);
+                let n = cross(&b2, &b3);
+                let lb2 = norm(&b2);
+                let d_i = scale(&m, -lb2/dot(&m, &m));
+                let d_l = scale(&n, lb2/dot(&n, &n));
+                let f1 = dot(&b1, &b2)/(lb2*lb2);
+                let f3 = dot(&b3, &b2)/(lb2*lb2);
+                let d_j = add(&scale(&d_i, -f1 - 1.0), &scale(&d_l, f3));
+                let d_k = add(&scale(&d_l, -f3 - 1.0), &scale(&d_i, f1));
+                vec![d_i, d_j, d_k, d_l]
+            },
+        }
+    }
+
+    pub fn formated_name(&self) -> String {
+        match self {
+            Primitive::Bond(i, j) => format!("R({},{})", i+1, j+1),
+            Primitive::Angle(i, j, k) => format!("A({},{},{})", i+1, j+1, k+1),
+            Primitive::Dihedral(i, j, k, l) => format!("D({},{},{},{})", i+1, j+1, k+1, l+1),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InternalCoordinates {
+    pub primitives: Vec<Primitive>,
+}
+
+impl InternalCoordinates {
+    /// Generate the redundant primitives for the given atomic charges and positions.
+    /// The `extra` primitives, e.g. the constraints, are always included.
+    pub fn build(charges: &[f64], coords: &[[f64;3]], extra: &[Primitive]) -> InternalCoordinates {
+        let num_atoms = coords.len();
+        let radii: Vec<f64> = charges.iter().map(|z| get_bragg_angstrom(z.round() as i32)/ANG).collect();
+        let dist = |i: usize, j: usize| norm(&sub(&coords[i], &coords[j]));
+
+        // the covalent bonds
+        let mut bonds: Vec<(usize, usize)> = vec![];
+        for i in 0..num_atoms {
+            for j in 0..i {
+                if dist(i, j) < BOND_SCALE_FACTOR*(radii[i] + radii[j]) {
+                    bonds.push((j, i));
+                }
+            }
+        }
+        // join the fragments by the shortest inter-fragment distances
+        loop {
+            let fragment = fragment_labels(num_atoms, &bonds);
+            let mut shortest: Option<(f64, usize, usize)> = None;
+            for i in 0..num_atoms {
+                for j in 0..i {
+                    if fragment[i] == fragment[j] {continue}
+                    let d = dist(i, j);
+                    if shortest.map_or(true, |(d0, _, _)| d < d0) {
+                        shortest = Some((d, j, i));
+                    }
+                }
+            }
+            match shortest {
+                Some((_, j, i)) => bonds.push((j, i)),
+                None => break,
+            }
+        }
+
+        let mut neighbours: Vec<Vec<usize>> = vec![vec![]; num_atoms];
+        bonds.iter().for_each(|(i, j)| {
+            neighbours[*i].push(*j);
+            neighbours[*j].push(*i);
+        });
+
+        let is_linear = |i: usize, j: usize, k: usize| {
+            Primitive::Angle(i, j, k).value(coords).to_degrees() > LINEAR_ANGLE_THRESHOLD
+        };
+
+        let mut primitives: Vec<Primitive> = bonds.iter().map(|(i, j)| Primitive::Bond(*i, *j)).collect();
+        for j in 0..num_atoms {
+            for (a, i) in neighbours[j].iter().enumerate() {
+                for k in neighbours[j][a+1..].iter() {
+                    if ! is_linear(*i, j, *k) {
+                        primitives.push(Primitive::Angle(*i, j, *k));
+                    }
+                }
+            }
+        }
+        bonds.iter().for_each(|(j, k)| {
+            neighbours[*j].iter().filter(|i| *i != k).for_each(|i| {
+                neighbours[*k].iter().filter(|l| *l != j && *l != i).for_each(|l| {
+                    if ! is_linear(*i, *j, *k) && ! is_linear(*j, *k, *l) {
+                        primitives.push(Primitive::Dihedral(*i, *j, *k, *l));
+                    }
+                });
+            });
+        });
+
+        extra.iter().for_each(|p| {
+            if ! primitives.iter().any(|q| q.is_equivalent(p)) {
+                primitives.push(p.clone());
+            }
+        });
+
+        InternalCoordinates {primitives}
+    }
+
+    pub fn len(&self) -> usize {
+        self.primitives.len()
+    }
+
+    pub fn values(&self, coords: &[[f64;3]]) -> Vec<f64> {
+        self.primitives.iter().map(|p| p.value(coords)).collect()
+    }
+
+    /// q1 - q0, where the differences of the dihedrals are wrapped into (-pi, pi]
+    pub fn difference(&self, q1: &[f64], q0: &[f64]) -> Vec<f64> {
+        let pi = std::f64::consts::PI;
+        self.primitives.iter().zip(q1.iter().zip(q0.iter())).map(|(p, (q1, q0))| {
+            let mut dq = q1 - q0;
+            if p.is_dihedral() {
+                while dq > pi {dq -= 2.0*pi}
+                while dq <= -pi {dq += 2.0*pi}
+            }
+            dq
+        }).collect()
+    }
+
+    /// The Wilson B-matrix in rows, [num_primitives][3*num_atoms]
+    pub fn b_matrix(&self, coords: &[[f64;3]]) -> Vec<Vec<f64>> {
+        let num_coords = 3*coords.len();
+        self.primitives.iter().map(|p| {
+            let mut row = vec![0.0; num_coords];
+            p.atoms().iter().zip(p.derivatives(coords).iter()).for_each(|(a, d)| {
+                for x in 0..3 {
+                    row[3*a+x] += d[x];
+                }
+            });
+            row
+        }).collect()
+    }
+
+    /// The diagonal model Hessian of Swart and Bickelhaupt,
+    /// with the decay of rho_ij = exp(1 - r_ij/(r_i + r_j))
+    pub fn model_hessian(&self, charges: &[f64], coords: &[[f64;3]]) -> Vec<f64> {
+        let radii: Vec<f64> = charges.iter().map(|z| get_bragg_angstrom(z.round() as i32)/ANG).collect();
+        let rho = |i: usize, j: usize| (1.0 - norm(&sub(&coords[i], &coords[j]))/(radii[i] + radii[j])).exp();
+        self.primitives.iter().map(|p| match p {
+            Primitive::Bond(i, j) => 0.45*rho(*i, *j),
+            Primitive::Angle(i, j, k) => 0.15*rho(*i, *j)*rho(*j, *k),
+            Primitive::Dihedral(i, j, k, l) => 0.005*rho(*i, *j)*rho(*j, *k)*rho(*k, *l),
+        }).collect()
+    }
+}
+
+/// Label the connected fragments of the given bonds
+fn fragment_labels(num_atoms: usize, bonds: &[(usize, usize)]) -> Vec<usize> {
+    let mut label: Vec<usize> = (0..num_atoms).collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        bonds.iter().for_each(|(i, j)| {
+            let min_label = label[*i].min(label[*j]);
+            if label[*i] != min_label || label[*j] != min_label {
+                label[*i] = min_label;
+                label[*j] = min_label;
+                changed = true;
+            }
+        });
+    }
+    label
+}
+
+#[test]
+fn test_b_matrix_finite_difference() {
+    // a distorted H2O2 with all kinds of the primitives
+    let coords = [[0.0, 1.33, -0.12], [0.0, -1.33, -0.12], [1.65, 1.60, 0.95], [-1.72, -1.55, 0.88]];
+    let ic = InternalCoordinates::build(&[8.0, 8.0, 1.0, 1.0], &coords, &[]);
+    assert_eq!(ic.primitives.iter().filter(|p| p.is_dihedral()).count(), 1);
+    let b = ic.b_matrix(&coords);
+    let h = 1.0e-5;
+    for a in 0..coords.len() {
+        for x in 0..3 {
+            let mut plus = coords.clone();
+            let mut minus = coords.clone();
+            plus[a][x] += h;
+            minus[a][x] -= h;
+            let dq = ic.difference(&ic.values(&plus), &ic.values(&minus));
+            dq.iter().zip(b.iter()).for_each(|(dq, b_row)| {
+                assert!((dq/(2.0*h) - b_row[3*a+x]).abs() < 1.0e-6);
+            });
+        }
+    }
+}
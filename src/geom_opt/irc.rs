@@ -0,0 +1,207 @@
+//! Intrinsic reaction coordinate (IRC) following in the mass-weighted Cartesian coordinates
+//! by the local quadratic approximation (LQA)[^1].
+//!
+//! Starting from the transition state along the transition vector (the imaginary mode), each
+//! step follows the steepest-descent path of the local quadratic model analytically:
+//! ```text
+//!   dq(t) = \sum_i u_i g_i [exp(-b_i t) - 1]/b_i,    ds/dt = sqrt(\sum_i g_i^2 exp(-2 b_i t))
+//! ```
+//! where `{b_i, u_i}` are the eigenpairs of the mass-weighted Hessian and `g_i = u_i^T g`.
+//! The parameter `t` is determined by the given arc length `s` of the step. The Hessian is
+//! computed by finite differences at the transition state and updated by the Bofill formula
+//! along the path. Both directions are followed until the energy rises or the forces converge.
+//!
+//! [^1]: J. Page and J. W. McIver, J. Chem. Phys. 88, 922 (1988).
+use tensors::MatrixFull;
+use crate::constants::{EV, KCAL_MOL};
+use crate::freq::{harmonic_analysis, numerical_hessian, projected_mass_weighted_hessian, trans_rot_projector};
+use crate::geom_io::get_mass_charge;
+use crate::grad::calc_force;
+use crate::mpi_io::MPIOperator;
+use crate::scf_io::SCF;
+use crate::{collect_total_energy, utilities};
+use crate::utilities::linear_algebra::{dot, eigh, mat_vec};
+use super::{positions_of, scf_at_geometry, set_positions, write_xyz_frame, FORCE_DISPLACEMENT};
+use super::ts::bofill_update;
+
+
+/// A point on the IRC: the arc length (in amu^1/2 Bohr, negative for the reverse direction),
+/// the energy and the positions
+pub struct IrcPoint {
+    pub arc_length: f64,
+    pub energy: f64,
+    pub positions: Vec<[f64;3]>,
+}
+
+/// The LQA step of the arc length `step_size` for the mass-weighted Hessian `h` and gradient `g`.
+/// The step is shorter if the steepest-descent path of the quadratic model ends earlier.
+pub fn lqa_step(h: &MatrixFull<f64>, g: &[f64], step_size: f64) -> Vec<f64> {
+    let n = g.len();
+    let (evec, eval) = eigh(h);
+    let gi: Vec<f64> = (0..n).map(|i| (0..n).fold(0.0, |acc, x| acc + evec[[x,i]]*g[x])).collect();
+    let speed = |t: f64| gi.iter().zip(eval.iter()).fold(0.0, |acc, (g, b)| acc + g*g*(-2.0*b*t).exp()).sqrt();
+
+    // integrate the arc length by the trapezoidal rule
+    let g_norm = dot(g, g).sqrt();
+    if g_norm < 1.0e-12 {return vec![0.0; n]}
+    let dt = step_size/g_norm/1000.0;
+    let (mut t, mut s, mut v0) = (0.0, 0.0, g_norm);
+    for _ in 0..100000 {
+        let v1 = speed(t + dt);
+        let ds = 0.5*(v0 + v1)*dt;
+        if s + ds >= step_size {
+            t += dt*(step_size - s)/ds;
+            break
+        }
+        s += ds;
+        t += dt;
+        v0 = v1;
+        if v1 < 1.0e-8*g_norm {break}
+    }
+
+    let mut step = vec![0.0; n];
+    (0..n).for_each(|i| {
+        let coeff = if (eval[i]*t).abs() > 1.0e-8 {
+            gi[i]*((-eval[i]*t).exp() - 1.0)/eval[i]
+        } else {
+            -gi[i]*t
+        };
+        for x in 0..n {
+            step[x] += coeff*evec[[x,i]];
+        }
+    });
+    step
+}
+
+/// Follow the IRC in both directions from the transition state in `scf_data`, which is left unchanged.
+/// The points are ordered from the reverse end, through the transition state, to the forward end.
+pub fn irc_calculations(scf_data: &SCF, time_mark: &mut utilities::TimeRecords, mpi_operator: &Option<MPIOperator>) -> Vec<IrcPoint> {
+    let is_master = mpi_operator.as_ref().map_or(true, |mp_op| mp_op.rank == 0);
+    let ctrl = &scf_data.mol.ctrl;
+    let print_level = ctrl.print_level;
+    let step_size = ctrl.irc_step_size;
+    let max_points = ctrl.irc_max_points;
+    let geom = &scf_data.mol.geom;
+    if geom.fix.iter().any(|fix| *fix) && is_master {
+        println!("WARNING: the fixed atoms are ignored in the IRC calculation");
+    }
+
+    let masses: Vec<f64> = get_mass_charge(&geom.elem).iter().map(|(m, _)| *m).collect();
+    let num_coords = 3*masses.len();
+    let sqrt_m: Vec<f64> = (0..num_coords).map(|i| masses[i/3].sqrt()).collect();
+    let x_ts = positions_of(geom);
+    let e_ts = collect_total_energy(scf_data);
+
+    // the Hessian and the transition vector at the transition state
+    time_mark.new_item("irc_hessian", "the Hessian at the transition state");
+    time_mark.count_start("irc_hessian");
+    let hx = numerical_hessian(scf_data, ctrl.freq_displacement, mpi_operator);
+    time_mark.count("irc_hessian");
+    let vib = harmonic_analysis(&hx, geom);
+    if is_master && print_level > 0 {
+        match vib.num_imaginary() {
+            1 => println!("IRC: the transition vector with the frequency of {:10.2}i cm-1", vib.frequencies[0].abs()),
+            n => println!("WARNING: {} imaginary frequencies at the starting geometry. The lowest mode is followed", n),
+        }
+    }
+    // the transition vector in the mass-weighted coordinates
+    let mut tv: Vec<f64> = (0..num_coords).map(|i| vib.normal_modes[[i,0]]*sqrt_m[i]).collect();
+    let tv_norm = dot(&tv, &tv).sqrt();
+    tv.iter_mut().for_each(|v| *v /= tv_norm);
+    let h_ts = projected_mass_weighted_hessian(&hx, geom);
+
+    let mut branches: Vec<Vec<IrcPoint>> = vec![];
+    for direction in [1.0, -1.0] {
+        let label = if direction > 0.0 {"forward"} else {"reverse"};
+        let mut new_scf = scf_data.clone();
+        new_scf.mol.ctrl.print_level = 0;
+        let mut hmw = h_ts.clone();
+        let mut x_mw: Vec<f64> = x_ts.iter().flat_map(|xa| *xa).zip(sqrt_m.iter()).map(|(x, m)| x*m).collect();
+        let mut dq: Vec<f64> = tv.iter().map(|v| direction*step_size*v).collect();
+        let mut g_old: Option<Vec<f64>> = None;
+        let mut e_old = e_ts;
+        let mut points: Vec<IrcPoint> = vec![];
+
+        for i_point in 1..=max_points {
+            x_mw.iter_mut().zip(dq.iter()).for_each(|(x, d)| *x += d);
+            let x: Vec<[f64;3]> = x_mw.chunks_exact(3).enumerate()
+                .map(|(a, xa)| [xa[0]/sqrt_m[3*a], xa[1]/sqrt_m[3*a], xa[2]/sqrt_m[3*a]]).collect();
+            let energy = scf_at_geometry(&mut new_scf, &x, time_mark, mpi_operator);
+            if energy > e_old {
+                if is_master && print_level > 0 {
+                    println!("IRC ({}): the energy rises at point {}. Stop", label, i_point);
+                }
+                break
+            }
+            let (_, gradient) = calc_force(&new_scf, FORCE_DISPLACEMENT, mpi_operator);
+            let proj = trans_rot_projector(&new_scf.mol.geom);
+            let g_mw: Vec<f64> = gradient.data.iter().zip(sqrt_m.iter()).map(|(g, m)| g/m).collect();
+            let g_mw = mat_vec(&proj, &g_mw);
+
+            if let Some(g_old) = &g_old {
+                let y: Vec<f64> = g_mw.iter().zip(g_old.iter()).map(|(g, g_old)| g - g_old).collect();
+                bofill_update(&mut hmw, &dq, &y);
+            }
+
+            let arc_length = direction*step_size*(i_point as f64);
+            if is_master && print_level > 0 {
+                println!("IRC ({}) point {:4}: s = {:8.4}, E = {:18.10} Ha, dE = {:10.4} kcal/mol",
+                    label, i_point, arc_length, energy, (energy - e_ts)*KCAL_MOL);
+            }
+            points.push(IrcPoint {arc_length, energy, positions: x.clone()});
+
+            let max_force = gradient.data.iter().fold(0.0_f64, |acc, g| acc.max(g.abs()));
+            let rms_force = (dot(&gradient.data, &gradient.data)/(num_coords as f64)).sqrt();
+            if max_force < ctrl.geom_opt_max_force && rms_force < ctrl.geom_opt_rms_force {
+                if is_master && print_level > 0 {
+                    println!("IRC ({}): the forces are converged at point {}", label, i_point);
+                }
+                break
+            }
+
+            dq = mat_vec(&proj, &lqa_step(&hmw, &g_mw, step_size));
+            g_old = Some(g_mw);
+            e_old = energy;
+        }
+        branches.push(points);
+    }
+
+    let reverse = branches.pop().unwrap();
+    let forward = branches.pop().unwrap();
+    let mut path: Vec<IrcPoint> = reverse.into_iter().rev().collect();
+    path.push(IrcPoint {arc_length: 0.0, energy: e_ts, positions: x_ts});
+    path.extend(forward.into_iter());
+
+    if is_master {
+        let mut frame_geom = geom.clone();
+        path.iter().enumerate().for_each(|(i, point)| {
+            set_positions(&mut frame_geom, &point.positions);
+            let comment = format!("IRC point {:4}  s = {:10.5}  Energy = {:18.10} Ha", i, point.arc_length, point.energy);
+            write_xyz_frame(&ctrl.irc_trajectory, &frame_geom, &comment, i > 0);
+        });
+        if print_level > 0 {
+            println!("----------------------------------------------------------------------");
+            println!("IRC profile (s in amu^1/2 Bohr), written to {}", ctrl.irc_trajectory);
+            println!("----------------------------------------------------------------------");
+            println!("{:>6} {:>12} {:>20} {:>16} {:>12}", "Point", "s", "Energy (Ha)", "dE (kcal/mol)", "dE (eV)");
+            path.iter().enumerate().for_each(|(i, point)| {
+                println!("{:6} {:12.5} {:20.10} {:16.4} {:12.5}", i, point.arc_length, point.energy,
+                    (point.energy - e_ts)*KCAL_MOL, (point.energy - e_ts)*EV);
+            });
+            println!("----------------------------------------------------------------------");
+        }
+    }
+    path
+}
+
+#[test]
+fn test_lqa_step_isotropic() {
+    // for an isotropic quadratic model, the steepest-descent path is a straight line along -g
+    let mut h = MatrixFull::new([3,3], 0.0);
+    (0..3).for_each(|i| h[[i,i]] = 0.5);
+    let g = vec![0.3, -0.4, 0.0];
+    let step = lqa_step(&h, &g, 0.2);
+    assert!((dot(&step, &step).sqrt() - 0.2).abs() < 1.0e-4);
+    assert!((step[0]/step[1] + 0.75).abs() < 1.0e-8);
+    assert!(step[0] < 0.0);
+}
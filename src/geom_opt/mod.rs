@@ -0,0 +1,398 @@
+//! Geometry optimization in the redundant internal coordinates.
+//!
+//! Each step is a rational function optimization (RFO) step on the quasi-Newton model, whose
+//! Hessian starts from the model Hessian of [`internal::InternalCoordinates::model_hessian`] and
+//! is improved by the BFGS updates. The frozen atoms (`GeomCell.fix`) are removed from the
+//! Wilson B-matrix, and the constrained bonds, angles and dihedrals (`GeomCell.constraints`)
+//! are projected out[^1]:
+//! ```text
+//!   P~ = P - P C (C P C)^- C P,   P = G G^-,   G = B B^T
+//! ```
+//! where `C` is the diagonal indicator of the constrained primitives. The steps raising the energy are
+//! rejected and retried with a smaller trust radius.
+//!
+//! [^1]: C. Peng, P. Y. Ayala, H. B. Schlegel, and M. J. Frisch, J. Comput. Chem. 17, 49 (1996).
+pub mod internal;
+pub mod ts;
+pub mod irc;
+pub mod scan;
+
+use std::fs;
+use std::io::Write;
+use tensors::MatrixFull;
+use tensors::matrix_blas_lapack::_dgemm_full;
+use crate::constants::ANG;
+use crate::ctrl_io::InputKeywords;
+use crate::geom_io::{get_mass_charge, GeomCell};
+use crate::grad::{calc_force, formated_force};
+use crate::mpi_io::MPIOperator;
+use crate::scf_io::{initialize_scf, SCF};
+use crate::{collect_total_energy, performance_essential_calculations, utilities};
+use crate::utilities::linear_algebra::{diagonal_matrix, dot, eigh, generalized_inverse, mat_vec, matmul};
+use self::internal::{InternalCoordinates, Primitive};
+
+/// The eigenvalues of G = B B^T below this value are treated as redundant
+pub const REDUNDANCY_THRESHOLD: f64 = 1.0e-6;
+/// The penalty in the projected Hessian for the redundant and constrained directions
+const PROJECTED_HESSIAN_SHIFT: f64 = 1000.0;
+/// The bounds of the trust radius
+const MIN_TRUST_RADIUS: f64 = 1.0e-3;
+const MAX_TRUST_RADIUS: f64 = 1.0;
+/// The steps raising the energy by more than this value (in Hartree) are rejected
+const STEP_REJECTION_THRESHOLD: f64 = 1.0e-6;
+/// The displacement used by the numerical forces, if the analytic ones are not available
+pub const FORCE_DISPLACEMENT: f64 = 0.0013/ANG;
+
+pub struct ConvergenceCriteria {
+    pub max_force: f64,
+    pub rms_force: f64,
+    pub max_displacement: f64,
+    pub rms_displacement: f64,
+}
+
+impl ConvergenceCriteria {
+    pub fn from_ctrl(ctrl: &InputKeywords) -> ConvergenceCriteria {
+        ConvergenceCriteria {
+            max_force: ctrl.geom_opt_max_force,
+            rms_force: ctrl.geom_opt_rms_force,
+            max_displacement: ctrl.geom_opt_max_displacement,
+            rms_displacement: ctrl.geom_opt_rms_displacement,
+        }
+    }
+
+    /// Check the forces and the displacements of the next step. The optimization is also
+    /// regarded as converged if the forces are two orders of magnitude below the criteria.
+    pub fn check(&self, force: &[f64], displacement: &[f64], print: bool) -> bool {
+        let max_abs = |v: &[f64]| v.iter().fold(0.0_f64, |acc, x| acc.max(x.abs()));
+        let rms = |v: &[f64]| (v.iter().fold(0.0, |acc, x| acc + x*x)/(v.len().max(1) as f64)).sqrt();
+        let items = [
+            ("Maximum force", max_abs(force), self.max_force),
+            ("RMS     force", rms(force), self.rms_force),
+            ("Maximum displacement", max_abs(displacement), self.max_displacement),
+            ("RMS     displacement", rms(displacement), self.rms_displacement),
+        ];
+        if print {
+            println!("{:>24} {:>14} {:>14} {:>10}", "Item", "Value", "Threshold", "Converged?");
+            items.iter().for_each(|(name, value, threshold)| {
+                println!("{:>24} {:14.6e} {:14.6e} {:>10}", name, value, threshold, if value <= threshold {"YES"} else {"NO"});
+            });
+        }
+        let forces_tiny = items[0].1 <= 0.01*items[0].2 && items[1].1 <= 0.01*items[1].2;
+        items.iter().all(|(_, value, threshold)| value <= threshold) || forces_tiny
+    }
+}
+
+/// The redundant internal coordinates together with the frozen atoms and the constraints
+pub struct RedundantInternals {
+    pub coords: InternalCoordinates,
+    pub charges: Vec<f64>,
+    /// false for the Cartesian components of the frozen atoms
+    pub active: Vec<bool>,
+    /// the indices of the constrained primitives
+    pub constrained: Vec<usize>,
+}
+
+impl RedundantInternals {
+    pub fn build(geom: &GeomCell) -> RedundantInternals {
+        let charges: Vec<f64> = get_mass_charge(&geom.elem).iter().map(|(_, c)| *c).collect();
+        let x = positions_of(geom);
+        let constraints: Vec<Primitive> = geom.constraints.iter().map(|atoms| Primitive::from_atoms(atoms)).collect();
+        let coords = InternalCoordinates::build(&charges, &x, &constraints);
+        let constrained: Vec<usize> = constraints.iter().map(|c| {
+            coords.primitives.iter().position(|p| p.is_equivalent(c)).unwrap()
+        }).collect();
+        let active: Vec<bool> = geom.fix.iter().flat_map(|fix| [!fix, !fix, !fix]).collect();
+        RedundantInternals {coords, charges, active, constrained}
+    }
+
+    pub fn len(&self) -> usize {
+        self.coords.len()
+    }
+
+    /// The Wilson B-matrix [num_primitives, 3*num_atoms] without the frozen atoms
+    pub fn b_matrix(&self, x: &[[f64;3]]) -> MatrixFull<f64> {
+        let rows = self.coords.b_matrix(x);
+        let num_coords = 3*x.len();
+        let mut b = MatrixFull::new([self.len(), num_coords], 0.0);
+        rows.iter().enumerate().for_each(|(q, row)| {
+            row.iter().zip(self.active.iter()).enumerate().filter(|(_, (_, active))| **active).for_each(|(i, (b_qi, _))| {
+                b[[q,i]] = *b_qi;
+            });
+        });
+        b
+    }
+
+    /// The generalized inverse of G = B B^T
+    fn g_inverse(b: &MatrixFull<f64>) -> (MatrixFull<f64>, MatrixFull<f64>) {
+        let mut g = MatrixFull::new([b.size[0], b.size[0]], 0.0);
+        _dgemm_full(b, 'N', b, 'T', &mut g, 1.0, 0.0);
+        (generalized_inverse(&g, REDUNDANCY_THRESHOLD), g)
+    }
+
+    /// The gradient in the internal coordinates: g_q = G^- B g_x
+    pub fn gradient(&self, x: &[[f64;3]], gx: &[f64]) -> Vec<f64> {
+        let b = self.b_matrix(x);
+        let (g_inv, _) = RedundantInternals::g_inverse(&b);
+        let bg = mat_vec(&b, gx);
+        mat_vec(&g_inv, &bg)
+    }
+
+    /// The projector onto the non-redundant and unconstrained space
+    pub fn projector(&self, x: &[[f64;3]]) -> MatrixFull<f64> {
+        let b = self.b_matrix(x);
+        let (g_inv, g) = RedundantInternals::g_inverse(&b);
+        let p = matmul(&g, &g_inv);
+        if self.constrained.len() == 0 {return p}
+
+        let num_c = self.constrained.len();
+        let mut cpc = MatrixFull::new([num_c, num_c], 0.0);
+        for (jc, j) in self.constrained.iter().enumerate() {
+            for (ic, i) in self.constrained.iter().enumerate() {
+                cpc[[ic,jc]] = p[[*i,*j]];
+            }
+        }
+        let cpc_inv = generalized_inverse(&cpc, REDUNDANCY_THRESHOLD);
+        let n = self.len();
+        let mut p_c = MatrixFull::new([n, num_c], 0.0);
+        for (jc, j) in self.constrained.iter().enumerate() {
+            for i in 0..n {
+                p_c[[i,jc]] = p[[i,*j]];
+            }
+        }
+        let tmp = matmul(&p_c, &cpc_inv);
+        let mut p_new = p.clone();
+        _dgemm_full(&tmp, 'N', &p_c, 'T', &mut p_new, -1.0, 1.0);
+        p_new
+    }
+
+    /// The projected gradient P~ g and Hessian P~ H P~ + shift (1 - P~)
+    pub fn project(&self, x: &[[f64;3]], gq: &[f64], hq: &MatrixFull<f64>) -> (Vec<f64>, MatrixFull<f64>) {
+        let p = self.projector(x);
+        let g_p = mat_vec(&p, gq);
+        let mut h_p = matmul(&p, &matmul(hq, &p));
+        for j in 0..self.len() {
+            for i in 0..self.len() {
+                let delta = if i == j {1.0} else {0.0};
+                h_p[[i,j]] += PROJECTED_HESSIAN_SHIFT*(delta - p[[i,j]]);
+            }
+        }
+        (g_p, h_p)
+    }
+
+    /// Find the Cartesian positions for the internal step dq by the iterative back-transformation.
+    /// The first-order estimate is used if the iterations diverge.
+    pub fn back_transform(&self, x0: &[[f64;3]], dq: &[f64]) -> Vec<[f64;3]> {
+        let q_target: Vec<f64> = self.coords.values(x0).iter().zip(dq.iter()).map(|(q, dq)| q + dq).collect();
+        let mut x = x0.to_vec();
+        let mut dq_left = dq.to_vec();
+        let mut first_order: Option<Vec<[f64;3]>> = None;
+        let mut last_rms = f64::MAX;
+        for _ in 0..50 {
+            let b = self.b_matrix(&x);
+            let (g_inv, _) = RedundantInternals::g_inverse(&b);
+            let tmp = mat_vec(&g_inv, &dq_left);
+            let mut dx = vec![0.0; 3*x.len()];
+            for q in 0..self.len() {
+                for i in 0..dx.len() {
+                    dx[i] += b[[q,i]]*tmp[q];
+                }
+            }
+            x.iter_mut().enumerate().for_each(|(a, xa)| {
+                for k in 0..3 {xa[k] += dx[3*a+k]}
+            });
+            if first_order.is_none() {first_order = Some(x.clone())}
+
+            dq_left = self.coords.difference(&q_target, &self.coords.values(&x));
+            let rms_dx = (dx.iter().fold(0.0, |acc, d| acc + d*d)/(dx.len() as f64)).sqrt();
+            let rms_dq = (dq_left.iter().fold(0.0, |acc, d| acc + d*d)/(dq_left.len().max(1) as f64)).sqrt();
+            if rms_dq > last_rms {
+                return first_order.unwrap()
+            }
+            last_rms = rms_dq;
+            if rms_dx < 1.0e-7 {break}
+        }
+        x
+    }
+}
+
+/// The positions of the atoms in Bohr
+pub fn positions_of(geom: &GeomCell) -> Vec<[f64;3]> {
+    geom.position.iter_columns_full().map(|pos| [pos[0], pos[1], pos[2]]).collect()
+}
+
+pub fn set_positions(geom: &mut GeomCell, x: &[[f64;3]]) {
+    geom.position = MatrixFull::from_vec([3, x.len()], x.iter().flat_map(|xa| *xa).collect()).unwrap();
+}
+
+/// Move the molecule to the new positions and perform the SCF (and post-SCF) calculations
+/// with the orbitals inherited from the previous geometry. Return the total energy.
+pub fn scf_at_geometry(scf_data: &mut SCF, x: &[[f64;3]], time_mark: &mut utilities::TimeRecords, mpi_operator: &Option<MPIOperator>) -> f64 {
+    set_positions(&mut scf_data.mol.geom, x);
+    scf_data.mol.ctrl.initial_guess = String::from("inherit");
+    initialize_scf(scf_data, mpi_operator);
+    performance_essential_calculations(scf_data, time_mark, mpi_operator)
+}
+
+/// Append (or create if `append` is false) a frame to the multi-frame xyz file
+pub fn write_xyz_frame(filename: &str, geom: &GeomCell, comment: &str, append: bool) {
+    let mut output = fs::OpenOptions::new().create(true).write(true).append(append).truncate(!append)
+        .open(filename).unwrap_or_else(|_| panic!("Failed to open the trajectory file: {}", filename));
+    write!(output, "{}\n{}\n{}", geom.elem.len(), comment, geom.formated_geometry()).unwrap();
+}
+
+/// The RFO step from the lowest eigenvector of the augmented Hessian [[H, g], [g^T, 0]]
+pub fn rfo_step(h: &MatrixFull<f64>, g: &[f64]) -> Vec<f64> {
+    let n = g.len();
+    let mut aug = MatrixFull::new([n+1, n+1], 0.0);
+    for j in 0..n {
+        for i in 0..n {
+            aug[[i,j]] = h[[i,j]];
+        }
+        aug[[j,n]] = g[j];
+        aug[[n,j]] = g[j];
+    }
+    let (evec, _) = eigh(&aug);
+    let v_n = evec[[n,0]];
+    if v_n.abs() < 1.0e-8 {
+        // fall back to the steepest descent
+        return g.iter().map(|g| -g).collect()
+    }
+    (0..n).map(|i| evec[[i,0]]/v_n).collect()
+}
+
+/// The BFGS update of the Hessian with the step `s` and the gradient change `y`.
+/// The update is skipped if the curvature condition is not satisfied.
+pub fn bfgs_update(h: &mut MatrixFull<f64>, s: &[f64], y: &[f64]) {
+    let n = s.len();
+    let hs = mat_vec(h, s);
+    let ys = dot(y, s);
+    let shs = dot(s, &hs);
+    if ys <= 1.0e-8 || shs <= 1.0e-8 {return}
+    for j in 0..n {
+        for i in 0..n {
+            h[[i,j]] += y[i]*y[j]/ys - hs[i]*hs[j]/shs;
+        }
+    }
+}
+
+/// Scale the step into the trust radius
+pub fn restrict_step(dq: &mut Vec<f64>, trust_radius: f64) {
+    let norm = dot(dq, dq).sqrt();
+    if norm > trust_radius {
+        dq.iter_mut().for_each(|d| *d *= trust_radius/norm);
+    }
+}
+
+/// Update the trust radius by the ratio of the actual and predicted energy changes
+pub fn update_trust_radius(trust_radius: f64, actual: f64, predicted: f64, step_norm: f64) -> f64 {
+    let ratio = if predicted.abs() > 1.0e-12 {actual/predicted} else {1.0};
+    if ratio < 0.25 {
+        (0.25*trust_radius).max(MIN_TRUST_RADIUS)
+    } else if ratio > 0.75 && step_norm > 0.8*trust_radius {
+        (2.0*trust_radius).min(MAX_TRUST_RADIUS)
+    } else {
+        trust_radius
+    }
+}
+
+/// The geometry optimization by the RFO steps and BFGS updates in the redundant internal coordinates.
+/// `scf_data` should be converged at the initial geometry, and is left at the final one.
+/// Return true if converged.
+pub fn geometry_optimization(scf_data: &mut SCF, time_mark: &mut utilities::TimeRecords, mpi_operator: &Option<MPIOperator>) -> bool {
+    let is_master = mpi_operator.as_ref().map_or(true, |mp_op| mp_op.rank == 0);
+    let print_level = scf_data.mol.ctrl.print_level;
+    let max_cycle = scf_data.mol.ctrl.geom_opt_max_cycle;
+    let criteria = ConvergenceCriteria::from_ctrl(&scf_data.mol.ctrl);
+    let trajectory = scf_data.mol.ctrl.geom_opt_trajectory.clone();
+    let mut trust_radius = scf_data.mol.ctrl.geom_opt_trust_radius;
+
+    let ri = RedundantInternals::build(&scf_data.mol.geom);
+    let mut x = positions_of(&scf_data.mol.geom);
+    let mut hq = diagonal_matrix(&ri.coords.model_hessian(&ri.charges, &x));
+    if print_level > 0 && is_master {
+        println!("Geometry optimization in {} redundant internal coordinates", ri.len());
+        if ri.constrained.len() > 0 {
+            let names: Vec<String> = ri.constrained.iter().map(|i| ri.coords.primitives[*i].formated_name()).collect();
+            println!("Constrained coordinates: {}", names.join(", "));
+        }
+        let num_fixed = scf_data.mol.geom.fix.iter().filter(|fix| **fix).count();
+        if num_fixed > 0 {
+            println!("Number of frozen atoms: {}", num_fixed);
+        }
+    }
+
+    let mut energy = collect_total_energy(scf_data);
+    // (q, g_q, energy, predicted energy change, step norm) of the previous step
+    let mut previous: Option<(Vec<f64>, Vec<f64>, f64, f64, f64)> = None;
+    let mut converged = false;
+    for cycle in 1..=max_cycle {
+        let (_, gradient) = calc_force(scf_data, FORCE_DISPLACEMENT, mpi_operator);
+        let gx: Vec<f64> = gradient.data.iter().zip(ri.active.iter()).map(|(g, active)| if *active {*g} else {0.0}).collect();
+        let q = ri.coords.values(&x);
+        let gq = ri.gradient(&x, &gx);
+
+        if let Some((q_old, gq_old, energy_old, predicted, step_norm)) = &previous {
+            let s = ri.coords.difference(&q, q_old);
+            let y: Vec<f64> = gq.iter().zip(gq_old.iter()).map(|(g, g_old)| g - g_old).collect();
+            bfgs_update(&mut hq, &s, &y);
+            trust_radius = update_trust_radius(trust_radius, energy - energy_old, *predicted, *step_norm);
+        }
+
+        let (g_p, h_p) = ri.project(&x, &gq, &hq);
+        let mut dq = rfo_step(&h_p, &g_p);
+        let p = ri.projector(&x);
+        dq = mat_vec(&p, &dq);
+        restrict_step(&mut dq, trust_radius);
+        let hdq = mat_vec(&hq, &dq);
+        let mut predicted = dot(&g_p, &dq) + 0.5*dot(&dq, &hdq);
+
+        if is_master {
+            let comment = format!("Step {:4}  Energy = {:18.10} Ha", cycle-1, energy);
+            write_xyz_frame(&trajectory, &scf_data.mol.geom, &comment, cycle > 1);
+        }
+        if print_level > 0 && is_master {
+            println!("----------------------------------------------------------------------");
+            println!("Geometry optimization cycle {:4}: E = {:18.10} Ha, trust radius = {:8.4}", cycle, energy, trust_radius);
+            if print_level > 1 {
+                println!("Forces [a.u.]:");
+                println!("{}", formated_force(&gradient, &scf_data.mol.geom.elem));
+            }
+        }
+        if criteria.check(&g_p, &dq, print_level > 0 && is_master) {
+            converged = true;
+            break
+        }
+
+        // reject the step raising the energy, and retry along the same direction with the shrunk trust radius
+        let mut x_new = ri.back_transform(&x, &dq);
+        let mut energy_new = scf_at_geometry(scf_data, &x_new, time_mark, mpi_operator);
+        while energy_new - energy > STEP_REJECTION_THRESHOLD && trust_radius > MIN_TRUST_RADIUS {
+            trust_radius = (0.25*dot(&dq, &dq).sqrt()).max(MIN_TRUST_RADIUS);
+            if print_level > 0 && is_master {
+                println!("Step rejected as the energy rises by {:12.4e} Ha; the trust radius is reduced to {:8.4}", energy_new - energy, trust_radius);
+            }
+            restrict_step(&mut dq, trust_radius);
+            let hdq = mat_vec(&hq, &dq);
+            predicted = dot(&g_p, &dq) + 0.5*dot(&dq, &hdq);
+            x_new = ri.back_transform(&x, &dq);
+            energy_new = scf_at_geometry(scf_data, &x_new, time_mark, mpi_operator);
+        }
+        previous = Some((q, gq, energy, predicted, dot(&dq, &dq).sqrt()));
+        x = x_new;
+        energy = energy_new;
+        if print_level > 1 && is_master {
+            println!("New geometry [Ang]:");
+            println!("{}", scf_data.mol.geom.formated_geometry());
+        }
+    }
+
+    if print_level > 0 && is_master {
+        if converged {
+            println!("Geometry optimization converged: E = {:18.10} Ha", energy);
+        } else {
+            println!("WARNING: geometry optimization is not converged in {} cycles", max_cycle);
+        }
+    }
+    converged
+}
+
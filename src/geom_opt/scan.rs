@@ -0,0 +1,273 @@
+//! Rigid and relaxed scans of the potential-energy surface (PES) over the bonds, angles and
+//! dihedrals given by `scan_coordinates`.
+//!
+//! More than one scanned coordinate spans a grid, which is visited with the last coordinate
+//! running fastest. At each point, the scanned coordinates are set by moving the fragment on one
+//! side of the coordinate rigidly: a translation along the bond, or a rotation around the normal of
+//! the angle or the axis of the dihedral. For a coordinate in a ring, only the terminal atom is moved.
+//! The SCF starts from the orbitals of the previous point. In the relaxed scan, all the other
+//! coordinates are then optimized with the scanned ones constrained, and the next point starts
+//! from the relaxed geometry. The optimization steps of each point are written to a separate trajectory,
+//! like `geom_opt_traj_scan0001.xyz` for `geom_opt_trajectory = "geom_opt_traj.xyz"`.
+use crate::constants::{ANG, EV, KCAL_MOL};
+use crate::ctrl_io::ScanCoordinate;
+use crate::mpi_io::MPIOperator;
+use crate::scf_io::SCF;
+use crate::{collect_total_energy, utilities};
+use crate::utilities::linear_algebra::vec3::{add, cross, dot, norm, scale, sub};
+use super::internal::{InternalCoordinates, Primitive};
+use super::{geometry_optimization, positions_of, scf_at_geometry, write_xyz_frame};
+
+/// The scanned coordinates are set iteratively until converged to this value (in Bohr or radian)
+const SET_COORDINATE_THRESHOLD: f64 = 1.0e-8;
+
+/// A point of the scan: the values of the scanned coordinates (in Angstrom or degree),
+/// the SCF and total energies, and the positions
+pub struct ScanPoint {
+    pub values: Vec<f64>,
+    pub scf_energy: f64,
+    pub total_energy: f64,
+    pub positions: Vec<[f64;3]>,
+    pub converged: bool,
+}
+
+/// The atoms connected to `start` without passing through the bond between `start` and `pivot`.
+/// Return None if `pivot` is reached in another way, i.e., the bond is in a ring.
+fn moving_fragment(num_atoms: usize, bonds: &[(usize, usize)], start: usize, pivot: usize) -> Option<Vec<usize>> {
+    let mut visited = vec![false; num_atoms];
+    visited[start] = true;
+    let mut stack = vec![start];
+    while let Some(a) = stack.pop() {
+        bonds.iter().for_each(|(i, j)| {
+            let b = if *i == a {*j} else if *j == a {*i} else {return};
+            if a == start && b == pivot {return}
+            if !visited[b] {
+                visited[b] = true;
+                stack.push(b);
+            }
+        });
+    }
+    if visited[pivot] {return None}
+    Some((0..num_atoms).filter(|a| visited[*a]).collect())
+}
+
+/// Rotate the point `p` around the axis through `origin` by the angle `theta` (right-handed)
+fn rotate(p: &[f64;3], origin: &[f64;3], axis: &[f64;3], theta: f64) -> [f64;3] {
+    let k = scale(axis, 1.0/norm(axis));
+    let v = sub(p, origin);
+    let kv = cross(&k, &v);
+    let kd = dot(&k, &v);
+    let (s, c) = theta.sin_cos();
+    let mut out = [0.0; 3];
+    for x in 0..3 {
+        out[x] = origin[x] + v[x]*c + kv[x]*s + k[x]*kd*(1.0 - c);
+    }
+    out
+}
+
+/// Move the atoms rigidly so that the primitive on `atoms` takes the value of `target` (in Bohr or radian)
+pub fn set_coordinate(x: &mut Vec<[f64;3]>, bonds: &[(usize, usize)], atoms: &[usize], target: f64) {
+    let num_atoms = x.len();
+    let primitive = Primitive::from_atoms(atoms);
+    // the moving side of the coordinate, or only the terminal atom if the coordinate is in a ring
+    let (start, pivot, terminal) = match primitive {
+        Primitive::Bond(i, j) => (j, i, j),
+        Primitive::Angle(_, j, k) => (k, j, k),
+        Primitive::Dihedral(_, j, k, l) => (k, j, l),
+    };
+    let moving = moving_fragment(num_atoms, bonds, start, pivot).unwrap_or(vec![terminal]);
+    let mut delta = target - primitive.value(x);
+    match primitive {
+        Primitive::Bond(i, j) => {
+            let u = sub(&x[j], &x[i]);
+            let u = scale(&u, delta/norm(&u));
+            moving.iter().for_each(|a| x[*a] = add(&x[*a], &u));
+        },
+        Primitive::Angle(i, j, k) => {
+            let mut axis = cross(&sub(&x[i], &x[j]), &sub(&x[k], &x[j]));
+            if norm(&axis) < 1.0e-8 {
+                // a linear angle: any direction perpendicular to the bonds
+                let u = sub(&x[k], &x[j]);
+                axis = if u[0].abs() < 0.9*norm(&u) {cross(&u, &[1.0, 0.0, 0.0])} else {cross(&u, &[0.0, 1.0, 0.0])};
+            }
+            let origin = x[j];
+            moving.iter().for_each(|a| x[*a] = rotate(&x[*a], &origin, &axis, delta));
+        },
+        Primitive::Dihedral(_, j, k, _) => {
+            let pi = std::f64::consts::PI;
+            while delta > pi {delta -= 2.0*pi}
+            while delta <= -pi {delta += 2.0*pi}
+            let axis = sub(&x[k], &x[j]);
+            let origin = x[k];
+            moving.iter().for_each(|a| x[*a] = rotate(&x[*a], &origin, &axis, delta));
+        },
+    }
+}
+
+/// Set all the scanned coordinates, repeated if they are coupled by the rigid moves
+fn set_coordinates(x: &mut Vec<[f64;3]>, bonds: &[(usize, usize)], coordinates: &[ScanCoordinate], targets: &[f64]) {
+    let primitives: Vec<Primitive> = coordinates.iter().map(|c| Primitive::from_atoms(&c.atoms)).collect();
+    let targets: Vec<f64> = primitives.iter().zip(targets.iter()).map(|(p, t)| to_internal_unit(p, *t)).collect();
+    for _ in 0..50 {
+        primitives.iter().zip(targets.iter()).for_each(|(p, t)| set_coordinate(x, bonds, &p.atoms(), *t));
+        let max_error = primitives.iter().zip(targets.iter()).fold(0.0_f64, |acc, (p, t)| {
+            let pi = std::f64::consts::PI;
+            let mut d = p.value(x) - t;
+            if p.is_dihedral() {
+                while d > pi {d -= 2.0*pi}
+                while d <= -pi {d += 2.0*pi}
+            }
+            acc.max(d.abs())
+        });
+        if max_error < SET_COORDINATE_THRESHOLD {break}
+    }
+}
+
+/// Angstrom or degree to Bohr or radian
+fn to_internal_unit(p: &Primitive, value: f64) -> f64 {
+    match p {
+        Primitive::Bond(_, _) => value/ANG,
+        _ => value.to_radians(),
+    }
+}
+
+/// Bohr or radian to Angstrom or degree
+fn to_output_unit(p: &Primitive, value: f64) -> f64 {
+    match p {
+        Primitive::Bond(_, _) => value*ANG,
+        _ => value.to_degrees(),
+    }
+}
+
+/// The indices of the grid points with the last coordinate running fastest
+fn grid_indices(num_points: &[usize]) -> Vec<Vec<usize>> {
+    let total = num_points.iter().product::<usize>();
+    (0..total).map(|mut n| {
+        let mut index = vec![0; num_points.len()];
+        for c in (0..num_points.len()).rev() {
+            index[c] = n % num_points[c];
+            n /= num_points[c];
+        }
+        index
+    }).collect()
+}
+
+/// The trajectory of the optimization at the scan point `i_point` (starting from 0), named after `trajectory`
+pub fn point_trajectory(trajectory: &str, i_point: usize) -> String {
+    let path = std::path::Path::new(trajectory);
+    let stem = path.file_stem().and_then(|x| x.to_str()).unwrap_or("geom_opt_traj");
+    let file_name = match path.extension().and_then(|x| x.to_str()) {
+        Some(ext) => format!("{}_scan{:04}.{}", stem, i_point+1, ext),
+        None => format!("{}_scan{:04}", stem, i_point+1),
+    };
+    path.with_file_name(file_name).to_string_lossy().to_string()
+}
+
+/// The PES scan starting from the converged `scf_data`, which is left at the last point.
+/// `scan_type` of "rigid" performs single points, and otherwise the relaxed scan.
+pub fn pes_scan(scf_data: &mut SCF, time_mark: &mut utilities::TimeRecords, mpi_operator: &Option<MPIOperator>) -> Vec<ScanPoint> {
+    let is_master = mpi_operator.as_ref().map_or(true, |mp_op| mp_op.rank == 0);
+    let print_level = scf_data.mol.ctrl.print_level;
+    let coordinates = scf_data.mol.ctrl.scan_coordinates.clone();
+    let relaxed = !scf_data.mol.ctrl.scan_type.eq("rigid");
+    let trajectory = scf_data.mol.ctrl.scan_trajectory.clone();
+    let num_atoms = scf_data.mol.geom.elem.len();
+    if coordinates.len() == 0 {
+        panic!("Error: no scan_coordinates are given for the PES scan");
+    }
+    coordinates.iter().for_each(|c| {
+        if c.atoms.iter().any(|a| *a >= num_atoms) {
+            panic!("Error: the atom indices in scan_coordinates exceed the number of atoms ({}): {:?}", num_atoms, c.atoms);
+        }
+    });
+    let primitives: Vec<Primitive> = coordinates.iter().map(|c| Primitive::from_atoms(&c.atoms)).collect();
+
+    // the connectivity for the rigid moves
+    let charges: Vec<f64> = crate::geom_io::get_mass_charge(&scf_data.mol.geom.elem).iter().map(|(_, z)| *z).collect();
+    let bonds: Vec<(usize, usize)> = InternalCoordinates::build(&charges, &positions_of(&scf_data.mol.geom), &[])
+        .primitives.iter().filter_map(|p| match p {
+            Primitive::Bond(i, j) => Some((*i, *j)),
+            _ => None,
+        }).collect();
+
+    let user_constraints = scf_data.mol.geom.constraints.clone();
+    let opt_trajectory = scf_data.mol.ctrl.geom_opt_trajectory.clone();
+    let grid = grid_indices(&coordinates.iter().map(|c| c.num_points).collect::<Vec<usize>>());
+    if print_level > 0 && is_master {
+        println!("{} PES scan over {} points", if relaxed {"Relaxed"} else {"Rigid"}, grid.len());
+        primitives.iter().zip(coordinates.iter()).for_each(|(p, c)| {
+            println!("  {:20}: from {:10.4} to {:10.4} with {} points", p.formated_name(), c.start, c.end, c.num_points);
+        });
+    }
+
+    let mut points: Vec<ScanPoint> = vec![];
+    for (i_point, index) in grid.iter().enumerate() {
+        let targets: Vec<f64> = index.iter().zip(coordinates.iter()).map(|(i, c)| c.value_at(*i)).collect();
+        let mut x = positions_of(&scf_data.mol.geom);
+        set_coordinates(&mut x, &bonds, &coordinates, &targets);
+        let mut total_energy = scf_at_geometry(scf_data, &x, time_mark, mpi_operator);
+        let mut converged = true;
+        if relaxed {
+            let mut constraints = user_constraints.clone();
+            constraints.extend(coordinates.iter().map(|c| c.atoms.clone()));
+            scf_data.mol.geom.constraints = constraints;
+            scf_data.mol.ctrl.geom_opt_trajectory = point_trajectory(&opt_trajectory, i_point);
+            converged = geometry_optimization(scf_data, time_mark, mpi_operator);
+            scf_data.mol.geom.constraints = user_constraints.clone();
+            scf_data.mol.ctrl.geom_opt_trajectory = opt_trajectory.clone();
+            total_energy = collect_total_energy(scf_data);
+        }
+        let x = positions_of(&scf_data.mol.geom);
+        let values: Vec<f64> = primitives.iter().map(|p| to_output_unit(p, p.value(&x))).collect();
+        if is_master {
+            let formated_values: Vec<String> = values.iter().map(|v| format!("{:10.4}", v)).collect();
+            let comment = format!("Scan point {:4}  Coordinates = {}  Energy = {:18.10} Ha", i_point+1, formated_values.join(" "), total_energy);
+            write_xyz_frame(&trajectory, &scf_data.mol.geom, &comment, i_point > 0);
+            if print_level > 0 {
+                println!("PES scan point {:4}: {}, E = {:18.10} Ha{}", i_point+1, formated_values.join(" "), total_energy,
+                    if converged {""} else {" (not converged)"});
+            }
+        }
+        points.push(ScanPoint {values, scf_energy: scf_data.scf_energy, total_energy, positions: x, converged});
+    }
+
+    if is_master {
+        let e_min = points.iter().fold(f64::MAX, |acc, p| acc.min(p.total_energy));
+        println!("----------------------------------------------------------------------");
+        println!("{} PES scan (in Angstrom and degree), written to {}", if relaxed {"Relaxed"} else {"Rigid"}, trajectory);
+        println!("----------------------------------------------------------------------");
+        let names: Vec<String> = primitives.iter().map(|p| format!("{:>12}", p.formated_name())).collect();
+        println!("{:>6} {} {:>20} {:>20} {:>14} {:>10}", "Point", names.join(" "), "SCF energy (Ha)", "Total energy (Ha)", "dE (kcal/mol)", "dE (eV)");
+        points.iter().enumerate().for_each(|(i, p)| {
+            let values: Vec<String> = p.values.iter().map(|v| format!("{:12.4}", v)).collect();
+            println!("{:6} {} {:20.10} {:20.10} {:14.4} {:10.5}{}", i+1, values.join(" "), p.scf_energy, p.total_energy,
+                (p.total_energy - e_min)*KCAL_MOL, (p.total_energy - e_min)*EV, if p.converged {""} else {" *"});
+        });
+        if points.iter().any(|p| !p.converged) {
+            println!("*: the constrained optimization is not converged");
+        }
+        println!("----------------------------------------------------------------------");
+    }
+    points
+}
+
+#[test]
+fn test_rigid_dihedral_rotation() {
+    // H2O2: rotating around the O-O bond moves only the second hydrogen
+    let mut x = vec![[0.0, 1.33, -0.12], [0.0, -1.33, -0.12], [1.65, 1.60, 0.95], [-1.72, -1.55, 0.88]];
+    let bonds = vec![(0, 1), (0, 2), (1, 3)];
+    let atoms = vec![2, 0, 1, 3];
+    let target = 120.0_f64.to_radians();
+    let x0 = x.clone();
+    set_coordinate(&mut x, &bonds, &atoms, target);
+    assert!((Primitive::from_atoms(&atoms).value(&x) - target).abs() < 1.0e-10);
+    assert!((Primitive::Bond(1, 3).value(&x) - Primitive::Bond(1, 3).value(&x0)).abs() < 1.0e-10);
+    (0..3).for_each(|a| assert!(norm(&sub(&x[a], &x0[a])) < 1.0e-12));
+}
+
+#[test]
+fn test_point_trajectory() {
+    assert_eq!(point_trajectory("geom_opt_traj.xyz", 0), String::from("geom_opt_traj_scan0001.xyz"));
+    assert_eq!(point_trajectory("out/traj.xyz", 11), String::from("out/traj_scan0012.xyz"));
+    assert_eq!(point_trajectory("traj", 2), String::from("traj_scan0003"));
+}
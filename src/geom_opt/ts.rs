@@ -0,0 +1,212 @@
+//! Transition-state search by the partitioned rational function optimization (P-RFO)[^1]
+//! in the redundant internal coordinates.
+//!
+//! In the eigenvector basis of the (projected) Hessian `{b_i, v_i}` with `F_i = v_i^T g`,
+//! the energy is maximized along the followed mode `k` and minimized along the others:
+//! ```text
+//!   dq = -F_k/(b_k - lambda_p) v_k - \sum_{i!=k} F_i/(b_i - lambda_n) v_i
+//!   lambda_p = b_k/2 + sqrt(b_k^2 + 4 F_k^2)/2
+//!   lambda_n = the lowest eigenvalue of [[diag(b_i), F], [F^T, 0]] (i!=k)
+//! ```
+//! The followed mode is tracked by the maximum overlap with that of the previous step, and the
+//! Hessian is updated by the Bofill formula[^2], which does not enforce positive definiteness.
+//!
+//! [^1]: J. Baker, J. Comput. Chem. 7, 385 (1986).
+//! [^2]: J. M. Bofill, J. Comput. Chem. 15, 1 (1994).
+use tensors::MatrixFull;
+use crate::freq::numerical_hessian;
+use crate::grad::{calc_force, formated_force};
+use crate::mpi_io::MPIOperator;
+use crate::scf_io::SCF;
+use crate::{collect_total_energy, utilities};
+use crate::utilities::linear_algebra::{diagonal_matrix, dot, eigh, mat_vec, matmul};
+use super::{positions_of, restrict_step, scf_at_geometry,
+    update_trust_radius, write_xyz_frame, ConvergenceCriteria, RedundantInternals, FORCE_DISPLACEMENT};
+
+/// The Bofill update of the Hessian: a mixture of the Murtagh-Sargent (symmetric rank-one)
+/// and Powell-symmetric-Broyden updates
+pub fn bofill_update(h: &mut MatrixFull<f64>, s: &[f64], y: &[f64]) {
+    let n = s.len();
+    let hs = mat_vec(h, s);
+    let e: Vec<f64> = y.iter().zip(hs.iter()).map(|(y, hs)| y - hs).collect();
+    let es = dot(&e, s);
+    let ee = dot(&e, &e);
+    let ss = dot(s, s);
+    if ss < 1.0e-12 || ee < 1.0e-16 {return}
+    let phi = es*es/(ee*ss);
+    for j in 0..n {
+        for i in 0..n {
+            let ms = if es.abs() > 1.0e-12 {e[i]*e[j]/es} else {0.0};
+            let psb = (e[i]*s[j] + s[i]*e[j])/ss - es*s[i]*s[j]/(ss*ss);
+            h[[i,j]] += phi*ms + (1.0 - phi)*psb;
+        }
+    }
+}
+
+/// The P-RFO step maximizing along the `mode`-th eigenvector of the Hessian `h`.
+/// Return the step and the followed eigenvector.
+pub fn prfo_step(h: &MatrixFull<f64>, g: &[f64], mode: usize) -> (Vec<f64>, Vec<f64>) {
+    let n = g.len();
+    let (evec, eval) = eigh(h);
+    let f: Vec<f64> = (0..n).map(|i| (0..n).fold(0.0, |acc, x| acc + evec[[x,i]]*g[x])).collect();
+
+    let b_k = eval[mode];
+    let lambda_p = 0.5*b_k + 0.5*(b_k*b_k + 4.0*f[mode]*f[mode]).sqrt();
+
+    let others: Vec<usize> = (0..n).filter(|i| *i != mode).collect();
+    let m = others.len();
+    let mut aug = MatrixFull::new([m+1, m+1], 0.0);
+    others.iter().enumerate().for_each(|(a, i)| {
+        aug[[a,a]] = eval[*i];
+        aug[[a,m]] = f[*i];
+        aug[[m,a]] = f[*i];
+    });
+    let (_, aug_eval) = eigh(&aug);
+    let lambda_n = aug_eval[0];
+
+    let mut step = vec![0.0; n];
+    let mut add_mode = |i: usize, lambda: f64| {
+        let denom = eval[i] - lambda;
+        if denom.abs() < 1.0e-10 {return}
+        let coeff = -f[i]/denom;
+        for x in 0..n {
+            step[x] += coeff*evec[[x,i]];
+        }
+    };
+    add_mode(mode, lambda_p);
+    others.iter().for_each(|i| add_mode(*i, lambda_n));
+
+    let followed: Vec<f64> = (0..n).map(|x| evec[[x,mode]]).collect();
+    (step, followed)
+}
+
+/// The index of the eigenvector with the maximum overlap with the previously followed mode
+fn track_mode(h: &MatrixFull<f64>, previous: &[f64]) -> usize {
+    let n = previous.len();
+    let (evec, _) = eigh(h);
+    let overlap = |i: usize| (0..n).fold(0.0, |acc, x| acc + evec[[x,i]]*previous[x]).abs();
+    (0..n).fold(0, |best, i| if overlap(i) > overlap(best) {i} else {best})
+}
+
+/// Convert the Cartesian Hessian into the internal coordinates, H_q = G^- B H_x B^T G^-,
+/// where the term of the second derivatives of the internals contracted with the gradient is neglected
+pub fn cartesian_to_internal_hessian(ri: &RedundantInternals, x: &[[f64;3]], hx: &MatrixFull<f64>) -> MatrixFull<f64> {
+    let b = ri.b_matrix(x);
+    let mut g = MatrixFull::new([ri.len(), ri.len()], 0.0);
+    tensors::matrix_blas_lapack::_dgemm_full(&b, 'N', &b, 'T', &mut g, 1.0, 0.0);
+    let g_inv = super::generalized_inverse(&g, super::REDUNDANCY_THRESHOLD);
+    let a = matmul(&g_inv, &b);
+    let ah = matmul(&a, hx);
+    let mut hq = MatrixFull::new([ri.len(), ri.len()], 0.0);
+    tensors::matrix_blas_lapack::_dgemm_full(&ah, 'N', &a, 'T', &mut hq, 1.0, 0.0);
+    hq
+}
+
+/// The transition-state search by the P-RFO steps and Bofill updates.
+/// `scf_data` should be converged at the initial geometry, and is left at the final one.
+/// Return true if converged.
+pub fn transition_state_search(scf_data: &mut SCF, time_mark: &mut utilities::TimeRecords, mpi_operator: &Option<MPIOperator>) -> bool {
+    let is_master = mpi_operator.as_ref().map_or(true, |mp_op| mp_op.rank == 0);
+    let print_level = scf_data.mol.ctrl.print_level;
+    let max_cycle = scf_data.mol.ctrl.geom_opt_max_cycle;
+    let criteria = ConvergenceCriteria::from_ctrl(&scf_data.mol.ctrl);
+    let trajectory = scf_data.mol.ctrl.geom_opt_trajectory.clone();
+    let mut trust_radius = scf_data.mol.ctrl.geom_opt_trust_radius;
+    let ts_mode = scf_data.mol.ctrl.ts_mode.max(1) - 1;
+
+    let ri = RedundantInternals::build(&scf_data.mol.geom);
+    let mut x = positions_of(&scf_data.mol.geom);
+    let mut hq = if scf_data.mol.ctrl.ts_initial_hessian.eq("model") {
+        diagonal_matrix(&ri.coords.model_hessian(&ri.charges, &x))
+    } else {
+        time_mark.new_item("ts_hessian", "the initial Hessian of the TS search");
+        time_mark.count_start("ts_hessian");
+        let hx = numerical_hessian(scf_data, scf_data.mol.ctrl.freq_displacement, mpi_operator);
+        time_mark.count("ts_hessian");
+        cartesian_to_internal_hessian(&ri, &x, &hx)
+    };
+    if print_level > 0 && is_master {
+        println!("Transition-state search in {} redundant internal coordinates, following mode {}", ri.len(), ts_mode+1);
+    }
+
+    let mut energy = collect_total_energy(scf_data);
+    let mut previous: Option<(Vec<f64>, Vec<f64>, f64, f64, f64)> = None;
+    let mut followed: Option<Vec<f64>> = None;
+    let mut converged = false;
+    for cycle in 1..=max_cycle {
+        let (_, gradient) = calc_force(scf_data, FORCE_DISPLACEMENT, mpi_operator);
+        let gx: Vec<f64> = gradient.data.iter().zip(ri.active.iter()).map(|(g, active)| if *active {*g} else {0.0}).collect();
+        let q = ri.coords.values(&x);
+        let gq = ri.gradient(&x, &gx);
+
+        if let Some((q_old, gq_old, energy_old, predicted, step_norm)) = &previous {
+            let s = ri.coords.difference(&q, q_old);
+            let y: Vec<f64> = gq.iter().zip(gq_old.iter()).map(|(g, g_old)| g - g_old).collect();
+            bofill_update(&mut hq, &s, &y);
+            trust_radius = update_trust_radius(trust_radius, energy - energy_old, *predicted, *step_norm);
+        }
+
+        let (g_p, h_p) = ri.project(&x, &gq, &hq);
+        let mode = match &followed {
+            Some(v) => track_mode(&h_p, v),
+            None => ts_mode,
+        };
+        let (mut dq, v) = prfo_step(&h_p, &g_p, mode);
+        followed = Some(v);
+        let p = ri.projector(&x);
+        dq = mat_vec(&p, &dq);
+        restrict_step(&mut dq, trust_radius);
+        let hdq = mat_vec(&hq, &dq);
+        let predicted = dot(&g_p, &dq) + 0.5*dot(&dq, &hdq);
+
+        if is_master {
+            let comment = format!("Step {:4}  Energy = {:18.10} Ha", cycle-1, energy);
+            write_xyz_frame(&trajectory, &scf_data.mol.geom, &comment, cycle > 1);
+        }
+        if print_level > 0 && is_master {
+            let (_, eval) = eigh(&h_p);
+            println!("----------------------------------------------------------------------");
+            println!("TS search cycle {:4}: E = {:18.10} Ha, trust radius = {:8.4}", cycle, energy, trust_radius);
+            println!("Followed mode {:4} with the Hessian eigenvalue {:12.6}, number of negative eigenvalues: {}",
+                mode+1, eval[mode], eval.iter().filter(|e| **e < 0.0).count());
+            if print_level > 1 {
+                println!("Forces [a.u.]:");
+                println!("{}", formated_force(&gradient, &scf_data.mol.geom.elem));
+            }
+        }
+        if criteria.check(&g_p, &dq, print_level > 0 && is_master) {
+            converged = true;
+            break
+        }
+
+        let x_new = ri.back_transform(&x, &dq);
+        previous = Some((q, gq, energy, predicted, dot(&dq, &dq).sqrt()));
+        x = x_new;
+        energy = scf_at_geometry(scf_data, &x, time_mark, mpi_operator);
+        if print_level > 1 && is_master {
+            println!("New geometry [Ang]:");
+            println!("{}", scf_data.mol.geom.formated_geometry());
+        }
+    }
+
+    if print_level > 0 && is_master {
+        if converged {
+            println!("Transition-state search converged: E = {:18.10} Ha", energy);
+            println!("Please verify the single imaginary frequency by the frequency calculation");
+        } else {
+            println!("WARNING: transition-state search is not converged in {} cycles", max_cycle);
+        }
+    }
+    converged
+}
+
+#[test]
+fn test_bofill_secant_condition() {
+    let mut h = MatrixFull::from_vec([3,3], vec![1.0,0.2,0.0, 0.2,-0.5,0.1, 0.0,0.1,2.0]).unwrap();
+    let s = vec![0.1, -0.05, 0.02];
+    let y = vec![0.07, 0.04, 0.05];
+    bofill_update(&mut h, &s, &y);
+    let hs = mat_vec(&h, &s);
+    hs.iter().zip(y.iter()).for_each(|(hs, y)| assert!((hs - y).abs() < 1.0e-10));
+    assert!((h[[0,1]] - h[[1,0]]).abs() < 1.0e-12);
+}
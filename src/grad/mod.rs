@@ -2,16 +2,74 @@ pub mod rhf;
 pub mod uhf;
 pub mod rks;
 pub mod uks;
+pub mod qmmm;
 
 use std::io::{self, Write};
 
-use crate::{collect_total_energy, constants::{ANG, EV}, performance_essential_calculations, scf_io::{initialize_scf, scf_without_build, SCF}, utilities};
+use crate::{collect_total_energy, constants::{ANG, EV}, performance_essential_calculations, scf_io::{initialize_scf, scf_without_build, SCF, SCFType}, utilities};
+use crate::geom_io::MOrC;
 use crate::mpi_io::{MPIData, MPIOperator};
 use tensors::MatrixFull;
 
+use self::rhf::Gradient;
+
+
+/// Check if the analytic nuclear gradients are available for the given calculation.
+/// Currently, they are limited to the RI-V approximation with the auxiliary-basis response for the SCF methods 
+/// with the LDA, GGA and global hybrid GGA functionals without VV10 in molecules without the ghost basis sets,
+/// whose shells are attached to the atoms beyond the [3, natm] gradients.
+pub fn analytic_force_is_available(scf_data: &SCF, mpi_operator: &Option<MPIOperator>) -> bool {
+    let ctrl = &scf_data.mol.ctrl;
+    let geom = &scf_data.mol.geom;
+    let xc_data = &scf_data.mol.xc_data;
+    let is_supported_scftype = match scf_data.scftype {
+        SCFType::RHF | SCFType::UHF => true,
+        _ => false,
+    };
+    mpi_operator.is_none() 
+        && is_supported_scftype
+        && ctrl.use_auxbas && ctrl.eri_type.eq("ri_v") && !ctrl.use_isdf && ctrl.auxbasis_response
+        && ctrl.empirical_dispersion.is_none()
+        && scf_data.mol.ecp_electrons == 0
+        && geom.ghost_ep_path.len() == 0
+        && geom.ghost_bs_elem.len() == 0
+        && scf_data.solvation.is_none()
+        && ctrl.electric_field.is_none()
+        && match geom.pbc {MOrC::Molecule => true, _ => false}
+        && !xc_data.is_fifth_dfa()
+        && !xc_data.is_range_separated()
+        && scf_data.vv10_coeff().is_none()
+        && !xc_data.use_kinetic_density()
+}
+
+/// Analytic nuclear gradients, i.e. dE/dR, with the shape of [3, natm]
+pub fn analytic_force(scf_data: &SCF) -> (f64, MatrixFull<f64>) {
+    if scf_data.mol.ctrl.print_level > 0 {
+        println!("Analytic force calculation ...");
+    }
+    let mut grad_data = Gradient::build(&scf_data.mol, scf_data);
+    // the electrostatic embedding by the MM point charges
+    if scf_data.mol.geom.ghost_pc_chrg.len() > 0 {
+        let (qm_grad, _) = qmmm::point_charge_gradients(scf_data);
+        grad_data.de.self_scaled_add(&qm_grad, 1.0);
+    }
+
+    (collect_total_energy(scf_data), grad_data.de)
+}
+
+/// Nuclear gradients of all atoms from the analytic derivatives if available, otherwise from finite differences.
+/// The frozen atoms (`GeomCell.fix`) are handled by the optimizers, so that their gradients are kept here for the Hessians
+pub fn calc_force(scf_data: &SCF, displace: f64, mpi_operator: &Option<MPIOperator>) -> (f64, MatrixFull<f64>) {
+    if analytic_force_is_available(scf_data, mpi_operator) {
+        analytic_force(scf_data)
+    } else {
+        numerical_force(scf_data, displace, mpi_operator)
+    }
+}
+
 
 pub fn numerical_force(scf_data: &SCF, displace: f64, mpi_operator: &Option<MPIOperator>) -> (f64,MatrixFull<f64>) {
-    let num_atoms =  scf_data.mol.geom.nfree;
+    let num_atoms =  scf_data.mol.geom.position.size[1];
     let mut num_force = MatrixFull::new([3,num_atoms],0.0);
     if scf_data.mol.ctrl.print_level > 0 {
         if let Some(mp_op) = mpi_operator {
@@ -104,6 +162,67 @@ pub fn numerical_force(scf_data: &SCF, displace: f64, mpi_operator: &Option<MPIO
     (collect_total_energy(scf_data),num_force)
 }
 
+/// Gradients on the MM point charges, [3, n_charges], by the central differences of the total energy,
+/// which are used for the methods without the analytic nuclear gradients
+pub fn numerical_point_charge_gradients(scf_data: &SCF, displace: f64, mpi_operator: &Option<MPIOperator>) -> MatrixFull<f64> {
+    let num_charges = scf_data.mol.geom.ghost_pc_chrg.len();
+    let mut mm_grad = MatrixFull::new([3,num_charges],0.0);
+    let is_master = if let Some(mp_op) = mpi_operator {mp_op.rank == 0} else {true};
+    if scf_data.mol.ctrl.print_level > 0 && is_master {
+        print!("Numerical gradients on the point charges ...");
+        io::stdout().flush().unwrap();
+    }
+    let displaced_energy = |m: usize, x: usize, shift: f64| -> f64 {
+        let mut time_mark = utilities::TimeRecords::new();
+        let mut new_scf = scf_data.clone();
+        new_scf.mol.geom.ghost_pc_pos[[x,m]] += shift;
+        new_scf.mol.ctrl.print_level = 0;
+        new_scf.mol.ctrl.initial_guess = String::from("inherit");
+        initialize_scf(&mut new_scf, mpi_operator);
+        performance_essential_calculations(&mut new_scf, &mut time_mark, mpi_operator)
+    };
+    for m in 0..num_charges {
+        if scf_data.mol.ctrl.print_level > 0 && is_master {
+            print!("| {:3}", &m);
+            io::stdout().flush().unwrap();
+        }
+        for x in 0..3 {
+            let de0 = displaced_energy(m, x, displace);
+            let de1 = displaced_energy(m, x, -displace);
+            mm_grad[[x,m]] = 0.5*(de0-de1)/displace;
+        }
+    }
+    if scf_data.mol.ctrl.print_level > 0 && is_master {
+        println!("|");
+    }
+
+    mm_grad
+}
+
+/// Print the gradients on the MM point charges, and write them to the file given by `point_charges_gradient_file`
+/// with the number of charges in the first line, followed by one line of `gx gy gz` for each charge.
+/// The gradients are evaluated by finite differences if the analytic nuclear gradients are not available.
+pub fn point_charge_gradients_output(scf_data: &SCF, displace: f64, mpi_operator: &Option<MPIOperator>) {
+    let mm_grad = if analytic_force_is_available(scf_data, mpi_operator) {
+        qmmm::point_charge_gradients(scf_data).1
+    } else {
+        numerical_point_charge_gradients(scf_data, displace, mpi_operator)
+    };
+    if let Some(mp_op) = mpi_operator {
+        if mp_op.rank != 0 {return}
+    }
+    let labels = vec![String::from("Q"); mm_grad.size[1]];
+    println!("Gradients on the point charges [a.u.]: ");
+    println!("{}", formated_force(&mm_grad, &labels));
+    if let Some(filename) = &scf_data.mol.ctrl.point_charges_gradient_file {
+        let mut output = format!("{}\n", mm_grad.size[1]);
+        mm_grad.iter_columns_full().for_each(|grad| {
+            output = format!("{}{:20.12}{:20.12}{:20.12}\n", output, grad[0], grad[1], grad[2]);
+        });
+        std::fs::write(filename, output).unwrap_or_else(|e| panic!("Fail to write the gradients on the point charges to {}: {}", filename, e));
+    }
+}
+
 pub fn formated_force(force: &MatrixFull<f64>, elem: &Vec<String>) -> String {
     let mut output = String::new();
     force.iter_columns_full().zip(elem.iter()).for_each(|(force, elem)| {
@@ -125,6 +244,100 @@ pub fn formated_force_ev(force: &MatrixFull<f64>, elem: &Vec<String>) -> String
 }
 
 
+/// Run the SCF calculation for the input in the toml format
+#[cfg(test)]
+pub fn scf_for_test(ctrl_str: &str) -> SCF {
+    use crate::ctrl_io::InputKeywords;
+    use crate::molecule_io::Molecule;
+    let tmp_keys = toml::from_str::<serde_json::Value>(ctrl_str).unwrap();
+    let (ctrl, geom) = InputKeywords::parse_ctl_from_json(&tmp_keys).unwrap();
+    let mol = Molecule::build_native(ctrl, geom, None).unwrap();
+    let mut scf_data = SCF::build(mol, &None);
+    let mut time_mark = utilities::TimeRecords::new();
+    performance_essential_calculations(&mut scf_data, &mut time_mark, &None);
+    scf_data
+}
+
+#[test]
+fn test_analytic_force_against_numerical_force() {
+    // (xc, charge, spin multiplicity, spin_polarization, tolerance), where the grid response neglected in the
+    // analytic RKS and UKS gradients is within the larger tolerance
+    let cases = [("hf", 0.0, 1.0, false, 2.0e-6), ("pbe", 0.0, 1.0, false, 5.0e-4),
+                 ("hf", 1.0, 2.0, true, 2.0e-6), ("pbe", 1.0, 2.0, true, 5.0e-4)];
+    for (xc, charge, spin, spin_polarization, tolerance) in cases {
+        let ctrl_str = format!("[ctrl]
+            print_level = 0
+            xc = \"{}\"
+            charge = {:.1}
+            spin = {:.1}
+            spin_polarization = {}
+            basis_path = \"basis-set-pool/def2-SVP\"
+            auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+            eri_type = \"ri-v\"
+            auxbasis_response = true
+            scf_acc_rho = 1.0e-9
+            scf_acc_eev = 1.0e-9
+            scf_acc_etot = 1.0e-11
+            [geom]
+            name = \"H2O\"
+            unit = \"Angstrom\"
+            position = \"\"\"
+                O   0.000   0.000   0.120
+                H   0.000   0.780  -0.470
+                H   0.100  -0.740  -0.480\"\"\"", xc, charge, spin, spin_polarization);
+        let mut scf_data = scf_for_test(&ctrl_str);
+        assert!(analytic_force_is_available(&scf_data, &None));
+        let (_, ana_force) = analytic_force(&scf_data);
+        let (_, num_force) = numerical_force(&scf_data, 1.0e-3, &None);
+        ana_force.data.iter().zip(num_force.data.iter()).for_each(|(ana, num)| {
+            assert!((ana-num).abs() < tolerance, "{}: analytic {} vs numerical {}", xc, ana, num);
+        });
+        // the tolerance is tight enough to detect the missing response of the auxiliary basis set
+        scf_data.mol.ctrl.auxbasis_response = false;
+        assert!(! analytic_force_is_available(&scf_data, &None));
+        let (_, ana_force) = analytic_force(&scf_data);
+        let max_error = ana_force.data.iter().zip(num_force.data.iter()).fold(0.0_f64, |acc, (ana, num)| acc.max((ana-num).abs()));
+        assert!(max_error > tolerance, "{}: the auxiliary-basis response of {} is not detected", xc, max_error);
+    }
+}
+
+
+/// The force of H2 from `calc_force` for the given keywords in the `[ctrl]` block, which should fall back to
+/// the finite differences
+#[cfg(test)]
+fn numerical_h2_force_for_test(keywords: &str) -> MatrixFull<f64> {
+    let ctrl_str = format!("[ctrl]
+        print_level = 0
+        {}
+        basis_path = \"basis-set-pool/def2-SVP\"
+        auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+        eri_type = \"ri-v\"
+        scf_acc_rho = 1.0e-9
+        scf_acc_etot = 1.0e-11
+        [geom]
+        name = \"H2\"
+        unit = \"Angstrom\"
+        position = \"\"\"
+            H   0.000   0.000   0.000
+            H   0.000   0.000   0.800\"\"\"", keywords);
+    let scf_data = scf_for_test(&ctrl_str);
+    assert!(! analytic_force_is_available(&scf_data, &None), "{}", keywords);
+    let (_, force) = calc_force(&scf_data, 1.0e-3, &None);
+    // the stretched H2 is pulled back along the bond
+    assert!(force[[2,0]] < -1.0e-3 && (force[[2,0]] + force[[2,1]]).abs() < 1.0e-6, "{}: {:?}", keywords, force.data);
+    force
+}
+
+#[test]
+fn test_range_separated_force_is_numerical() {
+    numerical_h2_force_for_test("xc = \"cam-b3lyp\"");
+}
+
+#[test]
+fn test_vv10_force_is_numerical() {
+    numerical_h2_force_for_test("xc = \"pbe\"\n        vv10_coeff = [5.9, 0.0093]");
+}
+
 //pub fn evaluate(x: &[f64], gx: &mut [f64]) -> f64 {
 //
 //    
@@ -0,0 +1,143 @@
+//! Gradients of the electrostatic QM/MM embedding, where the MM point charges `q_m` at `r_m`
+//! (see `ghost_pc_chrg` and `ghost_pc_pos` in [`GeomCell`](crate::geom_io::GeomCell)) interact with
+//! the QM electrons and nuclei by `q_m f(|r-r_m|)`, with `f(r) = 1/r` or `erf(r/width)/r` for the
+//! Gaussian-smeared charges.
+//!
+//! With the integrals `<i'|f_m|j>` of `int1e_iprinv` centered at `r_m`, the electronic contributions are
+//! ```text
+//!   dE/dR_A =  2 \sum_m q_m \sum_{i on A, j} D_ij <i'|f_m|j>
+//!   dE/dr_m = -2 q_m \sum_{ij} D_ij <i'|f_m|j>
+//! ```
+//! The remaining response of the wavefunction is included in the energy-weighted density matrix of the
+//! SCF gradients, as the embedding potential is a part of the core Hamiltonian.
+use std::f64::consts::PI;
+use std::sync::mpsc::channel;
+use rayon::prelude::*;
+use tensors::MatrixFull;
+use crate::geom_io::get_mass_charge;
+use crate::scf_io::SCF;
+use crate::utilities;
+
+/// The gradients of the QM/MM embedding energy with respect to the QM atoms, [3, natm],
+/// and to the MM point charges, [3, n_charges]
+pub fn point_charge_gradients(scf_data: &SCF) -> (MatrixFull<f64>, MatrixFull<f64>) {
+    let geom = &scf_data.mol.geom;
+    let natm = geom.elem.len();
+    let num_charges = geom.ghost_pc_chrg.len();
+    let mut qm_grad = MatrixFull::new([3, natm], 0.0);
+    let mut mm_grad = MatrixFull::new([3, num_charges], 0.0);
+    if num_charges == 0 {return (qm_grad, mm_grad)}
+
+    let mol = if let Some(width) = geom.ghost_pc_width {
+        scf_data.mol.with_rinv_zeta(1.0/(width*width))
+    } else {
+        scf_data.mol.clone()
+    };
+    // the total density matrix
+    let mut dm = scf_data.density_matrix[0].clone();
+    if scf_data.mol.spin_channel == 2 {
+        dm.data.iter_mut().zip(scf_data.density_matrix[1].data.iter()).for_each(|(to, from)| *to += from);
+    }
+
+    // the electronic contributions
+    let cur_op = String::from("iprinv");
+    let num_shell = mol.cint_bas.len();
+    let par_tasks = utilities::balancing(num_charges, rayon::current_num_threads());
+    let (sender, receiver) = channel();
+    par_tasks.par_iter().for_each_with(sender, |s, task_range| {
+        let mut cint_data = mol.initialize_cint(false);
+        cint_data.int1e_iprinv_optimizer_rust();
+        let mut loc_qm_grad = MatrixFull::new([3, natm], 0.0);
+        let mut loc_mm_grad = MatrixFull::new([3, task_range.len()], 0.0);
+        task_range.clone().for_each(|m| {
+            let charge = geom.ghost_pc_chrg[m];
+            cint_data.set_rinv_origin(&geom.ghost_pc_pos[(.., m)]);
+            for j in 0..num_shell {
+                let (start_j, len_j) = (mol.cint_fdqc[j][0], mol.cint_fdqc[j][1]);
+                for i in 0..num_shell {
+                    let (start_i, len_i) = (mol.cint_fdqc[i][0], mol.cint_fdqc[i][1]);
+                    // the shells of the ghost basis sets are attached to the atoms beyond natm, which only
+                    // contribute to the gradients on the point charges
+                    let atm_i = mol.cint_bas[i][0] as usize;
+                    // in the format of [len_i, len_j, 3]
+                    let buf = cint_data.cint_ip_ij(i as i32, j as i32, &cur_op);
+                    (0..3).for_each(|x| {
+                        let mut de = 0.0;
+                        for loc_j in 0..len_j {
+                            for loc_i in 0..len_i {
+                                de += dm[[start_i + loc_i, start_j + loc_j]]*buf[(x*len_j + loc_j)*len_i + loc_i];
+                            }
+                        }
+                        if atm_i < natm {loc_qm_grad[[x, atm_i]] += 2.0*charge*de};
+                        loc_mm_grad[[x, m - task_range.start]] -= 2.0*charge*de;
+                    });
+                }
+            }
+        });
+        cint_data.final_c2r();
+        s.send((task_range.start, loc_qm_grad, loc_mm_grad)).unwrap()
+    });
+    receiver.into_iter().for_each(|(start, loc_qm_grad, loc_mm_grad)| {
+        qm_grad.data.iter_mut().zip(loc_qm_grad.data.iter()).for_each(|(to, from)| *to += from);
+        let len = loc_mm_grad.size[1];
+        mm_grad.copy_from_matr(0..3, start..start+len, &loc_mm_grad, 0..3, 0..len);
+    });
+
+    // the nuclear contributions with the same effective nuclear charges as in calc_nuc_energy_with_point_charges
+    let mass_charge = get_mass_charge(&geom.elem);
+    geom.position.iter_columns_full().enumerate().for_each(|(a, r_a)| {
+        let mut z_a = mass_charge[a].1;
+        if let Some(ecp) = scf_data.mol.basis4elem[a].ecp_electrons {
+            z_a -= ecp as f64;
+        }
+        geom.ghost_pc_chrg.iter().zip(geom.ghost_pc_pos.iter_columns_full()).enumerate().for_each(|(m, (q_m, r_m))| {
+            let rdiff: Vec<f64> = r_a.iter().zip(r_m.iter()).map(|(a, m)| a - m).collect();
+            let dd = rdiff.iter().fold(0.0, |acc, x| acc + x*x).sqrt();
+            if dd < 1.0e-8 {return}
+            // the radial derivative of q*f(r)
+            let df = if let Some(width) = geom.ghost_pc_width {
+                2.0/(width*PI.sqrt())*(-(dd/width).powi(2)).exp()/dd - libm::erf(dd/width)/(dd*dd)
+            } else {
+                -1.0/(dd*dd)
+            };
+            (0..3).for_each(|x| {
+                let de = z_a*q_m*df*rdiff[x]/dd;
+                qm_grad[[x, a]] += de;
+                mm_grad[[x, m]] -= de;
+            });
+        });
+    });
+
+    (qm_grad, mm_grad)
+}
+
+#[test]
+fn test_point_charge_gradients_against_finite_differences() {
+    use super::{analytic_force, numerical_force, numerical_point_charge_gradients, scf_for_test};
+    let scf_data = scf_for_test("[ctrl]
+        print_level = 0
+        xc = \"hf\"
+        basis_path = \"basis-set-pool/def2-SVP\"
+        auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+        eri_type = \"ri-v\"
+        auxbasis_response = true
+        scf_acc_rho = 1.0e-9
+        scf_acc_eev = 1.0e-9
+        scf_acc_etot = 1.0e-11
+        [geom]
+        name = \"H2O with two point charges\"
+        unit = \"Angstrom\"
+        position = \"\"\"
+            O   0.000   0.000   0.120
+            H   0.000   0.760  -0.470
+            H   0.000  -0.760  -0.470\"\"\"
+        ghost = \"\"\"
+            point charge  -0.834   2.500   0.300   0.100
+            point charge   0.417   0.200   2.700  -0.600\"\"\"");
+    let (_, qm_grad) = analytic_force(&scf_data);
+    let (_, num_qm_grad) = numerical_force(&scf_data, 1.0e-3, &None);
+    qm_grad.data.iter().zip(num_qm_grad.data.iter()).for_each(|(ana, num)| assert!((ana-num).abs() < 2.0e-6));
+    let (_, mm_grad) = point_charge_gradients(&scf_data);
+    let num_mm_grad = numerical_point_charge_gradients(&scf_data, 1.0e-3, &None);
+    mm_grad.data.iter().zip(num_mm_grad.data.iter()).for_each(|(ana, num)| assert!((ana-num).abs() < 2.0e-5));
+}
@@ -4,7 +4,7 @@ use std::vec;
 use itertools::Itertools;
 use rest_tensors::RIFull;
 use tensors::{MatrixFull, ri};
-use tensors::matrix_blas_lapack::{_dgemm_nn, _dgemm_tn};
+use tensors::matrix_blas_lapack::{_dgemm_nn, _dgemm_tn, _dgemm_full};
 //use crate::ctrl_io::SCFType;
 use crate::molecule_io::Molecule;
 use crate::geom_io::{self, get_charge, get_mass_charge};
@@ -27,7 +27,6 @@ fn diag_idx_generator(n: usize) -> Vec<usize> {
         value += (i+2);
     }
 
-    println!("idx = {:?}", idx);
     idx
 }
 
@@ -206,8 +205,8 @@ impl Molecule {
 
         }
 
-        (0..4).into_iter().for_each(|i| aorange[i][0] = shell_start[i]);
-        (0..4).into_iter().for_each(|i| aorange[i][1] = shell_end[i]);
+        (0..natm).into_iter().for_each(|i| aorange[i][0] = shell_start[i]);
+        (0..natm).into_iter().for_each(|i| aorange[i][1] = shell_end[i]);
         let ao_start: Vec<usize> = shell_start.iter().map(|shell| ao_loc[*shell]).collect();
         let ao_end: Vec<usize> = shell_end.iter().map(|shell| ao_loc[*shell]).collect();
         ao_start.iter().enumerate().for_each(|(i, ao)| aorange[i][2] = *ao);
@@ -232,6 +231,8 @@ pub struct Gradient {
     pub ovlp_deriv: Vec<MatrixFull<f64>>,
     pub hcore_deriv: Vec<MatrixFull<f64>>,
     //pub eri_deriv: Vec<RIFull<f64>>,
+    /// total derivatives of the SCF energy with reference to nuclear coordinates: [3, natm]
+    pub de: MatrixFull<f64>,
 }
 
 impl Gradient {
@@ -241,6 +242,7 @@ impl Gradient {
             nuc_deriv: MatrixFull::empty(),
             ovlp_deriv: vec![],
             hcore_deriv: vec![],
+            de: MatrixFull::empty(),
         };
         grad_data
     }
@@ -253,18 +255,29 @@ impl Gradient {
             nuc_deriv: MatrixFull::empty(),
             ovlp_deriv: vec![],
             hcore_deriv: vec![],
+            de: MatrixFull::empty(),
         }
     }
 
     pub fn build(mol: &Molecule, scf: &SCF) -> Gradient {
+        if mol.xc_data.is_range_separated() {
+            panic!("Analytic nuclear gradients are not yet implemented for range-separated hybrid functionals");
+        }
+        if mol.xc_data.use_kinetic_density() {
+            panic!("Analytic nuclear gradients are not yet implemented for meta-GGA functionals");
+        }
+        if scf.vv10_coeff().is_some() {
+            panic!("Analytic nuclear gradients are not yet implemented for the VV10 non-local correlation");
+        }
         let mut grad_data = Gradient::new(mol);
         grad_data.nuc_deriv = grad_data.calc_nuc_energy_deriv();
         grad_data.ovlp_deriv = grad_data.calc_ovlp_deriv();
         grad_data.hcore_deriv = grad_data.calc_hcore_deriv();
-        let (vj, vjaux) = grad_data.calc_j(scf);
-        let (vk, vkaux) = grad_data.calc_k(scf);
-
-
+        grad_data.de = match scf.scftype {
+            SCFType::RHF => grad_data.calc_rhf_deriv(scf),
+            SCFType::UHF => grad_data.calc_uhf_deriv(scf),
+            _ => panic!("Analytic nuclear gradients are not yet implemented for {:?}", scf.scftype),
+        };
 
         grad_data
 
@@ -619,8 +632,13 @@ impl Gradient {
         // get a lower matrix
         // REST:
         // trying to use matrix upper
-        let mut dm_upper = scf_data.density_matrix[0].to_matrixupper();
-        let diag_idx = diag_idx_generator(scf_data.density_matrix[0].size[0]);
+        // the total density matrix is used for both the closed- and open-shell cases
+        let mut dm_total = scf_data.density_matrix[0].clone();
+        if scf_data.mol.spin_channel == 2 {
+            dm_total.self_scaled_add(&scf_data.density_matrix[1], 1.0);
+        }
+        let mut dm_upper = dm_total.to_matrixupper();
+        let diag_idx = diag_idx_generator(dm_total.size[0]);
         dm_upper.data.iter_mut().enumerate().filter(|(i, data)| !diag_idx.contains(i))
                     .for_each(|(i, data)| *data *= 2.0);
         //println!("dm_upper = {:?}", dm_upper);
@@ -651,7 +669,6 @@ impl Gradient {
         //let rho = rho.transpose(); //transpose not needed
         let rho2 = _dgemm_nn(&int2c_inv.to_matrixfullslice(), &rho.to_matrixfullslice()); //[naux, 1]
         rhoj.data.iter_mut().zip(rho2.data).for_each(|(rj, r)| *rj = r);
-        rhoj.formated_output_e(150, "full"); 
 
 
         // auxbasis_response
@@ -666,6 +683,8 @@ impl Gradient {
                                       // [nao*nao,naux]*[naux,1] -> [nao*nao,1]
                                       let mut c = _dgemm_nn(&mat.to_matrixfullslice(), &rhoj.to_matrixfullslice());
                                       c.reshape([nao,nao]);
+                                      // vj = -vj
+                                      c.data.iter_mut().for_each(|v| *v *= -1.0);
                                       c
                                     }).collect();
         
@@ -681,8 +700,9 @@ impl Gradient {
             // [3,131,1] 
             let mut vjaux: Vec<MatrixFull<f64>> = int3c_ip2_matfull.iter()
                 .map(|x| {
-                                            let temp = _dgemm_nn(&dm_mat.transpose().to_matrixfullslice(), &x.to_matrixfullslice()).transpose(); //[1,131]*3
-                                            temp+rhoj.clone()
+                                            let mut temp = _dgemm_nn(&dm_mat.transpose().to_matrixfullslice(), &x.to_matrixfullslice()).transpose(); //[131,1]
+                                            temp.data.iter_mut().zip(rhoj.data.iter()).for_each(|(t,r)| *t *= *r);
+                                            temp
                                             }).collect();
             // Here we use Matrixfull for substraction later
             // let vjaux: Vec<Vec<f64>> = c1.iter().map(|x| x.data).collect(); 
@@ -691,8 +711,9 @@ impl Gradient {
             // [3,131,131]
             let int2c_e1 = self.ip_2c2e_intor(); //[3,naux,naux]
             let mut vjaux2: Vec<MatrixFull<f64>> = int2c_e1.iter()
-                .map(|x| { let temp = _dgemm_nn(&x.to_matrixfullslice(), &rhoj.to_matrixfullslice());
-                                            temp+rhoj.clone()
+                .map(|x| { let mut temp = _dgemm_nn(&x.to_matrixfullslice(), &rhoj.to_matrixfullslice());
+                                            temp.data.iter_mut().zip(rhoj.data.iter()).for_each(|(t,r)| *t *= *r);
+                                            temp
                                             }).collect();
             //vjaux -= numpy.einsum('xpq,mp,nq->xp', int2c_e1, rhoj, rhoj)
             //actually 3*[131,131] [131,1] [131,1] -> [3,131,1]
@@ -715,7 +736,6 @@ impl Gradient {
                 vjaux_final_data.push(data)
             }
 
-            // vj needs to be multiplied by -1, but currently we just test its number
             return (vj, Some(vjaux_final_data))
         } else {
             return (vj, None)
@@ -726,14 +746,19 @@ impl Gradient {
     }
 
     pub fn calc_k(&self, scf_data: &SCF) -> (Vec<MatrixFull<f64>>,Option<Vec<Vec<f64>>>) {
+        self.calc_k_spin(scf_data, 0)
+    }
+
+    /// The exchange contribution from the density matrix of the given spin channel
+    pub fn calc_k_spin(&self, scf_data: &SCF, i_spin: usize) -> (Vec<MatrixFull<f64>>,Option<Vec<Vec<f64>>>) {
         // in pyscf, it uses HDF5 file to temporarily save data, but REST currently just use memory to save data
         let nao = self.mol.num_basis;
         let naux = self.mol.num_auxbas;
         let auxmol = self.mol.make_auxmol();
         let auxslices = auxmol.aoslice_by_atom();
         //vk [3, nao, nao]
-        let mo_coeff = &scf_data.eigenvectors[0];
-        let mo_occ = &scf_data.occupation[0];
+        let mo_coeff = &scf_data.eigenvectors[i_spin];
+        let mo_occ = &scf_data.occupation[i_spin];
         let nmo = mo_occ.len();
         // Here pyscf uses assert to judge if the mol is ROHF/RHF, 
         // if (mo_occ > 0) + (mo_occ == 2) != (mo_occ), return RUNTIME_ERROR
@@ -759,9 +784,10 @@ impl Gradient {
         let orbo = MatrixFull::from_vec([nao,occ], orbo_data).unwrap();
         //(P|Q)
         
-        let mut int2c = self.mol.int_ij_aux_columb();
+        let int2c = self.mol.int_ij_aux_columb();
         // [naux, naux]
-        int2c.to_matrixfullslicemut().cholesky_decompose_inverse('L').unwrap();
+        // pyscf uses cho_solve here, which equals to the full inverse of (P|Q)
+        let int2c = int2c.lapack_inverse().unwrap();
         let int3c = self.mol.int_ijk_rifull();
         // orbo [nao, occ] int3c [nao, nao, naux] -> [naux, nao, occ]
         let int3c_iter = int3c.data.chunks_exact(nao*nao);
@@ -787,7 +813,10 @@ impl Gradient {
         // actually 3*[nao,occ,naux]*[nao,occ,naux] -> [3,nao,nao]
         let rhok_matfull = rhok.transpose_jki().rifull_to_matfull_ij_k(); //[occ*naux,nao]
         let vk: Vec<MatrixFull<f64>> = tmp.iter().map(|tmp| {let c1 = tmp.rifull_to_matfull_i_jk();  //[nao,occ*naux]
-                                                                          _dgemm_nn(&c1.to_matrixfullslice(), &rhok_matfull.to_matrixfullslice())}).collect();
+                                                                          let mut c = _dgemm_nn(&c1.to_matrixfullslice(), &rhok_matfull.to_matrixfullslice());
+                                                                          // vk = -vk
+                                                                          c.data.iter_mut().for_each(|v| *v *= -1.0);
+                                                                          c}).collect();
         let rhok = rhok.transpose_kji(); // [naux,occ,nao]
         let rhok_matfull2 = rhok.rifull_to_matfull_ij_k(); //[naux*occ,nao]
         let rhok_oo = _dgemm_nn(&rhok_matfull2.to_matrixfullslice(), &orbo.to_matrixfullslice()); //[naux*occ,nao][nao,occ] -> [naux*occ,occ]
@@ -816,11 +845,12 @@ impl Gradient {
             let tmp = matfull_to_rifull(&tmp, &naux, &nao); //[naux,nao,nao] pij
             //pyscf 'xpij,pij->xp' [3,naux, nao, nao] [naux,nao,nao] -> [3,naux]
             //actually 3*[nao, nao, naux] [naux,nao,nao] -> 
-            let mut vkaux: Vec<Vec<f64>> = int3c_ip2.iter().map(|ri| { let int3c_mat = ri.rifull_to_matfull_ij_k().transpose(); //[naux,nao*nao]
-                                                      let tmp_ri = tmp.transpose_jki(); // [nao,nao,naux]
-                                                      let tmp_mat = tmp_ri.rifull_to_matfull_ij_k(); //  
-                                                      let result_mat = _dgemm_nn(&int3c_mat.to_matrixfullslice(), &tmp_mat.to_matrixfullslice()); // [naux,nao*nao]*[nao*nao,naux]-> [naux,naux]
-                                                      result_mat.get_diagonal_terms().unwrap().into_iter().map(|v| *v).collect_vec()}).collect(); 
+            // only the diagonal terms are required, so the contraction is done chunk by chunk
+            let tmp_ri = tmp.transpose_jki(); // [nao,nao,naux]
+            let mut vkaux: Vec<Vec<f64>> = int3c_ip2.iter().map(|ri| { 
+                                                      ri.data.chunks_exact(nao*nao).zip(tmp_ri.data.chunks_exact(nao*nao))
+                                                      .map(|(int3c_p, tmp_p)| int3c_p.iter().zip(tmp_p.iter()).fold(0.0,|acc,(a,b)| acc + a*b))
+                                                      .collect_vec()}).collect(); 
 
             // (d/dX P|Q)
             /* 
@@ -862,7 +892,6 @@ impl Gradient {
                 vkaux_final_data.push(data)
             }
 
-            // vk needs to be multiplied by -1, but currently we just test its number
             return (vk, Some(vkaux_final_data))
         }
         else {
@@ -873,12 +902,92 @@ impl Gradient {
     }
     
 
-    pub fn calc_xc_deriv(){
+    /// Energy-weighted density matrix of the given spin channel: 
+    /// $W_{\mu\nu} = \sum_i n_i \epsilon_i C_{\mu i} C_{\nu i}$
+    pub fn calc_dme0(&self, scf_data: &SCF, i_spin: usize) -> MatrixFull<f64> {
+        let nao = self.mol.num_basis;
+        let mo_coeff = &scf_data.eigenvectors[i_spin];
+        let mo_occ = &scf_data.occupation[i_spin];
+        let mo_energy = &scf_data.eigenvalues[i_spin];
+        let orbo_idx: Vec<usize> = mo_occ.iter().enumerate().filter(|(i,v)| **v>0.0).map(|(i,v)| i).collect();
+        let occ = orbo_idx.len();
+        let orbo_data: Vec<f64> = orbo_idx.iter().map(|i| mo_coeff[(..,*i)].to_vec()).flatten().collect();
+        let orbo = MatrixFull::from_vec([nao,occ], orbo_data).unwrap();
+        let mut orbo_e = orbo.clone();
+        orbo_e.iter_columns_full_mut().zip(orbo_idx.iter()).for_each(|(col,i)| {
+            let fac = mo_occ[*i]*mo_energy[*i];
+            col.iter_mut().for_each(|c| *c *= fac);
+        });
+        let mut dme0 = MatrixFull::new([nao,nao],0.0);
+        _dgemm_full(&orbo_e, 'N', &orbo, 'T', &mut dme0, 1.0, 0.0);
+
+        dme0
+    }
 
+    /// Contributions of the two-electron (Coulomb and exact-exchange) potentials,
+    /// using the RI-V approximation:
+    ///   vhf = vj - 0.5*c_x*vk   with the shape of [3, nao, nao], 
+    ///   aux = vjaux - 0.5*c_x*vkaux  with the shape of [natm, 3], if auxbasis_response = true
+    /// where c_x is the fraction of the exact exchange.
+    pub fn calc_veff_deriv(&self, scf_data: &SCF) -> (Vec<MatrixFull<f64>>, Option<Vec<Vec<f64>>>) {
+        let hyb = self.mol.xc_data.exx_fraction_scf();
+        let (mut vhf, mut vhfaux) = self.calc_j(scf_data);
+        if hyb.abs() >= 1.0e-6 {
+            let (vk, vkaux) = self.calc_k(scf_data);
+            vhf.iter_mut().zip(vk.iter()).for_each(|(vj,vk)| vj.self_scaled_add(vk, -0.5*hyb));
+            if let (Some(vjaux), Some(vkaux)) = (&mut vhfaux, &vkaux) {
+                vjaux.iter_mut().zip(vkaux.iter()).for_each(|(vj,vk)| {
+                    vj.iter_mut().zip(vk.iter()).for_each(|(vj,vk)| *vj -= 0.5*hyb*vk);
+                });
+            }
+        }
+        (vhf, vhfaux)
     }
 
-    pub fn calc_veff_deriv(){
+    /// Analytic nuclear gradients of RHF and RKS with the shape of [3, natm]
+    pub fn calc_rhf_deriv(&self, scf_data: &SCF) -> MatrixFull<f64> {
+        let natm = self.mol.geom.elem.len();
+        let dm = &scf_data.density_matrix[0];
+        let dme0 = self.calc_dme0(scf_data, 0);
+
+        let mut de = self.nuc_deriv.clone();
+
+        // one-electron contributions
+        for atm_id in 0..natm {
+            for x in 0..3 {
+                let h1 = &self.hcore_deriv[atm_id*3+x];
+                let s1 = &self.ovlp_deriv[atm_id*3+x];
+                let de_h1 = h1.data.iter().zip(dm.data.iter()).fold(0.0,|acc,(h,d)| acc + h*d);
+                let de_s1 = s1.data.iter().zip(dme0.data.iter()).fold(0.0,|acc,(s,w)| acc + s*w);
+                de[[x,atm_id]] += de_h1 - de_s1;
+            }
+        }
+
+        // two-electron contributions
+        let (vhf, vhfaux) = self.calc_veff_deriv(scf_data);
+        for atm_id in 0..natm {
+            let (p0, p1) = self.mol.mol_slice(atm_id);
+            for x in 0..3 {
+                let de_vhf = vhf[x].iter_columns_full().zip(dm.iter_columns_full())
+                    .fold(0.0,|acc,(vhf_j,dm_j)| {
+                        acc + vhf_j[p0..p1].iter().zip(dm_j[p0..p1].iter()).fold(0.0,|acc,(v,d)| acc + v*d)
+                    });
+                de[[x,atm_id]] += 2.0*de_vhf;
+            }
+        }
+        if let Some(vhfaux) = vhfaux {
+            vhfaux.iter().enumerate().for_each(|(atm_id, aux)| {
+                (0..3).for_each(|x| de[[x,atm_id]] += aux[x]);
+            });
+        }
+
+        // exchange-correlation contributions
+        if self.mol.xc_data.is_dfa_scf() {
+            let de_xc = self.calc_xc_deriv(scf_data);
+            de.self_scaled_add(&de_xc, 1.0);
+        }
 
+        de
     }
 
 
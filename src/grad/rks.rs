@@ -0,0 +1,169 @@
+use std::sync::mpsc::channel;
+use itertools::izip;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use tensors::MatrixFull;
+use tensors::matrix_blas_lapack::_dgemm_nn;
+use crate::basis_io::{spheric_gto_value_serial, spheric_gto_1st_value_serial, spheric_gto_2nd_value_serial};
+use crate::molecule_io::Molecule;
+use crate::scf_io::SCF;
+use crate::utilities;
+use super::rhf::Gradient;
+
+/// The atom index of each atomic orbital
+pub fn ao_atom_index(mol: &Molecule) -> Vec<usize> {
+    let mut ao_atm = vec![0_usize; mol.num_basis];
+    mol.aoslice_by_atom().iter().enumerate().for_each(|(atm_id, slice)| {
+        ao_atm[slice[2]..slice[3]].iter_mut().for_each(|a| *a = atm_id);
+    });
+    ao_atm
+}
+
+/// Tabulate the atomic orbitals and their derivatives on the given grids
+///   ao:   [num_basis, num_grids]
+///   aop:  3*[num_basis, num_grids]
+///   aopp: 3*3*[num_basis, num_grids], the analytic second derivatives, only for gga
+pub fn tabulate_ao_deriv(mol: &Molecule, coordinates: &[[f64;3]], with_2nd: bool)
+    -> (MatrixFull<f64>, Vec<MatrixFull<f64>>, Vec<Vec<MatrixFull<f64>>>) {
+    let num_basis = mol.num_basis;
+    let num_grids = coordinates.len();
+    let mut ao = MatrixFull::new([num_basis, num_grids],0.0);
+    let mut aop = vec![MatrixFull::new([num_basis, num_grids],0.0);3];
+    let mut aopp = if with_2nd {
+        vec![vec![MatrixFull::new([num_basis, num_grids],0.0);3];3]
+    } else {
+        vec![]
+    };
+
+    mol.basis4elem.iter().zip(mol.geom.position.iter_columns_full()).for_each(|(elem, geom)| {
+        let start = elem.global_index.0;
+        let loc_num_bas = elem.global_index.1;
+        let end = start + loc_num_bas;
+        let mut tmp_geom = [0.0;3];
+        tmp_geom.iter_mut().zip(geom.iter()).for_each(|value| {*value.0 = *value.1});
+
+        let tab_den = spheric_gto_value_serial(coordinates, &tmp_geom, elem);
+        ao.copy_from_matr(start..end, 0..num_grids, &tab_den, 0..loc_num_bas, 0..num_grids);
+
+        let tab_dev = spheric_gto_1st_value_serial(coordinates, &tmp_geom, elem);
+        for x in 0..3 {
+            aop[x].copy_from_matr(start..end, 0..num_grids, &tab_dev[x], 0..loc_num_bas, 0..num_grids);
+        }
+
+        if with_2nd {
+            let tab_hess = spheric_gto_2nd_value_serial(coordinates, &tmp_geom, elem);
+            for x in 0..3 {
+                for y in 0..3 {
+                    aopp[x][y].copy_from_matr(start..end, 0..num_grids, &tab_hess[x][y], 0..loc_num_bas, 0..num_grids);
+                }
+            }
+        }
+    });
+
+    (ao, aop, aopp)
+}
+
+/// Contract the potential on the grids with the AO derivatives for one spin channel:
+///   de_ao[mu,x] = \sum_g d_x phi_mu (wv0 psi_mu + \sum_y wv_y psi^y_mu) + \sum_y \sum_g d_x d_y phi_mu wv_y psi_mu
+/// with psi = dm*ao, and psi^y = dm*aop_y.
+///   wv0: w*vrho, [num_grids]
+///   wv:  the weighted potential of nabla rho, 3*[num_grids], only for gga
+pub fn contract_xc_deriv_ao(dm: &MatrixFull<f64>,
+    ao: &MatrixFull<f64>,
+    aop: &Vec<MatrixFull<f64>>,
+    aopp: &Vec<Vec<MatrixFull<f64>>>,
+    wv0: &[f64],
+    wv: &Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+
+    let num_basis = ao.size[0];
+    let use_gga = wv.len() == 3;
+
+    // psi = dm*ao [num_basis, num_grids]
+    let psi = _dgemm_nn(&dm.to_matrixfullslice(), &ao.to_matrixfullslice());
+    let mut psi_w = psi.clone();
+    psi_w.iter_columns_full_mut().zip(wv0.iter()).for_each(|(psi_g, wv0_g)| {
+        psi_g.iter_mut().for_each(|p| *p *= wv0_g);
+    });
+    if use_gga {
+        for y in 0..3 {
+            let psi_y = _dgemm_nn(&dm.to_matrixfullslice(), &aop[y].to_matrixfullslice());
+            izip!(psi_w.iter_columns_full_mut(), psi_y.iter_columns_full(), wv[y].iter()).for_each(|(psi_w_g, psi_y_g, wv_g)| {
+                psi_w_g.iter_mut().zip(psi_y_g.iter()).for_each(|(pw, py)| *pw += py*wv_g);
+            });
+        }
+    }
+
+    let mut de_ao = vec![vec![0.0;num_basis];3];
+    for x in 0..3 {
+        let de_ao_x = &mut de_ao[x];
+        aop[x].iter_columns_full().zip(psi_w.iter_columns_full()).for_each(|(aop_g, psi_g)| {
+            de_ao_x.iter_mut().zip(aop_g.iter().zip(psi_g.iter())).for_each(|(de,(a,p))| *de += a*p);
+        });
+        if use_gga {
+            for y in 0..3 {
+                izip!(aopp[x][y].iter_columns_full(), psi.iter_columns_full(), wv[y].iter()).for_each(|(aopp_g, psi_g, wv_g)| {
+                    de_ao_x.iter_mut().zip(aopp_g.iter().zip(psi_g.iter())).for_each(|(de,(a,p))| *de += a*p*wv_g);
+                });
+            }
+        }
+    }
+
+    de_ao
+}
+
+impl Gradient {
+    /// Exchange-correlation contribution to the analytic nuclear gradients of RKS, with the shape of [3, natm].
+    /// The response of the grids with respect to the nuclear displacement is neglected.
+    pub fn calc_xc_deriv(&self, scf_data: &SCF) -> MatrixFull<f64> {
+        let natm = self.mol.geom.elem.len();
+        let num_basis = self.mol.num_basis;
+        let mut de_xc = MatrixFull::new([3,natm],0.0);
+        let grids = if let Some(grids) = &scf_data.grids {
+            grids
+        } else {
+            return de_xc
+        };
+        let xc_data = &self.mol.xc_data;
+        let use_gga = xc_data.use_density_gradient();
+        let dm = vec![scf_data.density_matrix[0].clone()];
+
+        let default_omp_num_threads = utilities::omp_get_num_threads_wrapper();
+        utilities::omp_set_num_threads_wrapper(1);
+
+        let (sender, receiver) = channel();
+        grids.parallel_balancing.par_iter().for_each_with(sender, |s, range_grids| {
+            let loc_weights = &grids.weights[range_grids.clone()];
+            let (_, loc_rhop, loc_vrho, loc_vsigma) = xc_data.xc_vrho_vsigma_slots_dm_only(range_grids.clone(), grids, 1, &dm);
+
+            let wv0: Vec<f64> = loc_vrho.slice_column(0).iter().zip(loc_weights.iter()).map(|(v,w)| v*w).collect();
+            // wv_y = 2*w*vsigma*(d_y rho)
+            let wv: Vec<Vec<f64>> = if use_gga {
+                let loc_rhop_s = loc_rhop.get_reducing_matrix(0).unwrap();
+                let loc_vsigma_s = loc_vsigma.slice_column(0);
+                (0..3).map(|y| {
+                    izip!(loc_rhop_s.get_slice_x(y).iter(), loc_vsigma_s.iter(), loc_weights.iter())
+                        .map(|(r,v,w)| 2.0*r*v*w).collect()
+                }).collect()
+            } else {
+                vec![]
+            };
+
+            let (loc_ao, loc_aop, loc_aopp) = tabulate_ao_deriv(&self.mol, &grids.coordinates[range_grids.clone()], use_gga);
+            let loc_de_ao = contract_xc_deriv_ao(&dm[0], &loc_ao, &loc_aop, &loc_aopp, &wv0, &wv);
+
+            s.send(loc_de_ao).unwrap()
+        });
+
+        let ao_atm = ao_atom_index(&self.mol);
+        receiver.into_iter().for_each(|loc_de_ao| {
+            for x in 0..3 {
+                loc_de_ao[x].iter().zip(ao_atm.iter()).for_each(|(de, atm_id)| {
+                    de_xc[[x,*atm_id]] -= 2.0*de;
+                });
+            }
+        });
+
+        utilities::omp_set_num_threads_wrapper(default_omp_num_threads);
+
+        de_xc
+    }
+}
@@ -0,0 +1,77 @@
+use tensors::MatrixFull;
+use crate::scf_io::SCF;
+use super::rhf::Gradient;
+
+impl Gradient {
+    /// Contributions of the two-electron potentials for the open-shell cases, using the RI-V approximation:
+    ///   vhf[i_spin] = vj[dm_a+dm_b] - c_x*vk[dm_i_spin]   with the shape of 2*[3, nao, nao],
+    ///   aux = vjaux - c_x*(vkaux_a + vkaux_b)         with the shape of [natm, 3], if auxbasis_response = true
+    pub fn calc_veff_deriv_uhf(&self, scf_data: &SCF) -> (Vec<Vec<MatrixFull<f64>>>, Option<Vec<Vec<f64>>>) {
+        let hyb = self.mol.xc_data.exx_fraction_scf();
+        let (vj, mut vhfaux) = self.calc_j(scf_data);
+        let mut vhf = vec![vj.clone(), vj];
+        if hyb.abs() >= 1.0e-6 {
+            for i_spin in 0..2 {
+                let (vk, vkaux) = self.calc_k_spin(scf_data, i_spin);
+                vhf[i_spin].iter_mut().zip(vk.iter()).for_each(|(vj,vk)| vj.self_scaled_add(vk, -hyb));
+                if let (Some(vjaux), Some(vkaux)) = (&mut vhfaux, &vkaux) {
+                    vjaux.iter_mut().zip(vkaux.iter()).for_each(|(vj,vk)| {
+                        vj.iter_mut().zip(vk.iter()).for_each(|(vj,vk)| *vj -= hyb*vk);
+                    });
+                }
+            }
+        }
+        (vhf, vhfaux)
+    }
+
+    /// Analytic nuclear gradients of UHF and UKS with the shape of [3, natm]
+    pub fn calc_uhf_deriv(&self, scf_data: &SCF) -> MatrixFull<f64> {
+        let natm = self.mol.geom.elem.len();
+        let mut dm_total = scf_data.density_matrix[0].clone();
+        dm_total.self_scaled_add(&scf_data.density_matrix[1], 1.0);
+        let mut dme0 = self.calc_dme0(scf_data, 0);
+        dme0.self_scaled_add(&self.calc_dme0(scf_data, 1), 1.0);
+
+        let mut de = self.nuc_deriv.clone();
+
+        // one-electron contributions
+        for atm_id in 0..natm {
+            for x in 0..3 {
+                let h1 = &self.hcore_deriv[atm_id*3+x];
+                let s1 = &self.ovlp_deriv[atm_id*3+x];
+                let de_h1 = h1.data.iter().zip(dm_total.data.iter()).fold(0.0,|acc,(h,d)| acc + h*d);
+                let de_s1 = s1.data.iter().zip(dme0.data.iter()).fold(0.0,|acc,(s,w)| acc + s*w);
+                de[[x,atm_id]] += de_h1 - de_s1;
+            }
+        }
+
+        // two-electron contributions
+        let (vhf, vhfaux) = self.calc_veff_deriv_uhf(scf_data);
+        for i_spin in 0..2 {
+            let dm = &scf_data.density_matrix[i_spin];
+            for atm_id in 0..natm {
+                let (p0, p1) = self.mol.mol_slice(atm_id);
+                for x in 0..3 {
+                    let de_vhf = vhf[i_spin][x].iter_columns_full().zip(dm.iter_columns_full())
+                        .fold(0.0,|acc,(vhf_j,dm_j)| {
+                            acc + vhf_j[p0..p1].iter().zip(dm_j[p0..p1].iter()).fold(0.0,|acc,(v,d)| acc + v*d)
+                        });
+                    de[[x,atm_id]] += 2.0*de_vhf;
+                }
+            }
+        }
+        if let Some(vhfaux) = vhfaux {
+            vhfaux.iter().enumerate().for_each(|(atm_id, aux)| {
+                (0..3).for_each(|x| de[[x,atm_id]] += aux[x]);
+            });
+        }
+
+        // exchange-correlation contributions
+        if self.mol.xc_data.is_dfa_scf() {
+            let de_xc = self.calc_xc_deriv_uks(scf_data);
+            de.self_scaled_add(&de_xc, 1.0);
+        }
+
+        de
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::mpsc::channel;
+use itertools::izip;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use tensors::MatrixFull;
+use crate::scf_io::SCF;
+use crate::utilities;
+use super::rhf::Gradient;
+use super::rks::{ao_atom_index, contract_xc_deriv_ao, tabulate_ao_deriv};
+
+impl Gradient {
+    /// Exchange-correlation contribution to the analytic nuclear gradients of UKS, with the shape of [3, natm].
+    /// The response of the grids with respect to the nuclear displacement is neglected.
+    pub fn calc_xc_deriv_uks(&self, scf_data: &SCF) -> MatrixFull<f64> {
+        let natm = self.mol.geom.elem.len();
+        let mut de_xc = MatrixFull::new([3,natm],0.0);
+        let grids = if let Some(grids) = &scf_data.grids {
+            grids
+        } else {
+            return de_xc
+        };
+        let xc_data = &self.mol.xc_data;
+        let use_gga = xc_data.use_density_gradient();
+        let dm = vec![scf_data.density_matrix[0].clone(), scf_data.density_matrix[1].clone()];
+
+        let default_omp_num_threads = utilities::omp_get_num_threads_wrapper();
+        utilities::omp_set_num_threads_wrapper(1);
+
+        let (sender, receiver) = channel();
+        grids.parallel_balancing.par_iter().for_each_with(sender, |s, range_grids| {
+            let loc_weights = &grids.weights[range_grids.clone()];
+            let (_, loc_rhop, loc_vrho, loc_vsigma) = xc_data.xc_vrho_vsigma_slots_dm_only(range_grids.clone(), grids, 2, &dm);
+            let (loc_ao, loc_aop, loc_aopp) = tabulate_ao_deriv(&self.mol, &grids.coordinates[range_grids.clone()], use_gga);
+
+            let loc_de_ao: Vec<Vec<Vec<f64>>> = (0..2).map(|i_spin| {
+                let wv0: Vec<f64> = loc_vrho.slice_column(i_spin).iter().zip(loc_weights.iter()).map(|(v,w)| v*w).collect();
+                // wv_y = w*(2*vsigma_ss*(d_y rho_s) + vsigma_ab*(d_y rho_s'))
+                let wv: Vec<Vec<f64>> = if use_gga {
+                    let loc_rhop_s = loc_rhop.get_reducing_matrix(i_spin).unwrap();
+                    let loc_rhop_o = loc_rhop.get_reducing_matrix(1-i_spin).unwrap();
+                    let loc_vsigma_ss = loc_vsigma.slice_column(2*i_spin);
+                    let loc_vsigma_ab = loc_vsigma.slice_column(1);
+                    (0..3).map(|y| {
+                        izip!(loc_rhop_s.get_slice_x(y).iter(), loc_rhop_o.get_slice_x(y).iter(),
+                            loc_vsigma_ss.iter(), loc_vsigma_ab.iter(), loc_weights.iter())
+                            .map(|(rs,ro,vss,vab,w)| (2.0*vss*rs + vab*ro)*w).collect()
+                    }).collect()
+                } else {
+                    vec![]
+                };
+                contract_xc_deriv_ao(&dm[i_spin], &loc_ao, &loc_aop, &loc_aopp, &wv0, &wv)
+            }).collect();
+
+            s.send(loc_de_ao).unwrap()
+        });
+
+        let ao_atm = ao_atom_index(&self.mol);
+        receiver.into_iter().for_each(|loc_de_ao| {
+            loc_de_ao.iter().for_each(|loc_de_ao_s| {
+                for x in 0..3 {
+                    loc_de_ao_s[x].iter().zip(ao_atm.iter()).for_each(|(de, atm_id)| {
+                        de_xc[[x,*atm_id]] -= 2.0*de;
+                    });
+                }
+            });
+        });
+
+        utilities::omp_set_num_threads_wrapper(default_omp_num_threads);
+
+        de_xc
+    }
+}
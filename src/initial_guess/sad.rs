@@ -4,6 +4,7 @@ use crate::constants::{F_SHELL, KR_SHELL, NELE_IN_SHELLS, SPECIES_INFO, S_SHELL,
 use crate::molecule_io::Molecule;
 use crate::ctrl_io::InputKeywords;
 use crate::geom_io::{GeomCell, formated_element_name};
+use crate::basis_io::basis_assignment::find_basis_source;
 use crate::mpi_io::MPIOperator;
 use crate::scf_io::{initialize_scf, scf};
 use crate::utilities;
@@ -17,11 +18,14 @@ pub fn initial_guess_from_sad(mol: &Molecule, mpi_operator: &Option<MPIOperator>
     //let mut dms_beta: Vec<MatrixFull<f64>> = vec![];
     let mut atom_dms: HashMap<String, Vec<MatrixFull<f64>>> = HashMap::new();
 
-    mol.geom.elem.iter().for_each(|ielem| {
-        if let None = &mut atom_dms.get(&ielem.clone()) {
+    // the atoms with the basis sets assigned by the atom labels are treated separately from their elements
+    let atom_keys = sad_atom_keys(mol);
 
+    mol.geom.elem.iter().zip(atom_keys.iter()).for_each(|(ielem, ikey)| {
+        if let None = &mut atom_dms.get(&ikey.clone()) {
 
-            if mol.ctrl.print_level>0 {println!("Generate SAD of {}", &ielem)};
+
+            if mol.ctrl.print_level>0 {println!("Generate SAD of {}", &ikey)};
 
             //elem_name.push(ielem.to_string());
 
@@ -31,6 +35,12 @@ pub fn initial_guess_from_sad(mol: &Molecule, mpi_operator: &Option<MPIOperator>
             atom_ctrl.basis_type = mol.ctrl.basis_type.clone();
             atom_ctrl.auxbas_path = mol.ctrl.auxbas_path.clone();
             atom_ctrl.auxbas_type = mol.ctrl.auxbas_type.clone();
+            atom_ctrl.basis_library = mol.ctrl.basis_library.clone();
+            atom_ctrl.basis_offline = mol.ctrl.basis_offline;
+            atom_ctrl.basis_assignment = mol.ctrl.basis_assignment.clone();
+            atom_ctrl.auxbas_assignment = mol.ctrl.auxbas_assignment.clone();
+            atom_ctrl.basis_modifier = mol.ctrl.basis_modifier.clone();
+            atom_ctrl.auxbas_modifier = mol.ctrl.auxbas_modifier.clone();
             atom_ctrl.use_auxbas = true;
             atom_ctrl.num_threads = mol.ctrl.num_threads.clone();
             atom_ctrl.eri_type = String::from("ri_v");
@@ -58,6 +68,7 @@ pub fn initial_guess_from_sad(mol: &Molecule, mpi_operator: &Option<MPIOperator>
             atom_geom.name = ielem.to_string();
             atom_geom.position = MatrixFull::from_vec([3,1], vec![0.000,0.000,0.000]).unwrap();
             atom_geom.elem = vec![ielem.to_string()];
+            atom_geom.atom_label = vec![ikey.to_string()];
 
             let mut atom_mol = Molecule::build_native(atom_ctrl,atom_geom, None).unwrap();
 
@@ -83,14 +94,14 @@ pub fn initial_guess_from_sad(mol: &Molecule, mpi_operator: &Option<MPIOperator>
                 dms.push(atom_scf.density_matrix[1].clone());
             }
 
-            atom_dms.insert(ielem.clone(),dms);
+            atom_dms.insert(ikey.clone(),dms);
         }
     });
 
     // reset the omp_num_threads to be the correct one
     //utilities::omp_set_num_threads_wrapper(mol.ctrl.num_threads.unwrap());
 
-    let (dms_alpha, dms_beta) = block_diag_specific(&atom_dms, &mol.geom.elem);
+    let (dms_alpha, dms_beta) = block_diag_specific(&atom_dms, &atom_keys);
 
     if mol.geom.ghost_bs_elem.len() == 0 {
         if mol.spin_channel == 1 {
@@ -124,6 +135,17 @@ pub fn initial_guess_from_sad(mol: &Molecule, mpi_operator: &Option<MPIOperator>
     }   
 }
 
+/// The keys of the atomic density matrices: the atom label if the basis set is assigned to it, otherwise the element
+pub fn sad_atom_keys(mol: &Molecule) -> Vec<String> {
+    mol.geom.elem.iter().enumerate().map(|(i_atm, ielem)| {
+        let label = mol.geom.get_atom_label(i_atm);
+        match find_basis_source(&mol.ctrl.basis_assignment, &label, ielem) {
+            Some((key, _)) if formated_element_name(key).ne(&formated_element_name(ielem)) => label,
+            _ => ielem.clone(),
+        }
+    }).collect()
+}
+
 pub fn block_diag_specific(atom_dms: &HashMap<String,Vec<MatrixFull<f64>>>,elem: &Vec<String>) -> (MatrixFull<f64>, MatrixFull<f64>) {
     let mut atom_size = 0;
     elem.iter().for_each(|ielem| {
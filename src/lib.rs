@@ -55,16 +55,20 @@ extern crate lazy_static;
 pub mod basis_io;
 pub mod constants;
 pub mod check_norm;
+pub mod cphf;
 pub mod ctrl_io;
 pub mod dft;
 pub mod geom_io;
 pub mod initial_guess;
 pub mod isdf;
 pub mod molecule_io;
+pub mod response;
 pub mod scf_io;
 pub mod utilities;
 pub mod external_libs;
 pub mod mpi_io;
+pub mod pbc;
+pub mod solvation;
 
 //extern crate rest;
 
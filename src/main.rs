@@ -70,15 +70,23 @@ mod basis_io;
 mod ctrl_io;
 mod grad;
 mod dft;
+mod response;
 mod utilities;
 mod molecule_io;
 mod scf_io;
 mod initial_guess;
 mod check_norm;
+mod cphf;
 mod ri_pt2;
 //mod grad;
 mod ri_rpa;
+mod tddft;
+mod freq;
+mod finite_field;
+mod geom_opt;
 mod isdf;
+mod pbc;
+mod solvation;
 mod constants;
 mod post_scf_analysis;
 mod external_libs;
@@ -86,7 +94,7 @@ mod external_libs;
 #[global_allocator]
 static GLOBAL: jemallocator::Jemalloc = jemallocator::Jemalloc;
 use crate::constants::EV;
-use crate::grad::{formated_force, numerical_force};
+use crate::grad::{formated_force, calc_force};
 use crate::initial_guess::enxc::{effective_nxc_matrix, effective_nxc_tensors};
 //static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 //use crate::grad::rhf::Gradient;
@@ -187,46 +195,50 @@ fn main() -> anyhow::Result<()> {
             if scf_data.mol.ctrl.print_level>0 {
                 println!("Geometry optimization invoked");
             }
-            let displace = 0.0013/ANG;
-
-            //let (energy,nforce) = numerical_force(&scf_data, displace);
-            //println!("Total atomic forces [a.u.]: ");
-            //nforce.formated_output(5, "full");
-            //let mut nnforce = nforce.clone();
-            //nnforce.iter_mut().for_each(|x| *x *= ANG/EV);
-            //println!("Total atomic forces [EV/Ang]: ");
-            //nnforce.formated_output(5, "full");
-
-            let mut position = scf_data.mol.geom.position.iter().map(|x| *x).collect::<Vec<f64>>();
-            lbfgs().minimize(
-                &mut position, 
-                |x: &[f64], gx: &mut [f64]| {
-                    scf_data.mol.geom.position = MatrixFull::from_vec([3,x.len()/3], x.to_vec()).unwrap();
-                    if scf_data.mol.ctrl.print_level>0 {
-                        println!("Input geometry in this round is:");
-                        println!("{}", scf_data.mol.geom.formated_geometry());
-                    }
-                    scf_data.mol.ctrl.initial_guess = String::from("inherit");
-                    initialize_scf(&mut scf_data, &mpi_operator);
-                    performance_essential_calculations(&mut scf_data, &mut time_mark, &mpi_operator);
-                    let (energy, nforce) = numerical_force(&scf_data, displace, &mpi_operator);
-                    gx.iter_mut().zip(nforce.iter()).for_each(|(to, from)| {*to = *from});
-
-                    if scf_data.mol.ctrl.print_level>0 {
-                        println!("Output force in this round [a.u.] is:");
-                        println!("{}", formated_force(&nforce, &scf_data.mol.geom.elem));
-                    }
-
-                    Ok(energy)
-                },
-                |prgr| {
-                    println!("Iteration {}, Evaluation: {}", &prgr.niter, &prgr.neval);
-                    println!(" xnorm = {}, gnorm = {}, step = {}",
-                        &prgr.xnorm, &prgr.gnorm, &prgr.step
-                    );
-                    false
-                },
-            );
+            if scf_data.mol.ctrl.geom_opt_optimizer.eq("lbfgs") {
+                let displace = geom_opt::FORCE_DISPLACEMENT;
+
+                //let (energy,nforce) = numerical_force(&scf_data, displace);
+                //println!("Total atomic forces [a.u.]: ");
+                //nforce.formated_output(5, "full");
+                //let mut nnforce = nforce.clone();
+                //nnforce.iter_mut().for_each(|x| *x *= ANG/EV);
+                //println!("Total atomic forces [EV/Ang]: ");
+                //nnforce.formated_output(5, "full");
+
+                let mut position = scf_data.mol.geom.position.iter().map(|x| *x).collect::<Vec<f64>>();
+                lbfgs().minimize(
+                    &mut position, 
+                    |x: &[f64], gx: &mut [f64]| {
+                        scf_data.mol.geom.position = MatrixFull::from_vec([3,x.len()/3], x.to_vec()).unwrap();
+                        if scf_data.mol.ctrl.print_level>0 {
+                            println!("Input geometry in this round is:");
+                            println!("{}", scf_data.mol.geom.formated_geometry());
+                        }
+                        scf_data.mol.ctrl.initial_guess = String::from("inherit");
+                        initialize_scf(&mut scf_data, &mpi_operator);
+                        performance_essential_calculations(&mut scf_data, &mut time_mark, &mpi_operator);
+                        let (energy, nforce) = calc_force(&scf_data, displace, &mpi_operator);
+                        gx.iter_mut().zip(nforce.iter()).for_each(|(to, from)| {*to = *from});
+
+                        if scf_data.mol.ctrl.print_level>0 {
+                            println!("Output force in this round [a.u.] is:");
+                            println!("{}", formated_force(&nforce, &scf_data.mol.geom.elem));
+                        }
+
+                        Ok(energy)
+                    },
+                    |prgr| {
+                        println!("Iteration {}, Evaluation: {}", &prgr.niter, &prgr.neval);
+                        println!(" xnorm = {}, gnorm = {}, step = {}",
+                            &prgr.xnorm, &prgr.gnorm, &prgr.step
+                        );
+                        false
+                    },
+                );
+            } else {
+                geom_opt::geometry_optimization(&mut scf_data, &mut time_mark, &mpi_operator);
+            }
             println!("Geometry after relaxation [Ang]:");
             println!("{}", scf_data.mol.geom.formated_geometry());
             time_mark.count("geom_opt");
@@ -234,9 +246,57 @@ fn main() -> anyhow::Result<()> {
             time_mark.report("geom_opt");
 
         },
+        JobType::TS => {
+            time_mark.new_item("ts_search", "transition-state search");
+            time_mark.count_start("ts_search");
+            geom_opt::ts::transition_state_search(&mut scf_data, &mut time_mark, &mpi_operator);
+            if scf_data.mol.ctrl.print_level>0 {
+                println!("Transition-state geometry [Ang]:");
+                println!("{}", scf_data.mol.geom.formated_geometry());
+            }
+            time_mark.count("ts_search");
+
+            time_mark.report("ts_search");
+        },
+        JobType::IRC => {
+            time_mark.new_item("irc", "intrinsic reaction coordinate");
+            time_mark.count_start("irc");
+            geom_opt::irc::irc_calculations(&scf_data, &mut time_mark, &mpi_operator);
+            time_mark.count("irc");
+
+            time_mark.report("irc");
+        },
+        JobType::Scan => {
+            time_mark.new_item("pes_scan", "PES scan");
+            time_mark.count_start("pes_scan");
+            geom_opt::scan::pes_scan(&mut scf_data, &mut time_mark, &mpi_operator);
+            time_mark.count("pes_scan");
+
+            time_mark.report("pes_scan");
+        },
         _ => {}
     }
 
+    //====================================
+    // Now for the harmonic frequencies
+    //====================================
+    if matches!(jobtype, JobType::Freq) || scf_data.mol.ctrl.outputs.iter().any(|x| x.eq("frequency")) {
+        time_mark.new_item("Frequency", "the harmonic frequencies and thermochemistry");
+        time_mark.count_start("Frequency");
+        freq::frequency_calculations(&scf_data, &mpi_operator);
+        time_mark.count("Frequency");
+    }
+
+    //====================================
+    // Now for the finite-field (hyper)polarizabilities
+    //====================================
+    if scf_data.mol.ctrl.outputs.iter().any(|x| x.eq("finite_field")) {
+        time_mark.new_item("FiniteField", "the finite-field (hyper)polarizabilities");
+        time_mark.count_start("FiniteField");
+        finite_field::finite_field_calculations(&scf_data, &mpi_operator);
+        time_mark.count("FiniteField");
+    }
+
     //let mut grad_data = Gradient::build(&scf_data.mol, &scf_data);
 
     //grad_data.calc_j(&scf_data.density_matrix);
@@ -291,6 +351,16 @@ fn main() -> anyhow::Result<()> {
 
     post_scf_analysis::post_scf_output(&scf_data, &mpi_operator);
 
+    //====================================
+    // Now for the excited states
+    //====================================
+    if scf_data.mol.ctrl.tddft_nstates > 0 {
+        time_mark.new_item("TDDFT", "the TDHF/TDDFT calculation");
+        time_mark.count_start("TDDFT");
+        tddft::tddft_calculations(&scf_data, &mpi_operator)?;
+        time_mark.count("TDDFT");
+    }
+
     //====================================
     // Now for post-correlation calculations
     //====================================
@@ -322,6 +392,14 @@ pub fn output_result(scf_data: &scf_io::SCF) {
     println!("The SCF energy        : {:18.10} Ha", 
         //scf_data.mol.ctrl.xc.to_uppercase(),
         scf_data.scf_energy);
+    if let Some(smearing) = scf_data.energies.get("smearing") {
+        println!("The entropy term      : {:18.10} Ha", smearing[0]);
+        println!("The free energy       : {:18.10} Ha", smearing[1]);
+        println!("The energy (sigma->0) : {:18.10} Ha", smearing[2]);
+    }
+    if let Some(solvation) = scf_data.energies.get("solvation") {
+        println!("Elec. solvation energy: {:18.10} Ha", solvation[0]);
+    }
     
     let xc_name = scf_data.mol.ctrl.xc.to_lowercase();
     if xc_name.eq("mp2") || xc_name.eq("xyg3") || xc_name.eq("xygjos") || xc_name.eq("r-xdh7") || xc_name.eq("xyg7") || xc_name.eq("zrps") || xc_name.eq("scsrpa") {
@@ -22,15 +22,17 @@ use rest_libcint::{CINTR2CDATA, CintType};
 use std::path::Path;
 use regex::Regex;
 use crate::basis_io::etb::{get_etb_elem, etb_gen_for_atom_list, InfoV2};
-use crate::constants::{ATM_NUC, ATM_NUC_MOD_OF, AUXBAS_THRESHOLD, ELEM1ST, ELEM2ND, ELEM3RD, ELEM4TH, ELEM5TH, ELEM6TH, ELEMTMS, ENV_PRT_START, NUC_ECP, NUC_FRAC_CHARGE, NUC_STAD_CHARGE};
+use crate::constants::{ATM_NUC, ATM_NUC_MOD_OF, AUXBAS_THRESHOLD, ELEM1ST, ELEM2ND, ELEM3RD, ELEM4TH, ELEM5TH, ELEM6TH, ELEMTMS, ENV_PRT_START, NUC_ECP, NUC_FRAC_CHARGE, NUC_STAD_CHARGE, PTR_RANGE_OMEGA, PTR_RINV_ZETA};
 use crate::dft::DFA4REST;
 use crate::geom_io::{GeomCell,MOrC, GeomUnit, get_mass_charge};
 use crate::basis_io::{ecp, BasInfo, Basis4Elem};
 use crate::ctrl_io::{overall_report_on_ctrl_geom, InputKeywords};
 use crate::mpi_io::{mpi_isend_irecv_wrt_distribution, MPIData, MPIOperator, mpi_isend_irecv_wrt_distribution_v02};
 use crate::utilities;
-use crate::basis_io::bse_downloader::{self, ctrl_element_checker, local_element_checker};
+use crate::basis_io::bse_downloader::{self, local_element_checker};
 use crate::basis_io::basis_list::{self, basis_fuzzy_matcher, check_basis_name};
+use crate::basis_io::basis_library::{available_elements, missing_elements_message};
+use crate::basis_io::basis_assignment::{atom_basis_sources, assigned_paths, elements_of_path};
 
 //extern crate nalgebra as na;
 //use na::{DMatrix,DVector};
@@ -193,7 +195,7 @@ impl Molecule {
 
         let (mut basis4elem,mut cint_atm,mut cint_bas,cint_env,
             fdqc_bas,cint_fdqc,num_elec,num_basis,num_state, cint_ecpbas) 
-            = Molecule::collect_basis(&mut ctrl, &mut geom);
+            = Molecule::collect_basis(&mut ctrl, &mut geom)?;
 
 
 
@@ -221,6 +223,8 @@ impl Molecule {
         //let basis4elem = bas;
 
         let xc_data = DFA4REST::new(&ctrl.xc,spin_channel, ctrl.print_level);
+        xc_data.check_fifth_dfa()?;
+        xc_data.check_meta_gga()?;
 
         let use_eri = xc_data.use_eri() || ctrl.use_ri_vj;
         
@@ -279,7 +283,9 @@ impl Molecule {
             cint_type,
         };
         // check and prepare the auxiliary basis sets
-        if mol.ctrl.use_auxbas {mol.initialize_auxbas()};
+        if mol.ctrl.use_auxbas {mol.initialize_auxbas()?};
+        // reject the features that are not yet available for crystals before any integral is evaluated
+        if let MOrC::Crystal = mol.geom.pbc {crate::pbc::check_periodic_support(&mol)?};
 
         //println!("Debug for AuxBasis4Eelem");
         //mol.auxbas4elem.iter().enumerate().for_each(|(i,x)| {
@@ -294,7 +300,7 @@ impl Molecule {
         Ok(mol)
 
     }
-    pub fn initialize_auxbas(&mut self) {
+    pub fn initialize_auxbas(&mut self) -> anyhow::Result<()> {
         let cint_type = if self.ctrl.basis_type.to_lowercase()==String::from("spheric") {
             CintType::Spheric
         } else if self.ctrl.basis_type.to_lowercase()==String::from("cartesian") {
@@ -321,7 +327,7 @@ impl Molecule {
         let (mut auxbas,mut cint_aux_atm,mut cint_aux_bas,cint_aux_env,
                 mut fdqc_aux_bas,mut cint_aux_fdqc,num_auxbas) 
             = if self.ctrl.use_auxbas {
-                Molecule::collect_auxbas(&mut self.ctrl, &mut self.geom, etb)
+                Molecule::collect_auxbas(&mut self.ctrl, &mut self.geom, etb)?
         } else {
             (Vec::new(),Vec::new(),Vec::new(),Vec::new(),Vec::new(),Vec::new(),0)
         };
@@ -366,6 +372,7 @@ impl Molecule {
             println!("final nbas: {},final natm: {}", self.cint_bas.len()+self.cint_aux_bas.len(), self.cint_atm.len()+self.cint_aux_atm.len());
         }
 
+        Ok(())
     }
 
     pub fn initialize_cint(&self, for_ri: bool) -> CINTR2CDATA {
@@ -406,6 +413,22 @@ impl Molecule {
         }
     }
 
+    /// A copy of the molecule, for which all two-electron integrals are evaluated with the
+    /// long-range operator erf(omega*r)/r instead of 1/r
+    pub fn with_range_separation(&self, omega: f64) -> Molecule {
+        let mut mol_lr = self.clone();
+        mol_lr.cint_env[PTR_RANGE_OMEGA] = omega;
+        mol_lr
+    }
+
+    /// A copy of the molecule, for which the operator 1/|r-R| of `int1e_rinv` is replaced by the potential
+    /// erf(sqrt(zeta)|r-R|)/|r-R| of a Gaussian charge
+    pub fn with_rinv_zeta(&self, zeta: f64) -> Molecule {
+        let mut mol_zeta = self.clone();
+        mol_zeta.cint_env[PTR_RINV_ZETA] = zeta;
+        mol_zeta
+    }
+
     pub fn update_geom_poisition_in_cint_env(&self, position: &MatrixFull<f64>) -> Vec<f64> {
         let mut cint_env = self.cint_env.clone();
         self.cint_atm.iter().zip(position.iter_columns_full()).for_each(|(atm, position)| {
@@ -419,7 +442,7 @@ impl Molecule {
     }
 
     pub fn collect_auxbas(ctrl: &InputKeywords,geom: &mut GeomCell, etb: Option<InfoV2>) -> 
-            (Vec<Basis4Elem>, Vec<Vec<i32>>, Vec<Vec<i32>>, Vec<f64>, Vec<BasInfo>, Vec<Vec<usize>>, usize) {
+            anyhow::Result<(Vec<Basis4Elem>, Vec<Vec<i32>>, Vec<Vec<i32>>, Vec<f64>, Vec<BasInfo>, Vec<Vec<usize>>, usize)> {
 
         let mut aux_atm: Vec<Vec<i32>> = vec![];
         let mut aux_env: Vec<f64> = vec![];
@@ -474,60 +497,28 @@ impl Molecule {
         let mut auxbas_info: Vec<BasInfo> = vec![];
         let mut aux_cint_fdqc: Vec<Vec<usize>> = vec![];
 
-        let ctrl_elem = ctrl_element_checker(geom);
-        let local_elem = local_element_checker(&ctrl.auxbas_path);
-        //if ctrl.print_level>0 {
-        //    println!("Elements involved are {:?}", ctrl_elem);
-        //    println!("Elements with local basis set available are {:?}", local_elem);
-        //    println!("Start to gather the auxiliary basis sets of the rest elements online")
-        //}
+        // the auxiliary basis sets assigned per element or per atom label take the precedence over ETB
+        let elem_tot = geom.elem.clone().into_iter().chain(geom.ghost_bs_elem.clone().into_iter()).collect::<Vec<_>>();
+        let atom_sources = atom_basis_sources(&ctrl.auxbas_assignment, geom);
         let etb_elem = get_etb_elem(geom, &ctrl.etb_start_atom_number);
-
-        let elem_intersection = ctrl_elem.intersect(local_elem.clone());
-        let mut required_elem = vec![];
-        for ctrl_item in ctrl_elem {
-            if !elem_intersection.contains(&ctrl_item) {
-                if ! etb_elem.contains(&ctrl_item) {
-                    required_elem.push(ctrl_item)
-                }
-            }
+        let ctrl_elem = elements_of_path(&elem_tot, &atom_sources, None).into_iter()
+            .filter(|elem| ! etb_elem.contains(elem)).collect::<Vec<String>>();
+        prepare_local_basis(&ctrl.auxbas_path, ctrl_elem, true, ctrl, geom)?;
+        for path in assigned_paths(&atom_sources) {
+            prepare_local_basis(&path, elements_of_path(&elem_tot, &atom_sources, Some(&path)), true, ctrl, geom)?;
         }
-    
-        if required_elem.len() != 0 {
-            //bse_auxbas_getter insert here
-            let re = Regex::new(r"/?(?P<basis>[^/]*)/?$").unwrap();
-            let cap = re.captures(&ctrl.auxbas_path).unwrap();
-            let auxbas_name = cap.name("basis").unwrap().to_string();
-            if ctrl.print_level > 0 {
-                println!("auxbas_name = {} from {}", &auxbas_name, &ctrl.auxbas_path)
-            };
-            if check_basis_name(&auxbas_name) {
-                bse_downloader::bse_auxbas_getter_v2(&auxbas_name,&geom, &ctrl.auxbas_path, &required_elem, ctrl.print_level);
-            }
-            else {
-                if ctrl.print_level > 0 {
-                    println!("Error: Missing local aux basis sets for {:?}", &required_elem);
-                }
-                let matched = basis_fuzzy_matcher(&auxbas_name);
-                match matched {
-                    Some(_) => panic!("{} may not be a valid basis set name, similar name is {}", auxbas_name, matched.unwrap()),
-                    None => panic!("{} may not be a valid basis set name, please check.", auxbas_name),
-            }
-        }
-
-        };
 
         //import the basis set of atoms and ghost atoms one by one
-        let elem_tot = geom.elem.clone().into_iter().chain(geom.ghost_bs_elem.clone().into_iter()).collect::<Vec<_>>();
         for (atm_index, atm_elem) in elem_tot.iter().enumerate() {
-            let tmp_path = format!("{}/{}.json",&ctrl.auxbas_path, &atm_elem);
-            let mut tmp_basis = match &etb {
-                None => Basis4Elem::parse_json_from_file(tmp_path,&cint_type).unwrap(),
-                Some(x) => { match x.elements.get(atm_elem) {
+            let mut tmp_basis = match (atom_sources[atm_index], &etb) {
+                (Some(source), _) => source.to_basis4elem(atm_elem, &cint_type).unwrap(),
+                (None, None) => Basis4Elem::parse_from_basis_path(&ctrl.auxbas_path, atm_elem, &cint_type).unwrap(),
+                (None, Some(x)) => { match x.elements.get(atm_elem) {
                     Some(y) => y.clone(),
-                    None => Basis4Elem::parse_json_from_file(tmp_path,&cint_type).unwrap(),
+                    None => Basis4Elem::parse_from_basis_path(&ctrl.auxbas_path, atm_elem, &cint_type).unwrap(),
                 }},
             };
+            ctrl.auxbas_modifier.apply(&mut tmp_basis, &cint_type, ctrl.print_level);
            
             //println!("tmp_auxbasis = {:?}", tmp_basis);
 
@@ -595,11 +586,11 @@ impl Molecule {
         let num_auxbas = auxbas_info.len();
 
         //println!("auxbas_total = {:?}", auxbas_total);
-        (auxbas_total, aux_atm, aux_bas, aux_env,auxbas_info,aux_cint_fdqc,num_auxbas)
+        Ok((auxbas_total, aux_atm, aux_bas, aux_env,auxbas_info,aux_cint_fdqc,num_auxbas))
     }
 
     pub fn collect_basis(ctrl: &InputKeywords,geom: &mut GeomCell) -> 
-            (Vec<Basis4Elem>, Vec<Vec<i32>>, Vec<Vec<i32>>, Vec<f64>, Vec<BasInfo>, Vec<Vec<usize>>, [f64;3],usize, usize, Option<Vec<Vec<i32>>>) {
+            anyhow::Result<(Vec<Basis4Elem>, Vec<Vec<i32>>, Vec<Vec<i32>>, Vec<f64>, Vec<BasInfo>, Vec<Vec<usize>>, [f64;3],usize, usize, Option<Vec<Vec<i32>>>)> {
         //let (elem_name, elem_charge, elem_mass) = elements();
         let mut atm: Vec<Vec<i32>> = vec![];
         let mut env: Vec<f64> = vec![0.0;ENV_PRT_START];
@@ -657,39 +648,21 @@ impl Molecule {
         let mut bas_info: Vec<BasInfo> = vec![];
         let mut cint_fdqc: Vec<Vec<usize>> = vec![];
 
-        let ctrl_elem = ctrl_element_checker(geom);
-        let local_elem = local_element_checker(&ctrl.basis_path);
-        let elem_intersection = ctrl_elem.intersect(local_elem.clone());
-        let mut required_elem = vec![];
-        for ctrl_item in ctrl_elem {
-            if !elem_intersection.contains(&ctrl_item) {
-                required_elem.push(ctrl_item)
-            }
+        let elem_tot = geom.elem.clone().into_iter().chain(geom.ghost_bs_elem.clone().into_iter()).collect::<Vec<_>>();
+        let atom_sources = atom_basis_sources(&ctrl.basis_assignment, geom);
+        prepare_local_basis(&ctrl.basis_path, elements_of_path(&elem_tot, &atom_sources, None), false, ctrl, geom)?;
+        for path in assigned_paths(&atom_sources) {
+            prepare_local_basis(&path, elements_of_path(&elem_tot, &atom_sources, Some(&path)), false, ctrl, geom)?;
         }
-    
-        if required_elem.len() != 0 {
-            let re = Regex::new(r"/?(?P<basis>[^/]*)/?$").unwrap();
-            let cap = re.captures(&ctrl.basis_path).unwrap();
-            let basis_name = cap.name("basis").unwrap().to_string();
-            if check_basis_name(&basis_name) {
-                bse_downloader::bse_basis_getter_v2(&basis_name,&geom, &ctrl.basis_path, &required_elem);
-            }  else {
-                if ctrl.print_level > 0 {
-                    println!("Error: Missing local basis sets for {:?}", &required_elem);
-                }
-                let matched = basis_fuzzy_matcher(&basis_name);
-                match matched {
-                    Some(_) => panic!("{} may not be a valid basis set name, similar name is {}", basis_name, matched.unwrap()),
-                    None => panic!("{} may not be a valid basis set name, please check.", basis_name),
-                }
-            }
-        };
 
 
         // for standard atoms
         for (atm_index, atm_elem) in geom.elem.iter().enumerate() {
-            let tmp_path = format!("{}/{}.json",&ctrl.basis_path, &atm_elem);
-            let mut tmp_basis = Basis4Elem::parse_json_from_file(tmp_path,&cint_type).unwrap();
+            let mut tmp_basis = match atom_sources[atm_index] {
+                Some(source) => source.to_basis4elem(atm_elem, &cint_type).unwrap(),
+                None => Basis4Elem::parse_from_basis_path(&ctrl.basis_path, atm_elem, &cint_type).unwrap(),
+            };
+            ctrl.basis_modifier.apply(&mut tmp_basis, &cint_type, ctrl.print_level);
             let mut num_basis_per_atm = 0_usize;
             for tmp_bascell in &tmp_basis.electron_shells {
                 let mut num_primitive: i32 = tmp_bascell.exponents.len() as i32;
@@ -760,8 +733,11 @@ impl Molecule {
             let atm_index_start = geom.elem.len();
             for (local_atm_index, atm_elem) in geom.ghost_bs_elem.iter().enumerate() {
                 let atm_index = local_atm_index+atm_index_start;
-                let tmp_path = format!("{}/{}.json",&ctrl.basis_path, &atm_elem);
-                let mut tmp_basis = Basis4Elem::parse_json_from_file(tmp_path,&cint_type).unwrap();
+                let mut tmp_basis = match atom_sources[atm_index] {
+                    Some(source) => source.to_basis4elem(atm_elem, &cint_type).unwrap(),
+                    None => Basis4Elem::parse_from_basis_path(&ctrl.basis_path, atm_elem, &cint_type).unwrap(),
+                };
+                ctrl.basis_modifier.apply(&mut tmp_basis, &cint_type, ctrl.print_level);
                 let mut num_basis_per_atm = 0_usize;
                 for tmp_bascell in &tmp_basis.electron_shells {
                     let mut num_primitive: i32 = tmp_bascell.exponents.len() as i32;
@@ -935,7 +911,7 @@ impl Molecule {
 
         let final_ecpbas = if ecpbas.len() == 0 {None} else {Some(ecpbas)};
 
-        (basis_total, atm, bas, env,bas_info,cint_fdqc,num_elec,num_basis, num_state, final_ecpbas)
+        Ok((basis_total, atm, bas, env,bas_info,cint_fdqc,num_elec,num_basis, num_state, final_ecpbas))
     }
 
     pub fn int_ij_matrixuppers(&self,op_name: String, comp: usize) -> Vec<MatrixUpper<f64>> {
@@ -1198,6 +1174,10 @@ impl Molecule {
         } else if op_name.eq("point charge") {
             // for the ghost point charge term
             // <a|-Za/|Ra-r||b> -> -Za*<a|1/|r||b> by setting PTR_RINV_ORIG as Ra
+            // and the Gaussian-smeared charges with erf(|Ra-r|/width)/|Ra-r| by setting PTR_RINV_ZETA
+            if let Some(width) = self.geom.ghost_pc_width {
+                cint_data = self.with_rinv_zeta(1.0/(width*width)).initialize_cint(false);
+            }
             let orig_orig = cint_data.get_rinv_origin();
             self.geom.ghost_pc_chrg.iter().zip(self.geom.ghost_pc_pos.iter_columns_full()).for_each(|(charge, pos)| {
                 let mut tmp_out = vec![];
@@ -3114,6 +3094,49 @@ impl Molecule {
 
 }
 
+/// Check the local (auxiliary) basis sets of the given elements in `basis_path`, and fetch the missing ones from
+/// BasisSetExchange, unless in the offline mode
+pub fn prepare_local_basis(basis_path: &String, ctrl_elem: Vec<String>, auxbas: bool, ctrl: &InputKeywords, geom: &GeomCell) -> anyhow::Result<()> {
+    if ctrl_elem.len() == 0 {return Ok(())}
+    let local_elem = if ctrl.basis_offline {available_elements(basis_path)?} else {local_element_checker(basis_path)?};
+    let elem_intersection = ctrl_elem.intersect(local_elem.clone());
+    let mut required_elem = vec![];
+    for ctrl_item in ctrl_elem {
+        if !elem_intersection.contains(&ctrl_item) {
+            required_elem.push(ctrl_item)
+        }
+    }
+    if required_elem.len() == 0 {return Ok(())}
+
+    let basis_kind = if auxbas {"auxiliary basis"} else {"basis"};
+    if ctrl.basis_offline {
+        anyhow::bail!("{}", missing_elements_message(basis_kind, basis_path, &required_elem, &ctrl.basis_library));
+    }
+    let re = Regex::new(r"/?(?P<basis>[^/]*)/?$").unwrap();
+    let cap = re.captures(basis_path).unwrap();
+    let basis_name = cap.name("basis").unwrap().to_string();
+    if auxbas && ctrl.print_level > 0 {
+        println!("auxbas_name = {} from {}", &basis_name, basis_path)
+    };
+    if check_basis_name(&basis_name) {
+        if auxbas {
+            bse_downloader::bse_auxbas_getter_v2(&basis_name,&geom, basis_path, &required_elem, ctrl.print_level);
+        } else {
+            bse_downloader::bse_basis_getter_v2(&basis_name,&geom, basis_path, &required_elem);
+        }
+    } else {
+        if ctrl.print_level > 0 {
+            println!("Error: Missing local {} sets for {:?}", basis_kind, &required_elem);
+        }
+        let matched = basis_fuzzy_matcher(&basis_name);
+        match matched {
+            Some(_) => panic!("{} may not be a valid basis set name, similar name is {}", basis_name, matched.unwrap()),
+            None => panic!("{} may not be a valid basis set name, please check.", basis_name),
+        }
+    }
+    Ok(())
+}
+
 pub fn generate_ri3fn_from_rimatr(rimatr: &MatrixFull<f64>, basbas2baspar: &MatrixFull<usize>, baspar2basbas: &Vec<[usize;2]>) -> RIFull<f64> {
     let n_basis = basbas2baspar.size[0];
     let n_baspar = rimatr.size[0];
@@ -3318,3 +3341,31 @@ fn test_matrixupper() {
 }
 
 
+
+#[test]
+fn test_offline_missing_basis_error() {
+    use crate::ctrl_io::InputKeywords;
+    // only the basis set of H is available in the local folder
+    let basis_dir = std::env::temp_dir().join(format!("rest_offline_basis_{}", std::process::id()));
+    std::fs::create_dir_all(&basis_dir).unwrap();
+    std::fs::copy("basis-set-pool/def2-SVP/H.json", basis_dir.join("H.json")).unwrap();
+    let basis_path = basis_dir.to_str().unwrap().to_string();
+    let ctrl_str = format!("[ctrl]
+        print_level = 0
+        xc = \"hf\"
+        basis_path = \"{}\"
+        auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+        basis_offline = true
+        [geom]
+        name = \"H2O\"
+        unit = \"Angstrom\"
+        position = \"\"\"
+            O   0.000   0.000   0.120
+            H   0.000   0.760  -0.480
+            H   0.000  -0.760  -0.480\"\"\"", basis_path);
+    let tmp_keys = toml::from_str::<serde_json::Value>(&ctrl_str).unwrap();
+    let (ctrl, geom) = InputKeywords::parse_ctl_from_json(&tmp_keys).unwrap();
+    let err = Molecule::build_native(ctrl, geom, None).err().unwrap().to_string();
+    assert!(err.contains(&format!("{}/O.json", basis_path)), "{}", err);
+    std::fs::remove_dir_all(&basis_dir).unwrap();
+}
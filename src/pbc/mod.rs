@@ -0,0 +1,835 @@
+//! Gamma-point periodic boundary conditions for the Gaussian-basis DFT calculations.
+//!
+//! The Coulomb interactions in the crystal are evaluated with the periodic kernel, split by the
+//! Ewald technique[^1]:
+//! ```text
+//!   v(r) = \sum_L erfc(w|r+L|)/|r+L| + (4pi/V) \sum_{G!=0} exp(-G^2/4w^2)/G^2 exp(iGr) - pi/(V w^2)
+//! ```
+//! which does not depend on `w` and is exact for neutral cells. The short-range (SR) part is
+//! evaluated by libcint over the lattice images of the basis functions, the auxiliary functions and
+//! the nuclei, which are represented by steep Gaussian charges. The long-range (LR) part is evaluated
+//! in the reciprocal space with the analytic Fourier transforms of the auxiliary functions and of the
+//! nuclei, and is integrated with the lattice-summed basis functions on the periodic Becke grids.
+//!
+//! The three-center integrals `(ij|P)` and the Coulomb metric `(P|Q)` of the auxiliary basis give the
+//! same `rimatr` (or `ri3fn`) as in the molecular calculations, so that the RI-J procedure is used
+//! without change for the pure functionals.
+//!
+//! The scope is limited to the LDA and GGA functionals. HF and the hybrid functionals are out of scope:
+//! the Gamma-point exact exchange would need the lattice-summed exchange integrals and the Madelung
+//! correction of the G=0 term to converge with the size of the cell, neither of which is implemented.
+//! They are rejected by [`check_periodic_support`] instead of being evaluated with the molecular kernel.
+//! Slabs and molecular crystals are treated as three-dimensional crystals, for which enough vacuum
+//! should be included along the non-periodic directions.
+//!
+//! [^1]: P. P. Ewald, Ann. Phys. 369, 253 (1921).
+use rayon::prelude::*;
+use rest_libcint::{CINTR2CDATA, CintType};
+use std::f64::consts::{PI, SQRT_2};
+use std::sync::mpsc::channel;
+use tensors::matrix_blas_lapack::{_dgemm_full, _power_rayon_for_symmetric_matrix};
+use tensors::{MatrixFull, MatrixUpper};
+use crate::basis_io::{cint_norm_factor, spheric_gto_1st_value_serial, spheric_gto_value_serial, Basis4Elem};
+use crate::constants::{c2s_matrix_const, cartesian_gto_const, AUXBAS_THRESHOLD, PTR_RANGE_OMEGA};
+use crate::dft::Grids;
+use crate::geom_io::{get_mass_charge, GeomCell};
+use crate::molecule_io::Molecule;
+use crate::mpi_io::MPIOperator;
+use crate::utilities;
+use crate::utilities::linear_algebra::vec3::{add, cross, dot, norm, sub};
+
+/// The exponent of the Gaussian charge distributions that represent the point nuclei
+/// in the short-range three-center integrals
+const NUCLEAR_EXPONENT: f64 = 1.0e16;
+
+/// The number of grid points in a batch for the long-range integrals
+const GRID_BATCH: usize = 1024;
+
+#[derive(Clone, Debug)]
+pub struct Lattice {
+    /// the lattice vectors `a_i` in Bohr
+    pub vectors: [[f64;3];3],
+    /// the reciprocal vectors `b_i` with `a_i b_j = 2pi delta_ij`
+    pub reciprocal: [[f64;3];3],
+    /// the volume of the cell
+    pub volume: f64,
+}
+
+impl Lattice {
+    /// The lattice from the matrix in `GeomCell`, whose columns are the lattice vectors
+    pub fn new(lattice: &MatrixFull<f64>) -> Lattice {
+        let mut vectors = [[0.0;3];3];
+        (0..3).for_each(|j| (0..3).for_each(|i| vectors[j][i] = lattice[[i,j]]));
+        Lattice::from_vectors(vectors)
+    }
+
+    pub fn from_vectors(vectors: [[f64;3];3]) -> Lattice {
+        let det = dot(&vectors[0], &cross(&vectors[1], &vectors[2]));
+        if det.abs() < 1.0e-8 {
+            panic!("Error: the lattice vectors are linearly dependent: {:?}", &vectors);
+        }
+        let mut reciprocal = [[0.0;3];3];
+        (0..3).for_each(|i| {
+            let b = cross(&vectors[(i+1)%3], &vectors[(i+2)%3]);
+            (0..3).for_each(|x| reciprocal[i][x] = 2.0*PI*b[x]/det);
+        });
+        Lattice {vectors, reciprocal, volume: det.abs()}
+    }
+
+    pub fn translation(&self, n: [i32;3]) -> [f64;3] {
+        let mut l = [0.0;3];
+        (0..3).for_each(|i| (0..3).for_each(|x| l[x] += n[i] as f64*self.vectors[i][x]));
+        l
+    }
+
+    /// The lattice translations `L` with `|R_b + L - R_a| <= radius` for any atoms `a` and `b` in the cell,
+    /// sorted by the length with the zero translation first
+    pub fn translations(&self, positions: &[[f64;3]], radius: f64) -> Vec<[f64;3]> {
+        let span = positions.iter().fold(0.0_f64, |acc, ra| {
+            positions.iter().fold(acc, |acc, rb| acc.max(distance(ra, rb)))
+        });
+        let n_max: Vec<i32> = self.reciprocal.iter()
+            .map(|b| ((radius + span)*norm(b)/(2.0*PI)).ceil() as i32).collect();
+        let mut translations: Vec<[f64;3]> = vec![];
+        for n0 in -n_max[0]..=n_max[0] {
+            for n1 in -n_max[1]..=n_max[1] {
+                for n2 in -n_max[2]..=n_max[2] {
+                    let l = self.translation([n0, n1, n2]);
+                    let is_near = positions.iter().any(|ra| positions.iter().any(|rb| {
+                        distance(ra, &add(rb, &l)) <= radius
+                    }));
+                    if is_near {translations.push(l)}
+                }
+            }
+        }
+        translations.sort_by(|a, b| norm(a).partial_cmp(&norm(b)).unwrap());
+        translations
+    }
+
+    /// The reciprocal vectors with `0 < |G| <= g_max`. Only one of `G` and `-G` is included.
+    pub fn reciprocal_vectors(&self, g_max: f64) -> Vec<[f64;3]> {
+        let n_max: Vec<i32> = self.vectors.iter().map(|a| (g_max*norm(a)/(2.0*PI)).ceil() as i32).collect();
+        let mut g_vectors: Vec<[f64;3]> = vec![];
+        for n0 in 0..=n_max[0] {
+            for n1 in -n_max[1]..=n_max[1] {
+                for n2 in -n_max[2]..=n_max[2] {
+                    let is_half = n0 > 0 || (n0 == 0 && n1 > 0) || (n0 == 0 && n1 == 0 && n2 > 0);
+                    if !is_half {continue}
+                    let mut g = [0.0;3];
+                    [n0, n1, n2].iter().zip(self.reciprocal.iter()).for_each(|(n, b)| {
+                        (0..3).for_each(|x| g[x] += *n as f64*b[x]);
+                    });
+                    if norm(&g) <= g_max {g_vectors.push(g)}
+                }
+            }
+        }
+        g_vectors
+    }
+}
+
+/// The Ewald sum of the nuclear repulsion energy per cell
+pub fn ewald_energy(lattice: &Lattice, charges: &[f64], positions: &[[f64;3]], omega: f64, precision: f64) -> f64 {
+    let log_prec = -precision.ln();
+    let r_cut = log_prec.sqrt()/omega;
+    let g_max = 2.0*omega*log_prec.sqrt();
+
+    let mut e_real = 0.0;
+    lattice.translations(positions, r_cut).iter().for_each(|l| {
+        charges.iter().zip(positions.iter()).for_each(|(z_a, r_a)| {
+            charges.iter().zip(positions.iter()).for_each(|(z_b, r_b)| {
+                let d = distance(r_a, &add(r_b, l));
+                if d > 1.0e-10 && d <= r_cut {
+                    e_real += 0.5*z_a*z_b*libm::erfc(omega*d)/d;
+                }
+            });
+        });
+    });
+
+    let e_recip = lattice.reciprocal_vectors(g_max).iter().fold(0.0, |acc, g| {
+        let g2 = dot(g, g);
+        let (s_cos, s_sin) = charges.iter().zip(positions.iter()).fold((0.0, 0.0), |(c, s), (z, r)| {
+            let gr = dot(g, r);
+            (c + z*gr.cos(), s + z*gr.sin())
+        });
+        acc + (-0.25*g2/(omega*omega)).exp()/g2*(s_cos*s_cos + s_sin*s_sin)
+    })*4.0*PI/lattice.volume;
+
+    let e_self = -omega/PI.sqrt()*charges.iter().fold(0.0, |acc, z| acc + z*z);
+    let total_charge: f64 = charges.iter().sum();
+    let e_background = -PI*total_charge*total_charge/(2.0*lattice.volume*omega*omega);
+
+    e_real + e_recip + e_self + e_background
+}
+
+/// The lattice-summed one-electron integrals and the RI three-center integrals at the Gamma point
+pub struct PeriodicIntegrals {
+    pub nuc_energy: f64,
+    pub ovlp: MatrixUpper<f64>,
+    pub h_core: MatrixUpper<f64>,
+    pub rimatr: (MatrixFull<f64>, MatrixFull<usize>, Vec<[usize;2]>),
+}
+
+/// A shell of the cell in the supercell: the index of its copy in the i-th image is `first + i*stride`
+#[derive(Clone, Copy, Debug)]
+struct ImageShell {
+    first: usize,
+    stride: usize,
+    atom: usize,
+    alpha_min: f64,
+    start: usize,
+    len: usize,
+}
+
+impl ImageShell {
+    fn index(&self, image: usize) -> i32 {
+        (self.first + image*self.stride) as i32
+    }
+}
+
+/// The images of the basis functions, the auxiliary functions and the nuclei for libcint,
+/// where the two-electron integrals are evaluated with the short-range operator erfc(w*r)/r
+struct Supercell {
+    atm: Vec<Vec<i32>>,
+    bas: Vec<Vec<i32>>,
+    env: Vec<f64>,
+    cint_type: CintType,
+    positions: Vec<[f64;3]>,
+    translations: Vec<[f64;3]>,
+    basis: Vec<ImageShell>,
+    auxbas: Vec<ImageShell>,
+    nuclei: Vec<ImageShell>,
+}
+
+impl Supercell {
+    fn build(mol: &Molecule, positions: &[[f64;3]], translations: Vec<[f64;3]>, omega: f64) -> Supercell {
+        let num_atoms = mol.cint_atm.len();
+        let num_images = translations.len();
+        let num_shells = mol.cint_bas.len();
+        let num_aux_shells = mol.cint_aux_bas.len();
+
+        let mut env = mol.cint_env.clone();
+        env.extend(mol.cint_aux_env.iter());
+        env[PTR_RANGE_OMEGA] = -omega;
+        // the nuclei as the normalized s-type Gaussian charge distributions
+        let ptr_nuc_exp = env.len() as i32;
+        env.push(NUCLEAR_EXPONENT);
+        env.push(2.0*PI.sqrt()*(NUCLEAR_EXPONENT/PI).powf(1.5));
+
+        let mut atm: Vec<Vec<i32>> = vec![];
+        translations.iter().for_each(|l| {
+            mol.cint_atm.iter().zip(positions.iter()).for_each(|(cell_atm, r)| {
+                let mut image_atm = cell_atm.clone();
+                image_atm[1] = env.len() as i32;
+                env.extend(add(r, l).iter());
+                atm.push(image_atm);
+            });
+        });
+
+        let mut bas: Vec<Vec<i32>> = vec![];
+        let shift_atom = |shell: &Vec<i32>, image: usize| {
+            let mut image_shell = shell.clone();
+            image_shell[0] += (image*num_atoms) as i32;
+            image_shell
+        };
+        (0..num_images).for_each(|t| bas.extend(mol.cint_bas.iter().map(|shell| shift_atom(shell, t))));
+        (0..num_images).for_each(|t| bas.extend(mol.cint_aux_bas.iter().map(|shell| shift_atom(shell, t))));
+        (0..num_images).for_each(|t| (0..num_atoms).for_each(|a| {
+            bas.push(vec![(t*num_atoms + a) as i32, 0, 1, 1, 0, ptr_nuc_exp, ptr_nuc_exp+1, 0]);
+        }));
+
+        let alpha_min = |shell: &Vec<i32>| {
+            let ptr_exp = shell[5] as usize;
+            env[ptr_exp..ptr_exp+shell[2] as usize].iter().fold(f64::MAX, |acc, a| acc.min(*a))
+        };
+        let basis = mol.cint_bas.iter().zip(mol.cint_fdqc.iter()).enumerate().map(|(i, (shell, fdqc))| {
+            ImageShell {first: i, stride: num_shells, atom: shell[0] as usize, alpha_min: alpha_min(shell), start: fdqc[0], len: fdqc[1]}
+        }).collect();
+        let auxbas = mol.cint_aux_bas.iter().zip(mol.cint_aux_fdqc.iter()).enumerate().map(|(k, (shell, fdqc))| {
+            ImageShell {first: num_images*num_shells + k, stride: num_aux_shells, atom: shell[0] as usize,
+                alpha_min: alpha_min(shell), start: fdqc[0], len: fdqc[1]}
+        }).collect();
+        let nuclei = (0..num_atoms).map(|a| {
+            ImageShell {first: num_images*(num_shells + num_aux_shells) + a, stride: num_atoms, atom: a,
+                alpha_min: NUCLEAR_EXPONENT, start: mol.num_auxbas + a, len: 1}
+        }).collect();
+
+        Supercell {atm, bas, env, cint_type: mol.cint_type.clone(), positions: positions.to_vec(), translations, basis, auxbas, nuclei}
+    }
+
+    fn cint_data(&self) -> CINTR2CDATA {
+        let mut cint_data = CINTR2CDATA::new();
+        cint_data.set_cint_type(&self.cint_type);
+        cint_data.initial_r2c(&self.atm, self.atm.len() as i32, &self.bas, self.bas.len() as i32, &self.env);
+        cint_data
+    }
+
+    fn center(&self, shell: &ImageShell, image: usize) -> [f64;3] {
+        add(&self.positions[shell.atom], &self.translations[image])
+    }
+
+    /// The significant pairs of the shell `i` in the cell and the images of the shell `j`:
+    /// the image, the center and the extent of the product of the most diffuse primitives
+    fn shell_pairs(&self, i: &ImageShell, j: &ImageShell, log_prec: f64) -> Vec<(usize, [f64;3], f64)> {
+        let (a, b) = (i.alpha_min, j.alpha_min);
+        let p = a + b;
+        let r_i = self.center(i, 0);
+        (0..self.translations.len()).filter_map(|t| {
+            let r_j = self.center(j, t);
+            let r2 = dot(&sub(&r_i, &r_j), &sub(&r_i, &r_j));
+            if a*b/p*r2 > log_prec {return None}
+            let mut center = [0.0;3];
+            (0..3).for_each(|x| center[x] = (a*r_i[x] + b*r_j[x])/p);
+            Some((t, center, (log_prec/p).sqrt()))
+        }).collect()
+    }
+
+    /// The lattice-summed one-electron integrals in the upper-triangle format
+    fn one_electron(&self, op_name: &str, num_basis: usize, log_prec: f64) -> Vec<f64> {
+        let op_name = op_name.to_string();
+        let (sender, receiver) = channel();
+        self.basis.par_iter().for_each_with(sender, |s, sj| {
+            let mut cint_data = self.cint_data();
+            let upper_start = sj.start*(sj.start+1)/2;
+            let upper_end = (sj.start+sj.len)*(sj.start+sj.len+1)/2;
+            let mut loc_upper = vec![0.0; upper_end - upper_start];
+            self.basis.iter().filter(|si| si.start <= sj.start).for_each(|si| {
+                self.shell_pairs(si, sj, log_prec).iter().for_each(|(t, _, _)| {
+                    let buf = cint_data.cint_ij(si.index(0), sj.index(*t), &op_name);
+                    for loc_j in 0..sj.len {
+                        let gj = sj.start + loc_j;
+                        for loc_i in 0..si.len {
+                            let gi = si.start + loc_i;
+                            if gi > gj {break}
+                            loc_upper[(gj+1)*gj/2 + gi - upper_start] += buf[loc_j*si.len + loc_i];
+                        }
+                    }
+                });
+            });
+            cint_data.final_c2r();
+            s.send((upper_start, loc_upper)).unwrap()
+        });
+        let mut upper = vec![0.0; (num_basis+1)*num_basis/2];
+        receiver.into_iter().for_each(|(upper_start, loc_upper)| {
+            upper[upper_start..upper_start+loc_upper.len()].iter_mut().zip(loc_upper.iter()).for_each(|(to, from)| *to = *from);
+        });
+        upper
+    }
+
+    /// The lattice-summed short-range three-center integrals `(ij|P)` in the format of [n_baspar, n_auxbas + n_atom],
+    /// where the last columns are for the nuclei with unit charges
+    fn three_center(&self, num_basis: usize, num_columns: usize, log_prec: f64, r_sr: f64) -> MatrixFull<f64> {
+        let num_baspar = (num_basis+1)*num_basis/2;
+        let columns: Vec<&ImageShell> = self.auxbas.iter().chain(self.nuclei.iter()).collect();
+        let (sender, receiver) = channel();
+        self.basis.par_iter().for_each_with(sender, |s, sj| {
+            let mut cint_data = self.cint_data();
+            let upper_start = sj.start*(sj.start+1)/2;
+            let upper_end = (sj.start+sj.len)*(sj.start+sj.len+1)/2;
+            let num_rows = upper_end - upper_start;
+            let mut loc_ri = MatrixFull::new([num_rows, num_columns], 0.0);
+            self.basis.iter().filter(|si| si.start <= sj.start).for_each(|si| {
+                self.shell_pairs(si, sj, log_prec).iter().for_each(|(t1, center, extent)| {
+                    columns.iter().for_each(|sk| {
+                        let max_dist = r_sr + extent + (log_prec/sk.alpha_min).sqrt();
+                        (0..self.translations.len()).for_each(|t2| {
+                            if distance(center, &self.center(sk, t2)) > max_dist {return}
+                            let buf = cint_data.cint_3c2e(si.index(0), sj.index(*t1), sk.index(t2));
+                            for loc_k in 0..sk.len {
+                                let col = &mut loc_ri.data[(sk.start + loc_k)*num_rows..(sk.start + loc_k + 1)*num_rows];
+                                let buf_k = &buf[loc_k*si.len*sj.len..(loc_k+1)*si.len*sj.len];
+                                for loc_j in 0..sj.len {
+                                    let gj = sj.start + loc_j;
+                                    for loc_i in 0..si.len {
+                                        let gi = si.start + loc_i;
+                                        if gi > gj {break}
+                                        col[(gj+1)*gj/2 + gi - upper_start] += buf_k[loc_j*si.len + loc_i];
+                                    }
+                                }
+                            }
+                        });
+                    });
+                });
+            });
+            cint_data.final_c2r();
+            s.send((upper_start, loc_ri)).unwrap()
+        });
+        let mut ri = MatrixFull::new([num_baspar, num_columns], 0.0);
+        receiver.into_iter().for_each(|(upper_start, loc_ri)| {
+            let num_rows = loc_ri.size[0];
+            ri.copy_from_matr(upper_start..upper_start+num_rows, 0..num_columns, &loc_ri, 0..num_rows, 0..num_columns);
+        });
+        ri
+    }
+
+    /// The lattice-summed short-range Coulomb metric `(P|Q)` of the auxiliary basis
+    fn two_center(&self, num_auxbas: usize, log_prec: f64, r_sr: f64) -> MatrixFull<f64> {
+        let (sender, receiver) = channel();
+        self.auxbas.par_iter().for_each_with(sender, |s, sq| {
+            let mut cint_data = self.cint_data();
+            let mut loc_v = MatrixFull::new([num_auxbas, sq.len], 0.0);
+            self.auxbas.iter().for_each(|sp| {
+                let max_dist = r_sr + (log_prec/sp.alpha_min).sqrt() + (log_prec/sq.alpha_min).sqrt();
+                (0..self.translations.len()).for_each(|t| {
+                    if distance(&self.center(sp, 0), &self.center(sq, t)) > max_dist {return}
+                    let buf = cint_data.cint_2c2e(sp.index(0), sq.index(t));
+                    for loc_q in 0..sq.len {
+                        for loc_p in 0..sp.len {
+                            loc_v[[sp.start + loc_p, loc_q]] += buf[loc_q*sp.len + loc_p];
+                        }
+                    }
+                });
+            });
+            cint_data.final_c2r();
+            s.send((sq.start, loc_v)).unwrap()
+        });
+        let mut aux_v = MatrixFull::new([num_auxbas, num_auxbas], 0.0);
+        receiver.into_iter().for_each(|(start, loc_v)| {
+            let len = loc_v.size[1];
+            aux_v.copy_from_matr(0..num_auxbas, start..start+len, &loc_v, 0..num_auxbas, 0..len);
+        });
+        aux_v
+    }
+}
+
+/// The Fourier transforms `A exp(-i phi)` of the auxiliary functions on the given reciprocal vectors,
+/// returned as `A cos(phi)` and `A sin(phi)` in the format of [n_g, n_auxbas + n_atom], where the last columns
+/// are for the nuclei with unit charges. The total charges of the columns are also returned.
+fn fourier_components(mol: &Molecule, positions: &[[f64;3]], g_vectors: &[[f64;3]]) -> (MatrixFull<f64>, MatrixFull<f64>, Vec<f64>) {
+    let num_g = g_vectors.len();
+    let num_auxbas = mol.num_auxbas;
+    let num_columns = num_auxbas + positions.len();
+    let mut u_cos = MatrixFull::new([num_g, num_columns], 0.0);
+    let mut u_sin = MatrixFull::new([num_g, num_columns], 0.0);
+    let mut charges = vec![0.0; num_columns];
+
+    // For a real solid harmonic S_lm times exp(-a r^2) centered at R, the Fourier transform is
+    //   (pi/a)^{3/2} (2a)^{-l} S_lm(G) exp(-G^2/4a) exp(-i(G.R + l pi/2))
+    mol.auxbas4elem.iter().zip(positions.iter()).for_each(|(elem, r)| {
+        let mut start = elem.global_index.0;
+        elem.electron_shells.iter().for_each(|shell| {
+            let l = shell.angular_momentum[0] as usize;
+            let c2s = c2s_matrix_const(l);
+            let c2s = c2s.to_matrixfullslice();
+            let car_info = cartesian_gto_const(l);
+            let car_info = car_info.to_matrixfullslice();
+            let c_len = (l+1)*(l+2)/2;
+            let s_len = 2*l+1;
+            let solid_harmonics = |g: &[f64;3]| -> Vec<f64> {
+                let cart: Vec<f64> = (0..c_len).map(|c| {
+                    let info = &car_info.data[4*c..4*c+4];
+                    info[3]*g[0].powi(info[0] as i32)*g[1].powi(info[1] as i32)*g[2].powi(info[2] as i32)
+                }).collect();
+                (0..s_len).map(|m| (0..c_len).fold(0.0, |acc, c| acc + c2s.data[m*c_len + c]*cart[c])).collect()
+            };
+            shell.coefficients.iter().for_each(|coeff| {
+                let radial = |g2: f64| coeff.iter().zip(shell.exponents.iter()).fold(0.0, |acc, (c, a)| {
+                    let norm = c*cint_norm_factor(l as i32, *a)*(2.0*a/PI).powf(0.75)*(4.0*a).powf(0.5*l as f64);
+                    acc + norm*(PI/a).powf(1.5)*(2.0*a).powi(-(l as i32))*(-0.25*g2/a).exp()
+                });
+                if l == 0 {charges[start] = radial(0.0)*solid_harmonics(&[0.0;3])[0]}
+                g_vectors.iter().enumerate().for_each(|(ig, g)| {
+                    let amp = radial(dot(g, g));
+                    let phase = dot(g, r) + 0.5*PI*l as f64;
+                    solid_harmonics(g).iter().enumerate().for_each(|(m, s)| {
+                        u_cos[[ig, start + m]] = amp*s*phase.cos();
+                        u_sin[[ig, start + m]] = amp*s*phase.sin();
+                    });
+                });
+                start += s_len;
+            });
+        });
+    });
+    positions.iter().enumerate().for_each(|(a, r)| {
+        charges[num_auxbas + a] = 1.0;
+        g_vectors.iter().enumerate().for_each(|(ig, g)| {
+            u_cos[[ig, num_auxbas + a]] = dot(g, r).cos();
+            u_sin[[ig, num_auxbas + a]] = dot(g, r).sin();
+        });
+    });
+    (u_cos, u_sin, charges)
+}
+
+/// The long-range three-center integrals `\int_cell dr i(r) j(r) V_P(r)` with the lattice-summed basis functions
+/// on the periodic grids, where `V_P(r) = \sum_G cos(Gr) w_cos[G,P] + sin(Gr) w_sin[G,P]`
+fn long_range_three_center(mol: &Molecule, grids: &Grids, g_vectors: &[[f64;3]], w_cos: &MatrixFull<f64>, w_sin: &MatrixFull<f64>) -> MatrixFull<f64> {
+    let num_basis = mol.num_basis;
+    let num_baspar = (num_basis+1)*num_basis/2;
+    let num_g = g_vectors.len();
+    let num_columns = w_cos.size[1];
+    let images = ao_image_translations(mol);
+    let positions = cell_positions(&mol.geom);
+
+    let mut ri = MatrixFull::new([num_baspar, num_columns], 0.0);
+    let num_grids = grids.coordinates.len();
+    (0..num_grids).step_by(GRID_BATCH).for_each(|batch_start| {
+        let batch_end = (batch_start + GRID_BATCH).min(num_grids);
+        let nb = batch_end - batch_start;
+        let coordinates = &grids.coordinates[batch_start..batch_end];
+
+        let mut cos_gr = MatrixFull::new([nb, num_g], 0.0);
+        let mut sin_gr = MatrixFull::new([nb, num_g], 0.0);
+        cos_gr.data.par_chunks_mut(nb).zip(sin_gr.data.par_chunks_mut(nb)).zip(g_vectors.par_iter())
+            .for_each(|((cos_g, sin_g), g)| {
+            coordinates.iter().enumerate().for_each(|(p, r)| {
+                let gr = dot(g, r);
+                cos_g[p] = gr.cos();
+                sin_g[p] = gr.sin();
+            });
+        });
+        let mut potential = MatrixFull::new([nb, num_columns], 0.0);
+        _dgemm_full(&cos_gr, 'N', w_cos, 'N', &mut potential, 1.0, 0.0);
+        _dgemm_full(&sin_gr, 'N', w_sin, 'N', &mut potential, 1.0, 1.0);
+
+        let mut ao = MatrixFull::new([num_basis, nb], 0.0);
+        let (sender, receiver) = channel();
+        mol.basis4elem.par_iter().zip(positions.par_iter()).zip(images.par_iter()).for_each_with(sender, |s, ((elem, r), images)| {
+            s.send((elem.global_index, image_gto_value(coordinates, r, images, elem))).unwrap()
+        });
+        receiver.into_iter().for_each(|((start, len), tab_den)| {
+            ao.copy_from_matr(start..start+len, 0..nb, &tab_den, 0..len, 0..nb);
+        });
+
+        let mut ao_pair = MatrixFull::new([num_baspar, nb], 0.0);
+        ao_pair.data.par_chunks_mut(num_baspar).enumerate().for_each(|(p, pair)| {
+            let ao_p = &ao.data[p*num_basis..(p+1)*num_basis];
+            let weight = grids.weights[batch_start + p];
+            (0..num_basis).for_each(|j| {
+                let ao_j = ao_p[j]*weight;
+                let pair_j = &mut pair[(j+1)*j/2..(j+1)*j/2+j+1];
+                pair_j.iter_mut().zip(ao_p[..j+1].iter()).for_each(|(to, ao_i)| *to = ao_i*ao_j);
+            });
+        });
+        _dgemm_full(&ao_pair, 'N', &potential, 'N', &mut ri, 1.0, 1.0);
+    });
+    ri
+}
+
+/// Prepare the lattice-summed integrals for the Gamma-point periodic calculations:
+/// the Ewald nuclear repulsion, the overlap, the core Hamiltonian, and the periodic RI three-center integrals
+pub fn prepare_periodic_integrals(mol: &mut Molecule, mpi_operator: &Option<MPIOperator>) -> PeriodicIntegrals {
+    if let Some(mpi_op) = mpi_operator {
+        if mpi_op.size > 1 {
+            panic!("The periodic calculations are not yet parallelized with MPI. Please use one MPI process");
+        }
+    }
+    let print_level = mol.ctrl.print_level;
+    let precision = mol.ctrl.pbc_precision;
+    let omega = mol.ctrl.pbc_ewald_omega;
+    let log_prec = -precision.ln();
+    let num_basis = mol.num_basis;
+    let num_auxbas = mol.num_auxbas;
+    let num_baspar = (num_basis+1)*num_basis/2;
+
+    let lattice = Lattice::new(&mol.geom.lattice);
+    let positions = cell_positions(&mol.geom);
+    let nuc_charges: Vec<f64> = mol.cint_atm.iter().map(|atm| atm[0] as f64).collect();
+    let num_columns = num_auxbas + positions.len();
+
+    let nuc_energy = ewald_energy(&lattice, &nuc_charges, &positions, omega, precision);
+
+    let mut time_records = utilities::TimeRecords::new();
+    time_records.new_item("pbc sr", "for the short-range lattice sums");
+    time_records.new_item("pbc lr", "for the long-range integrals in the reciprocal space");
+
+    // the short-range parts over the lattice images
+    time_records.count_start("pbc sr");
+    let alpha_min = |bas: &Vec<Vec<i32>>, env: &Vec<f64>, offset: usize| bas.iter().fold(f64::MAX, |acc, shell| {
+        let ptr_exp = shell[5] as usize - offset;
+        env[ptr_exp..ptr_exp+shell[2] as usize].iter().fold(acc, |acc, a| acc.min(*a))
+    });
+    let ext_basis = (log_prec/alpha_min(&mol.cint_bas, &mol.cint_env, 0)).sqrt();
+    let ext_auxbas = (log_prec/alpha_min(&mol.cint_aux_bas, &mol.cint_aux_env, mol.cint_env.len())).sqrt();
+    let r_sr = log_prec.sqrt()/omega;
+    let translations = lattice.translations(&positions, (1.0 + SQRT_2)*ext_basis + r_sr + ext_auxbas);
+    let supercell = Supercell::build(mol, &positions, translations, omega);
+
+    let ovlp = supercell.one_electron("ovlp", num_basis, log_prec);
+    let kinetic = supercell.one_electron("kinetic", num_basis, log_prec);
+    let mut ri3c = supercell.three_center(num_basis, num_columns, log_prec, r_sr);
+    let mut aux_v = supercell.two_center(num_auxbas, log_prec, r_sr);
+    time_records.count("pbc sr");
+
+    // the long-range parts in the reciprocal space
+    time_records.count_start("pbc lr");
+    let g_vectors = lattice.reciprocal_vectors(2.0*omega*log_prec.sqrt());
+    let (u_cos, u_sin, charges) = fourier_components(mol, &positions, &g_vectors);
+    // the factor of 2 accounts for the reciprocal vectors of -G
+    let kernel: Vec<f64> = g_vectors.iter().map(|g| {
+        let g2 = dot(g, g);
+        8.0*PI/lattice.volume*(-0.25*g2/(omega*omega)).exp()/g2
+    }).collect();
+    let mut w_cos = u_cos.clone();
+    let mut w_sin = u_sin.clone();
+    w_cos.data.chunks_exact_mut(g_vectors.len()).zip(w_sin.data.chunks_exact_mut(g_vectors.len())).for_each(|(wc, ws)| {
+        wc.iter_mut().zip(ws.iter_mut()).zip(kernel.iter()).for_each(|((wc, ws), k)| {*wc *= k; *ws *= k});
+    });
+    let mut aux_v_lr = MatrixFull::new([num_columns, num_columns], 0.0);
+    _dgemm_full(&u_cos, 'T', &w_cos, 'N', &mut aux_v_lr, 1.0, 0.0);
+    _dgemm_full(&u_sin, 'T', &w_sin, 'N', &mut aux_v_lr, 1.0, 1.0);
+
+    let mpi_data = mol.mpi_data.take();
+    let grids = Grids::build(mol);
+    mol.mpi_data = mpi_data;
+    let ri3c_lr = long_range_three_center(mol, &grids, &g_vectors, &w_cos, &w_sin);
+    time_records.count("pbc lr");
+
+    // add the long-range parts and the terms of the neutralizing background
+    let background = PI/(lattice.volume*omega*omega);
+    ri3c.data.iter_mut().zip(ri3c_lr.data.iter()).enumerate().for_each(|(index, (to, lr))| {
+        let (pair, column) = (index%num_baspar, index/num_baspar);
+        *to += lr - background*ovlp[pair]*charges[column];
+    });
+    (0..num_auxbas).for_each(|q| (0..num_auxbas).for_each(|p| {
+        aux_v[[p,q]] += aux_v_lr[[p,q]] - background*charges[p]*charges[q];
+    }));
+    // symmetrize the lattice sums of the metric
+    (0..num_auxbas).for_each(|q| (0..q).for_each(|p| {
+        let v = 0.5*(aux_v[[p,q]] + aux_v[[q,p]]);
+        aux_v[[p,q]] = v;
+        aux_v[[q,p]] = v;
+    }));
+
+    // the RI three-center integrals (ij|P)(P|Q)^{-1/2}
+    let aux_v = _power_rayon_for_symmetric_matrix(&aux_v, -0.5, AUXBAS_THRESHOLD).unwrap();
+    let ri3c_aux = MatrixFull::from_vec([num_baspar, num_auxbas], ri3c.data[..num_baspar*num_auxbas].to_vec()).unwrap();
+    let mut rimatr = MatrixFull::new([num_baspar, num_auxbas], 0.0);
+    _dgemm_full(&ri3c_aux, 'N', &aux_v, 'N', &mut rimatr, 1.0, 0.0);
+    let (basbas2baspar, baspar2basbas) = mol.prepare_baspair_map();
+
+    // the core Hamiltonian with the nuclear attraction -\sum_A Z_A (ij|A)
+    let mut h_core = kinetic;
+    nuc_charges.iter().enumerate().for_each(|(a, z)| {
+        let column = &ri3c.data[(num_auxbas + a)*num_baspar..(num_auxbas + a + 1)*num_baspar];
+        h_core.iter_mut().zip(column.iter()).for_each(|(h, v)| *h -= z*v);
+    });
+
+    if print_level > 0 {
+        println!("Periodic integrals with {} lattice images and {} reciprocal vectors (G and -G counted once)",
+            supercell.translations.len(), g_vectors.len());
+        println!("Cell volume: {:16.8} Bohr^3, relative error of the overlap on the periodic grids: {:10.3e}",
+            lattice.volume, grid_overlap_error(&grids, mol, &ovlp));
+        println!("Ewald nuclear repulsion energy: {:16.8} Hartree", nuc_energy);
+    }
+    if print_level > 1 {
+        time_records.report_all();
+    }
+
+    PeriodicIntegrals {
+        nuc_energy,
+        ovlp: MatrixUpper::from_vec(num_baspar, ovlp).unwrap(),
+        h_core: MatrixUpper::from_vec(num_baspar, h_core).unwrap(),
+        rimatr: (rimatr, basbas2baspar, baspar2basbas),
+    }
+}
+
+/// The radius of the atomic grids in the cell, which is half of `pbc_grid_cutoff` but no smaller than
+/// the extent of the most diffuse basis function.
+///
+/// The image atoms within twice this radius are included in the Becke partition, so that the partition
+/// is exact for the grid points within the radius. The grid points farther away are dropped: if they
+/// belong to their own center, the basis functions of all atoms vanish there within `pbc_precision`.
+pub fn grid_radius(mol: &Molecule) -> f64 {
+    let log_prec = -mol.ctrl.pbc_precision.ln();
+    let alpha_min = mol.basis4elem.iter().fold(f64::MAX, |acc, elem| {
+        elem.electron_shells.iter().fold(acc, |acc, shell| shell.exponents.iter().fold(acc, |acc, a| acc.min(*a)))
+    });
+    (0.5*mol.ctrl.pbc_grid_cutoff).max((log_prec/alpha_min).sqrt())
+}
+
+/// The lattice translations of the basis functions on each atom that contribute to
+/// the lattice-summed basis functions on the periodic grids
+pub fn ao_image_translations(mol: &Molecule) -> Vec<Vec<[f64;3]>> {
+    let log_prec = -mol.ctrl.pbc_precision.ln();
+    let grid_radius = grid_radius(mol);
+    let lattice = Lattice::new(&mol.geom.lattice);
+    let positions = cell_positions(&mol.geom);
+    let extents: Vec<f64> = mol.basis4elem.iter().map(|elem| {
+        let alpha_min = elem.electron_shells.iter().fold(f64::MAX, |acc, shell| {
+            shell.exponents.iter().fold(acc, |acc, a| acc.min(*a))
+        });
+        (log_prec/alpha_min).sqrt() + grid_radius
+    }).collect();
+    let max_extent = extents.iter().fold(0.0_f64, |acc, e| acc.max(*e));
+    let translations = lattice.translations(&positions, max_extent);
+    positions.iter().zip(extents.iter()).map(|(r_a, extent)| {
+        translations.iter().filter(|l| {
+            let r_image = add(r_a, l);
+            positions.iter().any(|r_b| distance(&r_image, r_b) <= *extent)
+        }).cloned().collect()
+    }).collect()
+}
+
+/// The centers and the nuclear charges for the Becke partition of the periodic grids: the atoms in the cell,
+/// followed by the image atoms within `cutoff` from any atom in the cell
+pub fn grid_centers(geom: &GeomCell, cutoff: f64) -> (Vec<(f64,f64,f64)>, Vec<i32>) {
+    let lattice = Lattice::new(&geom.lattice);
+    let positions = cell_positions(geom);
+    let charges: Vec<i32> = get_mass_charge(&geom.elem).iter().map(|(_, z)| *z as i32).collect();
+    let mut centers: Vec<(f64,f64,f64)> = positions.iter().map(|r| (r[0], r[1], r[2])).collect();
+    let mut center_charges = charges.clone();
+    lattice.translations(&positions, cutoff).iter().skip(1).for_each(|l| {
+        positions.iter().zip(charges.iter()).for_each(|(r_a, z)| {
+            let r_image = add(r_a, l);
+            if positions.iter().any(|r_b| distance(&r_image, r_b) <= cutoff) {
+                centers.push((r_image[0], r_image[1], r_image[2]));
+                center_charges.push(*z);
+            }
+        });
+    });
+    (centers, center_charges)
+}
+
+/// The basis functions of an atom summed over the given lattice translations, in the format of [n_bas, n_grids]
+pub fn image_gto_value(r: &[[f64;3]], gto_center: &[f64;3], translations: &[[f64;3]], bas: &Basis4Elem) -> MatrixFull<f64> {
+    let mut tab_den = spheric_gto_value_serial(r, &add(gto_center, &translations[0]), bas);
+    translations.iter().skip(1).for_each(|l| {
+        let image_den = spheric_gto_value_serial(r, &add(gto_center, l), bas);
+        tab_den.data.iter_mut().zip(image_den.data.iter()).for_each(|(to, from)| *to += from);
+    });
+    tab_den
+}
+
+/// The first derivatives of the basis functions of an atom summed over the given lattice translations
+pub fn image_gto_1st_value(r: &[[f64;3]], gto_center: &[f64;3], translations: &[[f64;3]], bas: &Basis4Elem) -> Vec<MatrixFull<f64>> {
+    let mut tab_dev = spheric_gto_1st_value_serial(r, &add(gto_center, &translations[0]), bas);
+    translations.iter().skip(1).for_each(|l| {
+        let image_dev = spheric_gto_1st_value_serial(r, &add(gto_center, l), bas);
+        tab_dev.iter_mut().zip(image_dev.iter()).for_each(|(to, from)| {
+            to.data.iter_mut().zip(from.data.iter()).for_each(|(to, from)| *to += from);
+        });
+    });
+    tab_dev
+}
+
+/// The relative error of the trace of the lattice-summed overlap integrated on the periodic grids,
+/// which indicates the quality of the grids
+fn grid_overlap_error(grids: &Grids, mol: &Molecule, ovlp: &[f64]) -> f64 {
+    let images = ao_image_translations(mol);
+    let positions = cell_positions(&mol.geom);
+    let num_basis = mol.num_basis;
+    let trace_ovlp: f64 = (0..num_basis).map(|i| ovlp[(i+1)*i/2 + i]).sum();
+    let trace_grids = grids.coordinates.par_chunks(GRID_BATCH).zip(grids.weights.par_chunks(GRID_BATCH)).map(|(coordinates, weights)| {
+        mol.basis4elem.iter().zip(positions.iter()).zip(images.iter()).fold(0.0, |acc, ((elem, r), images)| {
+            let tab_den = image_gto_value(coordinates, r, images, elem);
+            acc + tab_den.data.chunks_exact(elem.global_index.1).zip(weights.iter())
+                .fold(0.0, |acc, (ao, w)| acc + w*ao.iter().fold(0.0, |acc, v| acc + v*v))
+        })
+    }).sum::<f64>();
+    (trace_grids - trace_ovlp).abs()/trace_ovlp
+}
+
+/// Check that the calculation is supported by the Gamma-point periodic implementation, which is
+/// limited to the pure (LDA, GGA and meta-GGA) functionals with the periodic RI-J
+pub fn check_periodic_support(mol: &Molecule) -> anyhow::Result<()> {
+    if !mol.ctrl.use_auxbas || !mol.use_eri {
+        anyhow::bail!("The periodic calculations require the auxiliary basis set for the periodic RI-J. Please turn on 'use_auxbas' and 'use_ri_vj'");
+    }
+    if let CintType::Cartesian = mol.cint_type {
+        anyhow::bail!("The periodic calculations are only available with the spherical basis sets");
+    }
+    if mol.cint_ecpbas.is_some() {
+        anyhow::bail!("The effective core potentials are not yet available in the periodic calculations");
+    }
+    if mol.geom.ghost_bs_elem.len() > 0 || mol.geom.ghost_pc_chrg.len() > 0 || mol.geom.ghost_ep_path.len() > 0 {
+        anyhow::bail!("The ghost atoms, point charges and effective potentials are not yet available in the periodic calculations");
+    }
+    if !mol.xc_data.is_dfa_scf() || mol.xc_data.use_eri() {
+        // the exact exchange misses the G=0 (Madelung) correction, which converges slowly with the size of the cell
+        anyhow::bail!("Only the LDA and GGA functionals are available in the periodic calculations. HF, hybrid, range-separated hybrid and doubly hybrid functionals are not supported, as the Gamma-point exact exchange with the Madelung correction is not implemented");
+    }
+    if mol.ctrl.use_isdf || mol.ctrl.isdf_new || mol.ctrl.isdf_k_only {
+        anyhow::bail!("ISDF is not yet available in the periodic calculations");
+    }
+    if mol.ctrl.electric_field.is_some() {
+        anyhow::bail!("The homogeneous electric field is not compatible with the periodic boundary conditions");
+    }
+    if !mol.ctrl.solvent.eq("none") || mol.ctrl.solvent_eps.is_some() {
+        anyhow::bail!("The implicit solvation models are not available in the periodic calculations");
+    }
+    if mol.ctrl.charge.abs() > 1.0e-8 && mol.ctrl.print_level > 0 {
+        println!("WARNING: the charged cell is neutralized by a uniform background charge");
+    }
+    Ok(())
+}
+
+fn cell_positions(geom: &GeomCell) -> Vec<[f64;3]> {
+    geom.position.iter_columns_full().map(|r| [r[0], r[1], r[2]]).collect()
+}
+
+fn distance(a: &[f64;3], b: &[f64;3]) -> f64 {
+    norm(&sub(a, b))
+}
+
+#[test]
+fn test_ewald_madelung_nacl() {
+    // the conventional cubic cell of rock salt with four ion pairs: E = -4*M/(a/2), M = 1.747565
+    let a = 10.0;
+    let lattice = Lattice::from_vectors([[a,0.0,0.0],[0.0,a,0.0],[0.0,0.0,a]]);
+    let fcc = [[0.0,0.0,0.0],[0.0,0.5,0.5],[0.5,0.0,0.5],[0.5,0.5,0.0]];
+    let mut positions: Vec<[f64;3]> = vec![];
+    let mut charges: Vec<f64> = vec![];
+    fcc.iter().for_each(|f| {
+        positions.push([f[0]*a, f[1]*a, f[2]*a]);
+        charges.push(1.0);
+        positions.push([(f[0]+0.5)*a, f[1]*a, f[2]*a]);
+        charges.push(-1.0);
+    });
+    let reference = -8.0*1.747565/a;
+    [0.3, 0.5, 0.8].iter().for_each(|omega| {
+        let energy = ewald_energy(&lattice, &charges, &positions, *omega, 1.0e-12);
+        assert!((energy - reference).abs() < 1.0e-6);
+    });
+}
+
+#[test]
+fn test_gamma_point_energy_against_molecule() {
+    // H2 in a large cubic box, where the interactions between the images are negligible
+    use crate::ctrl_io::InputKeywords;
+    let ctrl_str = |lattice: &str| format!("[ctrl]
+        print_level = 0
+        xc = \"pbe\"
+        basis_path = \"basis-set-pool/def2-SVP\"
+        auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+        scf_acc_rho = 1.0e-9
+        scf_acc_eev = 1.0e-9
+        scf_acc_etot = 1.0e-10
+        [geom]
+        name = \"H2\"
+        unit = \"Angstrom\"
+        position = \"\"\"
+            H   5.000   5.000   4.630
+            H   5.000   5.000   5.370\"\"\"
+        {}", lattice);
+    let energies: Vec<f64> = ["", "lattice = [\"10.0 0.0 0.0\", \"0.0 10.0 0.0\", \"0.0 0.0 10.0\"]"].iter().map(|lattice| {
+        let tmp_keys = toml::from_str::<serde_json::Value>(&ctrl_str(lattice)).unwrap();
+        let (ctrl, geom) = InputKeywords::parse_ctl_from_json(&tmp_keys).unwrap();
+        let mol = Molecule::build_native(ctrl, geom, None).unwrap();
+        crate::scf_io::scf(mol, &None).unwrap().scf_energy
+    }).collect();
+    assert!((energies[0] - energies[1]).abs() < 1.0e-5, "molecule {} vs Gamma point {}", energies[0], energies[1]);
+}
+
+#[test]
+fn test_periodic_support_errors() {
+    use crate::ctrl_io::InputKeywords;
+    ["xc = \"b3lyp\"", "xc = \"pbe\"\n        electric_field = [0.0, 0.0, 0.01]", "xc = \"pbe\"\n        outputs = \"finite_field\"",
+     "xc = \"pbe\"\n        solvent = \"water\""].iter().for_each(|keywords| {
+        let ctrl_str = format!("[ctrl]
+        print_level = 0
+        {}
+        basis_path = \"basis-set-pool/def2-SVP\"
+        auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+        [geom]
+        name = \"H2\"
+        unit = \"Angstrom\"
+        position = \"\"\"
+            H   0.000   0.000   0.000
+            H   0.000   0.000   0.740\"\"\"
+        lattice = [\"10.0 0.0 0.0\", \"0.0 10.0 0.0\", \"0.0 0.0 10.0\"]", keywords);
+        let tmp_keys = toml::from_str::<serde_json::Value>(&ctrl_str).unwrap();
+        // the electric field is rejected when parsing the input, and the others when building the molecule
+        let result = InputKeywords::parse_ctl_from_json(&tmp_keys)
+            .and_then(|(ctrl, geom)| Molecule::build_native(ctrl, geom, None));
+        assert!(result.is_err(), "{}", keywords);
+    });
+}
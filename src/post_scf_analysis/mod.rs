@@ -11,7 +11,7 @@ use tensors::{MathMatrix, MatrixFull, RIFull};
 use crate::constants::{ANG, AU2DEBYE, SPECIES_INFO};
 use crate::dft::DFAFamily;
 use crate::geom_io::get_mass_charge;
-use crate::grad::{formated_force, formated_force_ev, numerical_force};
+use crate::grad::{formated_force, formated_force_ev, calc_force, point_charge_gradients_output};
 use crate::mpi_io::MPIOperator;
 use crate::ri_pt2::sbge2::{close_shell_sbge2_rayon, open_shell_sbge2_rayon, close_shell_sbge2_detailed_rayon, open_shell_sbge2_detailed_rayon};
 use crate::ri_rpa::scsrpa::{evaluate_osrpa_correlation_rayon, evaluate_spin_response_rayon, evaluate_special_radius_only};
@@ -110,12 +110,19 @@ pub fn post_scf_output(scf_data: &SCF, mpi_operator: &Option<MPIOperator>) {
                 let dp = evaluate_dipole_moment(scf_data, None);
                 println!("Dipole Moment in DEBYE: {:16.8}, {:16.8}, {:16.8}", dp[0], dp[1], dp[2]);
             }
+        } else if output_type.eq("polarizability") {
+            let polar = crate::cphf::static_polarizability(scf_data, mpi_operator).unwrap_or_else(|err| panic!("{}", err));
+            println!("Static dipole polarizability [a.u.]: ");
+            polar.iter().zip(["x","y","z"].iter()).for_each(|(polar_x, x)| {
+                println!("{:>3} {:16.8} {:16.8} {:16.8}", x, polar_x[0], polar_x[1], polar_x[2]);
+            });
+            println!("Isotropic polarizability [a.u.]: {:16.8}", (polar[0][0] + polar[1][1] + polar[2][2])/3.0);
         } else if output_type.eq("force") {
             let displace = match scf_data.mol.geom.unit {
                 crate::geom_io::GeomUnit::Angstrom => scf_data.mol.ctrl.nforce_displacement/ANG,
                 crate::geom_io::GeomUnit::Bohr => scf_data.mol.ctrl.nforce_displacement,
             };
-            let (energy, num_force) = numerical_force(scf_data, displace, mpi_operator);
+            let (energy, num_force) = calc_force(scf_data, displace, mpi_operator);
             if let Some(mpi_op) = &mpi_operator {
                 if mpi_op.rank == 0 {
                     println!("Total atomic forces [a.u.]: ");
@@ -129,6 +136,9 @@ pub fn post_scf_output(scf_data: &SCF, mpi_operator: &Option<MPIOperator>) {
                 println!("Total atomic forces [ev/ang]: ");
                 println!("{}", formated_force_ev(&num_force, &scf_data.mol.geom.elem));
             }
+            if scf_data.mol.geom.ghost_pc_chrg.len() > 0 {
+                point_charge_gradients_output(scf_data, displace, mpi_operator);
+            }
         }
     });
 }
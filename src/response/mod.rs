@@ -0,0 +1,132 @@
+//! The occupied-virtual response space shared by the linear-response methods, i.e. TDHF/TDDFT and CPHF/CPKS,
+//! together with the common checks, the exchange-correlation kernel and the dipole integrals they rely on.
+//! The Coulomb and exchange responses of the trial densities are given by [`SCF::response_fn_ks`].
+use rest_libcint::prelude::int1e_r;
+use tensors::{MathMatrix, MatrixFull, RIFull};
+use tensors::matrix_blas_lapack::_dgemm_full;
+
+use crate::dft::fxc::XcKernel;
+use crate::mpi_io::MPIOperator;
+use crate::scf_io::{SCF, SCFType};
+
+/// The occupied-virtual orbital pairs of the response equations
+pub struct ResponseSpace {
+    pub spin_channel: usize,
+    pub num_occ: [usize;2],
+    pub num_vir: [usize;2],
+    pub co: [MatrixFull<f64>;2],
+    pub cv: [MatrixFull<f64>;2],
+    /// the orbital energy differences e_a - e_i in the layout of [vir, occ] for each spin channel
+    pub de: Vec<f64>,
+}
+
+impl ResponseSpace {
+    pub fn build(scf_data: &SCF) -> ResponseSpace {
+        let spin_channel = scf_data.mol.spin_channel;
+        let num_basis = scf_data.mol.num_basis;
+        let num_state = scf_data.mol.num_state;
+        let mut num_occ = [0,0];
+        let mut num_vir = [0,0];
+        let mut co = [MatrixFull::empty(), MatrixFull::empty()];
+        let mut cv = [MatrixFull::empty(), MatrixFull::empty()];
+        let mut de = vec![];
+        for i_spin in 0..spin_channel {
+            let occ = scf_data.homo[i_spin] + 1;
+            let vir = num_state - occ;
+            let mo = &scf_data.eigenvectors[i_spin];
+            let eigenvalues = &scf_data.eigenvalues[i_spin];
+            co[i_spin] = MatrixFull::from_vec([num_basis,occ],
+                mo.iter_submatrix(0..num_basis,0..occ).map(|i| *i).collect()).unwrap();
+            cv[i_spin] = MatrixFull::from_vec([num_basis,vir],
+                mo.iter_submatrix(0..num_basis,occ..num_state).map(|i| *i).collect()).unwrap();
+            for i in 0..occ {
+                for a in occ..num_state {
+                    de.push(eigenvalues[a] - eigenvalues[i]);
+                }
+            }
+            num_occ[i_spin] = occ;
+            num_vir[i_spin] = vir;
+        }
+        ResponseSpace {spin_channel, num_occ, num_vir, co, cv, de}
+    }
+
+    pub fn len(&self) -> usize {
+        self.de.len()
+    }
+
+    /// the range of the amplitudes for the given spin channel
+    pub fn spin_range(&self, i_spin: usize) -> std::ops::Range<usize> {
+        let start = if i_spin == 0 {0} else {self.num_occ[0]*self.num_vir[0]};
+        start..start + self.num_occ[i_spin]*self.num_vir[i_spin]
+    }
+
+    /// D = Cv X Co^T for each spin channel
+    pub fn amplitude_to_ao(&self, x: &[f64]) -> Vec<MatrixFull<f64>> {
+        (0..self.spin_channel).map(|i_spin| {
+            let xmat = MatrixFull::from_vec([self.num_vir[i_spin], self.num_occ[i_spin]], x[self.spin_range(i_spin)].to_vec()).unwrap();
+            let num_basis = self.cv[i_spin].size[0];
+            let mut x_co = MatrixFull::new([self.num_vir[i_spin], num_basis], 0.0);
+            _dgemm_full(&xmat, 'N', &self.co[i_spin], 'T', &mut x_co, 1.0, 0.0);
+            let mut dm = MatrixFull::new([num_basis, num_basis], 0.0);
+            _dgemm_full(&self.cv[i_spin], 'N', &x_co, 'N', &mut dm, 1.0, 0.0);
+            dm
+        }).collect()
+    }
+
+    /// Cv^T V Co for each spin channel, which is concatenated into the amplitude layout
+    pub fn ao_to_amplitude(&self, v: &Vec<MatrixFull<f64>>) -> Vec<f64> {
+        (0..self.spin_channel).flat_map(|i_spin| {
+            let num_basis = self.cv[i_spin].size[0];
+            let mut v_co = MatrixFull::new([num_basis, self.num_occ[i_spin]], 0.0);
+            _dgemm_full(&v[i_spin], 'N', &self.co[i_spin], 'N', &mut v_co, 1.0, 0.0);
+            let mut v_vo = MatrixFull::new([self.num_vir[i_spin], self.num_occ[i_spin]], 0.0);
+            _dgemm_full(&self.cv[i_spin], 'T', &v_co, 'N', &mut v_vo, 1.0, 0.0);
+            v_vo.data
+        }).collect()
+    }
+}
+
+/// The linear-response methods rely on the RI-V three-center integrals in the full format,
+/// and are not yet available for the ROHF references and the MPI environment
+pub fn check_response_availability(scf_data: &SCF, mpi_operator: &Option<MPIOperator>, method: &str) {
+    if mpi_operator.is_some() {
+        panic!("The MPI version is not yet implemented for {}", method);
+    }
+    match scf_data.scftype {
+        SCFType::ROHF => panic!("{} is not yet implemented for the ROHF/ROKS references", method),
+        _ => {}
+    }
+    if scf_data.ri3fn.is_none() {
+        panic!("{} relies on the RI-V three-center integrals. Please use eri_type = 'ri_v' with use_ri_symm = false", method);
+    }
+}
+
+/// The exchange-correlation kernel of the scf functional, which is None for HF.
+/// The VV10 non-local correlation is neglected in the kernel with a warning
+pub fn build_xc_kernel(scf_data: &SCF) -> anyhow::Result<Option<XcKernel>> {
+    if ! scf_data.mol.xc_data.is_dfa_scf() {return Ok(None)}
+    if scf_data.vv10_coeff().is_some() {
+        println!("WARNING: the VV10 non-local correlation is not included in the exchange-correlation kernel of the linear response");
+    }
+    let grids = scf_data.grids.as_ref().unwrap();
+    let dm_ab = if scf_data.mol.spin_channel == 1 {
+        let mut dm_a = scf_data.density_matrix[0].clone();
+        dm_a.data.iter_mut().for_each(|d| *d *= 0.5);
+        [dm_a.clone(), dm_a]
+    } else {
+        [scf_data.density_matrix[0].clone(), scf_data.density_matrix[1].clone()]
+    };
+    Ok(Some(XcKernel::build(&scf_data.mol.xc_data, grids, &dm_ab)?))
+}
+
+/// The dipole integrals <u|r|v> in the atomic-orbital basis with the origin of libcint
+pub fn ao_dipole_integrals(scf_data: &SCF) -> [MatrixFull<f64>;3] {
+    let num_basis = scf_data.mol.num_basis;
+    let mut cint_data = scf_data.mol.initialize_cint(false);
+    let (out, out_shape) = cint_data.integral_s1::<int1e_r>(None);
+    let ao_dip = RIFull::from_vec(out_shape.try_into().unwrap(), out).unwrap();
+    [0usize,1usize,2usize].map(|x| {
+        let ao_dip_x = ao_dip.get_reducing_matrix(x).unwrap();
+        MatrixFull::from_vec([num_basis, num_basis], ao_dip_x.iter_columns_full().flat_map(|col| col.iter().cloned()).collect()).unwrap()
+    })
+}
@@ -1,4 +1,7 @@
-use crate::scf_io::SCF;
+use crate::scf_io::{SCF, SCFType};
+use crate::check_norm::OCCType;
+use crate::mpi_io::MPIOperator;
+use crate::dft::fxc::XcKernel;
 use crate::scf_io::{vj_upper_with_ri_v, vj_full_with_ri_v,
                     vk_full_fromdm_with_ri_v};
 use rest_tensors::{
@@ -9,9 +12,10 @@ use rest_tensors::{
     //TensorSliceMut, RIFull, MatrixFullSlice, MatrixFullSliceMut
     };
 use rest_tensors::{davidson_solve, DavidsonParams};
+use rest_tensors::matrix_blas_lapack::_dgemm_full;
 use itertools::{//Itertools, 
                 iproduct, 
-                //izip
+                izip
                 };
 use tensors::MathMatrix;
 use crate::anyhow::{anyhow,Error};
@@ -218,10 +222,18 @@ impl SCF {
         };
 
     }
+    /// The orbital gradient, the diagonal hessian and the hessian operator of HF, which are used by the stability analysis
     pub fn generate_g_hop(&mut self, external: bool) -> Result<(Vec<f64>, Vec<f64>, 
                                                                     Box<dyn FnMut(&Vec<f64>) -> Vec<f64> + '_>),
                                                                     Error
                                                                   > {
+        self.generate_g_hop_with_exx(external, 1.0)
+    }
+    /// The same as [`SCF::generate_g_hop`], but the exchange response in the hessian operator is scaled by `exx_fraction`
+    pub fn generate_g_hop_with_exx(&mut self, external: bool, exx_fraction: f64) -> Result<(Vec<f64>, Vec<f64>, 
+                                                                    Box<dyn FnMut(&Vec<f64>) -> Vec<f64> + '_>),
+                                                                    Error
+                                                                  > {
         let num_basis = self.mol.num_basis;
         let num_state = self.mol.num_state;
         let num_auxbas = self.mol.num_auxbas;
@@ -314,7 +326,7 @@ impl SCF {
                 d1[ispin].self_add( &mut d1_t );
                 //d1[ispin].formated_output(num_basis, "full");            
             }
-            let mut vind = self.response_fn_hf(&d1, external);
+            let mut vind = self.response_fn_hf_with_exx(&d1, external, exx_fraction);
             for ispin in 0..spin_channel {
                 let occ = _occ[ispin];
                 let vir = _vir[ispin];
@@ -336,6 +348,128 @@ impl SCF {
         Ok((g_all, hdiag_all, Box::new(h_op)))
     }
 
+    /// Check if the second-order scf (`mixer = "newton"` or `"soscf"`) is available,
+    /// which relies on the orbital hessian from [`SCF::generate_g_hop`] using the RI-V approximation.
+    pub fn soscf_is_available(&self, mpi_operator: &Option<MPIOperator>) -> bool {
+        let scftype_flag = match self.scftype {
+            SCFType::RHF | SCFType::UHF => true,
+            _ => false,
+        };
+        let occupation_flag = match self.mol.ctrl.occupation_type {
+            OCCType::INTEGER => true,
+            _ => false,
+        };
+        scftype_flag && occupation_flag && self.ri3fn.is_some() && mpi_operator.is_none()
+            && ! self.mol.xc_data.is_range_separated()
+    }
+
+    /// Perform one step of the second-order scf using the augmented hessian (AH) with the trust radius.
+    ///
+    /// The orbitals are rotated as `C' = C*exp(K)`, where `K` is the anti-symmetric generator with
+    /// `K[a,i] = x[a,i]` and `K[i,a] = -x[a,i]`. The step `x` is from the lowest root of
+    /// ```text
+    ///     | 0   g^T | | 1 |         | 1 |
+    ///     | g   H   | | x | = eps * | x |
+    /// ```
+    /// and then scaled to the trust radius if necessary. The gradient `g` is exact, while the orbital hessian `H`
+    /// is approximate for DFAs: it only includes the orbital energy differences, the Coulomb response and the
+    /// exact-exchange response scaled by the fraction of the exact exchange, so that the response of the
+    /// exchange-correlation kernel (f_xc) is neglected. No level shift is applied to `H`, and the steps
+    /// are controlled by the trust radius only. The approximate `H` slows down the convergence near the
+    /// solution, but does not change the converged orbitals.
+    ///
+    /// The hamiltonian is replaced by a pseudo fock matrix `S*C'*F'*C'^{T}*S`, where `F'` is the fock matrix
+    /// in the rotated orbitals with the occupied-virtual blocks removed. As a result, the following
+    /// diagonalization in the scf loop reproduces the rotated occupied orbitals.
+    ///
+    /// Return [the norm of g, the norm of x, the predicted energy change]
+    pub fn soscf_rotate_orbitals(&mut self, trust_radius: f64) -> [f64;3] {
+        let num_basis = self.mol.num_basis;
+        let num_state = self.mol.num_state;
+        let spin_channel = self.mol.spin_channel;
+        let print_level = self.mol.ctrl.print_level;
+        // the energy gradient with respect to the orbital rotation is 4*F[a,i] for RHF and 2*F[a,i] for UHF,
+        // while the orbital hessian from h_op is in the same scale
+        let factor = if spin_channel == 1 {4.0} else {2.0};
+
+        let (g, mut x, mut hx) = {
+            let exx_fraction = self.mol.xc_data.exx_fraction_scf();
+            let (mut g, hdiag, mut h_op) = self.generate_g_hop_with_exx(false, exx_fraction).unwrap();
+            g.iter_mut().for_each(|g| *g *= factor);
+            let g_norm = g.iter().fold(0.0, |acc, g| acc + g*g).sqrt();
+            let ah_tol = (0.1*g_norm).min(1.0e-4).max(1.0e-10);
+            let (x, hx, eps) = solve_augmented_hessian(&g, &hdiag, &mut h_op, SOSCF_AH_MAX_CYCLE, ah_tol, print_level);
+            if print_level > 1 {
+                println!("SOSCF: the lowest eigenvalue of the augmented hessian: {:16.8}", eps);
+            }
+            (g, x, hx)
+        };
+
+        let g_norm = g.iter().fold(0.0, |acc, g| acc + g*g).sqrt();
+        let x_norm = x.iter().fold(0.0, |acc, x| acc + x*x).sqrt();
+        let x_norm = if x_norm > trust_radius {
+            let scale = trust_radius/x_norm;
+            x.iter_mut().for_each(|x| *x *= scale);
+            hx.iter_mut().for_each(|hx| *hx *= scale);
+            trust_radius
+        } else {
+            x_norm
+        };
+        // the predicted energy change from the second-order expansion
+        let pred_change = g.iter().zip(x.iter()).fold(0.0, |acc, (g, x)| acc + g*x)
+            + 0.5*hx.iter().zip(x.iter()).fold(0.0, |acc, (hx, x)| acc + hx*x);
+
+        let ovlp = self.ovlp.to_matrixfull().unwrap();
+        let mut start = 0;
+        for i_spin in 0..spin_channel {
+            let occ = self.homo[i_spin] + 1;
+            let vir = num_state - occ;
+            let x_s = &x[start..start+occ*vir];
+            start += occ*vir;
+
+            let mut kappa = MatrixFull::new([num_state, num_state], 0.0);
+            for i in 0..occ {
+                for a in 0..vir {
+                    kappa[[occ+a, i]] = x_s[i*vir+a];
+                    kappa[[i, occ+a]] = -x_s[i*vir+a];
+                }
+            }
+            let u = expm_antisymmetric(&kappa);
+
+            let mo = MatrixFull::from_vec([num_basis, num_state],
+                self.eigenvectors[i_spin].iter_submatrix(0..num_basis, 0..num_state).map(|i| *i).collect()
+            ).unwrap();
+            let mut mo_new = MatrixFull::new([num_basis, num_state], 0.0);
+            _dgemm_full(&mo, 'N', &u, 'N', &mut mo_new, 1.0, 0.0);
+
+            // F' = C'^{T}*F*C' without the occupied-virtual blocks
+            let fock = self.hamiltonian[i_spin].to_matrixfull().unwrap();
+            let mut f_mo = MatrixFull::new([num_basis, num_state], 0.0);
+            _dgemm_full(&fock, 'N', &mo_new, 'N', &mut f_mo, 1.0, 0.0);
+            let mut fock_mo = MatrixFull::new([num_state, num_state], 0.0);
+            _dgemm_full(&mo_new, 'T', &f_mo, 'N', &mut fock_mo, 1.0, 0.0);
+            for i in 0..occ {
+                for a in occ..num_state {
+                    fock_mo[[a, i]] = 0.0;
+                    fock_mo[[i, a]] = 0.0;
+                }
+            }
+
+            // the pseudo fock matrix: S*C'*F'*C'^{T}*S
+            let mut s_mo = MatrixFull::new([num_basis, num_state], 0.0);
+            _dgemm_full(&ovlp, 'N', &mo_new, 'N', &mut s_mo, 1.0, 0.0);
+            let mut tmp_mat = MatrixFull::new([num_basis, num_state], 0.0);
+            _dgemm_full(&s_mo, 'N', &fock_mo, 'N', &mut tmp_mat, 1.0, 0.0);
+            let mut fock_new = MatrixFull::new([num_basis, num_basis], 0.0);
+            _dgemm_full(&tmp_mat, 'N', &s_mo, 'T', &mut fock_new, 1.0, 0.0);
+
+            self.hamiltonian[i_spin] = fock_new.to_matrixupper();
+            self.eigenvectors[i_spin].copy_from_matr(0..num_basis, 0..num_state, &mo_new, 0..num_basis, 0..num_state);
+        }
+
+        [g_norm, x_norm, pred_change]
+    }
+
     //pub fn generate_g_hop_uhf(&mut self, external: bool) -> Result<(Vec<f64>, Vec<f64>, 
     //                                                                Box<dyn FnMut(Vec<f64>) -> Vec<f64> + '_>),
     //                                                                Error
@@ -348,26 +482,32 @@ impl SCF {
                                       //scaling_factor: f64,
                                       external: bool
                                       ) -> Vec<MatrixFull<f64>> {
+        self.response_fn_hf_with_exx(dm, external, 1.0)
+    }
+    /// The response of the Coulomb and exact-exchange potentials to the density change `dm`,
+    /// where the exchange is scaled by `hyb`, e.g. the fraction of the exact exchange of hybrid DFAs.
+    /// The response of the exchange-correlation kernel is not included.
+    pub fn response_fn_hf_with_exx(&mut self, dm: &Vec<MatrixFull<f64>>, external: bool, hyb: f64) -> Vec<MatrixFull<f64>> {
         if self.mol.ctrl.spin_channel == 1 {
             if external {
-                let mut vind = self.response_vk_full_with_ri_v( &dm, -0.5);
+                let mut vind = self.response_vk_full_with_ri_v( &dm, -0.5*hyb);
                 vind
             } else {
-                // vj - 0.5*vk
+                // vj - 0.5*c_x*vk
                 let mut vj = self.response_vj_full_with_ri_v( &dm, 1.0);
                 let mut vk = self.response_vk_full_with_ri_v( &dm, 1.0);
                 let mut vind = vj.clone();
-                vind[0] = vj[0].scaled_add(&vk[0], -0.5).unwrap();
+                vind[0] = vj[0].scaled_add(&vk[0], -0.5*hyb).unwrap();
                 vind
             }
         } else {
             if external {
-                let mut vind = self.response_vk_full_with_ri_v( &dm, -1.0);
+                let mut vind = self.response_vk_full_with_ri_v( &dm, -1.0*hyb);
                 vind
             } else {
-                // vind[ispin] = vj[alpha] + vj[beta] - vk[ispin]
+                // vind[ispin] = vj[alpha] + vj[beta] - c_x*vk[ispin]
                 let mut vj = self.response_vj_full_with_ri_v( &dm, 1.0);
-                let mut vk = self.response_vk_full_with_ri_v( &dm, -1.0);
+                let mut vk = self.response_vk_full_with_ri_v( &dm, -1.0*hyb);
                 let mut vind = vk.clone();
                 vind[0].self_add(&vj[0]);
                 vind[0].self_add(&vj[1]);
@@ -384,6 +524,70 @@ impl SCF {
     }
 
 
+    /// The response of the potentials to the (non-symmetric) density change `dm` for the linear-response
+    /// methods like TDDFT and CPKS. Return (v_jxc, v_k):
+    ///   v_jxc: the Coulomb and exchange-correlation responses to the symmetrized `dm + dm^T`
+    ///   v_k:   the exact-exchange response to `dm`, scaled by the fraction of the exact exchange and 
+    ///          including the long-range part of the range-separated hybrids
+    /// For the restricted references, the singlet (`singlet=true`) or the triplet response is given in the spatial-orbital form.
+    pub fn response_fn_ks(&self, dm: &Vec<MatrixFull<f64>>, kernel: Option<&XcKernel>, singlet: bool) 
+        -> (Vec<MatrixFull<f64>>, Vec<MatrixFull<f64>>) {
+        let spin_channel = self.mol.spin_channel;
+        let num_basis = self.mol.num_basis;
+        let dm_sym: Vec<MatrixFull<f64>> = dm.iter().map(|dm_s| {
+            let mut dm_sym = dm_s.transpose();
+            dm_sym.self_add(dm_s);
+            dm_sym
+        }).collect();
+
+        let mut v_jxc = vec![MatrixFull::new([num_basis,num_basis],0.0); spin_channel];
+        if spin_channel == 1 {
+            if singlet {
+                v_jxc[0] = vj_full_with_ri_v(&self.ri3fn, &dm_sym, spin_channel, 2.0).swap_remove(0);
+            }
+        } else {
+            let vj = vj_full_with_ri_v(&self.ri3fn, &dm_sym, spin_channel, 1.0);
+            for i_spin in 0..2 {
+                v_jxc[i_spin].self_add(&vj[0]);
+                v_jxc[i_spin].self_add(&vj[1]);
+            }
+        }
+        if let Some(kernel) = kernel {
+            let ddm = if spin_channel == 1 {
+                let mut ddm_b = dm_sym[0].clone();
+                if ! singlet {ddm_b.data.iter_mut().for_each(|d| *d = -*d)};
+                [dm_sym[0].clone(), ddm_b]
+            } else {
+                [dm_sym[0].clone(), dm_sym[1].clone()]
+            };
+            let vxc = kernel.apply(self.grids.as_ref().unwrap(), &ddm);
+            for i_spin in 0..spin_channel {
+                v_jxc[i_spin].self_add(&vxc[i_spin]);
+            }
+        }
+
+        let (hyb_full, hyb_lr) = if self.mol.xc_data.is_range_separated() {
+            self.mol.xc_data.exx_range_coeff_scf()
+        } else {
+            (self.mol.xc_data.exx_fraction_scf(), 0.0)
+        };
+        let mut v_k = if hyb_full.eq(&0.0) {
+            vec![MatrixFull::new([num_basis,num_basis],0.0); spin_channel]
+        } else {
+            let mut v_k = vk_full_fromdm_with_ri_v(&self.ri3fn, dm, spin_channel, hyb_full);
+            v_k.truncate(spin_channel);
+            v_k
+        };
+        if ! hyb_lr.eq(&0.0) {
+            let v_k_lr = vk_full_fromdm_with_ri_v(&self.ri3fn_lr, dm, spin_channel, hyb_lr);
+            for i_spin in 0..spin_channel {
+                v_k[i_spin].self_add(&v_k_lr[i_spin]);
+            }
+        }
+
+        (v_jxc, v_k)
+    }
+
     // Currently response fn can only be calculated with ri
     //
     pub fn response_vj_full_with_ri_v(&mut self, dm: &Vec<MatrixFull<f64>>,
@@ -415,4 +619,133 @@ impl SCF {
 
 }
 
+/// The maximum number of the Davidson iterations to solve the augmented hessian in the second-order scf
+pub const SOSCF_AH_MAX_CYCLE: usize = 12;
+
+/// Solve the lowest root of the augmented hessian
+/// ```text
+///     | 0   g^T | | 1 |         | 1 |
+///     | g   H   | | x | = eps * | x |
+/// ```
+/// by the Davidson algorithm, where `H` is only accessed by `h_op`, and `hdiag` is its diagonal terms.
+/// Return (x, H*x, eps)
+pub fn solve_augmented_hessian<F>(g: &Vec<f64>, hdiag: &Vec<f64>, h_op: &mut F,
+                                  max_cycle: usize, tol: f64, print_level: usize) -> (Vec<f64>, Vec<f64>, f64)
+    where F: FnMut(&Vec<f64>) -> Vec<f64> {
+    let length = g.len();
+    let dot = |a: &[f64], b: &[f64]| a.iter().zip(b.iter()).fold(0.0, |acc, (a, b)| acc + a*b);
+    let precond = |d: f64| if d.abs() < 1.0e-8 {1.0e-8_f64.copysign(d)} else {d};
+
+    let mut x = vec![0.0; length];
+    let mut hx = vec![0.0; length];
+    let mut eps = 0.0;
+    let mut xs: Vec<Vec<f64>> = vec![];
+    let mut hxs: Vec<Vec<f64>> = vec![];
+    // the initial guess from the diagonal hessian
+    let mut new_x: Vec<f64> = g.iter().zip(hdiag.iter()).map(|(g, h)| -g/precond(*h)).collect();
 
+    for i_cycle in 0..max_cycle {
+        // orthonormalize the new vector against the subspace twice for the numerical stability
+        for _ in 0..2 {
+            xs.iter().for_each(|xi| {
+                let proj = dot(xi, &new_x);
+                new_x.iter_mut().zip(xi.iter()).for_each(|(n, xi)| *n -= proj*xi);
+            });
+        }
+        let norm = dot(&new_x, &new_x).sqrt();
+        if norm < 1.0e-10 {break}
+        new_x.iter_mut().for_each(|n| *n /= norm);
+        hxs.push(h_op(&new_x));
+        xs.push(new_x);
+
+        // the augmented hessian in the subspace
+        let num_space = xs.len();
+        let mut ah = MatrixFull::new([num_space+1, num_space+1], 0.0);
+        for i in 0..num_space {
+            let gx = dot(g, &xs[i]);
+            ah[[0, i+1]] = gx;
+            ah[[i+1, 0]] = gx;
+            for j in 0..num_space {
+                ah[[i+1, j+1]] = 0.5*(dot(&xs[i], &hxs[j]) + dot(&xs[j], &hxs[i]));
+            }
+        }
+        let mut ah_upper = ah.to_matrixupper();
+        let (evec, eval, _) = ah_upper.to_matrixupperslicemut().lapack_dspevx().unwrap();
+        eps = eval[0];
+        let c0 = evec[[0, 0]];
+        if c0.abs() < 1.0e-8 {
+            if print_level > 1 {println!("SOSCF: the augmented hessian is ill-conditioned")};
+            break
+        }
+
+        x = vec![0.0; length];
+        hx = vec![0.0; length];
+        for i in 0..num_space {
+            let c = evec[[i+1, 0]]/c0;
+            x.iter_mut().zip(xs[i].iter()).for_each(|(x, xi)| *x += c*xi);
+            hx.iter_mut().zip(hxs[i].iter()).for_each(|(hx, hxi)| *hx += c*hxi);
+        }
+
+        // the residual: g + (H - eps)*x
+        let residual: Vec<f64> = izip!(g.iter(), hx.iter(), x.iter()).map(|(g, hx, x)| g + hx - eps*x).collect();
+        let r_norm = dot(&residual, &residual).sqrt();
+        if print_level > 2 {
+            println!("SOSCF: AH cycle {:3}, eps = {:16.8}, |r| = {:10.5e}", i_cycle, eps, r_norm);
+        }
+        if r_norm < tol {break}
+
+        new_x = residual.iter().zip(hdiag.iter()).map(|(r, h)| r/precond(eps - h)).collect();
+    }
+
+    (x, hx, eps)
+}
+
+/// The exponential of an anti-symmetric matrix using the Taylor expansion with scaling and squaring
+pub fn expm_antisymmetric(kappa: &MatrixFull<f64>) -> MatrixFull<f64> {
+    let size = kappa.size[0];
+    let norm = kappa.data.iter().fold(0.0, |acc, k| acc + k*k).sqrt();
+    let mut num_squaring = 0;
+    let mut scale = 1.0;
+    while norm*scale > 0.5 {
+        scale *= 0.5;
+        num_squaring += 1;
+    }
+    let mut kappa_s = kappa.clone();
+    kappa_s.data.iter_mut().for_each(|k| *k *= scale);
+
+    let mut expm = MatrixFull::new([size, size], 0.0);
+    (0..size).for_each(|i| expm[[i, i]] = 1.0);
+    let mut term = expm.clone();
+    for order in 1..30 {
+        let mut next_term = MatrixFull::new([size, size], 0.0);
+        _dgemm_full(&term, 'N', &kappa_s, 'N', &mut next_term, 1.0/order as f64, 0.0);
+        term = next_term;
+        expm.self_scaled_add(&term, 1.0);
+        if term.data.iter().fold(0.0_f64, |acc, t| acc.max(t.abs())) < 1.0e-16 {break}
+    }
+    for _ in 0..num_squaring {
+        let mut expm_2 = MatrixFull::new([size, size], 0.0);
+        _dgemm_full(&expm, 'N', &expm, 'N', &mut expm_2, 1.0, 0.0);
+        expm = expm_2;
+    }
+    expm
+}
+
+#[test]
+fn test_expm_antisymmetric() {
+    let mut kappa = MatrixFull::new([3,3], 0.0);
+    kappa[[1,0]] = 0.8; kappa[[0,1]] = -0.8;
+    kappa[[2,0]] = -1.3; kappa[[0,2]] = 1.3;
+    let u = expm_antisymmetric(&kappa);
+    let mut utu = MatrixFull::new([3,3], 0.0);
+    _dgemm_full(&u, 'T', &u, 'N', &mut utu, 1.0, 0.0);
+    for i in 0..3 {
+        for j in 0..3 {
+            let delta = if i==j {1.0} else {0.0};
+            assert!((utu[[i,j]]-delta).abs() < 1.0e-12);
+        }
+    }
+    // rotation about the axis (0, 1.3, 0.8) with the angle of sqrt(0.8^2+1.3^2)
+    let theta = (0.8_f64.powi(2) + 1.3_f64.powi(2)).sqrt();
+    assert!((u[[0,0]] - theta.cos()).abs() < 1.0e-12);
+}
@@ -1,9 +1,12 @@
 use crate::basis_io::ecp::ghost_effective_potential_matrix;
 use crate::check_norm::force_state_occupation::adapt_occupation_with_force_projection;
+use crate::check_norm::smearing::generate_occupation_smearing;
 use crate::check_norm::{self, generate_occupation_frac_occ, generate_occupation_integer, generate_occupation_sad, OCCType};
 use crate::dft::gen_grids::prune::prune_by_rho;
 use crate::dft::{numerical_density, Grids};
-use crate::geom_io::{calc_nuc_energy, calc_nuc_energy_with_point_charges};
+use crate::geom_io::{calc_nuc_energy, calc_nuc_energy_with_electric_field, calc_nuc_energy_with_point_charges, MOrC};
+use crate::pbc;
+use crate::solvation::PCM;
 use crate::mpi_io::{mpi_broadcast, mpi_broadcast_matrixfull, mpi_broadcast_vector, mpi_reduce, MPIOperator};
 use crate::utilities::{create_pool, TimeRecords};
 ////use blas_src::openblas::dgemm;
@@ -49,6 +52,10 @@ pub struct SCF {
     pub tab_ao: Option<MatrixFull<f64>>,
     pub m: Option<MatrixFull<f64>>,
     pub rimatr: Option<(MatrixFull<f64>,MatrixFull<usize>,Vec<[usize;2]>)>,
+    // the long-range integrals with erf(omega*r)/r for range-separated hybrids
+    pub ijkl_lr: Option<ERIFold4<f64>>,
+    pub ri3fn_lr: Option<RIFull<f64>>,
+    pub rimatr_lr: Option<(MatrixFull<f64>,MatrixFull<usize>,Vec<[usize;2]>)>,
     pub ri3mo: Option<Vec<(RIFull<f64>,std::ops::Range<usize> , std::ops::Range<usize>)>>,
     #[pyo3(get,set)]
     pub eigenvalues: [Vec<f64>;2],
@@ -71,6 +78,10 @@ pub struct SCF {
     #[pyo3(get,set)]
     pub scf_energy: f64,
     pub grids: Option<Grids>,
+    /// the (coarser) grids for the kernel of the VV10 non-local correlation
+    pub vv10_grids: Option<Grids>,
+    /// the implicit solvation model
+    pub solvation: Option<PCM>,
     pub empirical_dispersion_energy: f64,
     pub energies: HashMap<String,Vec<f64>>,
     pub ref_eigenvectors: HashMap<String, ([MatrixFull<f64>;2], [usize;4])>,
@@ -96,6 +107,9 @@ impl SCF {
             tab_ao: None,
             m: None,
             rimatr: None,
+            ijkl_lr: None,
+            ri3fn_lr: None,
+            rimatr_lr: None,
             ri3mo: None,
             eigenvalues: [vec![],vec![]],
             hamiltonian: [MatrixUpper::empty(),
@@ -115,6 +129,8 @@ impl SCF {
             scf_energy: 0.0,
             empirical_dispersion_energy: 0.0,
             grids: None,
+            vv10_grids: None,
+            solvation: None,
             energies: HashMap::new(),
         };
 
@@ -133,9 +149,6 @@ impl SCF {
                 if mol.ctrl.print_level>0 {println!("Restricted-orbital Hartree-Fock (or Kohn-Sham) algorithm is invoked.")};
                 scf_data.mol.ctrl.spin_channel=2;
                 scf_data.mol.spin_channel=2;
-                if mol.ctrl.print_level>0 {
-                    panic!("Restricted-orbital Hartree-Fock (or Kohn-Sham) algorithm is invoked, which, however, is not yet stable.")
-                };
             },
             SCFType::UHF => {
                 if mol.ctrl.print_level>0 {println!("Unrestricted Hartree-Fock (or Kohn-Sham) algorithm is invoked.")}
@@ -152,6 +165,12 @@ impl SCF {
 
         let print_level = self.mol.ctrl.print_level;
 
+        if let MOrC::Crystal = self.mol.geom.pbc {
+            self.prepare_periodic_integrals(mpi_operator);
+            self.check_overlap_singularity();
+            return
+        }
+
         //========================================
         // For nuclear energy, includin the interaction with the ghost atoms with point charges
         self.nuc_energy = calc_nuc_energy(&self.mol.geom, &self.mol.basis4elem);
@@ -159,11 +178,19 @@ impl SCF {
         let nuc_energy_pc = calc_nuc_energy_with_point_charges(&self.mol.geom, &self.mol.basis4elem);
         self.nuc_energy += nuc_energy_pc;
 
+        let nuc_energy_field = if let Some(field) = &self.mol.ctrl.electric_field {
+            calc_nuc_energy_with_electric_field(&self.mol.geom, &self.mol.basis4elem, field)
+        } else {0.0};
+        self.nuc_energy += nuc_energy_field;
+
         if print_level>0 {
             println!("Nuc_energy: {:16.8} Hartree",self.nuc_energy);
             if nuc_energy_pc.abs() > 1.0e-4 {
                 println!("External potential due to point charges exists: {:16.8} Hartree", &nuc_energy_pc);
             }
+            if nuc_energy_field.abs() > 1.0e-4 {
+                println!("Nuclear energy in the external electric field: {:16.8} Hartree", &nuc_energy_field);
+            }
         }
         //========================================
         // For emperial dispersion correction
@@ -206,6 +233,9 @@ impl SCF {
         if self.mol.geom.ghost_pc_chrg.len() > 0 {
             if self.mol.ctrl.print_level > 0 {
                 println!("There are {} point charges specified", self.mol.geom.ghost_pc_chrg.len());
+                if let Some(width) = self.mol.geom.ghost_pc_width {
+                    println!("The point charges are smeared by Gaussians with the width of {:8.4} Bohr", width);
+                }
             }
             let tmp_matr = self.mol.int_ij_matrixupper(String::from("point charge"));
             self.h_core.iter_mut().zip(tmp_matr.iter()).for_each(|(a,b)| *a += *b);
@@ -215,6 +245,14 @@ impl SCF {
             }
         }
 
+        // For the uniform external electric field, the electrons with the charge of -1 gain F*r
+        if let Some(field) = &self.mol.ctrl.electric_field {
+            let ao_dip = self.mol.int_ij_matrixuppers(String::from("dipole"), 3);
+            ao_dip.iter().zip(field.iter()).for_each(|(ao_dip_x, f_x)| {
+                self.h_core.data.iter_mut().zip(ao_dip_x.data.iter()).for_each(|(a,b)| *a += *f_x*b);
+            });
+        }
+
         //========================================
         // For four-center integrals
         self.ijkl = if self.mol.ctrl.use_auxbas {
@@ -295,7 +333,58 @@ impl SCF {
             None
         };
 
+        // preparing the long-range integrals for range-separated hybrids
+        if let Some([omega,_,_]) = self.mol.xc_data.dfa_hybrid_cam_scf() {
+            if isdf || self.mol.ctrl.isdf_new {
+                panic!("Range-separated hybrid functionals are not yet available with ISDF");
+            }
+            if print_level>0 {
+                println!("Range-separated exact exchange is employed with omega = {:8.4}", omega);
+            }
+            let mol_lr = self.mol.with_range_separation(omega);
+            self.ijkl_lr = if self.ijkl.is_some() {
+                Some(mol_lr.int_ijkl_erifold4())
+            } else {
+                None
+            };
+            self.ri3fn_lr = if self.ri3fn.is_some() {
+                Some(mol_lr.prepare_ri3fn_for_ri_v_full_rayon())
+            } else {
+                None
+            };
+            self.rimatr_lr = if self.rimatr.is_some() {
+                Some(mol_lr.prepare_rimatr_for_ri_v_mpi_rayon(mpi_operator))
+            } else {
+                None
+            };
+        }
+
+        self.check_overlap_singularity();
+    }
 
+    /// The lattice-summed integrals for the Gamma-point periodic calculations,
+    /// where the RI-J/K integrals are always prepared with the periodic Coulomb kernel
+    fn prepare_periodic_integrals(&mut self, mpi_operator: &Option<MPIOperator>) {
+        if self.mol.ctrl.empirical_dispersion.is_some() && self.mol.ctrl.print_level>0 {
+            println!("WARNING: the empirical dispersion correction is not yet available in the periodic calculations, and is neglected");
+        }
+        let pbc_ints = pbc::prepare_periodic_integrals(&mut self.mol, mpi_operator);
+        self.nuc_energy = pbc_ints.nuc_energy;
+        self.ovlp = pbc_ints.ovlp;
+        self.h_core = pbc_ints.h_core;
+        self.ijkl = None;
+        if self.mol.ctrl.use_ri_symm {
+            self.rimatr = Some(pbc_ints.rimatr);
+        } else {
+            let (rimatr, basbas2baspar, baspar2basbas) = pbc_ints.rimatr;
+            self.ri3fn = Some(generate_ri3fn_from_rimatr(&rimatr, &basbas2baspar, &baspar2basbas));
+        }
+        if self.mol.ctrl.print_level>0 {
+            println!("Nuc_energy: {:16.8} Hartree",self.nuc_energy);
+        }
+    }
+
+    fn check_overlap_singularity(&mut self) {
         // initial eigenvectors and eigenvalues
         let (eigenvectors, eigenvalues,n_found)=self.ovlp.to_matrixupperslicemut().lapack_dspevx().unwrap();
 
@@ -317,7 +406,8 @@ impl SCF {
 
     pub fn prepare_density_grids(&mut self) {
 
-        self.grids = if self.mol.xc_data.is_dfa_scf() || self.mol.ctrl.use_isdf || self.mol.ctrl.initial_guess == "vsap" {
+        self.grids = if self.mol.xc_data.is_dfa_scf() || self.mol.ctrl.use_isdf || self.mol.ctrl.initial_guess == "vsap" 
+            || self.vv10_coeff().is_some() {
             let grids = Grids::build(&mut self.mol);
             if self.mol.ctrl.print_level>0 {
                 println!("Grid size: {:}", grids.coordinates.len());
@@ -329,6 +419,58 @@ impl SCF {
             grids.prepare_tabulated_ao(&self.mol);
         }
 
+        // the kernel of VV10 requires the whole grids on each rank. So the kernel grids are 
+        // built separately either for a different generation level or in the MPI environment
+        self.vv10_grids = if self.vv10_coeff().is_some() && 
+            (self.mol.ctrl.vv10_grid_level.is_some() || self.mol.mpi_data.is_some()) {
+            let grid_gen_level = self.mol.ctrl.grid_gen_level;
+            let mpi_data = self.mol.mpi_data.take();
+            self.mol.ctrl.grid_gen_level = self.mol.ctrl.vv10_grid_level.unwrap_or(grid_gen_level);
+            let mut vv10_grids = Grids::build(&mut self.mol);
+            self.mol.ctrl.grid_gen_level = grid_gen_level;
+            self.mol.mpi_data = mpi_data;
+            vv10_grids.prepare_tabulated_ao(&self.mol);
+            if self.mol.ctrl.print_level>0 {
+                println!("Grid size for the VV10 kernel: {:}", vv10_grids.coordinates.len());
+            }
+            Some(vv10_grids)
+        } else {None};
+
+    }
+
+    /// The VV10 parameters [b, C]: those given by the keyword of "vv10_coeff" take 
+    /// precedence over the defaults of the functional
+    pub fn vv10_coeff(&self) -> Option<[f64;2]> {
+        self.mol.ctrl.vv10_coeff.or(self.mol.xc_data.vv10_coeff_scf())
+    }
+
+    /// Evaluate the VV10 energy and its potential matrix, which is the same for both spin channels
+    pub fn generate_vv10(&self, mpi_operator: &Option<MPIOperator>) -> (f64, MatrixUpper<f64>) {
+        let num_basis = self.mol.num_basis;
+        let coeff = if let Some(coeff) = self.vv10_coeff() {coeff} else {
+            return (0.0, MatrixUpper::new(num_basis*(num_basis+1)/2, 0.0))
+        };
+        let grids = self.grids.as_ref().unwrap();
+        let vv10_grids = self.vv10_grids.as_ref().unwrap_or(grids);
+
+        let mut dm = self.density_matrix[0].clone();
+        if self.mol.spin_channel==2 {
+            dm.data.iter_mut().zip(self.density_matrix[1].data.iter()).for_each(|(to, from)| *to += from);
+        }
+
+        let (energy, vnl) = crate::dft::vv10::vv10_energy_potential(grids, vv10_grids, &dm, coeff);
+        let vnl = vnl.to_matrixupper();
+
+        if let Some(mpi_world) = mpi_operator {
+            let world = &mpi_world.world;
+            let mut tot_energy = mpi_reduce(world, &[energy], 0, &SystemOperation::sum())[0];
+            mpi_broadcast(&world, &mut tot_energy, 0);
+            let mut result = mpi_reduce(world, vnl.data_ref().unwrap(), 0, &SystemOperation::sum());
+            mpi_broadcast_vector(&world, &mut result, 0);
+            (tot_energy, MatrixUpper::from_vec(result.len(), result).unwrap())
+        } else {
+            (energy, vnl)
+        }
     }
 
     pub fn prepare_isdf(&mut self, mpi_operator: &Option<MPIOperator>) {
@@ -398,7 +540,12 @@ impl SCF {
 
 
     pub fn generate_occupation(&mut self) {
-        (self.occupation, self.homo, self.lumo) = generate_occupation_outside(&self);
+        let smearing;
+        (self.occupation, self.homo, self.lumo, smearing) = generate_occupation_and_smearing_outside(&self);
+        // for the smearing occupations, record the entropy term (-sigma*S) and the fermi levels
+        if let Some((fermi_level, entropy_term)) = smearing {
+            self.energies.insert(String::from("smearing_entropy"), vec![entropy_term, fermi_level[0], fermi_level[1]]);
+        }
     }
 
     pub fn generate_density_matrix(&mut self) {
@@ -1677,10 +1824,12 @@ impl SCF {
                 });
         }
         let dt2 = time::Local::now();
-        let scaling_factor = match self.scftype {
+        let spin_factor = match self.scftype {
             SCFType::RHF => -0.5,
             _ => -1.0,
-        }*self.mol.xc_data.dfa_hybrid_scf ;
+        };
+        let (hyb_full, hyb_lr) = self.mol.xc_data.exx_range_coeff_scf();
+        let scaling_factor = spin_factor*hyb_full;
         if ! scaling_factor.eq(&0.0) {
             //let vk = self.generate_vk_with_ri_v(scaling_factor);
             let vk = self.generate_vk_with_erifold4_sync(scaling_factor);
@@ -1693,6 +1842,17 @@ impl SCF {
                     });
             };
         }
+        if ! hyb_lr.eq(&0.0) {
+            let vk_lr = self.generate_vk_lr(spin_factor*hyb_lr, false, &None);
+            for i_spin in (0..spin_channel) {
+                self.hamiltonian[i_spin].data
+                    .par_iter_mut()
+                    .zip(vk_lr[i_spin].data.par_iter())
+                    .for_each(|(h_ij,vk_ij)| {
+                        *h_ij += vk_ij
+                    });
+            };
+        }
         let dt3 = time::Local::now();
         if self.mol.xc_data.dfa_compnt_scf.len()!=0 {
             let (exc,vxc) = self.generate_vxc(1.0);
@@ -1712,6 +1872,23 @@ impl SCF {
                 vxc_total += SCF::par_energy_contraction(&dm_upper, &vxc[i_spin]);
             }
         }
+        if self.vv10_coeff().is_some() {
+            let (enl, vnl) = self.generate_vv10(&None);
+            if self.mol.ctrl.print_level>1 {
+                println!("VV10 non-local correlation energy: {:16.8} Ha.", enl);
+            }
+            exc_total += enl;
+            for i_spin in (0..spin_channel) {
+                self.hamiltonian[i_spin].data
+                                .par_iter_mut()
+                                .zip(vnl.data.par_iter())
+                                .for_each(|(h_ij,vnl_ij)| {
+                                    *h_ij += vnl_ij
+                                });
+                let dm_upper = self.density_matrix[i_spin].to_matrixupper();
+                vxc_total += SCF::par_energy_contraction(&dm_upper, &vnl);
+            }
+        }
 
         let dt4 = time::Local::now();
         
@@ -1768,10 +1945,12 @@ impl SCF {
                 });
         }
         let dt2 = time::Local::now();
-        let scaling_factor = match self.scftype {
+        let spin_factor = match self.scftype {
             SCFType::RHF => -0.5,
             _ => -1.0,
-        }*self.mol.xc_data.dfa_hybrid_scf ;
+        };
+        let (hyb_full, hyb_lr) = self.mol.xc_data.exx_range_coeff_scf();
+        let scaling_factor = spin_factor*hyb_full;
         if ! scaling_factor.eq(&0.0) {
             let use_dm_only = self.mol.ctrl.use_dm_only;
             //self.mol.ctrl.use_dm_only
@@ -1794,6 +1973,18 @@ impl SCF {
             //}
             //// ==== DEBUG IGOR ====
         }
+        if ! hyb_lr.eq(&0.0) {
+            let use_dm_only = self.mol.ctrl.use_dm_only;
+            let vk_lr = self.generate_vk_lr(spin_factor*hyb_lr, use_dm_only, mpi_operator);
+            for i_spin in (0..spin_channel) {
+                self.hamiltonian[i_spin].data
+                    .par_iter_mut()
+                    .zip(vk_lr[i_spin].data.par_iter())
+                    .for_each(|(h_ij,vk_ij)| {
+                        *h_ij += vk_ij
+                    });
+            };
+        }
         let dt3 = time::Local::now();
         if self.mol.xc_data.dfa_compnt_scf.len()!=0 {
             //let (exc,vxc) = self.generate_vxc_rayon_dm_only(1.0);
@@ -1819,6 +2010,23 @@ impl SCF {
                 vxc_total += SCF::par_energy_contraction(&dm_upper, &vxc[i_spin]);
             }
         }
+        if self.vv10_coeff().is_some() {
+            let (enl, vnl) = self.generate_vv10(mpi_operator);
+            if self.mol.ctrl.print_level>1 {
+                println!("VV10 non-local correlation energy: {:16.8} Ha.", enl);
+            }
+            exc_total += enl;
+            for i_spin in (0..spin_channel) {
+                self.hamiltonian[i_spin].data
+                                .par_iter_mut()
+                                .zip(vnl.data.par_iter())
+                                .for_each(|(h_ij,vnl_ij)| {
+                                    *h_ij += vnl_ij
+                                });
+                let dm_upper = self.density_matrix[i_spin].to_matrixupper();
+                vxc_total += SCF::par_energy_contraction(&dm_upper, &vnl);
+            }
+        }
 
         let dt4 = time::Local::now();
         
@@ -1998,11 +2206,21 @@ impl SCF {
             }
         }
 
+        // the reaction field of the implicit solvation, which contributes 1/2 q.V_elec to the energy evaluated below
+        let solvation_nuc_energy = if let Some(pcm) = &self.solvation {
+            let (reaction_field, solvation_energy, nuclear_energy) = pcm.reaction_field(&self.mol, &self.density_matrix);
+            self.hamiltonian.iter_mut().take(spin_channel).for_each(|ham_s| {
+                ham_s.data.par_iter_mut().zip(reaction_field.par_iter()).for_each(|(h, v)| *h += v);
+            });
+            self.energies.insert(String::from("solvation"), vec![solvation_energy]);
+            nuclear_energy
+        } else {0.0};
+
         let dm = &self.density_matrix;
 
         // The following scf energy evaluation follow the formula presented in
         // the quantum chemistry book of Szabo A. and Ostlund N.S. P 150, Formula (3.184)
-        self.scf_energy = self.nuc_energy;
+        self.scf_energy = self.nuc_energy + solvation_nuc_energy;
         // for DFT calculations, we should replace the exchange-correlation (xc) potential by the xc energy
         //if self.mol.ctrl.print_level>1 {println!("Exc: {:?}, Vxc: {:?}", exc_total, vxc_total)};
         self.scf_energy = self.scf_energy - vxc_total + exc_total;
@@ -2325,6 +2543,24 @@ impl SCF {
 
     }
 
+    /// The exchange matrices of the long-range operator erf(omega*r)/r for range-separated hybrids,
+    /// which reuse the standard K builders on the long-range integrals prepared in
+    /// [`SCF::prepare_necessary_integrals`]
+    pub fn generate_vk_lr(&mut self, scaling_factor: f64, use_dm_only: bool, mpi_operator: &Option<MPIOperator>) -> Vec<MatrixUpper<f64>> {
+        std::mem::swap(&mut self.ijkl, &mut self.ijkl_lr);
+        std::mem::swap(&mut self.ri3fn, &mut self.ri3fn_lr);
+        std::mem::swap(&mut self.rimatr, &mut self.rimatr_lr);
+        let vk = if self.mol.ctrl.use_auxbas {
+            self.generate_vk_with_ri_v(scaling_factor, use_dm_only, mpi_operator)
+        } else {
+            self.generate_vk_with_erifold4_sync(scaling_factor)
+        };
+        std::mem::swap(&mut self.ijkl, &mut self.ijkl_lr);
+        std::mem::swap(&mut self.ri3fn, &mut self.ri3fn_lr);
+        std::mem::swap(&mut self.rimatr, &mut self.rimatr_lr);
+        vk
+    }
+
     pub fn generate_vk_with_isdf(&mut self, scaling_factor: f64, use_dm_only: bool) -> Vec<MatrixUpper<f64>> {
         //let num_basis = self.mol.num_basis;
         //let num_state = self.mol.num_state;
@@ -2368,7 +2604,7 @@ impl SCF {
         let print_level = self.mol.ctrl.print_level;
         if let Some(grids) = &self.grids {
             let dt0 = utilities::init_timing();
-            let (exc,mut vxc_ao) = self.mol.xc_data.xc_exc_vxc(grids, spin_channel,dm, mo, occ, print_level);
+            let (exc,mut vxc_ao, vtau_mf) = self.mol.xc_data.xc_exc_vxc(grids, spin_channel,dm, mo, occ, print_level);
             let dt1 = utilities::timing(&dt0, Some("Total vxc_ao time"));
             exc_spin = exc;
             if let Some(ao) = &grids.ao {
@@ -2384,6 +2620,10 @@ impl SCF {
                     //vxc_mf_s.lapack_dgemm(ao, vxc_ao_s, 'N', 'T', 1.0, 0.0);
                 }
             }
+            // the contribution of the kinetic density for meta-GGA
+            vxc_mf.iter_mut().zip(vtau_mf.iter()).for_each(|(to_matr,from_matr)| {
+                to_matr.self_add(from_matr);
+            });
             let dt2 = utilities::timing(&dt1, Some("From vxc_ao to vxc"));
         }
 
@@ -2438,7 +2678,7 @@ impl SCF {
         if let Some(grids) = &self.grids {
             let (sender, receiver) = channel();
             grids.parallel_balancing.par_iter().for_each_with(sender,|s,range_grids| {
-                let (exc,vxc_ao,total_elec) = self.mol.xc_data.xc_exc_vxc_slots_dm_only(range_grids.clone(), grids, spin_channel,dm, mo, occ);
+                let (exc,vxc_ao,total_elec,vtau_mf) = self.mol.xc_data.xc_exc_vxc_slots_dm_only(range_grids.clone(), grids, spin_channel,dm, mo, occ);
                 //exc_spin = exc;
                 let mut vxc_mf: Vec<MatrixFull<f64>> = vec![MatrixFull::new([num_basis,num_basis],0.0f64);spin_channel];;
                 if let Some(ao) = &grids.ao {
@@ -2458,6 +2698,10 @@ impl SCF {
                         //    'N', 'T', 1.0, 0.0);
                     }
                 }
+                // the contribution of the kinetic density for meta-GGA
+                vxc_mf.iter_mut().zip(vtau_mf.iter()).for_each(|(to_matr,from_matr)| {
+                    to_matr.self_add(from_matr);
+                });
                 s.send((vxc_mf,exc,total_elec)).unwrap()
             });
             receiver.into_iter().for_each(|(vxc_mf_local,exc_local,loc_total_elec)| {
@@ -2621,7 +2865,7 @@ impl SCF {
         if let Some(grids) = &self.grids {
             let (sender, receiver) = channel();
             grids.parallel_balancing.par_iter().for_each_with(sender,|s,range_grids| {
-                let (exc,vxc_ao,total_elec) = self.mol.xc_data.xc_exc_vxc_slots(range_grids.clone(), grids, spin_channel,dm, mo, occ);
+                let (exc,vxc_ao,total_elec,vtau_mf) = self.mol.xc_data.xc_exc_vxc_slots(range_grids.clone(), grids, spin_channel,dm, mo, occ);
                 //exc_spin = exc;
                 let mut vxc_mf: Vec<MatrixFull<f64>> = vec![MatrixFull::new([num_basis,num_basis],0.0f64);spin_channel];;
                 if let Some(ao) = &grids.ao {
@@ -2641,6 +2885,10 @@ impl SCF {
                         //    'N', 'T', 1.0, 0.0);
                     }
                 }
+                // the contribution of the kinetic density for meta-GGA
+                vxc_mf.iter_mut().zip(vtau_mf.iter()).for_each(|(to_matr,from_matr)| {
+                    to_matr.self_add(from_matr);
+                });
                 s.send((vxc_mf,exc,total_elec)).unwrap()
             });
             receiver.into_iter().for_each(|(vxc_mf_local,exc_local,loc_total_elec)| {
@@ -3634,6 +3882,14 @@ pub struct ScfTraceRecord {
     pub density_matrix: [Vec<MatrixFull<f64>>;2],
     pub target_vector: Vec<[MatrixFull<f64>;2]>,
     pub error_vector: Vec<Vec<f64>>,
+    // the input density matrices and energies of the stored fock matrices for EDIIS and ADIIS
+    pub dm_vector: Vec<Vec<MatrixFull<f64>>>,
+    pub energy_vector: Vec<f64>,
+    // for the second-order scf (mixer = "newton" or "soscf")
+    pub soscf_active: bool,
+    pub soscf_trust_radius: f64,
+    pub soscf_step: [f64;2],
+    pub soscf_prev: Option<(f64, [MatrixUpper<f64>;2], [MatrixFull<f64>;2], [Vec<f64>;2], Vec<MatrixFull<f64>>)>,
 }
 
 impl ScfTraceRecord {
@@ -3659,6 +3915,12 @@ impl ScfTraceRecord {
                               MatrixFull::new([1,1],0.0)]],
             target_vector: Vec::<[MatrixFull<f64>;2]>::new(),
             error_vector: Vec::<Vec::<f64>>::new(),
+            dm_vector: Vec::<Vec<MatrixFull<f64>>>::new(),
+            energy_vector: Vec::<f64>::new(),
+            soscf_active: false,
+            soscf_trust_radius: 0.5,
+            soscf_step: [0.0;2],
+            soscf_prev: None,
         }
     }
     pub fn initialize(scf: &SCF) -> ScfTraceRecord {
@@ -3675,6 +3937,7 @@ impl ScfTraceRecord {
         tmp_records.eigenvectors=scf.eigenvectors.clone();
         tmp_records.eigenvalues=scf.eigenvalues.clone();
         tmp_records.density_matrix=[scf.density_matrix.clone(),scf.density_matrix.clone()];
+        tmp_records.soscf_trust_radius = scf.mol.ctrl.soscf_trust_radius;
         if tmp_records.mixer.eq(&"ddiis") {
             tmp_records.target_vector.push([scf.density_matrix[0].clone(),scf.density_matrix[1].clone()]);
         }
@@ -3722,20 +3985,46 @@ impl ScfTraceRecord {
     ///            - `f_i[out]` is the ith output fock matrix,   
     ///            - `d_i[out]` is the ith output density matrix,  
     ///            - `s` is the overlap matrix  
+    ///            For ROHF, the effective fock matrix from [`rohf_effective_fock`] and the total density matrix are used.
     /// * **Ref**: P. Pulay, Improved SCF Convergence Acceleration, JCC, 1982, 3:556-560.
+    /// * "ediis" or "adiis": the same extrapolation of the fock matrix as "diis", but the coefficients c_i
+    ///            minimize an energy model over the stored fock and density matrices, subject to `c_i>=0`
+    ///            and `sum_{i} c_i = 1`. See [`ediis_solver`] and [`adiis_solver`]. 
+    ///            The check of the energy oscillation is not needed for these energy-based mixers.
+    /// * "ediis+diis" or "adiis+diis": the coefficients of EDIIS (ADIIS) are used if the maximum element
+    ///            of the DIIS error `e` is larger than 0.1 and those of DIIS are used if `e<1.0e-4`.
+    ///            In between, `c = 10*e*c[EDIIS] + (1-10*e)*c[DIIS]`.
+    /// * **Ref**: K. N. Kudin, G. E. Scuseria, and E. Cancès, JCP, 2002, 116:8255-8261.  
+    ///            X. Hu and W. Yang, JCP, 2010, 132:054109.  
+    ///            A. J. Garza and G. E. Scuseria, JCP, 2012, 137:054110.
+    /// * "newton" or "soscf": the "diis" mixing is used until the norm of the DIIS error vector is below 
+    ///            `ctrl.start_soscf_error`. Then the occupied orbitals are rotated by the second-order steps 
+    ///            from the augmented hessian in a trust region, see [`SCF::soscf_rotate_orbitals`].
+    ///            The step is rejected and the trust radius is reduced if the energy increases.
+    ///            It is available for RHF, UHF, RKS and UKS with the RI-V approximation.
     ///
     pub fn prepare_next_input(&mut self, scf: &mut SCF, mpi_operator: &Option<MPIOperator>) {
         let spin_channel = scf.mol.spin_channel;
         let start_pulay = self.start_diis_cycle;
+        let use_soscf = self.mixer.eq(&"newton") || self.mixer.eq(&"soscf");
+        let use_energy_diis = self.mixer.eq(&"ediis") || self.mixer.eq(&"adiis")
+            || self.mixer.eq(&"ediis+diis") || self.mixer.eq(&"adiis+diis");
         //if self.residual_density.len()>=2 {
         let alpha = self.mix_param;
         let beta = 1.0-self.mix_param;
         if self.mixer.eq(&"direct") {
             scf.generate_hf_hamiltonian(mpi_operator);
         }
+        else if use_soscf && self.soscf_active {
+            self.prepare_next_input_soscf(scf, mpi_operator);
+            // the level shift is not applied to the pseudo fock matrix
+            return
+        }
         else if self.mixer.eq(&"linear") 
             || (self.mixer.eq(&"ddiis") && self.num_iter<start_pulay) 
             || (self.mixer.eq(&"diis") && self.num_iter<start_pulay) 
+            || (use_soscf && self.num_iter<start_pulay) 
+            || (use_energy_diis && self.num_iter<start_pulay) 
         {
             let mut alpha = self.mix_param;
             let mut beta = 1.0-alpha;
@@ -3749,7 +4038,7 @@ impl ScfTraceRecord {
                     .unwrap();
             }
             scf.generate_hf_hamiltonian(mpi_operator);
-        } else if self.mixer.eq(&"diis") && self.num_iter>=start_pulay {
+        } else if (self.mixer.eq(&"diis") || use_soscf || use_energy_diis) && self.num_iter>=start_pulay {
             // 
             // Reference: P. Pulay, Improved SCF Convergence Acceleration, JCC, 1982, 3:556-560.
             // 
@@ -3768,7 +4057,7 @@ impl ScfTraceRecord {
             let num_step = self.energy_records.len();
             let oscillation_flag = if num_step >=2 {
                 let change_1 = self.energy_records[num_step-1] - self.energy_records[num_step-2];
-                num_step > start_check_oscillation && change_1 > 0.0
+                num_step > start_check_oscillation && change_1 > 0.0 && ! use_energy_diis
                 //false
             }else {
                 false
@@ -3781,6 +4070,10 @@ impl ScfTraceRecord {
             if self.target_vector.len() == self.num_max_records {
                 self.target_vector.remove(0);
                 self.error_vector.remove(0);
+                if use_energy_diis {
+                    self.dm_vector.remove(0);
+                    self.energy_vector.remove(0);
+                }
             };
 
             //
@@ -3790,13 +4083,52 @@ impl ScfTraceRecord {
             //    //self.target_vector.push([scf.hamiltonian[i_spin].clone(), scf.hamiltonian[i_spin].clone()]);
             //    scf.hamiltonian[i_spin].formated_output(5, "upper");
             //}
-            let (cur_error_vec, cur_target) = generate_diis_error_vector(&scf.hamiltonian, &scf.ovlp, &mut self.density_matrix, spin_channel);
+            let (cur_error_vec, cur_target) = if let SCFType::ROHF = scf.scftype {
+                // for ROHF, the effective fock matrix is extrapolated against the commutator with the total density matrix
+                let fock_eff = rohf_effective_fock(&scf.hamiltonian, &scf.ovlp, &self.density_matrix[1]);
+                let mut dm_total = self.density_matrix[1][0].clone();
+                dm_total.self_scaled_add(&self.density_matrix[1][1], 1.0);
+                let mut tmp_dm = [vec![dm_total.clone()], vec![dm_total]];
+                generate_diis_error_vector(&[fock_eff.clone(), fock_eff], &scf.ovlp, &mut tmp_dm, 1)
+            } else {
+                generate_diis_error_vector(&scf.hamiltonian, &scf.ovlp, &mut self.density_matrix, spin_channel)
+            };
+
+            // switch to the second-order scf from the next step if the DIIS error is small enough
+            if use_soscf {
+                let error_norm = cur_error_vec.iter().fold(0.0, |acc, e| acc + e*e).sqrt();
+                if error_norm < scf.mol.ctrl.start_soscf_error {
+                    if scf.soscf_is_available(mpi_operator) {
+                        if scf.mol.ctrl.print_level>0 {
+                            println!("The DIIS error ({:10.5e}) is below {:10.5e}. Switch to the second-order SCF from the next step.", 
+                                error_norm, scf.mol.ctrl.start_soscf_error);
+                        }
+                        self.soscf_active = true;
+                    } else {
+                        if scf.mol.ctrl.print_level>0 {
+                            println!("WARNING: the second-order SCF is only available for RHF/UHF/RKS/UKS with RI-V and integer occupations. Turn to use the DIIS mixing.");
+                        }
+                        self.mixer = String::from("diis");
+                    }
+                }
+            }
+
+            let cur_error_max = cur_error_vec.iter().fold(0.0_f64, |acc, e| acc.max(e.abs()));
             self.error_vector.push(cur_error_vec);
             self.target_vector.push(cur_target);
+            if use_energy_diis {
+                self.dm_vector.push(self.density_matrix[1].clone());
+                self.energy_vector.push(scf.scf_energy);
+            }
 
 
-            // solve the DIIS against the error vector
-            if let Some(coeff) = diis_solver(&self.error_vector, &self.error_vector.len()) {
+            // solve the DIIS against the error vector, or the EDIIS/ADIIS against the energy model
+            let diis_coeff = if use_energy_diis {
+                self.energy_diis_coefficients(spin_channel, cur_error_max)
+            } else {
+                diis_solver(&self.error_vector, &self.error_vector.len())
+            };
+            if let Some(coeff) = diis_coeff {
                 // now extrapolate the fock matrix for the next step
                 (0..spin_channel).into_iter().for_each(|i_spin| {
                     let mut next_hamiltonian = MatrixFull::new(self.target_vector[0][i_spin].size.clone(),0.0);
@@ -3848,6 +4180,8 @@ impl ScfTraceRecord {
                 self.start_diis_cycle = self.num_iter + 8;
                 self.target_vector =  Vec::<[MatrixFull<f64>;2]>::new();
                 self.error_vector =  Vec::<Vec::<f64>>::new();
+                self.dm_vector =  Vec::<Vec<MatrixFull<f64>>>::new();
+                self.energy_vector =  Vec::<f64>::new();
             }
             let dt3 = time::Local::now();
             let timecost1 = (dt2.timestamp_millis()-dt1.timestamp_millis()) as f64 /1000.0;
@@ -3879,6 +4213,89 @@ impl ScfTraceRecord {
             }
         }
     }
+
+    /// The extrapolation coefficients of the stored fock matrices for the "ediis", "adiis", "ediis+diis"
+    /// and "adiis+diis" mixers, where `error_max` is the maximum element of the current DIIS error vector.
+    /// At most [`EDIIS_MAX_RECORDS`] latest records are used in the energy model.
+    fn energy_diis_coefficients(&self, spin_channel: usize, error_max: f64) -> Option<Vec<f64>> {
+        let num_records = self.target_vector.len();
+        let start = if num_records > EDIIS_MAX_RECORDS {num_records - EDIIS_MAX_RECORDS} else {0};
+
+        // tr_df[i][j] = sum_{s} tr(D_{i,s}*F_{j,s})
+        let tr_df: Vec<Vec<f64>> = (start..num_records).map(|i| {
+            (start..num_records).map(|j| {
+                (0..spin_channel).fold(0.0, |acc, i_spin| {
+                    acc + self.dm_vector[i][i_spin].data.iter().zip(self.target_vector[j][i_spin].data.iter())
+                        .fold(0.0, |acc, (d, f)| acc + d*f)
+                })
+            }).collect()
+        }).collect();
+
+        let coeff_energy = if self.mixer.starts_with("adiis") {
+            adiis_solver(&tr_df)
+        } else {
+            ediis_solver(&self.energy_vector[start..num_records].to_vec(), &tr_df)
+        };
+        let mut coeff = vec![0.0; num_records];
+        coeff[start..num_records].iter_mut().zip(coeff_energy.iter()).for_each(|(to, from)| *to = *from);
+
+        if self.mixer.ends_with("+diis") && error_max < EDIIS_SWITCH_ERROR[0] {
+            if let Some(coeff_diis) = diis_solver(&self.error_vector, &num_records) {
+                let weight = if error_max < EDIIS_SWITCH_ERROR[1] {0.0} else {error_max/EDIIS_SWITCH_ERROR[0]};
+                coeff.iter_mut().zip(coeff_diis.iter()).for_each(|(c, c_diis)| {
+                    *c = weight*(*c) + (1.0-weight)*c_diis
+                });
+            }
+        }
+
+        Some(coeff)
+    }
+
+    /// Prepare the pseudo fock matrix for the next step by the second-order scf in a trust region.
+    /// The energy change of the previous step is compared with the predicted one:
+    /// * ratio < 0.0:  the step is rejected and the previous orbitals and fock matrix are restored
+    /// * ratio < 0.25: the trust radius is halved
+    /// * ratio > 0.75: the trust radius is doubled if the previous step reaches the boundary
+    fn prepare_next_input_soscf(&mut self, scf: &mut SCF, mpi_operator: &Option<MPIOperator>) {
+        scf.generate_hf_hamiltonian(mpi_operator);
+
+        let print_level = scf.mol.ctrl.print_level;
+        let max_trust_radius = 2.0*scf.mol.ctrl.soscf_trust_radius;
+        let [prev_step_norm, pred_change] = self.soscf_step;
+        if let Some((prev_energy, prev_hamiltonian, prev_eigenvectors, prev_eigenvalues, prev_dm)) = &self.soscf_prev {
+            let actual_change = scf.scf_energy - prev_energy;
+            let ratio = if pred_change.abs() > 1.0e-14 {actual_change/pred_change} else {1.0};
+            if actual_change > scf.mol.ctrl.scf_acc_etot && ratio < 0.0 {
+                if print_level>0 {
+                    println!("SOSCF: the energy increases by {:10.5e} Ha. Reject the step and reduce the trust radius.", actual_change);
+                }
+                scf.scf_energy = *prev_energy;
+                scf.hamiltonian = prev_hamiltonian.clone();
+                scf.eigenvectors = prev_eigenvectors.clone();
+                scf.eigenvalues = prev_eigenvalues.clone();
+                scf.density_matrix = prev_dm.clone();
+                self.soscf_trust_radius = 0.25*prev_step_norm;
+            } else if ratio < 0.25 {
+                self.soscf_trust_radius = 0.5*prev_step_norm;
+            } else if ratio > 0.75 && prev_step_norm >= 0.99*self.soscf_trust_radius {
+                self.soscf_trust_radius = (2.0*self.soscf_trust_radius).min(max_trust_radius);
+            }
+            if print_level>1 {
+                println!("SOSCF: energy change (actual, predicted) = ({:16.8}, {:16.8}); trust radius: {:10.5e}", 
+                    actual_change, pred_change, self.soscf_trust_radius);
+            }
+        }
+        self.soscf_trust_radius = self.soscf_trust_radius.max(1.0e-4);
+
+        self.soscf_prev = Some((scf.scf_energy, scf.hamiltonian.clone(), scf.eigenvectors.clone(), 
+                                scf.eigenvalues.clone(), scf.density_matrix.clone()));
+
+        let [g_norm, step_norm, pred_change] = scf.soscf_rotate_orbitals(self.soscf_trust_radius);
+        self.soscf_step = [step_norm, pred_change];
+        if print_level>0 {
+            println!("SOSCF: |g| = {:10.5e}, |x| = {:10.5e}, predicted energy change = {:10.5e} Ha", g_norm, step_norm, pred_change);
+        }
+    }
 }
 
 pub fn generate_diis_error_vector(hamiltonian: &[MatrixUpper<f64>;2], 
@@ -3976,6 +4393,108 @@ pub fn diis_solver(em: &Vec<Vec<f64>>,
 
 }
 
+/// The maximum number of the stored fock matrices used in the EDIIS and ADIIS energy models
+pub const EDIIS_MAX_RECORDS: usize = 10;
+/// The thresholds of the maximum DIIS error to switch from EDIIS (ADIIS) to DIIS in "ediis+diis" and "adiis+diis"
+pub const EDIIS_SWITCH_ERROR: [f64;2] = [1.0e-1, 1.0e-4];
+
+/// The EDIIS coefficients minimizing the energy model
+///     E(c) = sum_{i} c_i*E_i - 1/4 sum_{ij} c_i*c_j*tr[(D_i-D_j)*(F_i-F_j)]
+/// with c_i >= 0 and sum_{i} c_i = 1, where
+/// * `energy[i]`: E_i, the energy of the ith input density matrix D_i,
+/// * `tr_df[i][j]`: sum over spin channels of tr(D_i*F_j), where F_j is the fock matrix of D_j.
+///
+/// The factor of 1/4 corresponds to the total density matrix for RHF (and the spin density matrices for UHF).
+/// * **Ref**: K. N. Kudin, G. E. Scuseria, and E. Cancès, JCP, 2002, 116:8255-8261.
+pub fn ediis_solver(energy: &Vec<f64>, tr_df: &Vec<Vec<f64>>) -> Vec<f64> {
+    let dim = energy.len();
+    let mut quad = MatrixFull::new([dim,dim],0.0);
+    for i in 0..dim {
+        for j in 0..dim {
+            quad[[i,j]] = -0.25*(tr_df[i][i] + tr_df[j][j] - tr_df[i][j] - tr_df[j][i]);
+        }
+    }
+    simplex_quadratic_minimizer(energy, &quad)
+}
+
+/// The ADIIS coefficients minimizing the energy model from the augmented Roothaan-Hall energy
+///     f(c) = sum_{i} c_i*tr[(D_i-D_n)*F_n] + 1/2 sum_{ij} c_i*c_j*tr[(D_i-D_n)*(F_j-F_n)]
+/// with c_i >= 0 and sum_{i} c_i = 1, where n is the latest record 
+/// and `tr_df[i][j]` is the sum over spin channels of tr(D_i*F_j).
+/// * **Ref**: X. Hu and W. Yang, JCP, 2010, 132:054109.
+pub fn adiis_solver(tr_df: &Vec<Vec<f64>>) -> Vec<f64> {
+    let dim = tr_df.len();
+    let n = dim-1;
+    let linear: Vec<f64> = (0..dim).map(|i| tr_df[i][n] - tr_df[n][n]).collect();
+    let mut quad = MatrixFull::new([dim,dim],0.0);
+    for i in 0..dim {
+        for j in 0..dim {
+            let q_ij = tr_df[i][j] - tr_df[i][n] - tr_df[n][j] + tr_df[n][n];
+            let q_ji = tr_df[j][i] - tr_df[j][n] - tr_df[n][i] + tr_df[n][n];
+            quad[[i,j]] = 0.25*(q_ij + q_ji);
+        }
+    }
+    simplex_quadratic_minimizer(&linear, &quad)
+}
+
+/// Minimize `f(c) = sum_{i} a_i*c_i + sum_{ij} c_i*Q_ij*c_j` on the simplex of `c_i>=0` and `sum_{i} c_i = 1`.
+///
+/// The minimum of a quadratic function on the simplex is a stationary point in the interior of one of its faces.
+/// All faces are therefore enumerated by solving the equality-constrained stationary conditions, which is
+/// affordable for the small number of stored records. It works for indefinite `Q` as well.
+pub fn simplex_quadratic_minimizer(linear: &Vec<f64>, quad: &MatrixFull<f64>) -> Vec<f64> {
+    let dim = linear.len();
+    let objective = |c: &Vec<f64>| {
+        let mut value = linear.iter().zip(c.iter()).fold(0.0, |acc, (a, c)| acc + a*c);
+        for i in 0..dim {
+            for j in 0..dim {
+                value += c[i]*quad[[i,j]]*c[j];
+            }
+        }
+        value
+    };
+
+    let mut coeff_min = vec![0.0; dim];
+    coeff_min[dim-1] = 1.0;
+    let mut value_min = objective(&coeff_min);
+    for subset in 1..(1_usize<<dim) {
+        let index: Vec<usize> = (0..dim).filter(|i| subset & (1<<i) != 0).collect();
+        let size = index.len();
+        let coeff_sub = if size == 1 {
+            vec![1.0]
+        } else {
+            // | 2Q_SS  1 | | c  |   | -a |
+            // | 1^T    0 | | mu | = |  1 |
+            let mut kkt = MatrixFull::new([size+1,size+1],0.0);
+            for (i, ii) in index.iter().enumerate() {
+                for (j, jj) in index.iter().enumerate() {
+                    kkt[[i,j]] = quad[[*ii,*jj]] + quad[[*jj,*ii]];
+                }
+                kkt[[i,size]] = 1.0;
+                kkt[[size,i]] = 1.0;
+            }
+            let mut rhs: Vec<f64> = index.iter().map(|i| -linear[*i]).collect();
+            rhs.push(1.0);
+            let inv_kkt = if let Some(inv_kkt) = _dinverse(&mut kkt) {inv_kkt} else {continue};
+            let sol: Vec<f64> = (0..size).map(|i| {
+                (0..size+1).fold(0.0, |acc, j| acc + inv_kkt[[i,j]]*rhs[j])
+            }).collect();
+            if sol.iter().any(|c| *c < -1.0e-12) {continue}
+            sol
+        };
+        let mut coeff = vec![0.0; dim];
+        index.iter().zip(coeff_sub.iter()).for_each(|(i, c)| coeff[*i] = c.max(0.0));
+        let sum = coeff.iter().sum::<f64>();
+        coeff.iter_mut().for_each(|c| *c /= sum);
+        let value = objective(&coeff);
+        if value < value_min {
+            value_min = value;
+            coeff_min = coeff;
+        }
+    }
+    coeff_min
+}
+
 pub fn scf(mol:Molecule, mpi_operator: &Option<MPIOperator>) -> anyhow::Result<SCF> {
     let dt0 = time::Local::now();
 
@@ -4107,6 +4626,62 @@ pub fn level_shift_fock(fock: &mut MatrixUpper<f64>, ovlp: &MatrixUpper<f64>, le
     fock.data.iter_mut().zip(tmp_s3.iter_matrixupper().unwrap()).for_each(|(to, from)| {*to += *from*level_shift});
 }
 
+/// The effective fock matrix of ROHF using the Guest-Saunders canonicalization:
+///
+///              | closed     open     virtual
+///     ---------+---------------------------
+///     closed   |   Fc        Fb        Fc
+///     open     |   Fb        Fc        Fa
+///     virtual  |   Fc        Fa        Fc
+///
+/// where `Fc = (Fa+Fb)/2`. The projectors onto the closed-, open-shell and virtual spaces are 
+/// `Pc = Db*S`, `Po = (Da-Db)*S` and `Pv = 1-Da*S`, respectively.
+/// If the density matrices are not yet available, `Fc` is returned.
+/// * **Ref**: M. F. Guest and V. R. Saunders, Mol. Phys., 1974, 28:819-828.
+pub fn rohf_effective_fock(hamiltonian: &[MatrixUpper<f64>;2], ovlp: &MatrixUpper<f64>, dm: &Vec<MatrixFull<f64>>) -> MatrixUpper<f64> {
+    let fock_a = hamiltonian[0].to_matrixfull().unwrap();
+    let fock_b = hamiltonian[1].to_matrixfull().unwrap();
+    let num_basis = fock_a.size[0];
+
+    let mut fock_c = fock_a.clone();
+    fock_c.self_scaled_add(&fock_b, 1.0);
+    fock_c.data.iter_mut().for_each(|f| *f *= 0.5);
+
+    if dm.len() < 2 || dm[0].size[0] != num_basis || dm[1].size[0] != num_basis {
+        return fock_c.to_matrixupper()
+    }
+
+    let full_ovlp = ovlp.to_matrixfull().unwrap();
+    let mut proj_c = MatrixFull::new([num_basis,num_basis],0.0);
+    _dgemm_full(&dm[1], 'N', &full_ovlp, 'N', &mut proj_c, 1.0, 0.0);
+    let mut proj_a = MatrixFull::new([num_basis,num_basis],0.0);
+    _dgemm_full(&dm[0], 'N', &full_ovlp, 'N', &mut proj_a, 1.0, 0.0);
+    let mut proj_o = proj_a.clone();
+    proj_o.self_scaled_add(&proj_c, -1.0);
+    let mut proj_v = MatrixFull::new([num_basis,num_basis],0.0);
+    proj_v.self_scaled_add(&proj_a, -1.0);
+    (0..num_basis).for_each(|i| proj_v.data[i*num_basis+i] += 1.0);
+
+    // fock += factor * p^{T} * f * q
+    let mut fock = MatrixFull::new([num_basis,num_basis],0.0);
+    let mut tmp_mat = MatrixFull::new([num_basis,num_basis],0.0);
+    let mut sandwich = |p: &MatrixFull<f64>, f: &MatrixFull<f64>, q: &MatrixFull<f64>, factor: f64, fock: &mut MatrixFull<f64>| {
+        _dgemm_full(p, 'T', f, 'N', &mut tmp_mat, 1.0, 0.0);
+        _dgemm_full(&tmp_mat, 'N', q, 'N', fock, factor, 1.0);
+    };
+    sandwich(&proj_c, &fock_c, &proj_c, 0.5, &mut fock);
+    sandwich(&proj_o, &fock_c, &proj_o, 0.5, &mut fock);
+    sandwich(&proj_v, &fock_c, &proj_v, 0.5, &mut fock);
+    sandwich(&proj_o, &fock_b, &proj_c, 1.0, &mut fock);
+    sandwich(&proj_o, &fock_a, &proj_v, 1.0, &mut fock);
+    sandwich(&proj_v, &fock_c, &proj_c, 1.0, &mut fock);
+
+    let fock_t = fock.transpose();
+    fock.self_add(&fock_t);
+
+    fock.to_matrixupper()
+}
+
 pub fn diagonalize_hamiltonian_outside(scf_data: &SCF, mpi_operator: &Option<MPIOperator>) -> ([MatrixFull<f64>;2], [Vec<f64>;2], usize) {
     let mut eigenvectors = [MatrixFull::empty(),MatrixFull::empty()];
     let mut eigenvalues = [Vec::new(),Vec::new()];
@@ -4145,7 +4720,14 @@ pub fn diagonalize_hamiltonian_outside_fast(scf_data: &SCF)  -> ([MatrixFull<f64
 
     match scf_data.scftype {
         SCFType::ROHF => {
-            panic!("ROHF is not yet implemented")
+            // the same set of orbitals for both spin channels from the effective fock matrix
+            let fock_eff = rohf_effective_fock(&scf_data.hamiltonian, &scf_data.ovlp, &scf_data.density_matrix);
+            let (eigenvector, eigenvalue)=
+                _hamiltonian_fast_solver(&fock_eff, &scf_data.ovlp, &mut num_state).unwrap();
+            eigenvectors[1] = eigenvector.clone();
+            eigenvalues[1] = eigenvalue.clone();
+            eigenvectors[0] = eigenvector;
+            eigenvalues[0] = eigenvalue;
         },
         _ => {
             for i_spin in (0..spin_channel) {
@@ -4173,19 +4755,14 @@ pub fn diagonalize_hamiltonian_outside_rayon(scf_data: &SCF) -> ([MatrixFull<f64
 
     match scf_data.scftype {
         SCFType::ROHF => {
-            panic!("ROHF is not yet implemented");
-            //let (eigenvector_spin, eigenvalue_spin, num_state_out)=
-            //    _dspgvx(&scf_data.hamiltonian[0], &scf_data.ovlp, num_state).unwrap();
-            //    //self.hamiltonian[0].to_matrixupperslicemut()
-            //    //.lapack_dspgvx(self.ovlp.to_matrixupperslicemut(),num_state).unwrap();
-            ////self.eigenvectors[0] = eigenvector_spin;
-            ////self.eigenvalues[0] = eigenvalue_spin;
-            ////self.eigenvectors[1] = self.eigenvectors[0].clone();
-            ////self.eigenvalues[1] = self.eigenvalues[0].clone();
-            //eigenvectors[0] = eigenvector_spin;
-            //eigenvectors[1] = eigenvectors[0].clone();
-            //eigenvalues[0] = eigenvalue_spin;
-            //eigenvalues[1] = eigenvalues[0].clone();
+            let fock_eff = rohf_effective_fock(&scf_data.hamiltonian, &scf_data.ovlp, &scf_data.density_matrix);
+            let (eigenvector, eigenvalue, tmp_num_state_out)=
+                _dspgvx(&fock_eff, &scf_data.ovlp, num_state).unwrap();
+            eigenvectors[1] = eigenvector.clone();
+            eigenvalues[1] = eigenvalue.clone();
+            eigenvectors[0] = eigenvector;
+            eigenvalues[0] = eigenvalue;
+            num_state_out = tmp_num_state_out;
         },
         _ => {
             for i_spin in (0..spin_channel) {
@@ -4206,6 +4783,14 @@ pub fn diagonalize_hamiltonian_outside_rayon(scf_data: &SCF) -> ([MatrixFull<f64
 }
 
 pub fn generate_occupation_outside(scf_data: &SCF) -> ([Vec<f64>;2], [usize;2], [usize;2]) {
+    let (occ, homo, lumo, _) = generate_occupation_and_smearing_outside(scf_data);
+    (occ, homo, lumo)
+}
+
+/// Generate the occupation as [`generate_occupation_outside`], together with the fermi levels and
+/// the entropy term (-sigma*S) for the smearing occupations, which are thus evaluated once per iteration
+pub fn generate_occupation_and_smearing_outside(scf_data: &SCF) -> ([Vec<f64>;2], [usize;2], [usize;2], Option<([f64;2], f64)>) {
+    let mut smearing = None;
     let mut occ = [vec![],vec![]];
     let mut homo = [0,0];
     let mut lumo = [0,0];
@@ -4218,6 +4803,11 @@ pub fn generate_occupation_outside(scf_data: &SCF) -> ([Vec<f64>;2], [usize;2],
         },
         OCCType::FRAC => {
             (occ,homo,lumo) = generate_occupation_frac_occ(&scf_data.mol,&scf_data.scftype, &scf_data.eigenvalues, scf_data.mol.ctrl.frac_tolerant);
+        },
+        OCCType::FERMI | OCCType::GAUSSIAN => {
+            let (fermi_level, entropy_term);
+            (occ,homo,lumo,fermi_level,entropy_term) = generate_occupation_smearing(&scf_data.mol,&scf_data.scftype, &scf_data.eigenvalues);
+            smearing = Some((fermi_level, entropy_term));
         }
     }
 
@@ -4263,7 +4853,7 @@ pub fn generate_occupation_outside(scf_data: &SCF) -> ([Vec<f64>;2], [usize;2],
 
 
 
-    (occ, homo, lumo)
+    (occ, homo, lumo, smearing)
 }
 
 pub fn generate_density_matrix_outside(scf_data: &SCF) -> Vec<MatrixFull<f64>>{
@@ -4339,6 +4929,11 @@ pub fn initialize_scf(scf_data: &mut SCF, mpi_operator: &Option<MPIOperator>) {
     scf_data.prepare_isdf(mpi_operator);
     time_mark.count("ISDF");
 
+    time_mark.new_item("Solvation", "Construction of the solvation cavity");
+    time_mark.count_start("Solvation");
+    scf_data.solvation = PCM::build(&scf_data.mol).unwrap_or_else(|err| panic!("{}", err));
+    time_mark.count("Solvation");
+
     time_mark.new_item("InitGuess", "Prepare initial guess");
     time_mark.count_start("InitGuess");
     initial_guess(scf_data, mpi_operator);
@@ -4440,7 +5035,26 @@ pub fn scf_without_build(scf_data: &mut SCF, mpi_operator: &Option<MPIOperator>)
                 scf_data.generate_density_matrix();
                 scf_data.generate_hf_hamiltonian(mpi_operator);
 
-            }
+            },
+            OCCType::FERMI | OCCType::GAUSSIAN => {
+                // the electronic free energy and the extrapolated energy to zero smearing width
+                if let Some(smearing) = scf_data.energies.get("smearing_entropy") {
+                    let entropy_term = smearing[0];
+                    let free_energy = scf_data.scf_energy + entropy_term;
+                    let zero_width_energy = scf_data.scf_energy + 0.5*entropy_term;
+                    if scf_data.mol.ctrl.print_level>0 {
+                        if scf_data.mol.spin_channel == 1 {
+                            println!("Fermi level: {:16.8} Ha", smearing[1]);
+                        } else {
+                            println!("Fermi levels: ({:16.8}, {:16.8}) Ha", smearing[1], smearing[2]);
+                        }
+                        println!("Smearing entropy term (-sigma*S): {:18.10} Ha", entropy_term);
+                        println!("Free energy (E-sigma*S):          {:18.10} Ha", free_energy);
+                        println!("Energy extrapolated to sigma->0:  {:18.10} Ha", zero_width_energy);
+                    }
+                    scf_data.energies.insert(String::from("smearing"), vec![entropy_term, free_energy, zero_width_energy]);
+                }
+            },
             _ => {}
         }
         // not yet implemented. Just an empty subroutine
@@ -4787,3 +5401,161 @@ pub fn evaluate_spin_angular_momentum(dm: &Vec<MatrixFull<f64>>, ovlp: &MatrixUp
 fn test_max() {
     println!("{}, {}, {}",1,2,std::cmp::max(1, 2));
 }
+
+#[test]
+fn test_simplex_quadratic_minimizer() {
+    // the unconstrained minimum of (c_0-0.3)^2 + (c_1-0.7)^2 + c_2^2 lies on the face of c_2 = 0
+    let linear = vec![-0.6, -1.4, 0.0];
+    let mut quad = MatrixFull::new([3,3],0.0);
+    (0..3).for_each(|i| quad[[i,i]] = 1.0);
+    let coeff = simplex_quadratic_minimizer(&linear, &quad);
+    assert!((coeff[0]-0.3).abs() < 1.0e-10);
+    assert!((coeff[1]-0.7).abs() < 1.0e-10);
+    assert!(coeff[2].abs() < 1.0e-10);
+    // for a concave function, the minimum is on a vertex
+    let energy = vec![-1.0, -2.0, -1.5];
+    let tr_df = vec![vec![0.0;3];3];
+    let coeff = ediis_solver(&energy, &tr_df);
+    assert!((coeff[1]-1.0).abs() < 1.0e-12);
+}
+
+/// Run the SCF calculation for the input in the toml format
+#[cfg(test)]
+fn scf_for_test(ctrl_str: &str) -> SCF {
+    use crate::ctrl_io::InputKeywords;
+    let tmp_keys = toml::from_str::<serde_json::Value>(ctrl_str).unwrap();
+    let (ctrl, geom) = InputKeywords::parse_ctl_from_json(&tmp_keys).unwrap();
+    let mol = Molecule::build_native(ctrl, geom, None).unwrap();
+    scf(mol, &None).unwrap()
+}
+
+#[test]
+fn test_rohf_energy_and_spin() {
+    // the linear H3 radical with the exact four-center integrals, where the reference ROHF energy
+    // is obtained by an independent implementation of the Guest-Saunders ROHF
+    let scf_data = scf_for_test("[ctrl]
+        print_level = 0
+        xc = \"hf\"
+        basis_path = \"basis-set-pool/6-31G\"
+        eri_type = \"analytic\"
+        spin = 2.0
+        spin_polarization = false
+        scf_acc_rho = 1.0e-10
+        scf_acc_eev = 1.0e-10
+        scf_acc_etot = 1.0e-12
+        [geom]
+        name = \"H3\"
+        unit = \"Bohr\"
+        position = \"\"\"
+            H   0.000   0.000   0.000
+            H   0.000   0.000   1.700
+            H   0.000   0.000   3.500\"\"\"");
+    assert!(matches!(scf_data.scftype, SCFType::ROHF));
+    assert!((scf_data.scf_energy - (-1.585862979)).abs() < 1.0e-7, "ROHF energy: {}", scf_data.scf_energy);
+    let [square_spin, _] = evaluate_spin_angular_momentum(&scf_data.density_matrix, &scf_data.ovlp, 2, &scf_data.mol.num_elec);
+    assert!((square_spin - 0.75).abs() < 1.0e-10, "<S^2> = {}", square_spin);
+
+    // the triplet CH2 is spin-pure with ROHF, and lies above UHF
+    let ch2_str = |spin_polarization: bool| format!("[ctrl]
+        print_level = 0
+        xc = \"hf\"
+        basis_path = \"basis-set-pool/def2-SVP\"
+        auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+        spin = 3.0
+        spin_polarization = {}
+        scf_acc_rho = 1.0e-9
+        scf_acc_eev = 1.0e-9
+        scf_acc_etot = 1.0e-11
+        [geom]
+        name = \"CH2\"
+        unit = \"Angstrom\"
+        position = \"\"\"
+            C   0.000   0.000   0.100
+            H   0.000   0.990  -0.300
+            H   0.000  -0.990  -0.300\"\"\"", spin_polarization);
+    let rohf_data = scf_for_test(&ch2_str(false));
+    let uhf_data = scf_for_test(&ch2_str(true));
+    let [square_spin, _] = evaluate_spin_angular_momentum(&rohf_data.density_matrix, &rohf_data.ovlp, 2, &rohf_data.mol.num_elec);
+    assert!((square_spin - 2.0).abs() < 1.0e-10, "<S^2> = {}", square_spin);
+    let e_diff = rohf_data.scf_energy - uhf_data.scf_energy;
+    assert!(e_diff > 0.0 && e_diff < 2.0e-2, "E(ROHF) - E(UHF) = {}", e_diff);
+}
+
+#[test]
+fn test_roks_reduces_to_rks_for_closed_shell() {
+    use crate::ctrl_io::InputKeywords;
+    let ctrl_str = "[ctrl]
+        print_level = 0
+        xc = \"pbe\"
+        basis_path = \"basis-set-pool/def2-SVP\"
+        auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+        scf_acc_rho = 1.0e-10
+        scf_acc_eev = 1.0e-10
+        scf_acc_etot = 1.0e-12
+        [geom]
+        name = \"H2O\"
+        unit = \"Angstrom\"
+        position = \"\"\"
+            O   0.000   0.000   0.120
+            H   0.000   0.760  -0.480
+            H   0.000  -0.760  -0.480\"\"\"";
+    let rks_data = scf_for_test(ctrl_str);
+    assert!(matches!(rks_data.scftype, SCFType::RHF));
+    // the same closed-shell molecule through the ROKS path with two spin channels
+    let tmp_keys = toml::from_str::<serde_json::Value>(ctrl_str).unwrap();
+    let (ctrl, geom) = InputKeywords::parse_ctl_from_json(&tmp_keys).unwrap();
+    let mol = Molecule::build_native(ctrl, geom, None).unwrap();
+    let mut roks_data = SCF::init_scf(&mol);
+    roks_data.scftype = SCFType::ROHF;
+    roks_data.mol.ctrl.spin_channel = 2;
+    roks_data.mol.spin_channel = 2;
+    initialize_scf(&mut roks_data, &None);
+    scf_without_build(&mut roks_data, &None);
+    assert!((roks_data.scf_energy - rks_data.scf_energy).abs() < 1.0e-8,
+        "ROKS {} vs RKS {}", roks_data.scf_energy, rks_data.scf_energy);
+}
+
+/// The inputs of the water molecule for the tests of the SCF mixers
+#[cfg(test)]
+fn water_ctrl_for_test(keywords: &str, oh_length: f64) -> String {
+    format!("[ctrl]
+        print_level = 0
+        {}
+        basis_path = \"basis-set-pool/def2-SVP\"
+        auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+        max_scf_cycle = 200
+        scf_acc_rho = 1.0e-9
+        scf_acc_eev = 1.0e-9
+        scf_acc_etot = 1.0e-11
+        [geom]
+        name = \"H2O\"
+        unit = \"Angstrom\"
+        position = \"\"\"
+            O   0.000   0.000   0.000
+            H   0.000   {:.6}   {:.6}
+            H   0.000  -{:.6}   {:.6}\"\"\"", keywords, oh_length*0.7907, -oh_length*0.6122, oh_length*0.7907, -oh_length*0.6122)
+}
+
+#[test]
+fn test_soscf_energy_against_diis() {
+    // RHF, RKS and UHF (for the cation)
+    for keywords in ["xc = \"hf\"", "xc = \"pbe\"", "xc = \"hf\"\n        charge = 1.0\n        spin = 2.0\n        spin_polarization = true"] {
+        let diis_data = scf_for_test(&water_ctrl_for_test(&format!("{}\n        mixer = \"diis\"", keywords), 0.96));
+        let soscf_data = scf_for_test(&water_ctrl_for_test(&format!("{}\n        mixer = \"soscf\"", keywords), 0.96));
+        assert!((soscf_data.scf_energy - diis_data.scf_energy).abs() < 1.0e-8,
+            "{}: SOSCF {} vs DIIS {}", keywords, soscf_data.scf_energy, diis_data.scf_energy);
+    }
+}
+
+#[test]
+fn test_energy_diis_against_diis() {
+    // the stretched water from the core hamiltonian, which is hard to converge
+    let ctrl_str = |mixer: &str| water_ctrl_for_test(
+        &format!("xc = \"hf\"\n        initial_guess = \"hcore\"\n        mixer = \"{}\"", mixer), 1.8);
+    let diis_energy = scf_for_test(&ctrl_str("diis")).scf_energy;
+    // the pure interpolation of EDIIS (ADIIS) converges only linearly close to the solution
+    for (mixer, tolerance) in [("ediis", 1.0e-6), ("adiis", 1.0e-6), ("adiis+diis", 1.0e-8)] {
+        let scf_energy = scf_for_test(&ctrl_str(mixer)).scf_energy;
+        assert!((scf_energy - diis_energy).abs() < tolerance, "{}: {} vs DIIS {}", mixer, scf_energy, diis_energy);
+    }
+}
@@ -0,0 +1,305 @@
+//! Implicit solvation by the conductor-like polarizable continuum model (C-PCM) and COSMO.
+//!
+//! The solute cavity is the union of the atomic spheres with the Bondi radii scaled by 1.2, and the
+//! sphere surfaces are discretized by the Lebedev grids. Following Scalmani and Frisch[^1], the surface
+//! charges are smeared by Gaussians and the points buried by the neighboring spheres are switched off
+//! smoothly, so that the Coulomb matrix `S` of the surface charges is well conditioned:
+//! ```text
+//!   S_ii = zeta_i sqrt(2/pi)/F_i,  S_ij = erf(zeta_ij r_ij)/r_ij,  zeta_ij = zeta_i zeta_j/sqrt(zeta_i^2+zeta_j^2)
+//! ```
+//! The surface charges `q = -f_eps S^{-1} V` are polarized by the total electrostatic potential `V` of
+//! the solute, including the MM point charges, on the surface points, with `f_eps = (eps-1)/eps` for C-PCM
+//! and `(eps-1)/(eps+1/2)` for COSMO. The electrostatic solvation energy is `G = 1/2 q.V`, and the reaction
+//! field `-\sum_i q_i <i|1/|r-s_i||j>` is added to the Fock matrix and included self-consistently.
+//! The non-electrostatic (cavitation, dispersion and repulsion) contributions are not included.
+//!
+//! The solvation contributions to the analytic gradients are not yet available, so the nuclear gradients
+//! in solution are evaluated by finite differences.
+//!
+//! [^1]: G. Scalmani and M. J. Frisch, J. Chem. Phys. 132, 114110 (2010).
+use rayon::prelude::*;
+use rest_libcint::prelude::*;
+use libm::erf;
+use std::f64::consts::PI;
+use tensors::matrix_blas_lapack::{_dgemv, _dinverse};
+use tensors::MatrixFull;
+use crate::constants::ANG;
+use crate::dft::gen_grids::lebedev::{angular_grid, get_closest_num_angular};
+use crate::geom_io::{get_mass_charge, MOrC};
+use crate::molecule_io::Molecule;
+
+/// The exponent parameter of the Gaussian surface charges, zeta_i = ZETA/sqrt(a_i)
+const ZETA: f64 = 4.9;
+/// The scaling factor of the Bondi radii for the cavity
+const RADII_SCALE: f64 = 1.2;
+/// The surface points with the switching functions below this threshold are removed
+const SWITCH_THRESHOLD: f64 = 1.0e-8;
+
+/// The static dielectric constants of the common solvents at 298 K
+pub fn dielectric_constant(solvent: &str) -> Option<f64> {
+    let name = solvent.to_lowercase().replace(&['-', ',', ' ', '_'][..], "");
+    match name.as_str() {
+        "water" | "h2o" => Some(78.3553),
+        "methanol" => Some(32.613),
+        "ethanol" => Some(24.852),
+        "acetonitrile" => Some(35.688),
+        "dimethylsulfoxide" | "dmso" => Some(46.826),
+        "nndimethylformamide" | "dmf" => Some(37.219),
+        "acetone" => Some(20.493),
+        "nitromethane" => Some(36.562),
+        "pyridine" => Some(12.978),
+        "dichloromethane" | "ch2cl2" => Some(8.93),
+        "tetrahydrofuran" | "thf" => Some(7.4257),
+        "ethylacetate" => Some(5.9867),
+        "chloroform" | "chcl3" => Some(4.7113),
+        "diethylether" | "ether" => Some(4.2400),
+        "toluene" => Some(2.3741),
+        "benzene" => Some(2.2706),
+        "carbontetrachloride" | "ccl4" => Some(2.2280),
+        "14dioxane" | "dioxane" => Some(2.2099),
+        "cyclohexane" => Some(2.0165),
+        "heptane" | "nheptane" => Some(1.9113),
+        "hexane" | "nhexane" => Some(1.8819),
+        _ => None
+    }
+}
+
+/// The Bondi van der Waals radii (in Angstrom), with those of Mantina et al. for the missing main-group elements.
+/// 2.0 Angstrom is used for the other elements
+pub fn bondi_radius(charge: usize) -> f64 {
+    match charge {
+        1 => 1.20, 2 => 1.40,
+        3 => 1.82, 4 => 1.53, 5 => 1.92, 6 => 1.70, 7 => 1.55, 8 => 1.52, 9 => 1.47, 10 => 1.54,
+        11 => 2.27, 12 => 1.73, 13 => 1.84, 14 => 2.10, 15 => 1.80, 16 => 1.80, 17 => 1.75, 18 => 1.88,
+        19 => 2.75, 20 => 2.31, 31 => 1.87, 32 => 2.11, 33 => 1.85, 34 => 1.90, 35 => 1.85, 36 => 2.02,
+        37 => 3.03, 38 => 2.49, 49 => 1.93, 50 => 2.17, 51 => 2.06, 52 => 2.06, 53 => 1.98, 54 => 2.16,
+        _ => 2.0
+    }
+}
+
+#[derive(Clone)]
+pub struct Cavity {
+    /// the surface points (in Bohr)
+    pub points: Vec<[f64;3]>,
+    /// the areas of the surface elements scaled by the switching functions
+    pub areas: Vec<f64>,
+    /// the Coulomb matrix `S` of the Gaussian surface charges
+    pub coulomb: MatrixFull<f64>,
+}
+
+impl Cavity {
+    /// Discretize the union of the spheres by `num_points` Lebedev points on each sphere
+    pub fn new(centers: &[[f64;3]], radii: &[f64], num_points: usize) -> Cavity {
+        let (unit_points, unit_weights) = angular_grid(get_closest_num_angular(num_points));
+        let mut points: Vec<[f64;3]> = vec![];
+        let mut areas: Vec<f64> = vec![];
+        let mut zetas: Vec<f64> = vec![];
+        let mut switches: Vec<f64> = vec![];
+        centers.iter().zip(radii.iter()).enumerate().for_each(|(a, (r_a, rad_a))| {
+            unit_points.iter().zip(unit_weights.iter()).for_each(|((x, y, z), w)| {
+                let point = [r_a[0] + rad_a*x, r_a[1] + rad_a*y, r_a[2] + rad_a*z];
+                let area = 4.0*PI*rad_a*rad_a*w;
+                let zeta = ZETA/area.sqrt();
+                let switch = centers.iter().zip(radii.iter()).enumerate()
+                    .filter(|(b, _)| *b != a)
+                    .fold(1.0, |acc, (_, (r_b, rad_b))| {
+                        let dist = distance(&point, r_b);
+                        acc*(1.0 - 0.5*(erf(zeta*(rad_b - dist)) + erf(zeta*(rad_b + dist))))
+                    });
+                if switch > SWITCH_THRESHOLD {
+                    points.push(point);
+                    areas.push(area*switch);
+                    zetas.push(zeta);
+                    switches.push(switch);
+                }
+            });
+        });
+
+        let num_points = points.len();
+        let mut coulomb = MatrixFull::new([num_points, num_points], 0.0);
+        coulomb.data.par_chunks_mut(num_points).enumerate().for_each(|(j, s_j)| {
+            s_j.iter_mut().enumerate().for_each(|(i, s_ij)| {
+                *s_ij = if i == j {
+                    zetas[i]*(2.0/PI).sqrt()/switches[i]
+                } else {
+                    let zeta_ij = zetas[i]*zetas[j]/(zetas[i]*zetas[i] + zetas[j]*zetas[j]).sqrt();
+                    let dist = distance(&points[i], &points[j]);
+                    erf(zeta_ij*dist)/dist
+                };
+            });
+        });
+
+        Cavity {points, areas, coulomb}
+    }
+}
+
+#[derive(Clone)]
+pub struct PCM {
+    pub cavity: Cavity,
+    pub epsilon: f64,
+    /// f_eps = (eps-1)/(eps+x) with x = 0 for C-PCM and x = 1/2 for COSMO
+    pub f_epsilon: f64,
+    /// the response matrix `-f_eps S^{-1}` that gives the surface charges from the solute potential
+    response: MatrixFull<f64>,
+    /// the potential of the nuclei and the MM point charges on the surface points
+    nuc_potential: Vec<f64>,
+    /// the integrals `<j|1/|r-s_i||k>` in the packed upper format for each surface point `s_i`: [num_baspar, num_points]
+    rinv: MatrixFull<f64>,
+}
+
+impl PCM {
+    /// Build the solvation model specified by the keywords of `solvent`, `solvent_eps`, `solvent_model`
+    /// and `solvent_grid_points`. Return None for the gas-phase calculations
+    pub fn build(mol: &Molecule) -> anyhow::Result<Option<PCM>> {
+        let ctrl = &mol.ctrl;
+        if ctrl.solvent.eq("none") && ctrl.solvent_eps.is_none() {return Ok(None)}
+        if let MOrC::Crystal = mol.geom.pbc {
+            anyhow::bail!("The implicit solvation is not available in the periodic calculations");
+        }
+        let epsilon = solvent_epsilon(&ctrl.solvent, ctrl.solvent_eps)?;
+        let f_epsilon = if ctrl.solvent_model.eq("cosmo") {
+            (epsilon - 1.0)/(epsilon + 0.5)
+        } else {
+            (epsilon - 1.0)/epsilon
+        };
+
+        let centers = atom_positions(mol);
+        let radii: Vec<f64> = get_mass_charge(&mol.geom.elem).iter()
+            .map(|(_, z)| bondi_radius(*z as usize)*RADII_SCALE/ANG).collect();
+        let cavity = Cavity::new(&centers, &radii, ctrl.solvent_grid_points);
+
+        let mut coulomb = cavity.coulomb.clone();
+        let mut response = _dinverse(&mut coulomb).ok_or_else(|| {
+            anyhow::anyhow!("The Coulomb matrix of the surface charges is singular")
+        })?;
+        response.data.iter_mut().for_each(|x| *x *= -f_epsilon);
+
+        let nuc_charges: Vec<f64> = mol.cint_atm.iter().take(centers.len()).map(|atm| atm[0] as f64).collect();
+        let pc_positions: Vec<[f64;3]> = mol.geom.ghost_pc_pos.iter_columns_full().map(|r| [r[0], r[1], r[2]]).collect();
+        let nuc_potential: Vec<f64> = cavity.points.iter().map(|s| {
+            centers.iter().zip(nuc_charges.iter()).fold(0.0, |acc, (r, z)| acc + z/distance(s, r))
+            + pc_positions.iter().zip(mol.geom.ghost_pc_chrg.iter()).fold(0.0, |acc, (r, q)| acc + q/distance(s, r))
+        }).collect();
+
+        // the integrals do not change during the SCF iterations, and are evaluated once for each surface point
+        let num_basis = mol.num_basis;
+        let num_baspar = (num_basis+1)*num_basis/2;
+        let num_points = cavity.points.len();
+        let mut rinv = MatrixFull::new([num_baspar, num_points], 0.0);
+        rinv.data.par_chunks_mut(num_baspar).zip(cavity.points.par_iter()).for_each_init(|| mol.initialize_cint(false), |cint_data, (to, point)| {
+            cint_data.set_rinv_origin(&point[..]);
+            let (buf, _) = cint_data.integral_s2ij::<int1e_rinv>(None);
+            to.iter_mut().zip(buf.iter()).for_each(|(to, from)| *to = *from);
+        });
+
+        if ctrl.print_level > 0 {
+            println!("Solvation cavity: {} surface points with the area of {:12.4} Bohr^2, dielectric constant: {:10.4}",
+                num_points, cavity.areas.iter().sum::<f64>(), epsilon);
+        }
+
+        Ok(Some(PCM {cavity, epsilon, f_epsilon, response, nuc_potential, rinv}))
+    }
+
+    /// The surface charges `q = -f_eps S^{-1} V` for the potential `V` on the surface points
+    pub fn surface_charges(&self, potential: &Vec<f64>) -> Vec<f64> {
+        let mut charges = vec![0.0; potential.len()];
+        _dgemv(&self.response, potential, &mut charges, 'N', 1.0, 0.0, 1, 1);
+        charges
+    }
+
+    /// The reaction field for the given density matrices, returned in the packed upper format as the Fock matrix,
+    /// together with the electrostatic solvation energy `1/2 q.V` and its part `1/2 q.V_nuc` of the nuclei and the MM point charges
+    pub fn reaction_field(&self, mol: &Molecule, dm: &[MatrixFull<f64>]) -> (Vec<f64>, f64, f64) {
+        let num_basis = mol.num_basis;
+        let num_baspar = (num_basis+1)*num_basis/2;
+        // the total density matrix in the packed upper format with the off-diagonal terms doubled
+        let mut dm_upper = vec![0.0; num_baspar];
+        dm.iter().take(mol.spin_channel).for_each(|dm_s| {
+            dm_upper.iter_mut().zip(dm_s.iter_matrixupper().unwrap()).for_each(|(to, from)| *to += 2.0*from);
+        });
+        (0..num_basis).for_each(|j| dm_upper[(j+1)*j/2 + j] *= 0.5);
+
+        // the total electrostatic potential on the surface points
+        let mut potential = self.nuc_potential.clone();
+        _dgemv(&self.rinv, &dm_upper, &mut potential, 'T', -1.0, 1.0, 1, 1);
+
+        let charges = self.surface_charges(&potential);
+        let solvation_energy = 0.5*charges.iter().zip(potential.iter()).fold(0.0, |acc, (q, v)| acc + q*v);
+        let nuclear_energy = 0.5*charges.iter().zip(self.nuc_potential.iter()).fold(0.0, |acc, (q, v)| acc + q*v);
+
+        // the reaction field -\sum_i q_i <j|1/|r-s_i||k>
+        let mut reaction_field = vec![0.0; num_baspar];
+        _dgemv(&self.rinv, &charges, &mut reaction_field, 'N', -1.0, 0.0, 1, 1);
+
+        (reaction_field, solvation_energy, nuclear_energy)
+    }
+}
+
+/// The dielectric constant given by `solvent_eps`, or taken from the table for `solvent`
+pub fn solvent_epsilon(solvent: &str, solvent_eps: Option<f64>) -> anyhow::Result<f64> {
+    match (solvent_eps, dielectric_constant(solvent)) {
+        (Some(eps), _) if eps >= 1.0 => Ok(eps),
+        (Some(eps), _) => anyhow::bail!("The dielectric constant of the solvent should be no smaller than 1.0, but {} is given by 'solvent_eps'", eps),
+        (None, Some(eps)) => Ok(eps),
+        (None, None) => anyhow::bail!("The solvent ({}) is not in the dielectric table. Please specify its dielectric constant by 'solvent_eps'", solvent),
+    }
+}
+
+fn atom_positions(mol: &Molecule) -> Vec<[f64;3]> {
+    mol.geom.position.iter_columns_full().map(|r| [r[0], r[1], r[2]]).collect()
+}
+
+fn distance(a: &[f64;3], b: &[f64;3]) -> f64 {
+    ((a[0]-b[0]).powi(2) + (a[1]-b[1]).powi(2) + (a[2]-b[2]).powi(2)).sqrt()
+}
+
+#[test]
+fn test_cpcm_born_ion() {
+    // a unit point charge in a spherical cavity of the radius R: G = -1/2 f_eps/R (the Born model)
+    let radius = 3.0;
+    let cavity = Cavity::new(&[[0.0;3]], &[radius], 302);
+    let mut coulomb = cavity.coulomb.clone();
+    let response = _dinverse(&mut coulomb).unwrap();
+    let potential = vec![1.0/radius; cavity.points.len()];
+    let mut charges = vec![0.0; potential.len()];
+    _dgemv(&response, &potential, &mut charges, 'N', 1.0, 0.0, 1, 1);
+    let energy = -0.5*charges.iter().zip(potential.iter()).fold(0.0, |acc, (q, v)| acc + q*v);
+    assert!((cavity.areas.iter().sum::<f64>() - 4.0*PI*radius*radius).abs() < 1.0e-8);
+    assert!((energy + 0.5/radius).abs()*radius < 1.0e-3);
+}
+
+#[test]
+fn test_cpcm_born_ion_scf() {
+    // Li+ is compact enough to be a point charge in its cavity, so that the electrostatic solvation energy
+    // and the change of the SCF energy both approach the Born energy
+    use crate::ctrl_io::InputKeywords;
+    let ctrl_str = |solvent: &str| format!("[ctrl]
+        print_level = 0
+        xc = \"hf\"
+        charge = 1.0
+        basis_path = \"basis-set-pool/def2-SVP\"
+        auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+        solvent = \"{}\"
+        solvent_grid_points = 302
+        scf_acc_rho = 1.0e-9
+        scf_acc_eev = 1.0e-9
+        scf_acc_etot = 1.0e-10
+        [geom]
+        name = \"Li\"
+        unit = \"Angstrom\"
+        position = \"\"\"
+            Li   0.000   0.000   0.000\"\"\"", solvent);
+    let results: Vec<(f64, Option<f64>)> = ["none", "water"].iter().map(|solvent| {
+        let tmp_keys = toml::from_str::<serde_json::Value>(&ctrl_str(solvent)).unwrap();
+        let (ctrl, geom) = InputKeywords::parse_ctl_from_json(&tmp_keys).unwrap();
+        let mol = Molecule::build_native(ctrl, geom, None).unwrap();
+        let scf_data = crate::scf_io::scf(mol, &None).unwrap();
+        (scf_data.scf_energy, scf_data.energies.get("solvation").map(|x| x[0]))
+    }).collect();
+    let epsilon = dielectric_constant("water").unwrap();
+    let born = -0.5*(epsilon - 1.0)/epsilon/(bondi_radius(3)*RADII_SCALE/ANG);
+    let solvation = results[1].1.unwrap();
+    assert!(results[0].1.is_none());
+    assert!((solvation - born).abs() < 2.0e-3*born.abs(), "solvation {} vs Born {}", solvation, born);
+    assert!((results[1].0 - results[0].0 - born).abs() < 2.0e-3*born.abs(), "delta G {} vs Born {}", results[1].0 - results[0].0, born);
+}
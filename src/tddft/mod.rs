@@ -0,0 +1,475 @@
+//! Linear-response TDHF/TDDFT excitation energies using the Davidson algorithm.
+//!
+//! The response equations are solved in the basis of the occupied-virtual orbital pairs:
+//! ```text
+//!   Casida:  | A  B | |X|         | 1  0 | |X|
+//!            | B  A | |Y| = Omega | 0 -1 | |Y|,  or (A-B)(A+B)|X+Y> = Omega^2 |X+Y>
+//!   TDA:     A |X> = Omega |X>
+//! ```
+//! with
+//! ```text
+//!   (A+B)X = (e_a-e_i)X + Cv^T [J(D+D^T) + fxc(D+D^T) - c_x K(D+D^T)] Co
+//!   (A-B)X = (e_a-e_i)X - c_x Cv^T K(D-D^T) Co,    D = Cv X Co^T
+//! ```
+//! The singlet and triplet excitations are available for the RHF/RKS references, and the
+//! spin-conserving excitations for the UHF/UKS references.
+//! The Coulomb and exchange responses rely on the RI-V approximation, see [`SCF::response_fn_ks`].
+use tensors::{MathMatrix, MatrixFull};
+use tensors::matrix_blas_lapack::_dgemm_full;
+
+use crate::constants::EV;
+use crate::dft::fxc::XcKernel;
+use crate::mpi_io::MPIOperator;
+use crate::response::{ResponseSpace, ao_dipole_integrals, build_xc_kernel, check_response_availability};
+use crate::scf_io::SCF;
+use crate::utilities::linear_algebra::{dot, eigh};
+use crate::utilities;
+
+/// The threshold of the amplitudes to be printed out as the dominant ones
+pub const TDDFT_PRINT_AMPLITUDE: f64 = 0.1;
+/// The lowest eigenvalue of (A-B) or Omega^2 in the Casida subspace, below which the reference is taken as unstable
+pub const CASIDA_INSTABILITY_THRESHOLD: f64 = 1.0e-8;
+
+pub struct ExcitedStates {
+    /// the excitation energies in Hartree
+    pub energies: Vec<f64>,
+    /// the amplitudes of X in the layout of [vir, occ] for each spin channel
+    pub x: Vec<Vec<f64>>,
+    /// the amplitudes of Y, which are empty for TDA
+    pub y: Vec<Vec<f64>>,
+    pub oscillator_strengths: Vec<f64>,
+    pub converged: bool,
+}
+
+/// Apply (A+B) and (A-B) to the trial amplitudes `x`
+pub fn apply_apb_amb(scf_data: &SCF, space: &ResponseSpace, kernel: Option<&XcKernel>, singlet: bool, x: &[f64]) -> (Vec<f64>, Vec<f64>) {
+    let dm = space.amplitude_to_ao(x);
+    let (v_jxc, v_k) = scf_data.response_fn_ks(&dm, kernel, singlet);
+    let v_k_t: Vec<MatrixFull<f64>> = v_k.iter().map(|v| v.transpose()).collect();
+    // J + fxc - (K + K^T) for (A+B), and -(K - K^T) for (A-B)
+    let v_apb: Vec<MatrixFull<f64>> = v_jxc.iter().zip(v_k.iter().zip(v_k_t.iter())).map(|(vj, (vk, vk_t))| {
+        let mut v = vj.clone();
+        v.self_scaled_add(vk, -1.0);
+        v.self_scaled_add(vk_t, -1.0);
+        v
+    }).collect();
+    let v_amb: Vec<MatrixFull<f64>> = v_k.iter().zip(v_k_t.iter()).map(|(vk, vk_t)| {
+        let mut v = vk_t.clone();
+        v.self_scaled_add(vk, -1.0);
+        v
+    }).collect();
+    let mut apb = space.ao_to_amplitude(&v_apb);
+    let mut amb = space.ao_to_amplitude(&v_amb);
+    apb.iter_mut().zip(amb.iter_mut()).zip(space.de.iter().zip(x.iter())).for_each(|((apb, amb), (de, x))| {
+        *apb += de*x;
+        *amb += de*x;
+    });
+    (apb, amb)
+}
+
+/// Orthonormalize the new vectors against the subspace and themselves, and drop the linearly-dependent ones
+fn orthonormalize_new_vectors(space: &Vec<Vec<f64>>, new_vecs: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+    let mut accepted: Vec<Vec<f64>> = vec![];
+    new_vecs.into_iter().for_each(|mut new_x| {
+        // twice for the numerical stability
+        for _ in 0..2 {
+            space.iter().chain(accepted.iter()).for_each(|xi| {
+                let proj = dot(xi, &new_x);
+                new_x.iter_mut().zip(xi.iter()).for_each(|(n, xi)| *n -= proj*xi);
+            });
+        }
+        let norm = dot(&new_x, &new_x).sqrt();
+        if norm > 1.0e-6 {
+            new_x.iter_mut().for_each(|n| *n /= norm);
+            accepted.push(new_x);
+        }
+    });
+    accepted
+}
+
+/// The initial guesses: the unit vectors of the lowest orbital energy differences
+fn initial_guess(de: &[f64], num_guess: usize) -> Vec<Vec<f64>> {
+    let mut order: Vec<usize> = (0..de.len()).collect();
+    order.sort_by(|a, b| de[*a].partial_cmp(&de[*b]).unwrap());
+    order.iter().take(num_guess).map(|i| {
+        let mut x = vec![0.0; de.len()];
+        x[*i] = 1.0;
+        x
+    }).collect()
+}
+
+fn precondition(r: &[f64], de: &[f64], omega: f64) -> Vec<f64> {
+    r.iter().zip(de.iter()).map(|(r, de)| {
+        let d = omega - de;
+        if d.abs() < 1.0e-8 {r/1.0e-8_f64.copysign(d)} else {r/d}
+    }).collect()
+}
+
+/// Solve the lowest `num_roots` eigenpairs of the Tamm-Dancoff equation `A X = Omega X` by the Davidson algorithm.
+pub fn davidson_tda<F>(a_op: &mut F, de: &[f64], num_roots: usize, tol: f64, max_cycle: usize, print_level: usize)
+    -> (Vec<f64>, Vec<Vec<f64>>, bool)
+    where F: FnMut(&[f64]) -> Vec<f64> {
+    let length = de.len();
+    let max_space = (20*num_roots).max(40).min(length);
+    let mut xs: Vec<Vec<f64>> = vec![];
+    let mut axs: Vec<Vec<f64>> = vec![];
+    let mut new_vecs = initial_guess(de, num_roots);
+    let mut omega = vec![0.0; num_roots];
+    let mut states = vec![vec![0.0; length]; num_roots];
+    let mut converged = false;
+
+    for i_cycle in 0..max_cycle {
+        // restart the subspace with the current states when it becomes too large
+        if xs.len() + new_vecs.len() > max_space {
+            new_vecs = states.iter().cloned().chain(new_vecs.into_iter()).collect();
+            xs.clear();
+            axs.clear();
+        }
+        let new_vecs_on = orthonormalize_new_vectors(&xs, new_vecs);
+        if new_vecs_on.len() == 0 {break}
+        new_vecs_on.into_iter().for_each(|x| {
+            axs.push(a_op(&x));
+            xs.push(x);
+        });
+
+        let num_space = xs.len();
+        let mut a_sub = MatrixFull::new([num_space, num_space], 0.0);
+        for i in 0..num_space {
+            for j in 0..=i {
+                let a_ij = 0.5*(dot(&xs[i], &axs[j]) + dot(&xs[j], &axs[i]));
+                a_sub[[i,j]] = a_ij;
+                a_sub[[j,i]] = a_ij;
+            }
+        }
+        let (evec, eval) = eigh(&a_sub);
+
+        new_vecs = vec![];
+        let mut max_r_norm: f64 = 0.0;
+        for k in 0..num_roots.min(num_space) {
+            omega[k] = eval[k];
+            let mut x = vec![0.0; length];
+            let mut ax = vec![0.0; length];
+            for i in 0..num_space {
+                let c = evec[[i,k]];
+                x.iter_mut().zip(xs[i].iter()).for_each(|(x, xi)| *x += c*xi);
+                ax.iter_mut().zip(axs[i].iter()).for_each(|(ax, axi)| *ax += c*axi);
+            }
+            let r: Vec<f64> = ax.iter().zip(x.iter()).map(|(ax, x)| ax - omega[k]*x).collect();
+            let r_norm = dot(&r, &r).sqrt();
+            max_r_norm = max_r_norm.max(r_norm);
+            if r_norm > tol {new_vecs.push(precondition(&r, de, omega[k]))};
+            states[k] = x;
+        }
+        if print_level > 1 {
+            println!("TDA Davidson cycle {:3}: subspace size = {:4}, max |r| = {:10.5e}", i_cycle, num_space, max_r_norm);
+        }
+        if new_vecs.len() == 0 && num_space >= num_roots {
+            converged = true;
+            break
+        }
+    }
+
+    (omega, states, converged)
+}
+
+/// Solve the lowest `num_roots` excitations of the Casida equation by the Davidson algorithm of
+/// Stratmann, Scuseria and Frisch [J. Chem. Phys. 109, 8218 (1998)].
+///
+/// Return (Omega, X+Y, X-Y, converged) with the normalization of (X+Y)^T (X-Y) = 1.
+/// An error is returned if (A-B) or (A+B) is not positive definite in the subspace, i.e. Omega^2 <= 0,
+/// which indicates an instability of the reference
+pub fn davidson_casida<F>(ab_op: &mut F, de: &[f64], num_roots: usize, tol: f64, max_cycle: usize, print_level: usize)
+    -> anyhow::Result<(Vec<f64>, Vec<Vec<f64>>, Vec<Vec<f64>>, bool)>
+    where F: FnMut(&[f64]) -> (Vec<f64>, Vec<f64>) {
+    let length = de.len();
+    let max_space = (20*num_roots).max(40).min(length);
+    let mut bs: Vec<Vec<f64>> = vec![];
+    let mut apb_bs: Vec<Vec<f64>> = vec![];
+    let mut amb_bs: Vec<Vec<f64>> = vec![];
+    let mut new_vecs = initial_guess(de, num_roots);
+    let mut omega = vec![0.0; num_roots];
+    let mut xpy = vec![vec![0.0; length]; num_roots];
+    let mut xmy = vec![vec![0.0; length]; num_roots];
+    let mut converged = false;
+
+    for i_cycle in 0..max_cycle {
+        if bs.len() + new_vecs.len() > max_space {
+            new_vecs = xpy.iter().cloned().chain(xmy.iter().cloned()).chain(new_vecs.into_iter()).collect();
+            bs.clear();
+            apb_bs.clear();
+            amb_bs.clear();
+        }
+        let new_vecs_on = orthonormalize_new_vectors(&bs, new_vecs);
+        if new_vecs_on.len() == 0 {break}
+        new_vecs_on.into_iter().for_each(|b| {
+            let (apb, amb) = ab_op(&b);
+            apb_bs.push(apb);
+            amb_bs.push(amb);
+            bs.push(b);
+        });
+
+        let num_space = bs.len();
+        let mut m_p = MatrixFull::new([num_space, num_space], 0.0);
+        let mut m_m = MatrixFull::new([num_space, num_space], 0.0);
+        for i in 0..num_space {
+            for j in 0..=i {
+                let p_ij = 0.5*(dot(&bs[i], &apb_bs[j]) + dot(&bs[j], &apb_bs[i]));
+                let m_ij = 0.5*(dot(&bs[i], &amb_bs[j]) + dot(&bs[j], &amb_bs[i]));
+                m_p[[i,j]] = p_ij; m_p[[j,i]] = p_ij;
+                m_m[[i,j]] = m_ij; m_m[[j,i]] = m_ij;
+            }
+        }
+        // S = (M-)^{1/2}, and then S M+ S w = Omega^2 w
+        let (u_m, l_m) = eigh(&m_m);
+        if l_m[0] <= CASIDA_INSTABILITY_THRESHOLD {
+            return Err(anyhow::anyhow!("The (A-B) matrix is not positive definite with the eigenvalue of {:16.8e} in the subspace", l_m[0]))
+        }
+        let mut sqrt_m = MatrixFull::new([num_space, num_space], 0.0);
+        for i in 0..num_space {
+            for j in 0..num_space {
+                sqrt_m[[i,j]] = (0..num_space).fold(0.0, |acc, k| acc + u_m[[i,k]]*l_m[k].sqrt()*u_m[[j,k]]);
+            }
+        }
+        let mut tmp = MatrixFull::new([num_space, num_space], 0.0);
+        _dgemm_full(&m_p, 'N', &sqrt_m, 'N', &mut tmp, 1.0, 0.0);
+        let mut h_sub = MatrixFull::new([num_space, num_space], 0.0);
+        _dgemm_full(&sqrt_m, 'N', &tmp, 'N', &mut h_sub, 1.0, 0.0);
+        let (w, omega2) = eigh(&h_sub);
+        if omega2[0] <= CASIDA_INSTABILITY_THRESHOLD {
+            return Err(anyhow::anyhow!("The (A+B) matrix is not positive definite with Omega^2 = {:16.8e} in the subspace", omega2[0]))
+        }
+
+        new_vecs = vec![];
+        let mut max_r_norm: f64 = 0.0;
+        for k in 0..num_roots.min(num_space) {
+            omega[k] = omega2[k].sqrt();
+            // u = S w, v = M+ u / Omega, scaled to u^T v = 1
+            let u: Vec<f64> = (0..num_space).map(|i| (0..num_space).fold(0.0, |acc, j| acc + sqrt_m[[i,j]]*w[[j,k]])).collect();
+            let v: Vec<f64> = (0..num_space).map(|i| (0..num_space).fold(0.0, |acc, j| acc + m_p[[i,j]]*u[j])/omega[k]).collect();
+            let scale = 1.0/dot(&u, &v).sqrt();
+            let mut xpy_k = vec![0.0; length];
+            let mut xmy_k = vec![0.0; length];
+            let mut r_p = vec![0.0; length];
+            let mut r_m = vec![0.0; length];
+            for i in 0..num_space {
+                let (ui, vi) = (u[i]*scale, v[i]*scale);
+                xpy_k.iter_mut().zip(bs[i].iter()).for_each(|(to, b)| *to += ui*b);
+                xmy_k.iter_mut().zip(bs[i].iter()).for_each(|(to, b)| *to += vi*b);
+                r_p.iter_mut().zip(apb_bs[i].iter()).for_each(|(to, apb)| *to += ui*apb);
+                r_m.iter_mut().zip(amb_bs[i].iter()).for_each(|(to, amb)| *to += vi*amb);
+            }
+            // r+ = (A+B)(X+Y) - Omega (X-Y), r- = (A-B)(X-Y) - Omega (X+Y)
+            r_p.iter_mut().zip(xmy_k.iter()).for_each(|(r, x)| *r -= omega[k]*x);
+            r_m.iter_mut().zip(xpy_k.iter()).for_each(|(r, x)| *r -= omega[k]*x);
+            let r_norm = (dot(&r_p, &r_p) + dot(&r_m, &r_m)).sqrt();
+            max_r_norm = max_r_norm.max(r_norm);
+            if r_norm > tol {
+                new_vecs.push(precondition(&r_p, de, omega[k]));
+                new_vecs.push(precondition(&r_m, de, omega[k]));
+            }
+            xpy[k] = xpy_k;
+            xmy[k] = xmy_k;
+        }
+        if print_level > 1 {
+            println!("Casida Davidson cycle {:3}: subspace size = {:4}, max |r| = {:10.5e}", i_cycle, num_space, max_r_norm);
+        }
+        if new_vecs.len() == 0 && num_space >= num_roots {
+            converged = true;
+            break
+        }
+    }
+
+    Ok((omega, xpy, xmy, converged))
+}
+
+/// Perform the TDHF/TDDFT calculation for the lowest `tddft_nstates` excitations
+pub fn tddft_calculations(scf_data: &SCF, mpi_operator: &Option<MPIOperator>) -> anyhow::Result<ExcitedStates> {
+    check_response_availability(scf_data, mpi_operator, "TDHF/TDDFT");
+
+    let num_states = scf_data.mol.ctrl.tddft_nstates;
+    let use_tda = scf_data.mol.ctrl.tddft_tda;
+    let singlet = ! scf_data.mol.ctrl.tddft_triplet;
+    let tol = scf_data.mol.ctrl.tddft_conv_tol;
+    let max_cycle = scf_data.mol.ctrl.tddft_max_cycle;
+    let print_level = scf_data.mol.ctrl.print_level;
+    let spin_channel = scf_data.mol.spin_channel;
+    if ! singlet && spin_channel == 2 {
+        println!("WARNING: tddft_triplet is ignored for the unrestricted references");
+    }
+
+    let space = ResponseSpace::build(scf_data);
+    let num_states = num_states.min(space.len());
+
+    let kernel = build_xc_kernel(scf_data)?;
+
+    let dt0 = utilities::init_timing();
+    let (energies, x, y, converged) = if use_tda {
+        let mut a_op = |x: &[f64]| -> Vec<f64> {
+            let (apb, amb) = apply_apb_amb(scf_data, &space, kernel.as_ref(), singlet, x);
+            apb.iter().zip(amb.iter()).map(|(p, m)| 0.5*(p + m)).collect()
+        };
+        let (omega, x, converged) = davidson_tda(&mut a_op, &space.de, num_states, tol, max_cycle, print_level);
+        (omega, x, vec![], converged)
+    } else {
+        let mut ab_op = |x: &[f64]| apply_apb_amb(scf_data, &space, kernel.as_ref(), singlet, x);
+        let instability = if spin_channel == 1 && ! singlet {"triplet"} else {"singlet (ground-state)"};
+        let (omega, xpy, xmy, converged) = davidson_casida(&mut ab_op, &space.de, num_states, tol, max_cycle, print_level)
+            .map_err(|err| anyhow::anyhow!("{}, which indicates a {} instability of the reference. \
+                Please check the stability of the SCF solution, or use the Tamm-Dancoff approximation (tddft_tda = true)", err, instability))?;
+        let x: Vec<Vec<f64>> = xpy.iter().zip(xmy.iter()).map(|(p, m)| p.iter().zip(m.iter()).map(|(p, m)| 0.5*(p + m)).collect()).collect();
+        let y: Vec<Vec<f64>> = xpy.iter().zip(xmy.iter()).map(|(p, m)| p.iter().zip(m.iter()).map(|(p, m)| 0.5*(p - m)).collect()).collect();
+        (omega, x, y, converged)
+    };
+    utilities::timing(&dt0, Some("TDHF/TDDFT Davidson iterations"));
+    if ! converged {
+        println!("WARNING: the TDHF/TDDFT Davidson iterations are not converged in {} cycles", max_cycle);
+    }
+
+    // the oscillator strengths f = 2/3 Omega |<0|r|n>|^2, which vanish for the triplets
+    let dip_ints = ao_dipole_integrals(scf_data).map(|ao_dip_x| space.ao_to_amplitude(&vec![ao_dip_x.clone(), ao_dip_x]));
+    let oscillator_strengths: Vec<f64> = (0..energies.len()).map(|n| {
+        if spin_channel == 1 && ! singlet {return 0.0}
+        let spin_factor = if spin_channel == 1 {2.0_f64.sqrt()} else {1.0};
+        let trans_dip = [0usize,1usize,2usize].map(|xyz| {
+            let xpy_dot = if y.len() == 0 {
+                dot(&x[n], &dip_ints[xyz])
+            } else {
+                dot(&x[n], &dip_ints[xyz]) + dot(&y[n], &dip_ints[xyz])
+            };
+            spin_factor*xpy_dot
+        });
+        2.0/3.0*energies[n]*trans_dip.iter().fold(0.0, |acc, d| acc + d*d)
+    }).collect();
+
+    let excited_states = ExcitedStates {energies, x, y, oscillator_strengths, converged};
+    if print_level > 0 {
+        excited_states.formated_output(&space, use_tda, singlet);
+    }
+    Ok(excited_states)
+}
+
+impl ExcitedStates {
+    pub fn formated_output(&self, space: &ResponseSpace, use_tda: bool, singlet: bool) {
+        let method = if use_tda {"TDA"} else {"Casida"};
+        let multiplicity = if space.spin_channel == 2 {"unrestricted"} else if singlet {"singlet"} else {"triplet"};
+        println!("----------------------------------------------------------------------");
+        println!("Excited states by linear-response TDHF/TDDFT ({}, {})", method, multiplicity);
+        println!("----------------------------------------------------------------------");
+        println!("{:>6} {:>16} {:>12} {:>12} {:>12}", "State", "Omega (Ha)", "Omega (eV)", "Lambda (nm)", "f");
+        self.energies.iter().zip(self.oscillator_strengths.iter()).enumerate().for_each(|(n, (omega, f))| {
+            // 1239.84198 eV*nm
+            let lambda = 1239.84198/(omega*EV);
+            println!("{:>6} {:16.8} {:12.4} {:12.2} {:12.6}", n+1, omega, omega*EV, lambda, f);
+            for i_spin in 0..space.spin_channel {
+                let spin_label = if space.spin_channel == 1 {""} else if i_spin == 0 {"a"} else {"b"};
+                let num_vir = space.num_vir[i_spin];
+                let num_occ = space.num_occ[i_spin];
+                let range = space.spin_range(i_spin);
+                self.x[n][range.clone()].iter().enumerate().filter(|(_, x)| x.abs() > TDDFT_PRINT_AMPLITUDE).for_each(|(ia, x)| {
+                    let (a, i) = (ia % num_vir + num_occ, ia / num_vir);
+                    println!("{:>12}{:<2} -> {:>5}{:<2} {:12.6}", i+1, spin_label, a+1, spin_label, x);
+                });
+                if self.y.len() > 0 {
+                    self.y[n][range].iter().enumerate().filter(|(_, y)| y.abs() > TDDFT_PRINT_AMPLITUDE).for_each(|(ia, y)| {
+                        let (a, i) = (ia % num_vir + num_occ, ia / num_vir);
+                        println!("{:>12}{:<2} <- {:>5}{:<2} {:12.6}", i+1, spin_label, a+1, spin_label, y);
+                    });
+                }
+            }
+        });
+        println!("----------------------------------------------------------------------");
+    }
+}
+
+#[test]
+fn test_davidson_casida_diagonal() {
+    // for the diagonal A and B = b*I, Omega = sqrt((a-b)(a+b))
+    let de = vec![0.5, 0.8, 1.1, 1.7, 2.3];
+    let b = 0.1;
+    let mut ab_op = |x: &[f64]| {
+        let apb: Vec<f64> = x.iter().zip(de.iter()).map(|(x, a)| (a + b)*x).collect();
+        let amb: Vec<f64> = x.iter().zip(de.iter()).map(|(x, a)| (a - b)*x).collect();
+        (apb, amb)
+    };
+    let (omega, xpy, xmy, converged) = davidson_casida(&mut ab_op, &de, 2, 1.0e-8, 20, 0).unwrap();
+    assert!(converged);
+    assert!((omega[0] - (0.4_f64*0.6).sqrt()).abs() < 1.0e-8);
+    assert!((omega[1] - (0.7_f64*0.9).sqrt()).abs() < 1.0e-8);
+    assert!((dot(&xpy[0], &xmy[0]) - 1.0).abs() < 1.0e-8);
+}
+
+#[test]
+fn test_davidson_casida_instability() {
+    // (A-B) or (A+B) with a negative eigenvalue for the unstable references
+    let de = vec![0.5, 0.8, 1.1];
+    for b in [0.6, -0.6] {
+        let mut ab_op = |x: &[f64]| {
+            let apb: Vec<f64> = x.iter().zip(de.iter()).map(|(x, a)| (a + b)*x).collect();
+            let amb: Vec<f64> = x.iter().zip(de.iter()).map(|(x, a)| (a - b)*x).collect();
+            (apb, amb)
+        };
+        assert!(davidson_casida(&mut ab_op, &de, 2, 1.0e-8, 20, 0).is_err());
+    }
+}
+
+#[test]
+fn test_tddft_against_full_diagonalization() {
+    use crate::utilities::linear_algebra::matmul;
+    for xc in ["hf", "pbe"] {
+        let ctrl_str = format!("[ctrl]
+            print_level = 0
+            xc = \"{}\"
+            basis_path = \"basis-set-pool/def2-SVP\"
+            auxbas_path = \"basis-set-pool/def2-SVP-JKFIT\"
+            eri_type = \"ri-v\"
+            use_ri_symm = false
+            scf_acc_rho = 1.0e-9
+            scf_acc_eev = 1.0e-9
+            scf_acc_etot = 1.0e-11
+            tddft_nstates = 3
+            tddft_conv_tol = 1.0e-7
+            [geom]
+            name = \"H2O\"
+            unit = \"Angstrom\"
+            position = \"\"\"
+                O   0.000   0.000   0.117
+                H   0.000   0.757  -0.467
+                H   0.000  -0.757  -0.467\"\"\"", xc);
+        let mut scf_data = crate::grad::scf_for_test(&ctrl_str);
+        let casida = tddft_calculations(&scf_data, &None).unwrap();
+        scf_data.mol.ctrl.tddft_tda = true;
+        let tda = tddft_calculations(&scf_data, &None).unwrap();
+        assert!(casida.converged && tda.converged);
+
+        // the dense (A+B) and (A-B) by the responses to the unit vectors
+        let space = ResponseSpace::build(&scf_data);
+        let kernel = build_xc_kernel(&scf_data).unwrap();
+        let n = space.len();
+        let mut apb = MatrixFull::new([n, n], 0.0);
+        let mut amb = MatrixFull::new([n, n], 0.0);
+        for i in 0..n {
+            let mut unit = vec![0.0; n];
+            unit[i] = 1.0;
+            let (apb_i, amb_i) = apply_apb_amb(&scf_data, &space, kernel.as_ref(), true, &unit);
+            apb.iter_column_mut(i).zip(apb_i.iter()).for_each(|(to, from)| *to = *from);
+            amb.iter_column_mut(i).zip(amb_i.iter()).for_each(|(to, from)| *to = *from);
+        }
+        let mut a = apb.clone();
+        a.self_add(&amb);
+        a.data.iter_mut().for_each(|x| *x *= 0.5);
+        let (_, omega_tda) = eigh(&a);
+        // Omega^2 are the eigenvalues of (A-B)^{1/2} (A+B) (A-B)^{1/2}
+        let (u_m, l_m) = eigh(&amb);
+        let mut u_sqrt = u_m.clone();
+        l_m.iter().enumerate().for_each(|(k, l)| u_sqrt.iter_column_mut(k).for_each(|u| *u *= l.sqrt()));
+        let sqrt_m = matmul(&u_sqrt, &u_m.transpose());
+        let (_, omega2) = eigh(&matmul(&sqrt_m, &matmul(&apb, &sqrt_m)));
+
+        for k in 0..3 {
+            assert!((tda.energies[k] - omega_tda[k]).abs() < 1.0e-6, "{}: TDA {} vs {}", xc, tda.energies[k], omega_tda[k]);
+            assert!((casida.energies[k] - omega2[k].sqrt()).abs() < 1.0e-6, "{}: Casida {} vs {}", xc, casida.energies[k], omega2[k].sqrt());
+        }
+        // the de-excitations lower the lowest excitation energy of the Casida equation
+        assert!(casida.energies[0] <= tda.energies[0] + 1.0e-8);
+    }
+}
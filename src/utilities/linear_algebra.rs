@@ -0,0 +1,78 @@
+//! The small dense linear-algebra helpers shared by the optimizers, the frequency analysis and the
+//! response solvers, which are built on the BLAS/LAPACK routines of `rest_tensors`.
+//! The 3-vector helpers for the Cartesian geometries are collected in [`vec3`].
+use tensors::MatrixFull;
+use tensors::matrix_blas_lapack::_dgemm_full;
+
+pub fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b.iter()).fold(0.0, |acc, (a, b)| acc + a*b)
+}
+
+pub fn diagonal_matrix(diag: &[f64]) -> MatrixFull<f64> {
+    let mut m = MatrixFull::new([diag.len(), diag.len()], 0.0);
+    diag.iter().enumerate().for_each(|(i, d)| m[[i,i]] = *d);
+    m
+}
+
+pub fn matmul(a: &MatrixFull<f64>, b: &MatrixFull<f64>) -> MatrixFull<f64> {
+    let mut c = MatrixFull::new([a.size[0], b.size[1]], 0.0);
+    _dgemm_full(a, 'N', b, 'N', &mut c, 1.0, 0.0);
+    c
+}
+
+pub fn mat_vec(a: &MatrixFull<f64>, v: &[f64]) -> Vec<f64> {
+    let v = MatrixFull::from_vec([v.len(), 1], v.to_vec()).unwrap();
+    matmul(a, &v).data
+}
+
+/// The eigenvalues (ascending) and eigenvectors (in columns) of a symmetric matrix
+pub fn eigh(mat: &MatrixFull<f64>) -> (MatrixFull<f64>, Vec<f64>) {
+    let mut mat_upper = mat.to_matrixupper();
+    let (evec, eval, _) = mat_upper.to_matrixupperslicemut().lapack_dspevx().unwrap();
+    (evec, eval.to_vec())
+}
+
+/// The generalized inverse of a symmetric matrix, neglecting the eigenvalues below the threshold
+pub fn generalized_inverse(mat: &MatrixFull<f64>, threshold: f64) -> MatrixFull<f64> {
+    let n = mat.size[0];
+    let (evec, eval) = eigh(mat);
+    // V diag(1/e) V^T over the retained eigenvalues
+    let mut evec_scaled = evec.clone();
+    eval.iter().enumerate().for_each(|(k, e)| {
+        let inv_e = if e.abs() > threshold {1.0/e} else {0.0};
+        evec_scaled.iter_column_mut(k).for_each(|v| *v *= inv_e);
+    });
+    let mut inv = MatrixFull::new([n, n], 0.0);
+    _dgemm_full(&evec_scaled, 'N', &evec, 'T', &mut inv, 1.0, 0.0);
+    inv
+}
+
+pub mod vec3 {
+    pub fn add(a: &[f64;3], b: &[f64;3]) -> [f64;3] {
+        [a[0]+b[0], a[1]+b[1], a[2]+b[2]]
+    }
+
+    pub fn sub(a: &[f64;3], b: &[f64;3]) -> [f64;3] {
+        [a[0]-b[0], a[1]-b[1], a[2]-b[2]]
+    }
+
+    pub fn scale(a: &[f64;3], f: f64) -> [f64;3] {
+        [a[0]*f, a[1]*f, a[2]*f]
+    }
+
+    pub fn dot(a: &[f64;3], b: &[f64;3]) -> f64 {
+        a[0]*b[0] + a[1]*b[1] + a[2]*b[2]
+    }
+
+    pub fn cross(a: &[f64;3], b: &[f64;3]) -> [f64;3] {
+        [a[1]*b[2]-a[2]*b[1], a[2]*b[0]-a[0]*b[2], a[0]*b[1]-a[1]*b[0]]
+    }
+
+    pub fn norm(a: &[f64;3]) -> f64 {
+        dot(a, a).sqrt()
+    }
+
+    pub fn normalize(a: &[f64;3]) -> [f64;3] {
+        scale(a, 1.0/norm(a))
+    }
+}
@@ -3,6 +3,9 @@ use rayon::ThreadPoolBuildError;
 use time::{DateTime,Local};
 use std::{time::Instant, collections::HashMap, ops::Range};
 use regex::Regex;
+
+pub mod linear_algebra;
+
 enum DebugTiming {
    Yes,
    Not,